@@ -0,0 +1,114 @@
+use crate::error::{Error, Result};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+
+/// Expands `ap NAME ARGS...` when NAME is an alias from the config. `{n}`
+/// or `{1}` to `{9}` in the alias take the arguments after the name, in
+/// order, and any arguments left over are appended.
+///
+/// Built-in subcommands always win, so an alias can't shadow `config`.
+pub fn expand(
+    args: Vec<OsString>,
+    aliases: &BTreeMap<String, String>,
+    builtins: &[&str],
+) -> Result<Vec<OsString>> {
+    let Some(name) = args.get(1).and_then(|a| a.to_str()) else {
+        return Ok(args);
+    };
+    if builtins.contains(&name) {
+        return Ok(args);
+    }
+    let Some(template) = aliases.get(name) else {
+        return Ok(args);
+    };
+    let words = shlex::split(template)
+        .ok_or_else(|| Error::Validation(format!("alias '{name}' has unbalanced quotes")))?;
+
+    let rest: Vec<String> = args[2..]
+        .iter()
+        .map(|a| a.to_string_lossy().into_owned())
+        .collect();
+    let mut used = vec![false; rest.len()];
+    let mut expanded = vec![args[0].clone()];
+    for word in words {
+        expanded.push(substitute(name, &word, &rest, &mut used)?.into());
+    }
+    expanded.extend(
+        rest.iter()
+            .zip(&used)
+            .filter(|(_, used)| !**used)
+            .map(|(arg, _)| OsString::from(arg)),
+    );
+    Ok(expanded)
+}
+
+fn substitute(name: &str, word: &str, rest: &[String], used: &mut [bool]) -> Result<String> {
+    let mut out = word.to_string();
+    for n in 1..=9 {
+        let numbered = format!("{{{n}}}");
+        let placeholders: &[&str] = if n == 1 {
+            &["{n}", &numbered]
+        } else {
+            &[&numbered]
+        };
+        for placeholder in placeholders {
+            if !out.contains(placeholder) {
+                continue;
+            }
+            let value = rest.get(n - 1).ok_or_else(|| {
+                Error::Validation(format!(
+                    "alias '{name}' needs {n} argument{}: ap {name} <arg>...",
+                    if n == 1 { "" } else { "s" }
+                ))
+            })?;
+            used[n - 1] = true;
+            out = out.replace(placeholder, value);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str], aliases: &[(&str, &str)]) -> Result<Vec<String>> {
+        let aliases = aliases
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let args = args.iter().map(OsString::from).collect();
+        Ok(expand(args, &aliases, &["config", "init"])?
+            .into_iter()
+            .map(|a| a.into_string().unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn expands_placeholders_and_appends_rest() {
+        let quick = [("quick", "-a {n} --auto-doc --theme dracula --force")];
+        let expanded = run(&["ap", "quick", "7", "--input", "5"], &quick).unwrap();
+        let expected = "ap -a 7 --auto-doc --theme dracula --force --input 5";
+        assert_eq!(expanded.join(" "), expected);
+    }
+
+    #[test]
+    fn quoted_words_and_numbered_args() {
+        let alias = [("t", "-a {1} --input \"{2}\\n\"")];
+        assert_eq!(
+            run(&["ap", "t", "7", "4 5"], &alias).unwrap(),
+            ["ap", "-a", "7", "--input", "4 5\\n"]
+        );
+        assert!(run(&["ap", "t", "7"], &alias).is_err());
+    }
+
+    #[test]
+    fn builtins_and_unknown_words_untouched() {
+        let alias = [("config", "-a 1"), ("quick", "-a {n}")];
+        assert_eq!(
+            run(&["ap", "config", "show"], &alias).unwrap(),
+            ["ap", "config", "show"]
+        );
+        assert_eq!(run(&["ap", "-a", "7"], &alias).unwrap(), ["ap", "-a", "7"]);
+    }
+}
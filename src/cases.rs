@@ -0,0 +1,414 @@
+use crate::error::{Error, Result, io_err};
+use crate::fs::read_text_lossy;
+use crate::terminal::TestCase;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CasesFile {
+    #[serde(default)]
+    tests: Vec<CaseDef>,
+    /// Same as `tests`, for specs written as a sequence of phases.
+    #[serde(default)]
+    phase: Vec<CaseDef>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CaseDef {
+    name: String,
+    input: Option<String>,
+    input_file: Option<PathBuf>,
+    expected: Option<String>,
+    expected_file: Option<PathBuf>,
+    /// Marks the phase that shows the build rather than a run.
+    #[serde(default)]
+    compile: bool,
+}
+
+/// Collects test cases from repeated `--test name=file` flags and an
+/// optional tests file. Inputs from files are used exactly as written.
+/// Also returns the name of the tests file's compile phase, if it has one.
+pub fn resolve(args: &[String], file: Option<&Path>) -> Result<(Vec<TestCase>, Option<String>)> {
+    let mut cases = Vec::new();
+    let mut compile_phase = None;
+    if let Some(path) = file {
+        let (loaded, compile) = load_file(path)?;
+        cases.extend(loaded);
+        compile_phase = compile;
+    }
+    for arg in args {
+        let (name, path) = parse_test_arg(arg)?;
+        cases.push(TestCase {
+            name: Some(name),
+            input: Some(read_input(&path)?),
+            expected: None,
+            script: None,
+            error_case: false,
+        });
+    }
+
+    let mut seen = HashSet::new();
+    for case in &cases {
+        let name = case.name.as_deref().unwrap_or_default();
+        if !seen.insert(name.to_lowercase()) {
+            return Err(Error::Validation(format!("test '{name}' is defined twice")));
+        }
+    }
+    Ok((cases, compile_phase))
+}
+
+fn parse_test_arg(arg: &str) -> Result<(String, PathBuf)> {
+    let (name, path) = arg
+        .split_once('=')
+        .ok_or_else(|| Error::Validation(format!("--test expects NAME=FILE, got '{arg}'")))?;
+    let name = clean_case_name(name)?;
+    if path.trim().is_empty() {
+        return Err(Error::Validation(format!(
+            "test '{name}' has no input file"
+        )));
+    }
+    Ok((name, PathBuf::from(path.trim())))
+}
+
+fn clean_case_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::Validation("test names cannot be blank".into()));
+    }
+    Ok(name.to_string())
+}
+
+/// Reads a TOML file of `[[tests]]` or `[[phase]]` tables, each with a
+/// `name`, an inline `input` or an `input_file`, and optionally `expected` or
+/// `expected_file`. File paths are relative to the tests file. The first
+/// phase may instead be `compile = true` to show the build.
+fn load_file(path: &Path) -> Result<(Vec<TestCase>, Option<String>)> {
+    let content =
+        fs::read_to_string(path).map_err(|e| io_err(format!("reading {}", path.display()), e))?;
+    let parsed = parse_file(&content)
+        .map_err(|e| Error::Validation(format!("bad tests file '{}': {e}", path.display())))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    build_cases(parsed, base)
+}
+
+fn build_cases(parsed: CasesFile, base: &Path) -> Result<(Vec<TestCase>, Option<String>)> {
+    if !parsed.tests.is_empty() && !parsed.phase.is_empty() {
+        return Err(Error::Validation(
+            "tests file mixes [[tests]] and [[phase]], use one or the other".into(),
+        ));
+    }
+    let mut defs = parsed.tests;
+    defs.extend(parsed.phase);
+
+    let mut compile_phase = None;
+    if let Some(pos) = defs.iter().position(|d| d.compile) {
+        let def = defs.remove(pos);
+        let name = clean_case_name(&def.name)?;
+        if pos != 0 {
+            return Err(Error::Validation(format!(
+                "compile phase '{name}' has to come first"
+            )));
+        }
+        if def.input.is_some()
+            || def.input_file.is_some()
+            || def.expected.is_some()
+            || def.expected_file.is_some()
+        {
+            return Err(Error::Validation(format!(
+                "compile phase '{name}' can't have input or expected output"
+            )));
+        }
+        if let Some(extra) = defs.iter().find(|d| d.compile) {
+            return Err(Error::Validation(format!(
+                "'{}' is a second compile phase, there can only be one",
+                extra.name
+            )));
+        }
+        compile_phase = Some(name);
+    }
+
+    let cases = defs
+        .into_iter()
+        .map(|def| {
+            let name = clean_case_name(&def.name)?;
+            let input = inline_or_file(&name, "input", def.input, def.input_file, base)?;
+            let expected =
+                inline_or_file(&name, "expected", def.expected, def.expected_file, base)?;
+            Ok(TestCase {
+                name: Some(name),
+                input,
+                expected,
+                script: None,
+                error_case: false,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok((cases, compile_phase))
+}
+
+fn inline_or_file(
+    name: &str,
+    key: &str,
+    inline: Option<String>,
+    file: Option<PathBuf>,
+    base: &Path,
+) -> Result<Option<String>> {
+    match (inline, file) {
+        (Some(_), Some(_)) => Err(Error::Validation(format!(
+            "test '{name}' sets both {key} and {key}_file"
+        ))),
+        (Some(text), None) => Ok(Some(text)),
+        (None, Some(file)) => read_input(&base.join(file)).map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Attaches `--expected` files to cases. `NAME=FILE` targets a named test,
+/// a bare `FILE` is only allowed when there's a single run.
+pub fn apply_expected(cases: &mut [TestCase], args: &[String]) -> Result<()> {
+    for arg in args {
+        let named = arg.split_once('=').and_then(|(name, file)| {
+            let idx = cases.iter().position(|c| {
+                c.name
+                    .as_deref()
+                    .is_some_and(|n| n.eq_ignore_ascii_case(name.trim()))
+            })?;
+            Some((idx, file.trim()))
+        });
+        let (idx, file) = match named {
+            Some(found) => found,
+            None if cases.len() == 1 => (0, arg.as_str()),
+            None => {
+                return Err(Error::Validation(format!(
+                    "--expected '{arg}' doesn't name a test, use NAME=FILE"
+                )));
+            }
+        };
+        if cases[idx].expected.is_some() {
+            return Err(Error::Validation(format!(
+                "expected output given twice for '{}'",
+                cases[idx].name.as_deref().unwrap_or("the run")
+            )));
+        }
+        cases[idx].expected = Some(read_input(Path::new(file))?);
+    }
+    Ok(())
+}
+
+/// Directories searched for numbered input files, in order.
+const TEST_DIRS: &[&str] = &["tests", "inputs"];
+const INPUT_EXTENSIONS: &[&str] = &["", "txt", "in", "input"];
+/// A file next to an input with the same stem and one of these extensions
+/// is taken as its expected output.
+const EXPECTED_EXTENSIONS: &[&str] = &["out", "expected", "ans"];
+
+/// Finds a `tests/` or `inputs/` directory in `dir` holding numbered input
+/// files like `1.txt` or `input02.in`, and makes one case per file in
+/// numeric order. Returns the directory used along with its cases.
+pub fn discover(dir: &Path) -> Result<Option<(PathBuf, Vec<TestCase>)>> {
+    for name in TEST_DIRS {
+        let test_dir = dir.join(name);
+        if !test_dir.is_dir() {
+            continue;
+        }
+        let entries = fs::read_dir(&test_dir)
+            .map_err(|e| io_err(format!("reading {}", test_dir.display()), e))?;
+        let mut inputs: Vec<(u64, String, PathBuf)> = entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && is_input_file(p))
+            .filter_map(|p| {
+                let stem = p.file_stem()?.to_str()?.to_string();
+                Some((leading_number(&stem)?, stem, p))
+            })
+            .collect();
+        if inputs.is_empty() {
+            continue;
+        }
+        inputs.sort();
+
+        // `1.txt` and `1.in` would both be test '1'
+        let mut seen: HashMap<String, &Path> = HashMap::new();
+        for (_, stem, path) in &inputs {
+            if let Some(first) = seen.insert(stem.to_lowercase(), path) {
+                return Err(Error::Validation(format!(
+                    "'{}' and '{}' are both test '{stem}', rename one of them",
+                    first.display(),
+                    path.display()
+                )));
+            }
+        }
+
+        let mut cases = Vec::with_capacity(inputs.len());
+        for (_, stem, path) in inputs {
+            let expected = EXPECTED_EXTENSIONS
+                .iter()
+                .map(|ext| path.with_extension(ext))
+                .find(|p| p.is_file())
+                .map(|p| read_text_lossy(&p))
+                .transpose()?;
+            cases.push(TestCase {
+                name: Some(stem),
+                input: Some(read_text_lossy(&path)?),
+                expected,
+                script: None,
+                error_case: false,
+            });
+        }
+        return Ok(Some((test_dir, cases)));
+    }
+    Ok(None)
+}
+
+fn is_input_file(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    INPUT_EXTENSIONS.contains(&ext.as_str())
+}
+
+/// The first run of digits in a file stem, e.g. 2 for `input02`.
+fn leading_number(stem: &str) -> Option<u64> {
+    let start = stem.find(|c: char| c.is_ascii_digit())?;
+    let digits: String = stem[start..]
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+fn parse_file(content: &str) -> std::result::Result<CasesFile, toml::de::Error> {
+    toml::from_str(content)
+}
+
+fn read_input(path: &Path) -> Result<String> {
+    if !path.is_file() {
+        return Err(Error::Validation(format!(
+            "test file not found: '{}'",
+            path.display()
+        )));
+    }
+    read_text_lossy(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::scratch_dir;
+
+    #[test]
+    fn test_arg_splits_on_first_equals() {
+        let (name, path) = parse_test_arg("small = in/a=b.txt").unwrap();
+        assert_eq!(name, "small");
+        assert_eq!(path, PathBuf::from("in/a=b.txt"));
+        assert!(parse_test_arg("small").is_err());
+        assert!(parse_test_arg("=in.txt").is_err());
+        assert!(parse_test_arg("small=").is_err());
+    }
+
+    #[test]
+    fn bare_expected_needs_single_run() {
+        let case = |name: &str| TestCase {
+            name: Some(name.into()),
+            input: None,
+            expected: None,
+            script: None,
+            error_case: false,
+        };
+        let mut cases = vec![case("small"), case("big")];
+        let err = apply_expected(&mut cases, &["out.txt".into()]).unwrap_err();
+        assert!(err.to_string().contains("NAME=FILE"));
+        let err = apply_expected(&mut cases, &["BIG=/nonexistent/out.txt".into()]).unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn numbered_inputs_discovered_in_order() {
+        let dir = scratch_dir("discover");
+        let tests = dir.join("tests");
+        fs::create_dir_all(&tests).unwrap();
+        assert!(discover(&dir).unwrap().is_none());
+
+        fs::write(tests.join("input10.txt"), "10\n").unwrap();
+        fs::write(tests.join("input2.txt"), "2\n").unwrap();
+        fs::write(tests.join("input2.out"), "two\n").unwrap();
+        fs::write(tests.join("README.md"), "notes").unwrap();
+        fs::write(tests.join("notes.txt"), "no number").unwrap();
+
+        let (found, cases) = discover(&dir).unwrap().unwrap();
+        assert_eq!(found, tests);
+        let names: Vec<_> = cases.iter().map(|c| c.name.as_deref().unwrap()).collect();
+        assert_eq!(names, ["input2", "input10"]);
+        assert_eq!(cases[0].expected.as_deref(), Some("two\n"));
+        assert!(cases[1].expected.is_none());
+    }
+
+    #[test]
+    fn inputs_with_the_same_stem_rejected() {
+        let dir = scratch_dir("discover_dup");
+        let tests = dir.join("tests");
+        fs::create_dir_all(&tests).unwrap();
+        fs::write(tests.join("1.txt"), "a\n").unwrap();
+        fs::write(tests.join("1.in"), "b\n").unwrap();
+
+        let err = discover(&dir).err().unwrap();
+        assert!(err.to_string().contains("both test '1'"));
+    }
+
+    #[test]
+    fn tests_file_parses() {
+        let parsed = parse_file(
+            r#"
+            [[tests]]
+            name = "small"
+            input = "3 4\n"
+
+            [[tests]]
+            name = "big"
+            input_file = "big.txt"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(parsed.tests.len(), 2);
+        assert_eq!(parsed.tests[0].input.as_deref(), Some("3 4\n"));
+        assert_eq!(parsed.tests[1].input_file, Some(PathBuf::from("big.txt")));
+        assert!(parse_file("[[tests]]\nname = \"x\"\ninptu = \"1\"").is_err());
+    }
+
+    #[test]
+    fn phases_with_a_compile_step() {
+        let build = |toml: &str| build_cases(parse_file(toml).unwrap(), Path::new("."));
+        let (cases, compile) = build(
+            r#"
+            [[phase]]
+            name = "compile"
+            compile = true
+
+            [[phase]]
+            name = "run with valid input"
+            input = "3\n4\n"
+
+            [[phase]]
+            name = "run with invalid input"
+            input = "x\n"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(compile.as_deref(), Some("compile"));
+        let names: Vec<_> = cases.iter().map(|c| c.name.as_deref().unwrap()).collect();
+        assert_eq!(names, ["run with valid input", "run with invalid input"]);
+
+        let late =
+            "[[phase]]\nname = \"a\"\ninput = \"1\"\n[[phase]]\nname = \"b\"\ncompile = true";
+        let err = build(late).err().unwrap();
+        assert!(err.to_string().contains("first"));
+        let mixed = "[[tests]]\nname = \"a\"\n[[phase]]\nname = \"b\"";
+        assert!(build(mixed).is_err());
+    }
+}
@@ -1,15 +1,25 @@
-use clap::{ArgAction, Args, Parser, Subcommand};
+use crate::config::{
+    DocCompat, DocFormat, DocSection, IndentPolicy, LineEndings, NamingPreset, PageSize,
+    WindowsShell,
+};
+use crate::history::commands::parse_age;
+use crate::terminal::limits::parse_size;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 #[command(
     version,
-    about = "Packs C assignment submissions for Canvas upload.",
+    about = "Packs C and C++ assignment submissions for Canvas upload.",
     after_help = "\x1b[1mExamples:\x1b[0m
   ap init                              # interactive first-time setup
-  ap -a 7                              # use saved defaults + auto-detect .c file
+  ap -a 7                              # use saved defaults + auto-detect source file
   ap -a 7 --input \"5\\nhello\"         # non-interactive stdin
+  ap -a 7 --stdin-file input.txt       # stdin from a file
+  ap -a 7 --test small=a.txt --test big=b.txt   # one run per test case
+  ap next --auto-doc                   # pack the assignment after the last one
   ap themes                            # list built-in/custom themes
+  ap theme install-examples            # add example themes to customise
   ap update                            # check for latest release
   ap config show                       # view saved defaults"
 )]
@@ -20,7 +30,7 @@ pub struct Cli {
     #[arg(
         long,
         short = 'a',
-        help = "Assignment number or label (e.g. 7 or Assignment7)"
+        help = "Assignment number or label (e.g. 7, Assignment7 or 7b)"
     )]
     pub assignment: Option<String>,
 
@@ -30,37 +40,143 @@ pub struct Cli {
     #[arg(long = "id", short = 'i', help = "Student ID")]
     pub student_id: Option<String>,
 
-    #[arg(long = "c-file", short = 'c', help = "Path to the .c source file")]
+    #[arg(long = "c-file", short = 'c', help = "Path to the .c/.cpp source file")]
     pub c_file: Option<PathBuf>,
 
+    #[arg(
+        long = "sources",
+        help = "Comma-separated source patterns for multi-file projects (e.g. \"*.c,*.h\")"
+    )]
+    pub sources: Option<String>,
+
     #[arg(long = "doc-file", short = 'd', help = "Path to the .doc file")]
     pub doc_file: Option<PathBuf>,
 
     #[arg(
         long = "auto-doc",
         action = ArgAction::SetTrue,
-        help = "Generate .doc from C source and a captured run screenshot"
+        help = "Generate .doc from the source and a captured run screenshot"
     )]
     pub auto_doc: bool,
 
     #[arg(
         long = "run-command",
-        help = "Shell command to run the program (runs as-is via sh/powershell)"
+        help = "Shell command to run the program (runs as-is via sh, or --windows-shell on Windows)"
     )]
     pub run_command: Option<String>,
 
+    #[arg(
+        long = "trust-run-command",
+        action = ArgAction::SetTrue,
+        help = "Run the run command without asking, even if it looks destructive"
+    )]
+    pub trust_run_command: bool,
+
+    #[arg(
+        long = "windows-shell",
+        value_enum,
+        value_name = "SHELL",
+        help = "Shell for the run command on Windows: powershell (default), pwsh or cmd"
+    )]
+    pub windows_shell: Option<WindowsShell>,
+
     #[arg(
         long = "input",
         help = "Input to pipe to stdin (use \\n for newlines, e.g. \"5\\nhello\")"
     )]
     pub input: Option<String>,
 
+    #[arg(
+        long = "stdin-file",
+        conflicts_with = "input",
+        help = "File to pipe to the program's stdin, sent exactly as written"
+    )]
+    pub stdin_file: Option<PathBuf>,
+
+    #[arg(
+        long = "test",
+        value_name = "NAME=FILE",
+        action = ArgAction::Append,
+        conflicts_with_all = ["input", "stdin_file"],
+        help = "Run the program once per test case with FILE as stdin (repeatable)"
+    )]
+    pub tests: Vec<String>,
+
+    #[arg(
+        long = "tests",
+        value_name = "FILE",
+        conflicts_with_all = ["input", "stdin_file"],
+        help = "TOML file of [[tests]] with a name and an input or input_file"
+    )]
+    pub tests_file: Option<PathBuf>,
+
+    #[arg(
+        long = "pty",
+        action = ArgAction::SetTrue,
+        help = "Feed input through a terminal so prompts and typed input interleave in the capture"
+    )]
+    pub pty: bool,
+
+    #[arg(
+        long = "no-echo-input",
+        action = ArgAction::SetTrue,
+        help = "Don't show piped input after the prompts in the screenshot"
+    )]
+    pub no_echo_input: bool,
+
+    #[arg(
+        long = "interact",
+        value_name = "FILE",
+        conflicts_with_all = ["input", "stdin_file", "tests", "tests_file"],
+        help = "TOML script of [[step]] expect/send pairs that drives an interactive program"
+    )]
+    pub interact: Option<PathBuf>,
+
+    #[arg(
+        long = "expected",
+        value_name = "[NAME=]FILE",
+        action = ArgAction::Append,
+        help = "Compare a run's output with FILE and add a diff to the doc (repeatable)"
+    )]
+    pub expected: Vec<String>,
+
+    #[arg(
+        long = "error-case",
+        value_name = "INPUT",
+        action = ArgAction::Append,
+        help = "Also run with deliberately invalid stdin and add it as error handling evidence (repeatable)"
+    )]
+    pub error_case: Vec<String>,
+
+    #[arg(
+        long = "no-auto-tests",
+        action = ArgAction::SetTrue,
+        help = "Don't turn numbered files in tests/ or inputs/ into test runs"
+    )]
+    pub no_auto_tests: bool,
+
     #[arg(
         long = "timeout",
         help = "Timeout in seconds for program execution (default: 30, range: 5-300)"
     )]
     pub timeout: Option<u64>,
 
+    #[arg(
+        long = "max-memory",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Memory limit for the program, e.g. 256M (Unix only)"
+    )]
+    pub max_memory: Option<u64>,
+
+    #[arg(
+        long = "max-output",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Stop the program once it prints this much, e.g. 1M (default: 16M)"
+    )]
+    pub max_output: Option<u64>,
+
     #[arg(
         long = "run-display-template",
         help = "Template for the displayed run path in evidence"
@@ -74,6 +190,27 @@ pub struct Cli {
     )]
     pub output_dir: Option<PathBuf>,
 
+    #[arg(
+        long = "naming",
+        value_enum,
+        value_name = "PRESET",
+        help = "How the folder, zip and doc are named: canvas_default, underscore_lower or course_prefix"
+    )]
+    pub naming: Option<NamingPreset>,
+
+    #[arg(
+        long = "bin-dir",
+        help = "Directory to build the test binary in instead of the system temp dir"
+    )]
+    pub bin_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "USER@HOST",
+        help = "Compile and run on this machine over SSH, e.g. the grading server"
+    )]
+    pub remote: Option<String>,
+
     #[arg(
         long,
         short = 't',
@@ -81,11 +218,322 @@ pub struct Cli {
     )]
     pub theme: Option<String>,
 
+    #[arg(
+        long = "line-numbers",
+        action = ArgAction::SetTrue,
+        help = "Number the lines of the run screenshot in a dimmed gutter"
+    )]
+    pub line_numbers: bool,
+
+    #[arg(
+        long = "max-lines",
+        value_name = "N",
+        help = "Lines of output the screenshot shows before cutting off (default: 80)"
+    )]
+    pub max_lines: Option<usize>,
+
+    #[arg(
+        long = "max-cols",
+        value_name = "N",
+        help = "Characters per screenshot line before the rest becomes \"...\" (default: 120)"
+    )]
+    pub max_cols: Option<usize>,
+
+    #[arg(
+        long = "wrap",
+        action = ArgAction::SetTrue,
+        help = "Wrap long screenshot lines onto the next row instead of cutting them off"
+    )]
+    pub wrap: bool,
+
+    #[arg(
+        long = "paginate",
+        action = ArgAction::SetTrue,
+        help = "Split output past --max-lines over several screenshots instead of cutting it off"
+    )]
+    pub paginate: bool,
+
+    #[arg(
+        long = "export-scale",
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..=3),
+        help = "Render images at N times the resolution (1-3) so they stay sharp when zoomed or printed"
+    )]
+    pub export_scale: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..=4),
+        help = "Draw images N times larger (1-4) and shrink them back down for smooth text edges"
+    )]
+    pub supersample: Option<u32>,
+
+    #[arg(
+        long = "image-note",
+        value_name = "TEXT",
+        help = "Embed TEXT as a comment in the doc's images (metadata is stripped either way)"
+    )]
+    pub image_note: Option<String>,
+
+    #[arg(
+        long = "output-lines",
+        help = "Max lines of captured output in the doc text section (default: 400, 0 = no limit)"
+    )]
+    pub output_lines: Option<usize>,
+
+    #[arg(
+        long = "chunk-lines",
+        value_name = "N",
+        help = "Split code and output listings in the doc into paragraphs of N lines (default: 200, 0 = don't split)"
+    )]
+    pub chunk_lines: Option<usize>,
+
+    #[arg(
+        long = "transcript-file",
+        action = ArgAction::SetTrue,
+        help = "Also write the full captured output to a .txt file in the submission"
+    )]
+    pub transcript_file: bool,
+
+    #[arg(
+        long = "save-screenshot",
+        value_name = "PATH",
+        num_args = 0..=1,
+        help = "Also save the screenshot as a PNG, in the submission folder unless PATH is given"
+    )]
+    pub save_screenshot: Option<Option<PathBuf>>,
+
+    #[arg(
+        long = "link",
+        value_name = "LABEL=URL",
+        action = ArgAction::Append,
+        help = "Add a clickable link to the doc, e.g. Repo=https://github.com/you/hw7 (repeatable)"
+    )]
+    pub link: Vec<String>,
+
+    #[arg(
+        long = "qr",
+        value_name = "URL",
+        num_args = 0..=1,
+        help = "Add a QR code of the source SHA-256 hashes, or of URL if given, at the end of the doc"
+    )]
+    pub qr: Option<Option<String>>,
+
+    #[arg(
+        long = "notes",
+        value_name = "FILE",
+        help = "Markdown file added to the doc as an Answers / Notes section (default: notes.md if present)"
+    )]
+    pub notes: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Source language (c, cpp), detected from the file extensions by default"
+    )]
+    pub lang: Option<String>,
+
+    #[arg(
+        long = "compile-staged",
+        action = ArgAction::SetTrue,
+        help = "Compile from the copies in the submission folder instead of the originals"
+    )]
+    pub compile_staged: bool,
+
+    #[arg(
+        long = "line-endings",
+        value_enum,
+        help = "Line endings of the copied text files: preserve (default), lf or crlf"
+    )]
+    pub line_endings: Option<LineEndings>,
+
+    #[arg(
+        long = "no-run",
+        action = ArgAction::SetTrue,
+        conflicts_with_all = ["run_command", "compile_staged", "valgrind", "sanitize", "save_screenshot", "remote"],
+        help = "Don't compile or run, the doc says the output wasn't captured"
+    )]
+    pub no_run: bool,
+
+    #[arg(
+        long = "no-exec",
+        global = true,
+        action = ArgAction::SetTrue,
+        help = "Never start another program (compiler, run command, editor), only copy, zip and write the doc"
+    )]
+    pub no_exec: bool,
+
+    #[arg(
+        long = "valgrind",
+        action = ArgAction::SetTrue,
+        help = "Re-run the program under valgrind --leak-check=full and add a Memory Check section"
+    )]
+    pub valgrind: bool,
+
+    #[arg(
+        long = "sanitize",
+        value_name = "LIST",
+        value_delimiter = ',',
+        help = "Build with -fsanitize=LIST (address, undefined, leak, thread) and add a Sanitizer Report section"
+    )]
+    pub sanitize: Vec<String>,
+
+    #[arg(
+        long = "include-compile-log",
+        alias = "include-warnings",
+        action = ArgAction::SetTrue,
+        help = "Add a Compiler Output section with the compiler's messages to the doc"
+    )]
+    pub include_compile_log: bool,
+
+    #[arg(
+        long = "deny-warnings",
+        action = ArgAction::SetTrue,
+        help = "Abort the pack if the compiler reports any warnings"
+    )]
+    pub deny_warnings: bool,
+
+    #[arg(
+        long = "expect-exit",
+        value_name = "CODE",
+        allow_negative_numbers = true,
+        help = "Exit code the program should return, any other code aborts the pack"
+    )]
+    pub expect_exit: Option<i32>,
+
+    #[arg(
+        long = "fail-on-nonzero-exit",
+        action = ArgAction::SetTrue,
+        help = "Abort the pack if the program exits with a non-zero code"
+    )]
+    pub fail_on_nonzero_exit: bool,
+
+    #[arg(
+        long = "compile-note",
+        action = ArgAction::SetTrue,
+        help = "Add a note under the code listing with the compiler's warning/error count"
+    )]
+    pub compile_note: bool,
+
+    #[arg(
+        long = "code-image",
+        action = ArgAction::SetTrue,
+        help = "Add a syntax-highlighted image of each source file under its listing"
+    )]
+    pub code_image: bool,
+
+    #[arg(
+        long = "doc-line-numbers",
+        action = ArgAction::SetTrue,
+        help = "Number the lines of the doc's code listing"
+    )]
+    pub doc_line_numbers: bool,
+
+    #[arg(
+        long = "cover-page",
+        action = ArgAction::SetTrue,
+        help = "Start the doc with a title page"
+    )]
+    pub cover_page: bool,
+
+    #[arg(
+        long = "appendix",
+        action = ArgAction::SetTrue,
+        help = "List only the main source file under Code and the others in appendices"
+    )]
+    pub appendix: bool,
+
+    #[arg(
+        long = "strict-rtf",
+        action = ArgAction::SetTrue,
+        help = "Check the generated doc with a strict RTF parser and abort if it's malformed"
+    )]
+    pub strict_rtf: bool,
+
+    #[arg(
+        long = "compat",
+        value_enum,
+        value_name = "APP",
+        help = "Tune the doc for the app it'll be opened in: word (default) or gdocs"
+    )]
+    pub compat: Option<DocCompat>,
+
+    #[arg(
+        long = "format",
+        value_enum,
+        value_name = "FORMAT",
+        value_delimiter = ',',
+        help = "Formats of the generated doc, comma-separated: doc (default), pdf, md, html"
+    )]
+    pub format: Vec<DocFormat>,
+
+    #[arg(
+        long = "sections",
+        value_enum,
+        value_name = "SECTIONS",
+        value_delimiter = ',',
+        help = "Parts of the doc to write, in order, e.g. header,screenshot,code"
+    )]
+    pub sections: Vec<DocSection>,
+
+    #[arg(long = "no-code", action = ArgAction::SetTrue, help = "Leave the code listing out of the doc")]
+    pub no_code: bool,
+
+    #[arg(
+        long = "no-screenshot",
+        action = ArgAction::SetTrue,
+        help = "Leave the run screenshots out of the doc"
+    )]
+    pub no_screenshot: bool,
+
+    #[arg(
+        long = "no-text-output",
+        action = ArgAction::SetTrue,
+        help = "Leave the captured output text out of the doc"
+    )]
+    pub no_text_output: bool,
+
+    #[arg(
+        long = "no-environment",
+        action = ArgAction::SetTrue,
+        help = "Leave the OS, architecture and shell line out of the run section"
+    )]
+    pub no_environment: bool,
+
     #[arg(long = "no-watermark", action = ArgAction::SetTrue, help = "Omit the watermark from the generated doc")]
     pub no_watermark: bool,
 
     #[arg(long, short = 'f', action = ArgAction::SetTrue, help = "Overwrite existing output")]
     pub force: bool,
+
+    #[arg(
+        long,
+        short = 'y',
+        action = ArgAction::SetTrue,
+        help = "Pack without showing the summary and asking first"
+    )]
+    pub yes: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "Report how long each packing stage took"
+    )]
+    pub profile: bool,
+
+    #[arg(
+        long = "profile-json",
+        value_name = "PATH",
+        help = "Also write the stage timings to PATH as JSON"
+    )]
+    pub profile_json: Option<PathBuf>,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "Script-friendly output: one `kind<TAB>path` line per artifact on stdout"
+    )]
+    pub porcelain: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -96,14 +544,160 @@ pub enum Commands {
         command: Option<ConfigCommand>,
     },
     Themes,
+    /// Manage screenshot themes
+    Theme {
+        #[command(subcommand)]
+        command: ThemeCommand,
+    },
     Update,
+    /// Pack the next assignment after the latest one from this course
+    Next {
+        /// Pack flags, as they'd be passed to `ap`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<std::ffi::OsString>,
+    },
+    /// Export or prune the record of past packs
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+    /// Summarise past packs: counts per course, run times and common warnings
+    Stats,
+    /// Work with a course folder holding one folder per assignment
+    Workspace {
+        #[command(subcommand)]
+        command: WorkspaceCommand,
+    },
+    /// Show the settings a pack here would use and where each came from
+    Info {
+        /// Pack flags, as they'd be passed to `ap`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<std::ffi::OsString>,
+    },
+    /// Open something from the last pack in the default app
+    Open {
+        #[arg(value_enum, default_value = "dir")]
+        target: OpenTarget,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OpenTarget {
+    /// The generated .doc
+    Doc,
+    /// The submission zip
+    Zip,
+    /// The submission folder
+    Dir,
+    /// The output folder holding both the submission folder and zip
+    Last,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WorkspaceCommand {
+    /// Make this course folder a workspace
+    Init,
+    /// Show which of the workspace's assignments have been packed, and when
+    Status,
+    /// Pack an assignment's folder from anywhere in the workspace
+    Pack {
+        /// Assignment to pack, e.g. 7 or Assignment7b
+        assignment: String,
+        /// Pack flags, as they'd be passed to `ap` in the assignment's folder
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<std::ffi::OsString>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum HistoryCommand {
+    /// Write every recorded pack as CSV (the default) or JSON
+    Export(ExportArgs),
+    /// Delete old submission folders and zips. Source files are never touched
+    #[command(alias = "clean")]
+    Prune(PruneArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    #[arg(long, conflicts_with = "json", help = "One row per pack (default)")]
+    pub csv: bool,
+
+    #[arg(long, help = "Every recorded field, hashes and paths included")]
+    pub json: bool,
+
+    #[arg(
+        long,
+        short = 'o',
+        value_name = "PATH",
+        help = "Write to a file instead of stdout"
+    )]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct PruneArgs {
+    #[arg(
+        long,
+        value_name = "AGE",
+        value_parser = parse_age,
+        help = "Only packs made longer ago than this, e.g. 90d, 12w or 36h"
+    )]
+    pub older_than: u64,
+
+    #[arg(long, help = "List what would be deleted without deleting it")]
+    pub dry_run: bool,
+
+    #[arg(
+        long,
+        short = 'y',
+        help = "Delete without listing the packs and asking first"
+    )]
+    pub yes: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ThemeCommand {
+    /// Write commented example themes (nord, gruvbox, catppuccin, high-contrast)
+    /// into the themes folder
+    InstallExamples {
+        /// Overwrite example files that already exist
+        #[arg(long)]
+        force: bool,
+    },
+    /// List built-in and custom themes with their colors
+    List,
+    /// Render a sample code image and run screenshot with a theme
+    Preview {
+        name: String,
+        /// Save the PNG here instead of opening it
+        #[arg(long, short = 'o', value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Write a commented theme file with the default colors to start from
+    New {
+        name: String,
+        /// Overwrite a theme of the same name
+        #[arg(long)]
+        force: bool,
+    },
+    /// Convert a VS Code color theme (.json) into a theme in the themes folder
+    Import {
+        file: PathBuf,
+        /// Theme name to save as, the VS Code theme's name by default
+        #[arg(long)]
+        name: Option<String>,
+        /// Overwrite a theme of the same name
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Debug, Subcommand)]
 pub enum ConfigCommand {
     Show,
     Path,
-    Set(ConfigSetArgs),
+    Set(Box<ConfigSetArgs>),
     Reset,
     Editor,
 }
@@ -119,6 +713,34 @@ pub struct ConfigSetArgs {
     #[arg(long = "output-dir", help = "Default output directory")]
     pub output_dir: Option<PathBuf>,
 
+    #[arg(
+        long = "naming-preset",
+        value_enum,
+        help = "Default naming preset: canvas_default, underscore_lower or course_prefix"
+    )]
+    pub naming_preset: Option<NamingPreset>,
+
+    #[arg(
+        long = "bin-dir",
+        conflicts_with = "clear_bin_dir",
+        help = "Directory to build the test binary in (relative paths are per project)"
+    )]
+    pub bin_dir: Option<PathBuf>,
+
+    #[arg(long = "clear-bin-dir", action = ArgAction::SetTrue)]
+    pub clear_bin_dir: bool,
+
+    #[arg(
+        long,
+        value_name = "USER@HOST",
+        conflicts_with = "clear_remote",
+        help = "Compile and run every pack on this machine over SSH"
+    )]
+    pub remote: Option<String>,
+
+    #[arg(long = "clear-remote", action = ArgAction::SetTrue)]
+    pub clear_remote: bool,
+
     #[arg(long = "auto-doc", help = "Default auto-doc behavior (true/false)")]
     pub auto_doc: Option<bool>,
 
@@ -128,15 +750,46 @@ pub struct ConfigSetArgs {
     #[arg(long = "clear-run-command", action = ArgAction::SetTrue)]
     pub clear_run_command: bool,
 
+    #[arg(
+        long = "windows-shell",
+        value_enum,
+        help = "Shell for the run command on Windows: powershell, pwsh or cmd"
+    )]
+    pub windows_shell: Option<WindowsShell>,
+
     #[arg(long = "input", conflicts_with = "clear_input")]
     pub input: Option<String>,
 
     #[arg(long = "clear-input", action = ArgAction::SetTrue)]
     pub clear_input: bool,
 
+    #[arg(
+        long = "stdin-file",
+        conflicts_with = "clear_stdin_file",
+        help = "Default stdin file (relative paths are per project, overrides input)"
+    )]
+    pub stdin_file: Option<PathBuf>,
+
+    #[arg(long = "clear-stdin-file", action = ArgAction::SetTrue)]
+    pub clear_stdin_file: bool,
+
     #[arg(long = "timeout", help = "Default timeout in seconds (5-300)")]
     pub timeout: Option<u64>,
 
+    #[arg(
+        long = "max-memory",
+        value_name = "SIZE",
+        help = "Default memory limit for the program, e.g. 256M (none = no limit)"
+    )]
+    pub max_memory: Option<String>,
+
+    #[arg(
+        long = "max-output",
+        value_name = "SIZE",
+        help = "Default output limit for the program, e.g. 1M (none = 16M)"
+    )]
+    pub max_output: Option<String>,
+
     #[arg(
         long = "run-display-template",
         conflicts_with = "clear_run_display_template"
@@ -152,6 +805,26 @@ pub struct ConfigSetArgs {
     #[arg(long = "clear-theme", action = ArgAction::SetTrue)]
     pub clear_theme: bool,
 
+    #[arg(
+        long = "theme-namespace",
+        conflicts_with = "clear_theme_namespace",
+        help = "Folder under themes/ to look in first, e.g. a course code"
+    )]
+    pub theme_namespace: Option<String>,
+
+    #[arg(long = "clear-theme-namespace", action = ArgAction::SetTrue)]
+    pub clear_theme_namespace: bool,
+
+    #[arg(
+        long = "image-note",
+        conflicts_with = "clear_image_note",
+        help = "Comment embedded in every image in the doc"
+    )]
+    pub image_note: Option<String>,
+
+    #[arg(long = "clear-image-note", action = ArgAction::SetTrue)]
+    pub clear_image_note: bool,
+
     #[arg(long, conflicts_with = "clear_editor")]
     pub editor: Option<String>,
 
@@ -163,4 +836,281 @@ pub struct ConfigSetArgs {
         help = "Show watermark in generated doc (true/false)"
     )]
     pub watermark: Option<bool>,
+
+    #[arg(
+        long = "output-lines",
+        help = "Default max lines of captured output in the doc (0 = no limit)"
+    )]
+    pub output_lines: Option<usize>,
+
+    #[arg(
+        long = "chunk-lines",
+        help = "Default lines per paragraph in the doc's listings (0 = don't split)"
+    )]
+    pub chunk_lines: Option<usize>,
+
+    #[arg(
+        long = "doc-font",
+        value_name = "FONT",
+        conflicts_with = "clear_doc_font",
+        help = "Font of the doc's text, e.g. \"Times New Roman\""
+    )]
+    pub doc_font: Option<String>,
+
+    #[arg(long = "clear-doc-font", action = ArgAction::SetTrue)]
+    pub clear_doc_font: bool,
+
+    #[arg(
+        long = "doc-code-font",
+        value_name = "FONT",
+        conflicts_with = "clear_doc_code_font",
+        help = "Font of the doc's code and output listings"
+    )]
+    pub doc_code_font: Option<String>,
+
+    #[arg(long = "clear-doc-code-font", action = ArgAction::SetTrue)]
+    pub clear_doc_code_font: bool,
+
+    #[arg(
+        long = "doc-code-size",
+        value_name = "POINTS",
+        help = "Point size of the doc's code and output listings (default 9)"
+    )]
+    pub doc_code_size: Option<u32>,
+
+    #[arg(
+        long = "export-scale",
+        value_parser = clap::value_parser!(u32).range(1..=3),
+        help = "Default image resolution factor, 1 to 3"
+    )]
+    pub export_scale: Option<u32>,
+
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..=4),
+        help = "Default supersampling factor for images, 1 to 4"
+    )]
+    pub supersample: Option<u32>,
+
+    #[arg(
+        long = "transcript-file",
+        help = "Write the full captured output to a .txt file (true/false)"
+    )]
+    pub transcript_file: Option<bool>,
+
+    #[arg(
+        long = "compile-staged",
+        help = "Compile from the submission folder copies by default (true/false)"
+    )]
+    pub compile_staged: Option<bool>,
+
+    #[arg(
+        long = "line-endings",
+        value_enum,
+        help = "Line endings of the copied text files: preserve, lf or crlf"
+    )]
+    pub line_endings: Option<LineEndings>,
+
+    #[arg(
+        long = "valgrind",
+        help = "Add a valgrind Memory Check section to docs by default (true/false)"
+    )]
+    pub valgrind: Option<bool>,
+
+    #[arg(
+        long = "include-code",
+        help = "Put the code listing in docs by default (true/false)"
+    )]
+    pub include_code: Option<bool>,
+
+    #[arg(
+        long = "include-screenshot",
+        help = "Put the run screenshots in docs by default (true/false)"
+    )]
+    pub include_screenshot: Option<bool>,
+
+    #[arg(
+        long = "include-text-output",
+        help = "Put the captured output text in docs by default (true/false)"
+    )]
+    pub include_text_output: Option<bool>,
+
+    #[arg(
+        long = "include-environment",
+        help = "Note the OS, architecture and shell under each run by default (true/false)"
+    )]
+    pub include_environment: Option<bool>,
+
+    #[arg(
+        long = "include-compile-log",
+        alias = "include-warnings",
+        help = "Add the Compiler Output section to docs by default (true/false)"
+    )]
+    pub include_compile_log: Option<bool>,
+
+    #[arg(
+        long = "deny-warnings",
+        help = "Abort the pack when the compiler reports warnings (true/false)"
+    )]
+    pub deny_warnings: Option<bool>,
+
+    #[arg(
+        long = "fail-on-nonzero-exit",
+        help = "Abort the pack when the program exits with a non-zero code (true/false)"
+    )]
+    pub fail_on_nonzero_exit: Option<bool>,
+
+    #[arg(
+        long = "pty",
+        help = "Feed input through a terminal instead of a pipe by default (true/false)"
+    )]
+    pub pty: Option<bool>,
+
+    #[arg(
+        long = "echo-input",
+        help = "Show piped input after the prompts in the screenshot (true/false)"
+    )]
+    pub echo_input: Option<bool>,
+
+    #[arg(
+        long = "auto-tests",
+        help = "Run once per numbered file in tests/ or inputs/ when no input is given (true/false)"
+    )]
+    pub auto_tests: Option<bool>,
+
+    #[arg(
+        long = "run-script",
+        help = "Use a project's `make run` target or run.sh as the run command: true, false, or unset to ask"
+    )]
+    pub run_script: Option<bool>,
+
+    #[arg(
+        long = "confirm",
+        help = "Show a summary and ask before packing in a terminal (true/false)"
+    )]
+    pub confirm: Option<bool>,
+
+    #[arg(
+        long = "compile-note",
+        help = "Add the compiler warning count under the code listing (true/false)"
+    )]
+    pub compile_note: Option<bool>,
+
+    #[arg(
+        long = "code-image",
+        help = "Add a highlighted image of the code under its listing (true/false)"
+    )]
+    pub code_image: Option<bool>,
+
+    #[arg(
+        long = "doc-line-numbers",
+        help = "Number the lines of the doc's code listing (true/false)"
+    )]
+    pub doc_line_numbers: Option<bool>,
+
+    #[arg(
+        long = "cover-page",
+        help = "Start the doc with a title page (true/false)"
+    )]
+    pub cover_page: Option<bool>,
+
+    #[arg(
+        long = "appendix",
+        help = "Put source files other than the main one in appendices (true/false)"
+    )]
+    pub appendix: Option<bool>,
+
+    #[arg(
+        long = "strict-rtf",
+        help = "Check every generated doc with a strict RTF parser (true/false)"
+    )]
+    pub strict_rtf: Option<bool>,
+
+    #[arg(
+        long = "compat",
+        value_enum,
+        help = "Default app to tune the doc for: word or gdocs"
+    )]
+    pub compat: Option<DocCompat>,
+
+    #[arg(
+        long = "page",
+        value_enum,
+        help = "Paper size of the doc: letter or a4"
+    )]
+    pub page: Option<PageSize>,
+
+    #[arg(
+        long = "margin",
+        value_name = "CM",
+        help = "Page margins of the doc in cm (default 3.175, i.e. 1.25 in)"
+    )]
+    pub margin: Option<f64>,
+
+    #[arg(
+        long = "doc-template",
+        value_name = "FILE",
+        conflicts_with = "clear_doc_template",
+        help = "RTF file with {{code}}, {{output}}, ... placeholders to lay out the doc"
+    )]
+    pub doc_template: Option<PathBuf>,
+
+    #[arg(long = "clear-doc-template", action = ArgAction::SetTrue)]
+    pub clear_doc_template: bool,
+
+    #[arg(
+        long = "format",
+        value_enum,
+        value_delimiter = ',',
+        help = "Default formats of the generated doc, comma-separated: doc, pdf, md, html"
+    )]
+    pub format: Vec<DocFormat>,
+
+    #[arg(
+        long = "sections",
+        value_enum,
+        value_delimiter = ',',
+        conflicts_with = "clear_sections",
+        help = "Default parts of the doc and their order: header, code, compile-log, screenshot, output, notes"
+    )]
+    pub sections: Vec<DocSection>,
+
+    #[arg(long = "clear-sections", action = ArgAction::SetTrue)]
+    pub clear_sections: bool,
+
+    #[arg(
+        long = "paginate",
+        help = "Split long output over several screenshots (true/false)"
+    )]
+    pub paginate: Option<bool>,
+
+    #[arg(long = "indent", help = "Required source indentation: tabs or spaces")]
+    pub indent: Option<IndentPolicy>,
+
+    #[arg(
+        long = "trailing-whitespace",
+        help = "Flag trailing whitespace in sources (true/false)"
+    )]
+    pub trailing_whitespace: Option<bool>,
+
+    #[arg(
+        long = "max-line-length",
+        help = "Flag source lines longer than this (0 = no limit)"
+    )]
+    pub max_line_length: Option<usize>,
+
+    #[arg(
+        long = "hygiene-fail",
+        help = "Abort the pack on source style issues instead of warning (true/false)"
+    )]
+    pub hygiene_fail: Option<bool>,
+
+    #[arg(long = "color", help = "Use colors in terminal output (true/false)")]
+    pub color: Option<bool>,
+
+    #[arg(
+        long = "accent",
+        help = "Accent color for step arrows (e.g. cyan, bright-magenta, #ff8000)"
+    )]
+    pub accent: Option<String>,
 }
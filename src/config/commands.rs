@@ -1,12 +1,17 @@
-use super::{AppConfig, config_path, load, save};
 use super::editor::run_config_editor;
+use super::{
+    AppConfig, DocCompat, DocFormat, DocSection, IndentPolicy, LineEndings, NamingPreset, PageSize,
+    WindowsShell, config_path, load, save,
+};
 use crate::cli::{ConfigCommand, ConfigSetArgs};
 use crate::error::{Error, Result, io_err};
+use crate::fs as afs;
+use crate::render::rtf;
+use crate::terminal::limits::parse_size;
+use crate::terminal::remote::check_host;
 use crate::ui;
 use crate::validate::clean_name;
-use owo_colors::OwoColorize;
 use std::fs;
-use std::io::{self, BufRead, Write};
 use std::path::Path;
 
 pub fn run_init() -> Result<()> {
@@ -16,22 +21,22 @@ pub fn run_init() -> Result<()> {
     ui::header("ap setup");
     eprintln!();
 
-    let name = prompt("Student name (e.g. JoeBloggs)")?;
+    let name = ui::prompt("Student name (e.g. JoeBloggs)")?;
     if !name.is_empty() {
         cfg.name = Some(clean_name(&name, "name")?);
     }
 
-    let id = prompt("Student ID")?;
+    let id = ui::prompt("Student ID")?;
     if !id.is_empty() {
         cfg.student_id = Some(clean_name(&id, "student ID")?);
     }
 
-    let theme = prompt("Theme (default, light, dracula, monokai, solarized, custom)")?;
+    let theme = ui::prompt("Theme (default, light, dracula, monokai, solarized, custom)")?;
     if !theme.is_empty() {
         cfg.theme = Some(clean_name(&theme, "theme")?);
     }
 
-    let auto = prompt("Enable auto-doc? [Y/n] ")?;
+    let auto = ui::prompt("Enable auto-doc? [Y/n] ")?;
     let auto = auto.trim().to_ascii_lowercase();
     if auto.is_empty() || auto == "y" || auto == "yes" {
         cfg.auto_doc = Some(true);
@@ -46,17 +51,14 @@ pub fn run_init() -> Result<()> {
     Ok(())
 }
 
-fn prompt(label: &str) -> Result<String> {
-    eprint!("  {} ", label.bold());
-    io::stderr()
-        .flush()
-        .map_err(|e| io_err("flushing stderr", e))?;
-    let mut line = String::new();
-    io::stdin()
-        .lock()
-        .read_line(&mut line)
-        .map_err(|e| io_err("reading input", e))?;
-    Ok(line.trim().to_string())
+/// `none` clears a size limit, anything else has to parse as a size.
+fn size_setting(value: &str) -> Result<Option<String>> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    parse_size(value).map_err(Error::Validation)?;
+    Ok(Some(value.to_string()))
 }
 
 pub fn run_config_command(command: Option<ConfigCommand>) -> Result<()> {
@@ -72,7 +74,7 @@ pub fn run_config_command(command: Option<ConfigCommand>) -> Result<()> {
             println!("{}", path.display());
             Ok(())
         }
-        Some(ConfigCommand::Set(args)) => apply_set(args),
+        Some(ConfigCommand::Set(args)) => apply_set(*args),
         Some(ConfigCommand::Reset) => {
             let path = config_path()?;
             if path.exists() {
@@ -108,6 +110,31 @@ fn apply_set(args: ConfigSetArgs) -> Result<()> {
         cfg.output_dir = Some(dir);
         changed = true;
     }
+    if let Some(v) = args.naming_preset {
+        cfg.naming_preset = Some(v);
+        changed = true;
+    }
+    if args.clear_bin_dir {
+        cfg.bin_dir = None;
+        changed = true;
+    }
+    if let Some(dir) = args.bin_dir {
+        if dir.as_os_str().is_empty() {
+            return Err(Error::Validation("bin-dir cannot be blank".into()));
+        }
+        cfg.bin_dir = Some(dir);
+        changed = true;
+    }
+    if args.clear_remote {
+        cfg.remote = None;
+        changed = true;
+    }
+    if let Some(host) = args.remote {
+        let host = host.trim();
+        check_host(host)?;
+        cfg.remote = Some(host.to_string());
+        changed = true;
+    }
     if let Some(v) = args.auto_doc {
         cfg.auto_doc = Some(v);
         changed = true;
@@ -124,6 +151,10 @@ fn apply_set(args: ConfigSetArgs) -> Result<()> {
         cfg.run_command = Some(trimmed.to_string());
         changed = true;
     }
+    if let Some(shell) = args.windows_shell {
+        cfg.windows_shell = Some(shell);
+        changed = true;
+    }
     if args.clear_run_display_template {
         cfg.run_display_template = None;
         changed = true;
@@ -150,6 +181,18 @@ fn apply_set(args: ConfigSetArgs) -> Result<()> {
         cfg.theme = Some(trimmed.to_string());
         changed = true;
     }
+    if args.clear_theme_namespace {
+        cfg.theme_namespace = None;
+        changed = true;
+    }
+    if let Some(ns) = args.theme_namespace {
+        let trimmed = ns.trim().trim_end_matches(['/', '\\']);
+        if trimmed.is_empty() {
+            return Err(Error::Validation("theme-namespace cannot be blank".into()));
+        }
+        cfg.theme_namespace = Some(trimmed.to_string());
+        changed = true;
+    }
     if args.clear_editor {
         cfg.editor = None;
         changed = true;
@@ -174,6 +217,25 @@ fn apply_set(args: ConfigSetArgs) -> Result<()> {
         cfg.input = Some(inp);
         changed = true;
     }
+    if args.clear_stdin_file {
+        cfg.stdin_file = None;
+        changed = true;
+    }
+    if let Some(path) = args.stdin_file {
+        if path.as_os_str().is_empty() {
+            return Err(Error::Validation("stdin-file cannot be blank".into()));
+        }
+        cfg.stdin_file = Some(path);
+        changed = true;
+    }
+    if let Some(size) = args.max_memory {
+        cfg.max_memory = size_setting(&size)?;
+        changed = true;
+    }
+    if let Some(size) = args.max_output {
+        cfg.max_output = size_setting(&size)?;
+        changed = true;
+    }
     if let Some(t) = args.timeout {
         if !(5..=300).contains(&t) {
             return Err(Error::Validation("timeout must be 5-300 seconds".into()));
@@ -181,6 +243,215 @@ fn apply_set(args: ConfigSetArgs) -> Result<()> {
         cfg.timeout = Some(t);
         changed = true;
     }
+    if let Some(n) = args.output_lines {
+        cfg.output_lines = Some(n);
+        changed = true;
+    }
+    if let Some(n) = args.chunk_lines {
+        cfg.chunk_lines = Some(n);
+        changed = true;
+    }
+    if args.clear_doc_font {
+        cfg.doc_font = None;
+        changed = true;
+    }
+    if let Some(font) = args.doc_font {
+        cfg.doc_font = Some(rtf::check_font(&font)?.to_string());
+        changed = true;
+    }
+    if args.clear_doc_code_font {
+        cfg.doc_code_font = None;
+        changed = true;
+    }
+    if let Some(font) = args.doc_code_font {
+        cfg.doc_code_font = Some(rtf::check_font(&font)?.to_string());
+        changed = true;
+    }
+    if let Some(points) = args.doc_code_size {
+        rtf::check_code_size(points)?;
+        cfg.doc_code_size = Some(points);
+        changed = true;
+    }
+    if args.clear_image_note {
+        cfg.image_note = None;
+        changed = true;
+    }
+    if let Some(note) = args.image_note {
+        let trimmed = note.trim();
+        if trimmed.is_empty() {
+            return Err(Error::Validation("image-note cannot be blank".into()));
+        }
+        cfg.image_note = Some(trimmed.to_string());
+        changed = true;
+    }
+    if let Some(n) = args.export_scale {
+        cfg.export_scale = Some(n);
+        changed = true;
+    }
+    if let Some(n) = args.supersample {
+        cfg.supersample = Some(n);
+        changed = true;
+    }
+    if let Some(v) = args.transcript_file {
+        cfg.transcript_file = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.compile_note {
+        cfg.compile_note = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.code_image {
+        cfg.code_image = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.compat {
+        cfg.compat = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.page {
+        cfg.page = Some(v);
+        changed = true;
+    }
+    if let Some(cm) = args.margin {
+        rtf::check_margin(cm)?;
+        cfg.margin = Some(cm);
+        changed = true;
+    }
+    if args.clear_doc_template {
+        cfg.doc_template = None;
+        changed = true;
+    }
+    if let Some(path) = args.doc_template {
+        if !path.is_file() {
+            return Err(Error::Validation(format!(
+                "doc template not found: '{}'",
+                path.display()
+            )));
+        }
+        rtf::check_template(&afs::read_text_lossy(&path)?)?;
+        // Kept whole, since packs run from other folders
+        cfg.doc_template =
+            Some(std::path::absolute(&path).map_err(|e| io_err("resolving doc template", e))?);
+        changed = true;
+    }
+    if !args.format.is_empty() {
+        cfg.format = args.format;
+        changed = true;
+    }
+    if args.clear_sections {
+        cfg.sections.clear();
+        changed = true;
+    }
+    if !args.sections.is_empty() {
+        DocSection::check_list(&args.sections)?;
+        cfg.sections = args.sections;
+        changed = true;
+    }
+    if let Some(v) = args.doc_line_numbers {
+        cfg.doc_line_numbers = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.cover_page {
+        cfg.cover_page = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.appendix {
+        cfg.appendix = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.strict_rtf {
+        cfg.strict_rtf = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.paginate {
+        cfg.paginate = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.compile_staged {
+        cfg.compile_staged = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.line_endings {
+        cfg.line_endings = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.valgrind {
+        cfg.valgrind = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.include_code {
+        cfg.include_code = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.include_screenshot {
+        cfg.include_screenshot = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.include_text_output {
+        cfg.include_text_output = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.include_environment {
+        cfg.include_environment = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.include_compile_log {
+        cfg.include_compile_log = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.deny_warnings {
+        cfg.deny_warnings = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.pty {
+        cfg.pty = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.echo_input {
+        cfg.echo_input = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.auto_tests {
+        cfg.auto_tests = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.run_script {
+        cfg.run_script = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.confirm {
+        cfg.confirm = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.fail_on_nonzero_exit {
+        cfg.fail_on_nonzero_exit = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.indent {
+        cfg.hygiene.indent = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.trailing_whitespace {
+        cfg.hygiene.trailing_whitespace = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.max_line_length {
+        cfg.hygiene.max_line_length = (v > 0).then_some(v);
+        changed = true;
+    }
+    if let Some(v) = args.hygiene_fail {
+        cfg.hygiene.fail = Some(v);
+        changed = true;
+    }
+    if let Some(v) = args.color {
+        cfg.ui.color = Some(v);
+        changed = true;
+    }
+    if let Some(c) = args.accent {
+        ui::parse_color(&c)?;
+        cfg.ui.accent = Some(c.trim().to_string());
+        changed = true;
+    }
     if !changed {
         return Err(Error::Validation(
             "nothing to update - pass at least one flag (see `config set --help`)".into(),
@@ -206,6 +477,15 @@ fn print_config(path: &Path, cfg: &AppConfig) {
             .map(|p| p.to_string_lossy().into_owned())
             .unwrap_or_else(|| "-".into()),
     );
+    ui::kv(
+        "naming_preset",
+        match cfg.naming_preset {
+            Some(NamingPreset::CanvasDefault) => "canvas_default",
+            Some(NamingPreset::UnderscoreLower) => "underscore_lower",
+            Some(NamingPreset::CoursePrefix) => "course_prefix",
+            None => "-",
+        },
+    );
     ui::kv(
         "auto_doc",
         match cfg.auto_doc {
@@ -215,11 +495,21 @@ fn print_config(path: &Path, cfg: &AppConfig) {
         },
     );
     ui::kv("run_command", &val(cfg.run_command.as_deref()));
+    ui::kv(
+        "windows_shell",
+        match cfg.windows_shell {
+            Some(WindowsShell::Powershell) => "powershell",
+            Some(WindowsShell::Pwsh) => "pwsh",
+            Some(WindowsShell::Cmd) => "cmd",
+            None => "-",
+        },
+    );
     ui::kv(
         "run_display_template",
         &val(cfg.run_display_template.as_deref()),
     );
     ui::kv("theme", &val(cfg.theme.as_deref()));
+    ui::kv("theme_namespace", &val(cfg.theme_namespace.as_deref()));
     ui::kv("editor", &val(cfg.editor.as_deref()));
     ui::kv(
         "watermark",
@@ -230,10 +520,365 @@ fn print_config(path: &Path, cfg: &AppConfig) {
         },
     );
     ui::kv("input", &val(cfg.input.as_deref()));
+    ui::kv(
+        "stdin_file",
+        &cfg.stdin_file
+            .as_ref()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "-".into()),
+    );
     ui::kv(
         "timeout",
         &cfg.timeout
             .map(|t| format!("{t}s"))
             .unwrap_or_else(|| "-".into()),
     );
+    ui::kv("max_memory", cfg.max_memory.as_deref().unwrap_or("-"));
+    ui::kv("max_output", cfg.max_output.as_deref().unwrap_or("-"));
+    ui::kv(
+        "output_lines",
+        &cfg.output_lines
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| "-".into()),
+    );
+    ui::kv(
+        "chunk_lines",
+        &cfg.chunk_lines
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| "-".into()),
+    );
+    ui::kv("doc_font", &val(cfg.doc_font.as_deref()));
+    ui::kv("doc_code_font", &val(cfg.doc_code_font.as_deref()));
+    ui::kv(
+        "doc_code_size",
+        &cfg.doc_code_size
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| "-".into()),
+    );
+    ui::kv("image_note", &val(cfg.image_note.as_deref()));
+    ui::kv(
+        "export_scale",
+        &cfg.export_scale
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| "-".into()),
+    );
+    ui::kv(
+        "supersample",
+        &cfg.supersample
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| "-".into()),
+    );
+    ui::kv(
+        "transcript_file",
+        match cfg.transcript_file {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "compile_note",
+        match cfg.compile_note {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "code_image",
+        match cfg.code_image {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "doc_line_numbers",
+        match cfg.doc_line_numbers {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "cover_page",
+        match cfg.cover_page {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "appendix",
+        match cfg.appendix {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "strict_rtf",
+        match cfg.strict_rtf {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "compat",
+        match cfg.compat {
+            Some(DocCompat::Word) => "word",
+            Some(DocCompat::Gdocs) => "gdocs",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "page",
+        match cfg.page {
+            Some(PageSize::Letter) => "letter",
+            Some(PageSize::A4) => "a4",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "margin",
+        &cfg.margin
+            .map(|cm| format!("{cm} cm"))
+            .unwrap_or_else(|| "-".into()),
+    );
+    ui::kv(
+        "doc_template",
+        &cfg.doc_template
+            .as_ref()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "-".into()),
+    );
+    let formats: Vec<&str> = cfg
+        .format
+        .iter()
+        .map(|f| match f {
+            DocFormat::Rtf => "rtf",
+            DocFormat::Pdf => "pdf",
+            DocFormat::Md => "md",
+            DocFormat::Html => "html",
+        })
+        .collect();
+    ui::kv(
+        "format",
+        &if formats.is_empty() {
+            "-".to_string()
+        } else {
+            formats.join(",")
+        },
+    );
+    let sections: Vec<&str> = cfg.sections.iter().map(|s| s.name()).collect();
+    ui::kv(
+        "sections",
+        &if sections.is_empty() {
+            "-".to_string()
+        } else {
+            sections.join(",")
+        },
+    );
+    ui::kv(
+        "paginate",
+        match cfg.paginate {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "valgrind",
+        match cfg.valgrind {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "include_code",
+        match cfg.include_code {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "include_screenshot",
+        match cfg.include_screenshot {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "include_text_output",
+        match cfg.include_text_output {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "include_environment",
+        match cfg.include_environment {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "include_compile_log",
+        match cfg.include_compile_log {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "deny_warnings",
+        match cfg.deny_warnings {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "pty",
+        match cfg.pty {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "echo_input",
+        match cfg.echo_input {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "auto_tests",
+        match cfg.auto_tests {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "run_script",
+        match cfg.run_script {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "ask",
+        },
+    );
+    ui::kv(
+        "confirm",
+        match cfg.confirm {
+            Some(false) => "false",
+            _ => "true",
+        },
+    );
+    ui::kv(
+        "fail_on_nonzero_exit",
+        match cfg.fail_on_nonzero_exit {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "compile_staged",
+        match cfg.compile_staged {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "line_endings",
+        match cfg.line_endings {
+            Some(LineEndings::Preserve) => "preserve",
+            Some(LineEndings::Lf) => "lf",
+            Some(LineEndings::Crlf) => "crlf",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "bin_dir",
+        &cfg.bin_dir
+            .as_ref()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "-".into()),
+    );
+    ui::kv("remote", &val(cfg.remote.as_deref()));
+    ui::kv(
+        "hygiene.indent",
+        match cfg.hygiene.indent {
+            Some(IndentPolicy::Tabs) => "tabs",
+            Some(IndentPolicy::Spaces) => "spaces",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "hygiene.trailing_whitespace",
+        match cfg.hygiene.trailing_whitespace {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "hygiene.max_line_length",
+        &cfg.hygiene
+            .max_line_length
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| "-".into()),
+    );
+    ui::kv(
+        "hygiene.fail",
+        match cfg.hygiene.fail {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv(
+        "assignment_suffixes",
+        &match &cfg.assignment_suffixes {
+            Some(patterns) if patterns.is_empty() => "none".to_string(),
+            Some(patterns) => patterns.join(", "),
+            None => "default".to_string(),
+        },
+    );
+    ui::kv(
+        "redact",
+        &match cfg.redact.len() {
+            0 => "-".to_string(),
+            1 => "1 rule".to_string(),
+            n => format!("{n} rules"),
+        },
+    );
+    for link in &cfg.link {
+        ui::kv(&format!("link.{}", link.label), &link.url);
+    }
+    for (name, expansion) in &cfg.alias {
+        ui::kv(&format!("alias.{name}"), expansion);
+    }
+    ui::kv(
+        "ui.color",
+        match cfg.ui.color {
+            Some(true) => "true",
+            Some(false) => "false",
+            None => "-",
+        },
+    );
+    ui::kv("ui.accent", &val(cfg.ui.accent.as_deref()));
+    ui::kv("ui.success", &val(cfg.ui.success.as_deref()));
+    ui::kv("ui.warning", &val(cfg.ui.warning.as_deref()));
+    ui::kv("ui.error", &val(cfg.ui.error.as_deref()));
 }
@@ -1,7 +1,6 @@
 use super::{AppConfig, config_path, load, save};
 use crate::error::{Error, Result, io_err};
 use crate::ui;
-use owo_colors::OwoColorize;
 use std::io::{self, BufRead, Write};
 use std::process::Command;
 
@@ -21,6 +20,7 @@ const KNOWN_EDITORS: &[&str] = &[
 ];
 
 pub fn run_config_editor() -> Result<()> {
+    crate::terminal::exec::check_allowed("an editor")?;
     let path = config_path()?;
     let mut cfg = load(&path)?;
 
@@ -93,7 +93,7 @@ fn editor_exists(cmd: &str) -> bool {
 fn pick_editor_menu() -> Option<String> {
     eprintln!("  No editor detected. Pick one:\n");
     for (i, &name) in KNOWN_EDITORS.iter().enumerate() {
-        eprintln!("    {}  {}", format!("[{}]", i + 1).bold(), name);
+        eprintln!("    {}  {}", ui::bold(&format!("[{}]", i + 1)), name);
     }
     eprintln!();
 
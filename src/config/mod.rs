@@ -2,7 +2,8 @@ pub mod commands;
 pub mod editor;
 
 use crate::error::{Error, Result, io_err};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -16,17 +17,342 @@ pub struct AppConfig {
     #[serde(rename = "id", alias = "student_id")]
     pub student_id: Option<String>,
     pub output_dir: Option<PathBuf>,
+    pub naming_preset: Option<NamingPreset>,
+    /// Regexes a suffix after the assignment number has to match, like
+    /// `b` in `7b`. Unset allows the built-in part and redo suffixes.
+    pub assignment_suffixes: Option<Vec<String>>,
     #[serde(alias = "autoDoc")]
     pub auto_doc: Option<bool>,
     #[serde(alias = "runCommand")]
     pub run_command: Option<String>,
     #[serde(alias = "runDisplayTemplate")]
     pub run_display_template: Option<String>,
+    /// Use a detected `make run` target or `run.sh` without asking (true)
+    /// or never offer it (false).
+    pub run_script: Option<bool>,
+    /// Shell for the run command on Windows.
+    pub windows_shell: Option<WindowsShell>,
+    /// Show the pack summary and ask before writing anything. On unless
+    /// set to false.
+    pub confirm: Option<bool>,
     pub theme: Option<String>,
+    /// Folder under `themes/` searched first for bare theme names.
+    pub theme_namespace: Option<String>,
     pub editor: Option<String>,
     pub watermark: Option<bool>,
     pub input: Option<String>,
+    pub stdin_file: Option<PathBuf>,
+    pub auto_tests: Option<bool>,
+    pub pty: Option<bool>,
+    pub echo_input: Option<bool>,
     pub timeout: Option<u64>,
+    pub max_memory: Option<String>,
+    pub max_output: Option<String>,
+    pub output_lines: Option<usize>,
+    pub chunk_lines: Option<usize>,
+    pub export_scale: Option<u32>,
+    pub supersample: Option<u32>,
+    /// Comment written into embedded PNGs, which otherwise carry no metadata.
+    pub image_note: Option<String>,
+    pub transcript_file: Option<bool>,
+    pub compile_note: Option<bool>,
+    pub code_image: Option<bool>,
+    pub doc_line_numbers: Option<bool>,
+    pub cover_page: Option<bool>,
+    pub appendix: Option<bool>,
+    pub strict_rtf: Option<bool>,
+    pub compat: Option<DocCompat>,
+    /// Font of the doc's text, in place of the one `compat` picks.
+    pub doc_font: Option<String>,
+    /// Font of the doc's code and output listings.
+    pub doc_code_font: Option<String>,
+    /// Point size of the doc's listings.
+    pub doc_code_size: Option<u32>,
+    pub page: Option<PageSize>,
+    /// Page margins of the doc in cm, the same on every side.
+    pub margin: Option<f64>,
+    /// RTF file with placeholders that lays out the doc in place of the
+    /// usual one.
+    pub doc_template: Option<PathBuf>,
+    /// Parts of the doc to write and their order, empty for all of the
+    /// usual ones.
+    pub sections: Vec<DocSection>,
+    /// Formats of the generated doc, one file each.
+    #[serde(deserialize_with = "one_or_many")]
+    pub format: Vec<DocFormat>,
+    pub paginate: Option<bool>,
+    pub include_code: Option<bool>,
+    pub include_screenshot: Option<bool>,
+    pub include_text_output: Option<bool>,
+    pub include_environment: Option<bool>,
+    #[serde(alias = "include_warnings")]
+    pub include_compile_log: Option<bool>,
+    pub valgrind: Option<bool>,
+    pub deny_warnings: Option<bool>,
+    pub fail_on_nonzero_exit: Option<bool>,
+    pub compile_staged: Option<bool>,
+    pub line_endings: Option<LineEndings>,
+    pub bin_dir: Option<PathBuf>,
+    /// `user@host` to compile and run on over SSH.
+    pub remote: Option<String>,
+    pub ui: UiConfig,
+    pub hygiene: HygieneConfig,
+    pub redact: Vec<RedactRule>,
+    /// Links listed near the top of the doc, e.g. the project's repo.
+    pub link: Vec<Link>,
+    /// `ap NAME ...` shortcuts, e.g. `quick = "-a {n} --auto-doc --force"`.
+    pub alias: BTreeMap<String, String>,
+}
+
+/// Course style rules checked against every source file before packing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HygieneConfig {
+    pub indent: Option<IndentPolicy>,
+    pub trailing_whitespace: Option<bool>,
+    pub max_line_length: Option<usize>,
+    /// Abort the pack on any issue instead of warning.
+    pub fail: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum IndentPolicy {
+    Tabs,
+    Spaces,
+}
+
+/// Reads a list that can also be written as one value, so `format = "pdf"`
+/// means `format = ["pdf"]`.
+fn one_or_many<'de, D, T>(de: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+    Ok(match OneOrMany::deserialize(de)? {
+        OneOrMany::One(v) => vec![v],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+/// How a submission's folder, zip and doc are named.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum NamingPreset {
+    /// Assignment7_JoeBloggs_123_Submission, holding Assignment7_JoeBloggs_123.doc
+    #[default]
+    CanvasDefault,
+    /// The same names in lower case
+    UnderscoreLower,
+    /// The course folder's name in front, like CS101_Assignment7_JoeBloggs_123
+    CoursePrefix,
+}
+
+/// Which app the doc is tuned to open well in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DocCompat {
+    /// Word and LibreOffice
+    #[default]
+    Word,
+    /// Google Docs and the Google Drive preview
+    Gdocs,
+}
+
+/// Paper size of the RTF doc.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    /// US Letter, 8.5 x 11 in
+    #[default]
+    Letter,
+    /// A4, 21 x 29.7 cm
+    A4,
+}
+
+/// Line endings of the text files copied into the submission.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEndings {
+    /// As they are in the project folder
+    #[default]
+    Preserve,
+    /// Unix line endings, \n
+    Lf,
+    /// Windows line endings, \r\n
+    Crlf,
+}
+
+/// A part of the doc, as listed in `sections`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum DocSection {
+    /// The title, student and source file lines
+    Header,
+    /// The source code listing
+    Code,
+    /// The compiler's messages
+    #[value(alias = "compile_log")]
+    CompileLog,
+    /// Each run's screenshot
+    Screenshot,
+    /// Each run's output as text
+    Output,
+    /// The notes file's answers
+    Notes,
+}
+
+impl DocSection {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Header => "header",
+            Self::Code => "code",
+            Self::CompileLog => "compile_log",
+            Self::Screenshot => "screenshot",
+            Self::Output => "output",
+            Self::Notes => "notes",
+        }
+    }
+
+    /// Rejects a `sections` list that names a part twice.
+    pub fn check_list(sections: &[DocSection]) -> Result<()> {
+        for (i, section) in sections.iter().enumerate() {
+            if sections[..i].contains(section) {
+                return Err(Error::Validation(format!(
+                    "sections lists {} twice",
+                    section.name()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Shell that runs `--run-command` on Windows. Elsewhere it's always `sh`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowsShell {
+    /// Windows PowerShell 5, built into Windows
+    #[default]
+    Powershell,
+    /// PowerShell 7 and later
+    Pwsh,
+    /// The classic command prompt
+    Cmd,
+}
+
+impl WindowsShell {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Powershell => "PowerShell",
+            Self::Pwsh => "PowerShell 7",
+            Self::Cmd => "cmd",
+        }
+    }
+}
+
+/// File format of the generated doc.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DocFormat {
+    /// Rich text saved as .doc, which Word opens directly
+    #[default]
+    #[serde(alias = "doc")]
+    #[value(alias = "doc")]
+    Rtf,
+    Pdf,
+    /// Markdown, with its images saved next to it
+    Md,
+    /// A single web page with its images inline
+    Html,
+}
+
+impl DocFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            DocFormat::Rtf => "doc",
+            DocFormat::Pdf => "pdf",
+            DocFormat::Md => "md",
+            DocFormat::Html => "html",
+        }
+    }
+}
+
+/// A regex whose matches in captured output are replaced before the output
+/// reaches the screenshot, doc or transcript.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactRule {
+    pub pattern: String,
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+}
+
+fn default_replacement() -> String {
+    "[redacted]".into()
+}
+
+/// A clickable link in the doc's Links section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Link {
+    pub label: String,
+    pub url: String,
+}
+
+impl Link {
+    /// Parses `--link LABEL=URL`.
+    pub fn parse(arg: &str) -> Result<Self> {
+        let (label, url) = arg
+            .split_once('=')
+            .ok_or_else(|| Error::Validation(format!("--link expects LABEL=URL, got '{arg}'")))?;
+        let link = Self {
+            label: label.trim().to_string(),
+            url: url.trim().to_string(),
+        };
+        link.validate()?;
+        Ok(link)
+    }
+
+    /// Only web and mail links, and nothing that would break out of the RTF
+    /// field the link is written into.
+    pub fn validate(&self) -> Result<()> {
+        if self.label.trim().is_empty() {
+            return Err(Error::Validation(format!(
+                "link '{}' needs a label",
+                self.url
+            )));
+        }
+        let scheme_ok = ["http://", "https://", "mailto:"]
+            .iter()
+            .any(|s| self.url.to_ascii_lowercase().starts_with(s));
+        let clean = !self
+            .url
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '"' | '\\' | '{' | '}'));
+        if !scheme_ok || !clean {
+            return Err(Error::Validation(format!(
+                "link '{}' has an unusable URL '{}', use an http(s) or mailto address",
+                self.label, self.url
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    pub color: Option<bool>,
+    pub accent: Option<String>,
+    pub success: Option<String>,
+    pub warning: Option<String>,
+    pub error: Option<String>,
 }
 
 pub fn config_path() -> Result<PathBuf> {
@@ -77,4 +403,56 @@ mod tests {
         assert_eq!(parsed.name.as_deref(), Some("Alice"));
         assert_eq!(parsed.auto_doc, Some(true));
     }
+
+    #[test]
+    fn ui_table_round_trips() {
+        let cfg = AppConfig {
+            name: Some("Alice".into()),
+            ui: UiConfig {
+                color: Some(false),
+                accent: Some("magenta".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let s = toml::to_string_pretty(&cfg).unwrap();
+        let parsed: AppConfig = toml::from_str(&s).unwrap();
+        assert_eq!(parsed.ui.color, Some(false));
+        assert_eq!(parsed.ui.accent.as_deref(), Some("magenta"));
+    }
+
+    #[test]
+    fn redact_rules_default_replacement() {
+        let cfg: AppConfig = toml::from_str(
+            "name = \"Alice\"\n[[redact]]\npattern = \"/home/\\\\w+\"\nreplacement = \"~\"\n\n[[redact]]\npattern = \"sk-\\\\w+\"\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.redact.len(), 2);
+        assert_eq!(cfg.redact[0].replacement, "~");
+        assert_eq!(cfg.redact[1].replacement, "[redacted]");
+        let s = toml::to_string_pretty(&cfg).unwrap();
+        let parsed: AppConfig = toml::from_str(&s).unwrap();
+        assert_eq!(parsed.redact[1].pattern, "sk-\\w+");
+    }
+
+    #[test]
+    fn links_need_a_label_and_a_web_url() {
+        let link = Link::parse("Repo = https://github.com/me/hw7").unwrap();
+        assert_eq!(link.label, "Repo");
+        assert_eq!(link.url, "https://github.com/me/hw7");
+        assert!(Link::parse("https://github.com/me/hw7").is_err());
+        assert!(Link::parse("=https://x.dev").is_err());
+        assert!(Link::parse("Bad=javascript:alert(1)").is_err());
+        assert!(Link::parse("Bad=https://x.dev/\"}{").is_err());
+    }
+
+    #[test]
+    fn format_takes_one_name_or_a_list() {
+        let cfg: AppConfig = toml::from_str("format = \"pdf\"").unwrap();
+        assert_eq!(cfg.format, vec![DocFormat::Pdf]);
+        let cfg: AppConfig = toml::from_str("format = [\"doc\", \"md\"]").unwrap();
+        assert_eq!(cfg.format, vec![DocFormat::Rtf, DocFormat::Md]);
+        let cfg: AppConfig = toml::from_str("").unwrap();
+        assert!(cfg.format.is_empty());
+    }
 }
@@ -0,0 +1,199 @@
+use std::fmt::Write;
+
+const CONTEXT: usize = 3;
+/// Above this many LCS cells the diff is skipped and only the verdict given.
+const MAX_CELLS: usize = 16_000_000;
+
+pub struct Comparison {
+    pub passed: bool,
+    /// Unified diff from expected to actual, empty when the outputs match.
+    pub unified: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Compares output line by line, ignoring trailing whitespace on each line
+/// and trailing blank lines, which terminals and editors disagree about.
+pub fn compare(expected: &str, actual: &str) -> Comparison {
+    let exp = normalized_lines(expected);
+    let act = normalized_lines(actual);
+    if exp == act {
+        return Comparison {
+            passed: true,
+            unified: String::new(),
+        };
+    }
+    let unified = if exp.len().saturating_mul(act.len()) > MAX_CELLS {
+        format!(
+            "(output too long to diff: {} expected lines, {} actual lines)\n",
+            exp.len(),
+            act.len()
+        )
+    } else {
+        unified(&exp, &act, &diff_ops(&exp, &act))
+    };
+    Comparison {
+        passed: false,
+        unified,
+    }
+}
+
+fn normalized_lines(text: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn diff_ops(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let (n, m) = (a.len(), b.len());
+    // lcs[i][j] = LCS length of a[i..] and b[j..]
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[at(i, j)] = if a[i] == b[j] {
+                lcs[at(i + 1, j + 1)] + 1
+            } else {
+                lcs[at(i + 1, j)].max(lcs[at(i, j + 1)])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(n + m);
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            ops.push(Op::Equal(i, j));
+            i += 1;
+            j += 1;
+        } else if lcs[at(i + 1, j)] >= lcs[at(i, j + 1)] {
+            ops.push(Op::Delete(i));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(j));
+            j += 1;
+        }
+    }
+    ops.extend((i..n).map(Op::Delete));
+    ops.extend((j..m).map(Op::Insert));
+    ops
+}
+
+fn unified(a: &[&str], b: &[&str], ops: &[Op]) -> String {
+    let mut out = String::from("--- expected\n+++ actual\n");
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(..)))
+        .map(|(k, _)| k)
+        .collect();
+
+    let mut k = 0;
+    while k < changes.len() {
+        let start = changes[k].saturating_sub(CONTEXT);
+        let mut end = changes[k];
+        // Merge changes whose context windows touch
+        while k + 1 < changes.len() && changes[k + 1] <= end + 2 * CONTEXT + 1 {
+            k += 1;
+            end = changes[k];
+        }
+        let end = (end + CONTEXT + 1).min(ops.len());
+        k += 1;
+
+        let hunk = &ops[start..end];
+        let (a_start, b_start) = position(ops, start);
+        let a_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Insert(_)))
+            .count();
+        let b_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, Op::Delete(_)))
+            .count();
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            range(a_start, a_len),
+            range(b_start, b_len)
+        );
+        for op in hunk {
+            let _ = match *op {
+                Op::Equal(i, _) => writeln!(out, " {}", a[i]),
+                Op::Delete(i) => writeln!(out, "-{}", a[i]),
+                Op::Insert(j) => writeln!(out, "+{}", b[j]),
+            };
+        }
+    }
+    out
+}
+
+/// Line indexes in `a` and `b` where the op at `k` starts.
+fn position(ops: &[Op], k: usize) -> (usize, usize) {
+    ops[..k].iter().fold((0, 0), |(i, j), op| match op {
+        Op::Equal(..) => (i + 1, j + 1),
+        Op::Delete(_) => (i + 1, j),
+        Op::Insert(_) => (i, j + 1),
+    })
+}
+
+fn range(start: usize, len: usize) -> String {
+    // Unified diff ranges are 1-based, and an empty range names the line before
+    if len == 0 {
+        format!("{start},0")
+    } else if len == 1 {
+        format!("{}", start + 1)
+    } else {
+        format!("{},{len}", start + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_output_passes() {
+        let c = compare("sum 7\n", "sum 7   \n\n\n");
+        assert!(c.passed);
+        assert!(c.unified.is_empty());
+    }
+
+    #[test]
+    fn single_change_diff() {
+        let c = compare("a\nb\nc\n", "a\nx\nc\n");
+        assert!(!c.passed);
+        assert_eq!(
+            c.unified,
+            "--- expected\n+++ actual\n@@ -1,3 +1,3 @@\n a\n-b\n+x\n c\n"
+        );
+    }
+
+    #[test]
+    fn distant_changes_get_separate_hunks() {
+        let exp: String = (1..=20).map(|n| format!("{n}\n")).collect();
+        let act = exp
+            .replacen("2\n", "two\n", 1)
+            .replacen("19\n", "nineteen\n", 1);
+        let c = compare(&exp, &act);
+        assert_eq!(c.unified.matches("@@ -").count(), 2);
+        assert!(c.unified.contains("-19\n+nineteen\n"));
+    }
+
+    #[test]
+    fn missing_and_extra_lines() {
+        let c = compare("a\nb\n", "");
+        assert_eq!(
+            c.unified,
+            "--- expected\n+++ actual\n@@ -1,2 +0,0 @@\n-a\n-b\n"
+        );
+        let c = compare("", "a\n");
+        assert_eq!(c.unified, "--- expected\n+++ actual\n@@ -0,0 +1 @@\n+a\n");
+    }
+}
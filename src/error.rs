@@ -11,6 +11,9 @@ pub enum Error {
     #[error("compile failed:\n{0}")]
     CompileFailed(String),
 
+    #[error("compile produced warnings (--deny-warnings):\n{0}")]
+    WarningsDenied(String),
+
     #[error("{0}")]
     Image(String),
 }
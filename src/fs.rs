@@ -1,4 +1,8 @@
+use crate::config::LineEndings;
 use crate::error::{Error, Result, io_err};
+use crate::lang;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs::{self, File};
 use std::io;
 use std::path::{Path, PathBuf};
@@ -13,6 +17,17 @@ const DELETE_DELAY: Duration = Duration::from_millis(80);
 
 const BINARY_EXTENSIONS: &[&str] = &["exe", "com", "dll", "so", "dylib", "out", "bin", "msi"];
 
+/// Besides sources and headers, the only files whose line endings are
+/// rewritten. Anything else, a PDF say, is copied byte for byte.
+const TEXT_EXTENSIONS: &[&str] = &["txt", "md", "csv", "in"];
+const TEXT_NAMES: &[&str] = &["Makefile", "makefile", "GNUmakefile"];
+
+pub fn has_extension(path: &Path, exts: &[&str]) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| exts.iter().any(|x| x.eq_ignore_ascii_case(e)))
+}
+
 pub fn check_extension(path: &Path, allowed: &[&str], label: &str) -> Result<()> {
     if !path.exists() {
         return Err(Error::Validation(format!(
@@ -70,26 +85,137 @@ pub fn prepare_output(dir: &Path, zip: &Path, force: bool) -> Result<()> {
     Ok(())
 }
 
-pub fn copy_non_binary_files(src: &Path, dst: &Path) -> Result<()> {
-    let entries = fs::read_dir(src).map_err(|e| io_err(format!("reading {}", src.display()), e))?;
+#[derive(Debug, Default)]
+pub struct CopyReport {
+    pub skipped: Vec<String>,
+    pub renamed: Vec<(String, String)>,
+}
 
+/// Copies every non-binary file in `src` into `dst`, skipping any whose name
+/// is in `skip`. Names in `reserved` belong to files the pack generates
+/// itself, so a source file with one of those names is an error rather than
+/// something to overwrite. Files whose names differ only by case are renamed
+/// with a numeric suffix, since they can't coexist on Windows or macOS.
+pub fn copy_non_binary_files(
+    src: &Path,
+    dst: &Path,
+    skip: &[String],
+    reserved: &[String],
+    line_endings: LineEndings,
+) -> Result<CopyReport> {
+    let entries = fs::read_dir(src).map_err(|e| io_err(format!("reading {}", src.display()), e))?;
+    let mut paths = Vec::new();
     for entry in entries {
         let entry = entry.map_err(|e| io_err("reading directory entry", e))?;
-        let path = entry.path();
-        if !path.is_file() || is_binary_ext(&path) {
+        paths.push(entry.path());
+    }
+    paths.sort();
+
+    let mut report = CopyReport::default();
+    let mut taken: HashSet<String> = HashSet::new();
+
+    for path in paths {
+        if !path.is_file() || is_binary_ext(&path) || lang::is_artifact(&path) {
             continue;
         }
         let name = file_name(&path)?;
-        let dest = dst.join(name);
+        if skip.iter().any(|s| s == name) {
+            report.skipped.push(name.to_string());
+            continue;
+        }
+        if reserved.iter().any(|r| r.eq_ignore_ascii_case(name)) {
+            return Err(Error::Validation(format!(
+                "'{}' would be overwritten by a generated file, rename or remove it",
+                path.display()
+            )));
+        }
+
+        let mut dest_name = name.to_string();
+        if taken.contains(&name.to_lowercase()) {
+            dest_name = unique_name(name, &taken);
+            report.renamed.push((name.to_string(), dest_name.clone()));
+        }
+        let dest = dst.join(&dest_name);
         if paths_equal(&path, &dest) {
             continue;
         }
-        fs::copy(&path, &dest).map_err(|e| io_err(format!("copying '{}'", path.display()), e))?;
+        copy_text(&path, &dest, line_endings)
+            .map_err(|e| io_err(format!("copying '{}'", path.display()), e))?;
+        taken.insert(dest_name.to_lowercase());
     }
-    Ok(())
+    report.skipped.sort();
+    Ok(report)
 }
 
+/// Copies a file, with its line endings rewritten if it's a source or known
+/// text file and they aren't to be kept as they are.
+pub fn copy_text(from: &Path, to: &Path, line_endings: LineEndings) -> io::Result<()> {
+    if line_endings == LineEndings::Preserve || !is_text_file(from) {
+        return fs::copy(from, to).map(|_| ());
+    }
+    let bytes = fs::read(from)?;
+    fs::write(to, convert_line_endings(&bytes, line_endings))
+}
+
+fn is_text_file(path: &Path) -> bool {
+    lang::is_unit(path)
+        || lang::is_header(path)
+        || has_extension(path, TEXT_EXTENSIONS)
+        || path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| TEXT_NAMES.contains(&n))
+}
+
+/// `bytes` with every line ending, `\n` or `\r\n`, made the one asked for.
+/// A lone `\r` is left alone, and so is anything with a NUL in it, since
+/// that's no text file.
+pub fn convert_line_endings(bytes: &[u8], to: LineEndings) -> Cow<'_, [u8]> {
+    let ending: &[u8] = match to {
+        LineEndings::Preserve => return Cow::Borrowed(bytes),
+        LineEndings::Lf => b"\n",
+        LineEndings::Crlf => b"\r\n",
+    };
+    if bytes.contains(&0) {
+        return Cow::Borrowed(bytes);
+    }
+    let mut out = Vec::with_capacity(bytes.len() + bytes.len() / 32);
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'\r' if bytes.get(i + 1) == Some(&b'\n') => {}
+            b'\n' => out.extend_from_slice(ending),
+            _ => out.push(b),
+        }
+    }
+    Cow::Owned(out)
+}
+
+fn unique_name(name: &str, taken: &HashSet<String>) -> String {
+    let p = Path::new(name);
+    let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let ext = p.extension().and_then(|e| e.to_str());
+    (2..)
+        .map(|n| match ext {
+            Some(ext) => format!("{stem}_{n}.{ext}"),
+            None => format!("{stem}_{n}"),
+        })
+        .find(|candidate| !taken.contains(&candidate.to_lowercase()))
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// Builds the zip as a `.part` file next to `zip_path` and renames it once
+/// it's complete, so an interrupted pack never leaves a truncated zip behind.
+/// Staying in the same directory keeps the rename on one filesystem.
 pub fn create_zip(source_dir: &Path, zip_path: &Path) -> Result<()> {
+    let part = zip_path.with_file_name(format!("{}.part", file_name(zip_path)?));
+    let result = write_zip(source_dir, &part).and_then(|()| move_file(&part, zip_path));
+    if result.is_err() {
+        let _ = fs::remove_file(&part);
+    }
+    result
+}
+
+fn write_zip(source_dir: &Path, zip_path: &Path) -> Result<()> {
     let zip_file = File::create(zip_path)
         .map_err(|e| io_err(format!("creating {}", zip_path.display()), e))?;
 
@@ -123,8 +249,51 @@ pub fn create_zip(source_dir: &Path, zip_path: &Path) -> Result<()> {
     }
 
     zip.finish()
-        .map_err(|e| Error::Validation(format!("finalizing zip: {e}")))?;
-    Ok(())
+        .map_err(|e| Error::Validation(format!("finalizing zip: {e}")))?
+        .sync_all()
+        .map_err(|e| io_err(format!("syncing '{}'", zip_path.display()), e))
+}
+
+/// Renames `from` to `to`, falling back to copy, fsync and delete when they
+/// are on different filesystems (e.g. a temp dir and a network home).
+pub fn move_file(from: &Path, to: &Path) -> Result<()> {
+    move_with(from, to, |from, to| fs::rename(from, to))
+}
+
+fn move_with<F>(from: &Path, to: &Path, rename: F) -> Result<()>
+where
+    F: Fn(&Path, &Path) -> io::Result<()>,
+{
+    match rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => copy_across(from, to),
+        Err(e) => Err(io_err(
+            format!("moving '{}' to '{}'", from.display(), to.display()),
+            e,
+        )),
+    }
+}
+
+// Copies next to the destination first so `to` only ever appears complete,
+// then syncs before the original is deleted. The copy gets its own suffix so
+// moving a `.part` file never copies it onto itself.
+fn copy_across(from: &Path, to: &Path) -> Result<()> {
+    let part = to.with_file_name(format!("{}.moving", file_name(to)?));
+    let result = (|| {
+        fs::copy(from, &part).map_err(|e| io_err(format!("copying '{}'", from.display()), e))?;
+        // Windows only flushes a handle opened for writing
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&part)
+            .and_then(|f| f.sync_all())
+            .map_err(|e| io_err(format!("syncing '{}'", part.display()), e))?;
+        fs::rename(&part, to).map_err(|e| io_err(format!("moving into '{}'", to.display()), e))
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&part);
+        return result;
+    }
+    remove_file_retry(from)
 }
 
 pub fn resolve_c_file(provided: Option<&Path>) -> Result<PathBuf> {
@@ -138,12 +307,7 @@ pub fn resolve_c_file(provided: Option<&Path>) -> Result<PathBuf> {
     for entry in fs::read_dir(&cwd).map_err(|e| io_err("reading cwd", e))? {
         let entry = entry.map_err(|e| io_err("directory entry", e))?;
         let path = entry.path();
-        let is_c = path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|e| e.eq_ignore_ascii_case("c"))
-            .unwrap_or(false);
-        if path.is_file() && is_c {
+        if path.is_file() && lang::is_unit(&path) {
             found.push(path);
         }
     }
@@ -152,7 +316,7 @@ pub fn resolve_c_file(provided: Option<&Path>) -> Result<PathBuf> {
 
     match found.len() {
         0 => Err(Error::Validation(
-            "no .c files found in current directory".into(),
+            "no .c or .cpp files found in current directory".into(),
         )),
         1 => Ok(found.remove(0)),
         _ => {
@@ -161,13 +325,80 @@ pub fn resolve_c_file(provided: Option<&Path>) -> Result<PathBuf> {
                 .filter_map(|p| p.file_name()?.to_str())
                 .collect();
             Err(Error::Validation(format!(
-                "multiple .c files found: {}, specify --c-file",
+                "multiple source files found: {}, specify --c-file or --sources \"*.c\"",
                 names.join(", ")
             )))
         }
     }
 }
 
+/// Expands a comma-separated list of source patterns (e.g. `*.c,*.h` or
+/// `src/*.c`) relative to the current directory. `*` and `?` wildcards are
+/// supported in the file name part and matching ignores case.
+pub fn resolve_sources(patterns: &str) -> Result<Vec<PathBuf>> {
+    let cwd = std::env::current_dir().map_err(|e| io_err("current directory", e))?;
+    let mut found: Vec<PathBuf> = Vec::new();
+
+    for pattern in patterns.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let pattern = pattern.replace('\\', "/");
+        let (dir, file_pattern) = match pattern.rsplit_once('/') {
+            Some((dir, file)) => (cwd.join(dir), file.to_string()),
+            None => (cwd.clone(), pattern.clone()),
+        };
+
+        let entries =
+            fs::read_dir(&dir).map_err(|e| io_err(format!("reading {}", dir.display()), e))?;
+        let mut matched = false;
+        for entry in entries {
+            let path = entry.map_err(|e| io_err("directory entry", e))?.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if path.is_file() && wildcard_match(&file_pattern, name) {
+                matched = true;
+                if !found.contains(&path) {
+                    found.push(path);
+                }
+            }
+        }
+        if !matched {
+            return Err(Error::Validation(format!(
+                "no files match source pattern '{pattern}'"
+            )));
+        }
+    }
+
+    if found.is_empty() {
+        return Err(Error::Validation("--sources cannot be blank".into()));
+    }
+    found.sort_by(|a, b| a.to_string_lossy().cmp(&b.to_string_lossy()));
+    Ok(found)
+}
+
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.to_lowercase().chars().collect();
+    let n: Vec<char> = name.to_lowercase().chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
 pub fn resolve_doc_file(provided: Option<&Path>, expected_name: &str) -> Result<PathBuf> {
     if let Some(p) = provided {
         return Ok(p.to_path_buf());
@@ -189,6 +420,18 @@ pub fn read_text_lossy(path: &Path) -> Result<String> {
     Ok(String::from_utf8_lossy(&bytes).into_owned())
 }
 
+/// Lowercase hex SHA-256 of `bytes`.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    use std::fmt::Write;
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::with_capacity(64), |mut out, b| {
+            let _ = write!(out, "{b:02x}");
+            out
+        })
+}
+
 pub fn file_name(path: &Path) -> Result<&str> {
     path.file_name()
         .and_then(|n| n.to_str())
@@ -202,6 +445,41 @@ pub fn paths_equal(a: &Path, b: &Path) -> bool {
     }
 }
 
+/// Whether `file` sits directly in `dir`. A bare file name has an empty
+/// parent, which is the current directory.
+pub fn is_in_dir(file: &Path, dir: &Path) -> bool {
+    match file.parent() {
+        Some(p) if p.as_os_str().is_empty() => paths_equal(Path::new("."), dir),
+        Some(p) => paths_equal(p, dir),
+        None => false,
+    }
+}
+
+/// `shot.png` with suffix `Big Input` becomes `shot_big-input.png`. Anything
+/// that isn't a letter, digit, `-` or `_` turns into a dash.
+pub fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let slug: String = suffix
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{stem}_{slug}.{ext}"),
+        None => format!("{stem}_{slug}"),
+    };
+    path.with_file_name(name)
+}
+
 fn is_binary_ext(path: &Path) -> bool {
     let ext = path
         .extension()
@@ -265,11 +543,227 @@ where
     ))
 }
 
+/// An empty directory for a test, removed when dropped so a failed
+/// assertion doesn't leave it in the temp dir.
+#[cfg(test)]
+pub struct ScratchDir(PathBuf);
+
+#[cfg(test)]
+impl std::ops::Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Makes a fresh [`ScratchDir`], unique to the process and the call.
+#[cfg(test)]
+pub fn scratch_dir(tag: &str) -> ScratchDir {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static N: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "ap_test_{tag}_{}_{}",
+        std::process::id(),
+        N.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    ScratchDir(dir)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::path::Path;
 
+    #[test]
+    fn copy_renames_case_insensitive_duplicates() {
+        let src = scratch_dir("src");
+        let dst = scratch_dir("dst");
+        fs::write(src.join("Notes.txt"), "a").unwrap();
+        fs::write(src.join("notes.txt"), "b").unwrap();
+
+        let report = copy_non_binary_files(&src, &dst, &[], &[], LineEndings::Preserve).unwrap();
+        assert_eq!(
+            report.renamed,
+            vec![("notes.txt".to_string(), "notes_2.txt".to_string())]
+        );
+        assert!(dst.join("Notes.txt").exists());
+        assert!(dst.join("notes_2.txt").exists());
+    }
+
+    #[test]
+    fn copy_rejects_reserved_names() {
+        let src = scratch_dir("src");
+        let dst = scratch_dir("dst");
+        fs::write(src.join("assignment7_joe_1.DOC"), "stale").unwrap();
+
+        let reserved = vec!["Assignment7_Joe_1.doc".to_string()];
+        assert!(copy_non_binary_files(&src, &dst, &[], &reserved, LineEndings::Preserve).is_err());
+    }
+
+    #[test]
+    fn copy_leaves_originals_untouched() {
+        let src = scratch_dir("untouched_src");
+        let dst = scratch_dir("untouched_dst");
+        let original = src.join("main.c");
+        fs::write(&original, "int main(void) { return 0; }\r\n").unwrap();
+        let before = fs::metadata(&original).unwrap();
+
+        copy_non_binary_files(&src, &dst, &[], &[], LineEndings::Preserve).unwrap();
+
+        let after = fs::metadata(&original).unwrap();
+        assert_eq!(
+            fs::read(&original).unwrap(),
+            b"int main(void) { return 0; }\r\n"
+        );
+        assert_eq!(before.modified().unwrap(), after.modified().unwrap());
+        assert_eq!(before.permissions(), after.permissions());
+        assert_eq!(
+            fs::read(dst.join("main.c")).unwrap(),
+            fs::read(&original).unwrap()
+        );
+    }
+
+    #[test]
+    fn copies_can_have_their_line_endings_changed() {
+        let src = scratch_dir("eol_src");
+        let dst = scratch_dir("eol_dst");
+        fs::write(src.join("main.c"), "int a;\r\nint b;\nint c;\r").unwrap();
+
+        copy_non_binary_files(&src, &dst, &[], &[], LineEndings::Crlf).unwrap();
+        assert_eq!(
+            fs::read(dst.join("main.c")).unwrap(),
+            b"int a;\r\nint b;\r\nint c;\r"
+        );
+        let lf = convert_line_endings(b"a\r\nb\n", LineEndings::Lf);
+        assert_eq!(&*lf, b"a\nb\n");
+        let binary = b"a\0\r\n";
+        assert_eq!(&*convert_line_endings(binary, LineEndings::Lf), binary);
+    }
+
+    #[test]
+    fn only_text_files_have_their_line_endings_changed() {
+        let src = scratch_dir("eol_kinds_src");
+        let dst = scratch_dir("eol_kinds_dst");
+        let pdf = b"%PDF-1.4\r\n1 0 obj\n<<>>\r\nendobj\n";
+        let rtf = b"{\\rtf1 hi\\par\r\n}\n";
+        fs::write(src.join("report.pdf"), pdf).unwrap();
+        fs::write(src.join("notes.rtf"), rtf).unwrap();
+        fs::write(src.join("Makefile"), "all:\r\n").unwrap();
+        fs::write(src.join("input.txt"), "1\r\n").unwrap();
+        // Taken for a.out, so never packed whatever its line endings
+        fs::write(src.join("expected.out"), "1\r\n").unwrap();
+
+        copy_non_binary_files(&src, &dst, &[], &[], LineEndings::Lf).unwrap();
+        assert_eq!(fs::read(dst.join("report.pdf")).unwrap(), pdf);
+        assert_eq!(fs::read(dst.join("notes.rtf")).unwrap(), rtf);
+        assert_eq!(fs::read(dst.join("Makefile")).unwrap(), b"all:\n");
+        assert_eq!(fs::read(dst.join("input.txt")).unwrap(), b"1\n");
+        assert!(!dst.join("expected.out").exists());
+    }
+
+    #[test]
+    fn move_file_within_filesystem() {
+        let dir = scratch_dir("move_same");
+        fs::write(dir.join("a.zip"), "zip").unwrap();
+        move_file(&dir.join("a.zip"), &dir.join("b.zip")).unwrap();
+        assert!(!dir.join("a.zip").exists());
+        assert_eq!(fs::read_to_string(dir.join("b.zip")).unwrap(), "zip");
+    }
+
+    #[test]
+    fn cross_device_fallback_moves_and_cleans_up() {
+        let src = scratch_dir("move_across_src");
+        let dst = scratch_dir("move_across_dst");
+        fs::write(src.join("a.zip.part"), "zip").unwrap();
+        fs::write(dst.join("a.zip"), "stale").unwrap();
+        let cross_device = |_: &Path, _: &Path| Err(io::ErrorKind::CrossesDevices.into());
+        move_with(&src.join("a.zip.part"), &dst.join("a.zip"), cross_device).unwrap();
+        assert!(!src.join("a.zip.part").exists());
+        assert!(!dst.join("a.zip.moving").exists());
+        assert_eq!(fs::read_to_string(dst.join("a.zip")).unwrap(), "zip");
+    }
+
+    #[test]
+    fn other_move_errors_are_not_retried_as_copies() {
+        let dir = scratch_dir("move_denied");
+        fs::write(dir.join("a.zip"), "zip").unwrap();
+        let denied = |_: &Path, _: &Path| Err(io::ErrorKind::PermissionDenied.into());
+        assert!(move_with(&dir.join("a.zip"), &dir.join("b.zip"), denied).is_err());
+        assert!(dir.join("a.zip").exists());
+        assert!(!dir.join("b.zip").exists());
+    }
+
+    #[test]
+    fn zip_is_created_at_destination() {
+        let src = scratch_dir("zip_src");
+        let out = scratch_dir("zip_out");
+        fs::write(src.join("main.c"), "int main(void) { return 0; }").unwrap();
+        create_zip(&src, &out.join("sub.zip")).unwrap();
+        let archive = zip::ZipArchive::new(File::open(out.join("sub.zip")).unwrap()).unwrap();
+        assert_eq!(archive.len(), 1);
+        assert!(!out.join("sub.zip.part").exists());
+    }
+
+    #[test]
+    fn failed_zip_leaves_nothing_behind() {
+        let out = scratch_dir("zip_failed");
+        assert!(create_zip(&out.join("missing"), &out.join("sub.zip")).is_err());
+        assert!(!out.join("sub.zip").exists());
+        assert!(!out.join("sub.zip.part").exists());
+    }
+
+    #[test]
+    fn copy_skips_listed_names() {
+        let src = scratch_dir("src");
+        let dst = scratch_dir("dst");
+        fs::write(src.join("old.zip"), "zip").unwrap();
+        fs::write(src.join("main.c"), "int main;").unwrap();
+
+        let report =
+            copy_non_binary_files(&src, &dst, &["old.zip".into()], &[], LineEndings::Preserve)
+                .unwrap();
+        assert_eq!(report.skipped, vec!["old.zip"]);
+        assert!(!dst.join("old.zip").exists());
+        assert!(dst.join("main.c").exists());
+    }
+
+    #[test]
+    fn sha256_known_vector() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn wildcard_matching() {
+        assert!(wildcard_match("*.c", "main.c"));
+        assert!(wildcard_match("*.c", "MAIN.C"));
+        assert!(!wildcard_match("*.c", "main.h"));
+        assert!(!wildcard_match("*.c", "main.cpp"));
+        assert!(wildcard_match("list?.c", "list2.c"));
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("a*b*c", "aXXbYYc"));
+        assert!(!wildcard_match("a*b*c", "aXXbYY"));
+    }
+
+    #[test]
+    fn unique_name_increments() {
+        let taken: HashSet<String> = ["a.c", "a_2.c"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(unique_name("A.c", &taken), "A_3.c");
+        assert_eq!(unique_name("Makefile", &HashSet::new()), "Makefile_2");
+    }
+
     #[test]
     fn binary_extensions_detected() {
         assert!(is_binary_ext(Path::new("prog.exe")));
@@ -279,4 +773,25 @@ mod tests {
         assert!(!is_binary_ext(Path::new("notes.txt")));
         assert!(!is_binary_ext(Path::new("Makefile")));
     }
+
+    #[test]
+    fn suffix_goes_before_the_extension() {
+        assert_eq!(
+            with_suffix(Path::new("out/shot.png"), "Big Input"),
+            Path::new("out/shot_big-input.png")
+        );
+        assert_eq!(with_suffix(Path::new("shot"), "2"), Path::new("shot_2"));
+    }
+
+    #[test]
+    fn bare_file_names_are_in_the_cwd() {
+        let cwd = std::env::current_dir().unwrap();
+        assert!(is_in_dir(Path::new("main.c"), &cwd));
+        assert!(is_in_dir(&cwd.join("main.c"), &cwd));
+
+        let dir = scratch_dir("in_dir");
+        assert!(is_in_dir(&dir.join("main.c"), &dir));
+        assert!(!is_in_dir(&dir.join("main.c"), &cwd));
+        assert!(!is_in_dir(Path::new("main.c"), &dir));
+    }
 }
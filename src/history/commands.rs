@@ -0,0 +1,425 @@
+use super::{Entry, History, date, history_path, load};
+use crate::cli::{ExportArgs, HistoryCommand, PruneArgs};
+use crate::error::{Error, Result, io_err};
+use crate::profile::format_duration;
+use crate::ui;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use walkdir::WalkDir;
+
+/// Warnings listed by `ap stats`, most frequent first.
+const TOP_WARNINGS: usize = 5;
+
+pub fn run_history_command(command: HistoryCommand) -> Result<()> {
+    match command {
+        HistoryCommand::Export(args) => export(&args),
+        HistoryCommand::Prune(args) => prune(&args),
+    }
+}
+
+fn export(args: &ExportArgs) -> Result<()> {
+    let history = load(&history_path()?)?;
+    let text = if args.json {
+        serde_json::to_string_pretty(&history.entries)
+            .map_err(|e| Error::Validation(format!("serializing history: {e}")))?
+            + "\n"
+    } else {
+        to_csv(&history.entries)
+    };
+    match &args.output {
+        Some(path) => {
+            fs::write(path, text).map_err(|e| io_err(format!("writing {}", path.display()), e))?;
+            ui::done(&format!(
+                "exported {} packs to {}",
+                history.entries.len(),
+                path.display()
+            ));
+        }
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+/// One row per pack. Paths and hashes stay in the JSON export, this is the
+/// spreadsheet-sized view.
+fn to_csv(entries: &[Entry]) -> String {
+    let mut out = String::from(
+        "date,assignment,number,name,student_id,course,source_dir,zip,run_ms,warnings\n",
+    );
+    for e in entries {
+        let row = [
+            date(e.timestamp),
+            e.assignment.clone(),
+            e.number.to_string(),
+            e.name.clone(),
+            e.student_id.clone(),
+            course(e),
+            e.source_dir.display().to_string(),
+            e.zip.display().to_string(),
+            e.run_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+            e.warnings.len().to_string(),
+        ];
+        let cells: Vec<String> = row.iter().map(|c| csv_cell(c)).collect();
+        out.push_str(&cells.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn csv_cell(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The folder holding the assignment's folder, as `ap next` groups packs.
+fn course(entry: &Entry) -> String {
+    entry
+        .source_dir
+        .parent()
+        .and_then(|p| p.file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "-".into())
+}
+
+/// Parses ages like `90d`, `12w` or `36h` into seconds.
+pub fn parse_age(s: &str) -> std::result::Result<u64, String> {
+    let t = s.trim();
+    let (digits, seconds) = match t.chars().last().map(|c| c.to_ascii_lowercase()) {
+        Some('h') => (&t[..t.len() - 1], 3600),
+        Some('d') => (&t[..t.len() - 1], 86_400),
+        Some('w') => (&t[..t.len() - 1], 7 * 86_400),
+        _ => ("", 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(seconds))
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("'{t}' isn't an age, use e.g. 90d, 12w or 36h"))
+}
+
+fn prune(args: &PruneArgs) -> Result<()> {
+    let history = load(&history_path()?)?;
+    let cutoff = super::now().saturating_sub(args.older_than);
+    let cwd = std::env::current_dir().map_err(|e| io_err("current directory", e))?;
+    let targets = prune_targets(&history, cutoff, &cwd);
+    if targets.is_empty() {
+        ui::success(&format!(
+            "Nothing packed before {} left to delete",
+            date(cutoff)
+        ));
+        return Ok(());
+    }
+
+    let verb = if args.dry_run {
+        "Would delete"
+    } else {
+        "About to delete"
+    };
+    ui::header(&format!("{verb} packs from before {}", date(cutoff)));
+    let sizes: Vec<u64> = targets.iter().map(|p| disk_size(p)).collect();
+    for (path, bytes) in targets.iter().zip(&sizes) {
+        ui::kv(&path.display().to_string(), &ui::size(*bytes));
+    }
+    if args.dry_run {
+        ui::step(&format!(
+            "{} to free, run again without --dry-run to delete",
+            ui::size(sizes.iter().sum())
+        ));
+        return Ok(());
+    }
+    // The paths come from the history file, which may be stale or edited
+    if !args.yes {
+        match ui::confirm(&format!("Delete these {}?", targets.len()))? {
+            Some(true) => {}
+            Some(false) => {
+                ui::step("Nothing was deleted");
+                return Ok(());
+            }
+            None => {
+                return Err(Error::Validation(
+                    "can't confirm without a terminal, pass --yes to delete anyway".into(),
+                ));
+            }
+        }
+    }
+
+    let mut freed = 0;
+    let mut deleted = 0;
+    for (path, bytes) in targets.iter().zip(sizes) {
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+        match removed {
+            Ok(()) => {
+                freed += bytes;
+                deleted += 1;
+            }
+            Err(e) => ui::warn(&format!("couldn't delete {}: {e}", path.display())),
+        }
+    }
+    ui::done(&format!("deleted {deleted}, freed {}", ui::size(freed)));
+    Ok(())
+}
+
+/// Submission folders and zips from packs made before `cutoff` that are
+/// still on disk. Anything a newer pack wrote to the same place is kept, as
+/// is any folder holding `cwd` or a source folder some pack was made from.
+fn prune_targets(history: &History, cutoff: u64, cwd: &Path) -> Vec<PathBuf> {
+    let (old, recent): (Vec<&Entry>, Vec<&Entry>) =
+        history.entries.iter().partition(|e| e.timestamp < cutoff);
+    let keep: BTreeSet<&Path> = recent
+        .iter()
+        .flat_map(|e| [e.submission_dir.as_path(), e.zip.as_path()])
+        .collect();
+    let sources: Vec<&Path> = history
+        .entries
+        .iter()
+        .map(|e| e.source_dir.as_path())
+        .chain([cwd])
+        .collect();
+    let mut targets = BTreeSet::new();
+    for e in old {
+        let folder = &e.submission_dir;
+        if folder.is_dir()
+            && !sources.iter().any(|s| s.starts_with(folder))
+            && !keep.contains(folder.as_path())
+        {
+            targets.insert(folder.clone());
+        }
+        let zip = &e.zip;
+        if zip.is_file()
+            && zip.extension().is_some_and(|x| x == "zip")
+            && !keep.contains(zip.as_path())
+        {
+            targets.insert(zip.clone());
+        }
+    }
+    targets.into_iter().collect()
+}
+
+fn disk_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+pub fn run_stats() -> Result<()> {
+    let history = load(&history_path()?)?;
+    if history.entries.is_empty() {
+        ui::step("Nothing packed yet, run ap first");
+        return Ok(());
+    }
+    let stats = Stats::from(&history);
+
+    let first = history
+        .entries
+        .iter()
+        .map(|e| e.timestamp)
+        .min()
+        .unwrap_or(0);
+    ui::header(&format!(
+        "{} packs since {}",
+        history.entries.len(),
+        date(first)
+    ));
+    for (course, count) in &stats.courses {
+        let noun = if *count == 1 { "pack" } else { "packs" };
+        ui::kv(course, &format!("{count} {noun}"));
+    }
+    match stats.average_run {
+        Some(avg) => ui::kv(
+            "average run",
+            &format!("{} over {} packs", format_duration(avg), stats.timed),
+        ),
+        None => ui::kv("average run", "-"),
+    }
+    if stats.warnings.is_empty() {
+        ui::success("No compiler warnings recorded");
+    } else {
+        ui::header("Most common warnings");
+        for (flag, count) in stats.warnings.iter().take(TOP_WARNINGS) {
+            ui::kv(flag, &count.to_string());
+        }
+    }
+    Ok(())
+}
+
+struct Stats {
+    /// Packs per course folder.
+    courses: BTreeMap<String, usize>,
+    average_run: Option<Duration>,
+    /// Packs with a recorded run time.
+    timed: usize,
+    /// Each warning flag with how often it came up, most frequent first.
+    warnings: Vec<(String, usize)>,
+}
+
+impl From<&History> for Stats {
+    fn from(history: &History) -> Self {
+        let mut courses = BTreeMap::new();
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for e in &history.entries {
+            *courses.entry(course(e)).or_default() += 1;
+            for flag in &e.warnings {
+                *counts.entry(flag).or_default() += 1;
+            }
+        }
+        let runs: Vec<u64> = history.entries.iter().filter_map(|e| e.run_ms).collect();
+        let average_run = (!runs.is_empty())
+            .then(|| Duration::from_millis(runs.iter().sum::<u64>() / runs.len() as u64));
+        let mut warnings: Vec<(String, usize)> = counts
+            .into_iter()
+            .map(|(flag, n)| (flag.to_string(), n))
+            .collect();
+        warnings.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        Self {
+            courses,
+            average_run,
+            timed: runs.len(),
+            warnings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::scratch_dir;
+
+    fn entry(dir: &str, run_ms: Option<u64>, warnings: &[&str]) -> Entry {
+        Entry {
+            assignment: "Assignment1".into(),
+            number: 1,
+            source_dir: dir.into(),
+            run_ms,
+            warnings: warnings.iter().map(|w| w.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn csv_quotes_awkward_cells() {
+        assert_eq!(csv_cell("plain"), "plain");
+        assert_eq!(csv_cell("a,b"), "\"a,b\"");
+        assert_eq!(csv_cell("say \"hi\""), "\"say \"\"hi\"\"\"");
+        let csv = to_csv(&[entry("/home/me/cs101/a1", Some(12), &["-Wunused"])]);
+        let row = csv.lines().nth(1).unwrap();
+        assert_eq!(
+            row,
+            "1970-01-01,Assignment1,1,,,cs101,/home/me/cs101/a1,,12,1"
+        );
+    }
+
+    #[test]
+    fn ages_parse() {
+        assert_eq!(parse_age("90d"), Ok(90 * 86_400));
+        assert_eq!(parse_age("2W"), Ok(14 * 86_400));
+        assert_eq!(parse_age("36h"), Ok(36 * 3600));
+        assert!(parse_age("90").is_err());
+        assert!(parse_age("0d").is_err());
+        assert!(parse_age("d").is_err());
+    }
+
+    #[test]
+    fn prune_keeps_sources_and_recent_packs() {
+        let root = scratch_dir("prune");
+        let src = root.join("a1");
+        fs::create_dir_all(&src).unwrap();
+        let pack = |name: &str, timestamp: u64| {
+            let dir = src.join(format!("{name}_Submission"));
+            fs::create_dir_all(&dir).unwrap();
+            let zip = src.join(format!("{name}_Submission.zip"));
+            fs::write(&zip, b"zip").unwrap();
+            Entry {
+                timestamp,
+                source_dir: src.clone(),
+                submission_dir: dir,
+                zip,
+                ..Default::default()
+            }
+        };
+        let old = pack("Old", 10);
+        let repacked = pack("Again", 10);
+        let mut in_place = pack("InPlace", 10);
+        in_place.submission_dir = src.clone();
+        let history = History {
+            entries: vec![old.clone(), repacked.clone(), pack("Again", 500), in_place],
+        };
+        let targets = prune_targets(&history, 100, &root);
+        assert_eq!(
+            targets,
+            vec![
+                src.join("InPlace_Submission.zip"),
+                old.submission_dir,
+                old.zip
+            ]
+        );
+        assert_eq!(disk_size(&repacked.zip), 3);
+    }
+
+    #[test]
+    fn prune_keeps_folders_a_newer_pack_or_the_cwd_is_in() {
+        let root = scratch_dir("prune-sources");
+        let old = |name: &str| {
+            let dir = root.join(format!("{name}_Submission"));
+            fs::create_dir_all(&dir).unwrap();
+            Entry {
+                timestamp: 10,
+                source_dir: root.to_path_buf(),
+                submission_dir: dir,
+                ..Default::default()
+            }
+        };
+        let edited = old("Edited");
+        let nested = old("Nested");
+        let current = old("Current");
+        let gone = old("Gone");
+        let newer = |source_dir: PathBuf| Entry {
+            timestamp: 500,
+            source_dir,
+            submission_dir: root.join("elsewhere"),
+            ..Default::default()
+        };
+        let history = History {
+            entries: vec![
+                edited.clone(),
+                nested.clone(),
+                current.clone(),
+                gone.clone(),
+                newer(edited.submission_dir.clone()),
+                newer(nested.submission_dir.join("src")),
+            ],
+        };
+        let targets = prune_targets(&history, 100, &current.submission_dir.join("lab"));
+        assert_eq!(targets, vec![gone.submission_dir]);
+    }
+
+    #[test]
+    fn stats_group_courses_and_rank_warnings() {
+        let history = History {
+            entries: vec![
+                entry("/s/cs101/a1", Some(10), &["-Wunused-variable"]),
+                entry("/s/cs101/a2", None, &["-Wformat", "-Wunused-variable"]),
+                entry("/s/cs202/a1", Some(30), &[]),
+            ],
+        };
+        let stats = Stats::from(&history);
+        assert_eq!(stats.courses["cs101"], 2);
+        assert_eq!(stats.courses["cs202"], 1);
+        assert_eq!(stats.average_run, Some(Duration::from_millis(20)));
+        assert_eq!(stats.timed, 2);
+        assert_eq!(stats.warnings[0], ("-Wunused-variable".to_string(), 2));
+    }
+}
@@ -0,0 +1,179 @@
+pub mod commands;
+
+use crate::config;
+use crate::error::{Error, Result, io_err};
+use crate::terminal::format::Diagnostics;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const FILE_NAME: &str = "history.toml";
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct History {
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Entry {
+    pub timestamp: u64,
+    pub assignment: String,
+    pub number: u32,
+    pub name: String,
+    pub student_id: String,
+    pub source_dir: PathBuf,
+    pub submission_dir: PathBuf,
+    pub zip: PathBuf,
+    pub doc: Option<PathBuf>,
+    /// File names of everything this pack generated, used to keep them out
+    /// of later submissions when the output directory is the source directory.
+    pub artifacts: Vec<String>,
+    pub sources: Vec<SourceHash>,
+    /// How long the program ran for, over every test case.
+    pub run_ms: Option<u64>,
+    /// Flag of each compiler warning, e.g. `-Wunused-variable`.
+    pub warnings: Vec<String>,
+    /// Warning and error counts from the compile, if there was one.
+    pub diagnostics: Option<Diagnostics>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SourceHash {
+    pub file: String,
+    pub sha256: String,
+}
+
+impl History {
+    pub fn artifact_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .entries
+            .iter()
+            .flat_map(|e| e.artifacts.iter().cloned())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    pub fn push(&mut self, entry: Entry) {
+        self.entries.push(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+}
+
+pub fn history_path() -> Result<PathBuf> {
+    config::config_path()?
+        .parent()
+        .map(|p| p.join(FILE_NAME))
+        .ok_or_else(|| Error::Validation("can't determine history location".into()))
+}
+
+pub fn load(path: &Path) -> Result<History> {
+    if !path.exists() {
+        return Ok(History::default());
+    }
+    let content = fs::read_to_string(path).map_err(|e| io_err("reading history", e))?;
+    toml::from_str(&content).map_err(|e| Error::Validation(format!("bad history file: {e}")))
+}
+
+pub fn save(path: &Path, history: &History) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| io_err("creating config directory", e))?;
+    }
+    let content = toml::to_string_pretty(history)
+        .map_err(|e| Error::Validation(format!("serializing history: {e}")))?;
+    fs::write(path, content).map_err(|e| io_err("writing history", e))
+}
+
+pub fn record(entry: Entry) -> Result<()> {
+    let path = history_path()?;
+    let mut history = load(&path)?;
+    history.push(entry);
+    save(&path, &history)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The UTC day of a Unix `timestamp`, as `YYYY-MM-DD`.
+pub fn date(timestamp: u64) -> String {
+    // Howard Hinnant's days-to-civil, for days since 1970-01-01
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(artifacts: &[&str]) -> Entry {
+        Entry {
+            assignment: "Assignment7".into(),
+            number: 7,
+            artifacts: artifacts.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn history_round_trips() {
+        let mut h = History::default();
+        let diagnostics = Diagnostics {
+            warnings: 3,
+            errors: 0,
+        };
+        h.push(Entry {
+            diagnostics: Some(diagnostics),
+            ..entry(&["a.zip", "a.doc"])
+        });
+        let s = toml::to_string_pretty(&h).unwrap();
+        let parsed: History = toml::from_str(&s).unwrap();
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(parsed.entries[0].number, 7);
+        assert_eq!(parsed.entries[0].diagnostics, Some(diagnostics));
+    }
+
+    #[test]
+    fn artifact_names_are_deduplicated() {
+        let mut h = History::default();
+        h.push(entry(&["a.zip", "a.doc"]));
+        h.push(entry(&["a.zip", "b.zip"]));
+        assert_eq!(h.artifact_names(), vec!["a.doc", "a.zip", "b.zip"]);
+    }
+
+    #[test]
+    fn push_caps_entries() {
+        let mut h = History::default();
+        for _ in 0..MAX_ENTRIES + 5 {
+            h.push(entry(&[]));
+        }
+        assert_eq!(h.entries.len(), MAX_ENTRIES);
+    }
+
+    #[test]
+    fn dates_are_utc_days() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(1_792_108_800), "2026-10-16");
+    }
+}
@@ -0,0 +1,119 @@
+use crate::config::{HygieneConfig, IndentPolicy};
+
+/// Tabs count as this many columns when measuring line length, matching how
+/// the doc's code listing renders them.
+const TAB_WIDTH: usize = 4;
+
+pub struct Issue {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+pub fn is_enabled(cfg: &HygieneConfig) -> bool {
+    cfg.indent.is_some() || cfg.trailing_whitespace == Some(true) || cfg.max_line_length.is_some()
+}
+
+/// Checks one source file against the configured course style rules.
+pub fn check(file: &str, code: &str, cfg: &HygieneConfig) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut push = |line: usize, message: String| {
+        issues.push(Issue {
+            file: file.to_string(),
+            line,
+            message,
+        })
+    };
+
+    for (idx, raw) in code.lines().enumerate() {
+        let line = raw.strip_suffix('\r').unwrap_or(raw);
+        let n = idx + 1;
+
+        if let Some(policy) = cfg.indent {
+            let indent: &str = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+            let rest = &line[indent.len()..];
+            match policy {
+                IndentPolicy::Spaces if indent.contains('\t') => {
+                    push(n, "indented with tabs".into());
+                }
+                // A space before `*` is block comment alignment, not indentation
+                IndentPolicy::Tabs
+                    if indent.contains(' ') && !rest.is_empty() && !rest.starts_with('*') =>
+                {
+                    push(n, "indented with spaces".into());
+                }
+                _ => {}
+            }
+        }
+
+        if cfg.trailing_whitespace == Some(true) && line.ends_with([' ', '\t']) {
+            push(n, "trailing whitespace".into());
+        }
+
+        if let Some(max) = cfg.max_line_length {
+            let width: usize = line
+                .chars()
+                .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+                .sum();
+            if width > max {
+                push(n, format!("line is {width} columns (max {max})"));
+            }
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(indent: Option<IndentPolicy>, trailing: bool, max: Option<usize>) -> HygieneConfig {
+        HygieneConfig {
+            indent,
+            trailing_whitespace: Some(trailing),
+            max_line_length: max,
+            fail: None,
+        }
+    }
+
+    fn lines(issues: &[Issue]) -> Vec<(usize, &str)> {
+        issues
+            .iter()
+            .map(|i| (i.line, i.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn indent_policies() {
+        let code = "int main(void) {\n\treturn 0;\n    return 1;\n}\n";
+        let spaces = check("a.c", code, &cfg(Some(IndentPolicy::Spaces), false, None));
+        assert_eq!(lines(&spaces), vec![(2, "indented with tabs")]);
+        let tabs = check("a.c", code, &cfg(Some(IndentPolicy::Tabs), false, None));
+        assert_eq!(lines(&tabs), vec![(3, "indented with spaces")]);
+    }
+
+    #[test]
+    fn comment_alignment_is_not_space_indentation() {
+        let code = "/*\n * note\n */\n";
+        assert!(check("a.c", code, &cfg(Some(IndentPolicy::Tabs), false, None)).is_empty());
+    }
+
+    #[test]
+    fn trailing_whitespace_and_length() {
+        let code = "int x; \r\n\tint yy;\nint z;\n";
+        let issues = check("a.c", code, &cfg(None, true, Some(6)));
+        assert_eq!(
+            lines(&issues),
+            vec![
+                (1, "trailing whitespace"),
+                (1, "line is 7 columns (max 6)"),
+                (2, "line is 11 columns (max 6)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(!is_enabled(&HygieneConfig::default()));
+    }
+}
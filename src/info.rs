@@ -0,0 +1,186 @@
+//! `ap info`: what a pack in this folder would use and where each setting
+//! came from, without compiling or running anything.
+
+use crate::cli::Cli;
+use crate::config;
+use crate::error::{Error, Result, io_err};
+use crate::fs as afs;
+use crate::lang;
+use crate::pack;
+use crate::render::theme;
+use crate::run_script;
+use crate::terminal;
+use crate::ui;
+use clap::Parser;
+use std::env;
+use std::ffi::OsString;
+
+/// Prints the resolved settings. `args` are pack flags, parsed as if given
+/// to `ap` directly, so their effect can be checked too.
+pub fn run(args: Vec<OsString>) -> Result<()> {
+    let cli = Cli::parse_from(std::iter::once(OsString::from("ap")).chain(args));
+    if cli.command.is_some() {
+        return Err(Error::Validation(
+            "ap info takes pack flags, not another command".into(),
+        ));
+    }
+    let cfg_path = config::config_path()?;
+    let cfg = config::load(&cfg_path)?;
+    let cwd = env::current_dir().map_err(|e| io_err("current directory", e))?;
+
+    ui::header(&format!("Settings for {}", cwd.display()));
+    let config_note = if cfg_path.exists() {
+        ""
+    } else {
+        " (not created yet)"
+    };
+    ui::kv("config", &format!("{}{config_note}", cfg_path.display()));
+    let unset = || "-".to_string();
+    ui::kv(
+        "assignment",
+        &describe(cli.assignment.clone(), None).unwrap_or_else(unset),
+    );
+    ui::kv(
+        "name",
+        &describe(cli.name.clone(), cfg.name.clone()).unwrap_or_else(unset),
+    );
+    ui::kv(
+        "id",
+        &describe(cli.student_id.clone(), cfg.student_id.clone()).unwrap_or_else(unset),
+    );
+    let auto_doc = cli.auto_doc || (cli.doc_file.is_none() && cfg.auto_doc.unwrap_or(false));
+    ui::kv(
+        "auto_doc",
+        &or_default(
+            describe(
+                cli.auto_doc.then_some(true),
+                cfg.auto_doc.filter(|_| cli.doc_file.is_none()),
+            ),
+            "false",
+        ),
+    );
+
+    let sources = match cli.sources.as_deref() {
+        Some(patterns) => afs::resolve_sources(patterns),
+        None => afs::resolve_c_file(cli.c_file.as_deref()).map(|p| vec![p]),
+    };
+    match &sources {
+        Ok(found) => {
+            let main = match cli.c_file.as_deref() {
+                Some(p) => Ok(p.to_path_buf()),
+                None => pack::main_source(found),
+            };
+            let origin = if cli.c_file.is_some() {
+                "flag"
+            } else {
+                "detected"
+            };
+            match main {
+                Ok(main) => ui::kv("c_file", &format!("{} ({origin})", main.display())),
+                Err(e) => ui::kv("c_file", &format!("none ({e})")),
+            }
+            if found.len() > 1 {
+                let names: Vec<String> = found.iter().map(|p| p.display().to_string()).collect();
+                ui::kv("sources", &names.join(", "));
+            }
+            let backend = match cli.lang.as_deref() {
+                Some(id) => lang::by_id(id)?,
+                None => lang::for_sources(found),
+            };
+            let compiler = if cli.no_exec || crate::terminal::exec::is_forbidden() {
+                "not checked with --no-exec"
+            } else {
+                backend.detect().unwrap_or("no compiler found")
+            };
+            ui::kv("language", &format!("{} ({compiler})", backend.name()));
+        }
+        Err(e) => ui::kv("c_file", &format!("none ({e})")),
+    }
+
+    let theme_name = cli.theme.clone().or_else(|| cfg.theme.clone());
+    let theme_value = or_default(describe(cli.theme.clone(), cfg.theme.clone()), "default");
+    match theme::resolve(theme_name.as_deref(), cfg.theme_namespace.as_deref()) {
+        Ok(_) => ui::kv("theme", &theme_value),
+        Err(e) => ui::kv("theme", &format!("{theme_value}, doesn't load: {e}")),
+    }
+
+    let run_command = if let Some(command) =
+        describe(cli.run_command.clone(), cfg.run_command.clone())
+    {
+        command
+    } else if let Some(script) = run_script::detect(&cwd).filter(|_| cfg.run_script != Some(false))
+    {
+        format!("{} (from {})", script.command, script.source)
+    } else {
+        "built-in compile and run".into()
+    };
+    ui::kv("run_command", &run_command);
+
+    let input = match (&cli.input, &cli.stdin_file) {
+        (Some(text), _) => format!("{text:?} (flag)"),
+        (None, Some(file)) => format!("{} (flag)", file.display()),
+        (None, None) => match (&cfg.stdin_file, &cfg.input) {
+            (Some(file), _) => format!("{} (config)", file.display()),
+            (None, Some(text)) => format!("{text:?} (config)"),
+            (None, None) => "none, asked for at run time".into(),
+        },
+    };
+    ui::kv("input", &input);
+    ui::kv(
+        "timeout",
+        &or_default(
+            describe(
+                cli.timeout.map(|t| format!("{t}s")),
+                cfg.timeout.map(|t| format!("{t}s")),
+            ),
+            &format!("{}s", terminal::DEFAULT_TIMEOUT.as_secs()),
+        ),
+    );
+    ui::kv(
+        "output_dir",
+        &or_default(
+            describe(
+                cli.output_dir.as_ref().map(|p| p.display().to_string()),
+                cfg.output_dir.as_ref().map(|p| p.display().to_string()),
+            ),
+            ".",
+        ),
+    );
+    ui::kv(
+        "watermark",
+        &or_default(
+            describe(cli.no_watermark.then_some(false), cfg.watermark),
+            "true",
+        ),
+    );
+    if !auto_doc {
+        ui::step("Without --auto-doc the doc comes from --doc-file or the current folder");
+    }
+    Ok(())
+}
+
+/// A setting's value and where it came from, the flag winning over the
+/// config.
+fn describe<T: ToString>(flag: Option<T>, config: Option<T>) -> Option<String> {
+    match (flag, config) {
+        (Some(v), _) => Some(format!("{} (flag)", v.to_string())),
+        (None, Some(v)) => Some(format!("{} (config)", v.to_string())),
+        (None, None) => None,
+    }
+}
+
+fn or_default(value: Option<String>, default: &str) -> String {
+    value.unwrap_or_else(|| format!("{default} (default)"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_beat_config_beats_default() {
+        assert_eq!(describe(Some(1), Some(2)).unwrap(), "1 (flag)");
+        assert_eq!(describe(None, Some(2)).unwrap(), "2 (config)");
+        assert_eq!(or_default(describe::<u8>(None, None), "3"), "3 (default)");
+    }
+}
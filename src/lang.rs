@@ -0,0 +1,184 @@
+use crate::error::{Error, Result};
+use crate::fs::has_extension;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Everything the pack needs to know about a source language: which files
+/// belong to it, how to find a toolchain, and how to build and run a program.
+pub trait LanguageBackend: Sync {
+    /// Identifier accepted by `--lang`.
+    fn id(&self) -> &'static str;
+
+    /// Name used in messages, e.g. "C++".
+    fn name(&self) -> &'static str;
+
+    /// Extensions of files that are compiled.
+    fn unit_extensions(&self) -> &'static [&'static str];
+
+    /// Extensions of files that are listed and copied but not compiled.
+    fn header_extensions(&self) -> &'static [&'static str];
+
+    /// Compilers to try, in order of preference.
+    fn compilers(&self) -> &'static [&'static str];
+
+    /// Build outputs that should never be copied into a submission.
+    fn artifact_extensions(&self) -> &'static [&'static str] {
+        &["o", "obj"]
+    }
+
+    fn detect(&self) -> Option<&'static str> {
+        self.compilers()
+            .iter()
+            .copied()
+            .find(|c| Command::new(c).arg("--version").output().is_ok())
+    }
+
+    fn compile_command(&self, compiler: &str, units: &[&Path], out: &Path) -> Command {
+        let mut cmd = Command::new(compiler);
+        cmd.args(units).arg("-o").arg(out);
+        cmd
+    }
+
+    /// Program and arguments that run what `compile_command` produced.
+    fn run_command(&self, built: &Path) -> Vec<OsString> {
+        vec![built.as_os_str().to_owned()]
+    }
+}
+
+pub struct C;
+
+impl LanguageBackend for C {
+    fn id(&self) -> &'static str {
+        "c"
+    }
+
+    fn name(&self) -> &'static str {
+        "C"
+    }
+
+    fn unit_extensions(&self) -> &'static [&'static str] {
+        &["c"]
+    }
+
+    fn header_extensions(&self) -> &'static [&'static str] {
+        &["h"]
+    }
+
+    fn compilers(&self) -> &'static [&'static str] {
+        &["gcc", "clang"]
+    }
+}
+
+pub struct Cpp;
+
+impl LanguageBackend for Cpp {
+    fn id(&self) -> &'static str {
+        "cpp"
+    }
+
+    fn name(&self) -> &'static str {
+        "C++"
+    }
+
+    fn unit_extensions(&self) -> &'static [&'static str] {
+        &["cpp", "cc", "cxx"]
+    }
+
+    fn header_extensions(&self) -> &'static [&'static str] {
+        &["hpp", "hh", "hxx"]
+    }
+
+    fn compilers(&self) -> &'static [&'static str] {
+        &["g++", "clang++"]
+    }
+}
+
+// Most specific first: a project with any C++ file is built as C++, even
+// though it may also include plain `.h` headers
+static BACKENDS: &[&dyn LanguageBackend] = &[&Cpp, &C];
+
+pub fn by_id(id: &str) -> Result<&'static dyn LanguageBackend> {
+    BACKENDS
+        .iter()
+        .copied()
+        .find(|b| b.id().eq_ignore_ascii_case(id))
+        .ok_or_else(|| {
+            let ids: Vec<_> = BACKENDS.iter().map(|b| b.id()).collect();
+            Error::Validation(format!(
+                "unknown language '{id}', expected one of: {}",
+                ids.join(", ")
+            ))
+        })
+}
+
+/// Picks the backend from the source extensions, falling back to C.
+pub fn for_sources(sources: &[PathBuf]) -> &'static dyn LanguageBackend {
+    BACKENDS
+        .iter()
+        .copied()
+        .find(|b| sources.iter().any(|p| claims(*b, p)))
+        .unwrap_or(&C)
+}
+
+fn claims(backend: &dyn LanguageBackend, path: &Path) -> bool {
+    has_extension(path, backend.unit_extensions())
+        || has_extension(path, backend.header_extensions())
+}
+
+pub fn is_unit(path: &Path) -> bool {
+    BACKENDS
+        .iter()
+        .any(|b| has_extension(path, b.unit_extensions()))
+}
+
+pub fn is_header(path: &Path) -> bool {
+    BACKENDS
+        .iter()
+        .any(|b| has_extension(path, b.header_extensions()))
+}
+
+pub fn is_artifact(path: &Path) -> bool {
+    BACKENDS
+        .iter()
+        .any(|b| has_extension(path, b.artifact_extensions()))
+}
+
+/// Every extension some backend accepts as a source or header file.
+pub fn source_extensions() -> Vec<&'static str> {
+    BACKENDS
+        .iter()
+        .flat_map(|b| b.unit_extensions().iter().chain(b.header_extensions()))
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_kinds_detected() {
+        assert!(is_header(Path::new("list.h")));
+        assert!(is_header(Path::new("list.HPP")));
+        assert!(!is_header(Path::new("list.c")));
+        assert!(is_unit(Path::new("main.cc")));
+        assert!(is_artifact(Path::new("main.o")));
+    }
+
+    #[test]
+    fn backend_follows_extensions() {
+        let c = [PathBuf::from("main.c"), PathBuf::from("list.h")];
+        assert_eq!(for_sources(&c).id(), "c");
+        let cpp = [PathBuf::from("main.cpp"), PathBuf::from("list.h")];
+        assert_eq!(for_sources(&cpp).id(), "cpp");
+        let header_only = [PathBuf::from("vec.hpp")];
+        assert_eq!(for_sources(&header_only).id(), "cpp");
+    }
+
+    #[test]
+    fn backend_by_id() {
+        assert_eq!(by_id("CPP").unwrap().name(), "C++");
+        assert!(by_id("rust").is_err());
+    }
+}
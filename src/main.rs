@@ -1,48 +1,74 @@
+mod alias;
+mod base64;
+mod cases;
 mod cli;
-mod terminal;
 mod config;
+mod diff;
 mod error;
 mod fs;
+mod history;
+mod hygiene;
+mod info;
+mod lang;
+mod naming;
+mod next;
+mod open;
 mod pack;
+mod pager;
+mod profile;
+mod redact;
 mod render;
+mod run_script;
+mod terminal;
 mod ui;
 mod update;
 mod validate;
+mod workspace;
 
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use cli::{Cli, Commands};
-use owo_colors::OwoColorize;
 
 fn main() {
     if let Err(e) = run() {
-        eprintln!("{} {e}", "error:".red().bold());
+        // Long compile errors are paged rather than scrolled past
+        if let error::Error::CompileFailed(log) = &e
+            && pager::compile_errors(log)
+        {
+            std::process::exit(1);
+        }
+        ui::error(&e.to_string());
         std::process::exit(1);
     }
 }
 
 fn run() -> error::Result<()> {
-    let cli = Cli::parse();
+    // A broken config is reported by whichever command loads it, so only
+    // the ui and alias tables matter here.
+    let cfg = config::config_path()
+        .and_then(|p| config::load(&p))
+        .unwrap_or_default();
+    if let Err(e) = ui::init(&cfg.ui) {
+        ui::warn(&e.to_string());
+    }
 
-    if cli.command.is_some() {
-        let has_pack_flags = cli.assignment.is_some()
-            || cli.name.is_some()
-            || cli.student_id.is_some()
-            || cli.c_file.is_some()
-            || cli.doc_file.is_some()
-            || cli.auto_doc
-            || cli.run_command.is_some()
-            || cli.input.is_some()
-            || cli.timeout.is_some()
-            || cli.run_display_template.is_some()
-            || cli.output_dir.is_some()
-            || cli.theme.is_some()
-            || cli.no_watermark
-            || cli.force;
-        if has_pack_flags {
-            return Err(error::Error::Validation(
-                "pack flags (like -a, -n, --auto-doc) cannot be used with subcommands".into(),
-            ));
-        }
+    let command = Cli::command();
+    let builtins: Vec<&str> = command
+        .get_subcommands()
+        .map(|c| c.get_name())
+        .chain(["help"])
+        .collect();
+    let args = alias::expand(std::env::args_os().collect(), &cfg.alias, &builtins)?;
+    let cli = Cli::parse_from(args);
+    if cli.no_exec {
+        terminal::exec::forbid();
+    }
+
+    if cli.command.is_some()
+        && let Some((flag, _)) = pack::pack_flags(&cli).into_iter().find(|(_, set)| *set)
+    {
+        return Err(error::Error::Validation(format!(
+            "pack flags (like {flag}) cannot be used with subcommands"
+        )));
     }
 
     match cli.command {
@@ -50,6 +76,13 @@ fn run() -> error::Result<()> {
         Some(Commands::Config { command }) => config::commands::run_config_command(command),
         Some(Commands::Update) => update::run(),
         Some(Commands::Themes) => render::theme::run_list(),
+        Some(Commands::Theme { command }) => render::theme::run_theme_command(command),
+        Some(Commands::Open { target }) => open::run(target),
+        Some(Commands::Next { args }) => next::run(args),
+        Some(Commands::Info { args }) => info::run(args),
+        Some(Commands::History { command }) => history::commands::run_history_command(command),
+        Some(Commands::Stats) => history::commands::run_stats(),
+        Some(Commands::Workspace { command }) => workspace::run_workspace_command(command),
         None => pack::run_pack(cli),
     }
 }
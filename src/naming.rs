@@ -0,0 +1,88 @@
+//! Naming presets: how a submission's folder, zip and doc are named, picked
+//! as a bundle with `--naming` or `naming_preset` in the config.
+
+use crate::config::NamingPreset;
+use crate::error::{Error, Result};
+use crate::validate::clean_name;
+use std::path::Path;
+
+/// Names of a submission's files. `folder` names the submission folder and
+/// its zip, `stem` starts the doc and every other generated file.
+#[derive(Debug, PartialEq)]
+pub struct Names {
+    pub folder: String,
+    pub stem: String,
+}
+
+/// The names `preset` gives a pack of the project in `source_dir`.
+pub fn names(
+    preset: NamingPreset,
+    assignment: &str,
+    name: &str,
+    student_id: &str,
+    source_dir: &Path,
+) -> Result<Names> {
+    let stem = format!("{assignment}_{name}_{student_id}");
+    Ok(match preset {
+        NamingPreset::CanvasDefault => Names {
+            folder: format!("{stem}_Submission"),
+            stem,
+        },
+        NamingPreset::UnderscoreLower => {
+            let stem = stem.to_lowercase();
+            Names {
+                folder: format!("{stem}_submission"),
+                stem,
+            }
+        }
+        NamingPreset::CoursePrefix => {
+            let course = course(source_dir).ok_or_else(|| {
+                Error::Validation(
+                    "the course_prefix naming preset needs the project inside a course folder"
+                        .into(),
+                )
+            })?;
+            let stem = format!("{course}_{stem}");
+            Names {
+                folder: format!("{stem}_Submission"),
+                stem,
+            }
+        }
+    })
+}
+
+/// The course a project belongs to: the folder it sits in, the same
+/// grouping `ap next` and `ap history stats` use.
+pub fn course(source_dir: &Path) -> Option<String> {
+    let folder = source_dir.parent()?.file_name()?.to_string_lossy();
+    clean_name(&folder, "course").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(preset: NamingPreset, dir: &str) -> Result<Names> {
+        names(preset, "Assignment7", "JoeBloggs", "123", Path::new(dir))
+    }
+
+    #[test]
+    fn presets_name_the_folder_and_doc() {
+        let canvas = named(NamingPreset::CanvasDefault, "/home/joe/CS101/a7").unwrap();
+        assert_eq!(canvas.folder, "Assignment7_JoeBloggs_123_Submission");
+        assert_eq!(canvas.stem, "Assignment7_JoeBloggs_123");
+
+        let lower = named(NamingPreset::UnderscoreLower, "/home/joe/CS101/a7").unwrap();
+        assert_eq!(lower.folder, "assignment7_joebloggs_123_submission");
+        assert_eq!(lower.stem, "assignment7_joebloggs_123");
+
+        let course = named(NamingPreset::CoursePrefix, "/home/joe/CS 101/a7").unwrap();
+        assert_eq!(course.folder, "CS101_Assignment7_JoeBloggs_123_Submission");
+        assert_eq!(course.stem, "CS101_Assignment7_JoeBloggs_123");
+    }
+
+    #[test]
+    fn course_prefix_needs_a_course_folder() {
+        assert!(named(NamingPreset::CoursePrefix, "/").is_err());
+    }
+}
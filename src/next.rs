@@ -0,0 +1,90 @@
+use crate::cli::Cli;
+use crate::config;
+use crate::error::{Error, Result, io_err};
+use crate::history::{self, Entry};
+use crate::pack;
+use crate::ui;
+use clap::Parser;
+use std::env;
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Packs the assignment after the latest one from this course, where the
+/// course is the folder holding each week's assignment folder. `args` are
+/// pack flags, parsed as if given to `ap` directly.
+pub fn run(args: Vec<OsString>) -> Result<()> {
+    let mut cli = Cli::parse_from(std::iter::once(OsString::from("ap")).chain(args));
+    if cli.command.is_some() {
+        return Err(Error::Validation(
+            "ap next takes pack flags, not another command".into(),
+        ));
+    }
+    if cli.assignment.is_some() {
+        return Err(Error::Validation(
+            "ap next picks the assignment number itself, drop -a".into(),
+        ));
+    }
+    let past = history::load(&history::history_path()?)?;
+    let cwd = env::current_dir().map_err(|e| io_err("current directory", e))?;
+    let latest = latest_in_course(&past.entries, &cwd).ok_or_else(|| {
+        Error::Validation("no earlier packs from this course, use -a for the first one".into())
+    })?;
+    let next = latest.number + 1;
+
+    let mut question = format!(
+        "Last pack was {}, pack Assignment{next}?",
+        latest.assignment
+    );
+    if let Some(done) = past.entries.iter().rev().find(|e| e.source_dir == cwd) {
+        question = format!(
+            "This folder was packed as {} before, pack it as Assignment{next}?",
+            done.assignment
+        );
+    }
+    // The number is a guess, so unlike a plain pack this one isn't waved
+    // through just because no one is at the terminal
+    let cfg = config::load(&config::config_path()?)?;
+    if !cli.yes && cfg.confirm != Some(false) && !ui::is_interactive() {
+        return Err(Error::Validation(format!(
+            "can't confirm without a terminal, pass --yes or run ap -a {next} instead"
+        )));
+    }
+
+    cli.assignment = Some(next.to_string());
+    pack::run_pack_asking(cli, &question)
+}
+
+/// The highest numbered pack made from `cwd` or a sibling folder.
+fn latest_in_course<'a>(entries: &'a [Entry], cwd: &Path) -> Option<&'a Entry> {
+    let course = cwd.parent();
+    entries
+        .iter()
+        .filter(|e| e.source_dir == cwd || (course.is_some() && e.source_dir.parent() == course))
+        .max_by_key(|e| (e.number, e.timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(number: u32, source_dir: &str) -> Entry {
+        Entry {
+            assignment: format!("Assignment{number}"),
+            number,
+            source_dir: source_dir.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn next_follows_the_course_folder() {
+        let entries = [
+            entry(3, "/uni/cs101/a3"),
+            entry(9, "/uni/maths/week9"),
+            entry(4, "/uni/cs101/a4"),
+        ];
+        let latest = latest_in_course(&entries, Path::new("/uni/cs101/a5")).unwrap();
+        assert_eq!(latest.number, 4);
+        assert!(latest_in_course(&entries, Path::new("/uni/physics/lab1")).is_none());
+    }
+}
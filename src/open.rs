@@ -0,0 +1,94 @@
+use crate::cli::OpenTarget;
+use crate::error::{Error, Result, io_err};
+use crate::history::{self, Entry};
+use crate::ui;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub fn run(target: OpenTarget) -> Result<()> {
+    let past = history::load(&history::history_path()?)?;
+    let cwd = env::current_dir().map_err(|e| io_err("current directory", e))?;
+    let entry = latest_for(&past.entries, &cwd)
+        .ok_or_else(|| Error::Validation("nothing packed yet, run ap first".into()))?;
+
+    let path = match target {
+        OpenTarget::Doc => entry.doc.clone().ok_or_else(|| {
+            Error::Validation(format!("the last pack of {} had no doc", entry.assignment))
+        })?,
+        OpenTarget::Zip => entry.zip.clone(),
+        OpenTarget::Dir => entry.submission_dir.clone(),
+        OpenTarget::Last => entry
+            .zip
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(".")),
+    };
+    if !path.exists() {
+        return Err(Error::Validation(format!(
+            "'{}' no longer exists",
+            path.display()
+        )));
+    }
+
+    ui::step(&format!("Opening {} ...", path.display()));
+    open_path(&path)
+}
+
+/// The most recent pack made from `cwd`, or the most recent pack anywhere.
+fn latest_for<'a>(entries: &'a [Entry], cwd: &Path) -> Option<&'a Entry> {
+    entries
+        .iter()
+        .rev()
+        .find(|e| e.source_dir == cwd)
+        .or_else(|| entries.last())
+}
+
+pub fn open_path(path: &Path) -> Result<()> {
+    crate::terminal::exec::check_allowed("the default app")?;
+    let mut cmd = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.args(["/C", "start", ""]);
+        c
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+    let status = cmd
+        .arg(path)
+        .status()
+        .map_err(|e| io_err("launching the default app", e))?;
+    if !status.success() {
+        ui::warn(&format!("opener exited with {status}"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(assignment: &str, source_dir: &str) -> Entry {
+        Entry {
+            assignment: assignment.into(),
+            source_dir: source_dir.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prefers_packs_from_current_dir() {
+        let entries = [
+            entry("Assignment1", "/a"),
+            entry("Assignment2", "/b"),
+            entry("Assignment3", "/a"),
+            entry("Assignment4", "/c"),
+        ];
+        let e = latest_for(&entries, Path::new("/a")).unwrap();
+        assert_eq!(e.assignment, "Assignment3");
+        let e = latest_for(&entries, Path::new("/elsewhere")).unwrap();
+        assert_eq!(e.assignment, "Assignment4");
+        assert!(latest_for(&[], Path::new("/a")).is_none());
+    }
+}
@@ -1,17 +1,35 @@
+use crate::cases;
 use crate::cli::Cli;
-use crate::terminal;
-use crate::config;
+use crate::config::{self, DocCompat, DocFormat, DocSection, LineEndings};
+use crate::diff;
 use crate::error::{Error, Result, io_err};
 use crate::fs as afs;
+use crate::history;
+use crate::hygiene;
+use crate::lang;
+use crate::naming;
+use crate::profile::Profile;
+use crate::redact::Redactor;
+use crate::render::doc::{self, DocBuilder};
+use crate::render::notes;
+use crate::render::pdf;
+use crate::render::png_meta;
+use crate::render::qr;
+use crate::render::report;
 use crate::render::rtf;
 use crate::render::screenshot;
 use crate::render::theme;
+use crate::run_script;
+use crate::terminal;
 use crate::ui;
-use crate::validate::{clean_name, parse_assignment, render_display_command};
-use owo_colors::OwoColorize;
+use crate::validate::{check_portable_tree, clean_name, parse_assignment, render_display_command};
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 use std::{env, fs};
 
+const DEFAULT_OUTPUT_LINES: usize = 400;
+const MAX_HYGIENE_REPORTED: usize = 10;
+
 struct CleanupGuard<'a> {
     dir: &'a Path,
     zip: &'a Path,
@@ -27,9 +45,461 @@ impl Drop for CleanupGuard<'_> {
     }
 }
 
-pub fn run_pack(cli: Cli) -> Result<()> {
-    let cfg_path = config::config_path()?;
-    let cfg = config::load(&cfg_path)?;
+fn file_node(path: &Path) -> Result<ui::TreeNode> {
+    let meta = fs::metadata(path).map_err(|e| io_err(format!("reading {}", path.display()), e))?;
+    Ok(ui::TreeNode::leaf(format!(
+        "{} {}",
+        afs::file_name(path)?,
+        ui::dim(&format!("({})", ui::size(meta.len())))
+    )))
+}
+
+fn dir_tree(dir: &Path) -> Result<ui::TreeNode> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| io_err(format!("reading {}", dir.display()), e))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    paths.sort();
+
+    let mut children = Vec::with_capacity(paths.len());
+    for path in paths {
+        children.push(if path.is_dir() {
+            dir_tree(&path)?
+        } else {
+            file_node(&path)?
+        });
+    }
+    Ok(ui::TreeNode {
+        label: ui::bold(&format!("{}/", afs::file_name(dir)?)),
+        children,
+    })
+}
+
+/// Picks the file that defines `main` as the one naming the run, falling back
+/// to the first translation unit.
+pub fn main_source(sources: &[PathBuf]) -> Result<PathBuf> {
+    let units: Vec<&PathBuf> = sources.iter().filter(|p| !lang::is_header(p)).collect();
+    if units.is_empty() {
+        return Err(Error::Validation(
+            "--sources matched only headers, include at least one .c/.cpp file".into(),
+        ));
+    }
+    if units.len() > 1 {
+        for unit in &units {
+            let code = afs::read_text_lossy(unit)?;
+            if defines_main(&code) {
+                return Ok(unit.to_path_buf());
+            }
+        }
+    }
+    Ok(units[0].to_path_buf())
+}
+
+fn check_hygiene(sources: &[PathBuf], rules: &config::HygieneConfig) -> Result<()> {
+    let mut issues = Vec::new();
+    for source in sources {
+        let code = afs::read_text_lossy(source)?;
+        issues.extend(hygiene::check(afs::file_name(source)?, &code, rules));
+    }
+    if issues.is_empty() {
+        return Ok(());
+    }
+    for issue in issues.iter().take(MAX_HYGIENE_REPORTED) {
+        ui::warn(&format!("{}:{}: {}", issue.file, issue.line, issue.message));
+    }
+    if issues.len() > MAX_HYGIENE_REPORTED {
+        ui::warn(&format!(
+            "... and {} more style issues",
+            issues.len() - MAX_HYGIENE_REPORTED
+        ));
+    }
+    if rules.fail == Some(true) {
+        let noun = if issues.len() == 1 { "issue" } else { "issues" };
+        return Err(Error::Validation(format!(
+            "sources have {} style {noun} (hygiene.fail is on)",
+            issues.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Offers a project's own run script as the run command, asking first
+/// unless the config already says to use it.
+fn detect_run_script(always: bool) -> Result<Option<String>> {
+    let cwd = env::current_dir().map_err(|e| io_err("current directory", e))?;
+    let Some(script) = run_script::detect(&cwd) else {
+        return Ok(None);
+    };
+    if always {
+        ui::step(&format!(
+            "Running with `{}` from {}",
+            script.command, script.source
+        ));
+        return Ok(Some(script.command));
+    }
+    let question = format!(
+        "{} found, run the program with `{}`?",
+        script.source, script.command
+    );
+    match ui::confirm(&question)? {
+        Some(true) => Ok(Some(script.command)),
+        Some(false) => Ok(None),
+        None => {
+            ui::step(&format!(
+                "{} found, pass --run-command \"{}\" to run with it",
+                script.source, script.command
+            ));
+            Ok(None)
+        }
+    }
+}
+
+/// Shows the exact command about to go to the shell and asks before running
+/// anything that looks destructive, since run commands get copied around.
+fn check_run_command(command: &str, sources: &[PathBuf], trusted: bool) -> Result<()> {
+    ui::step(&format!("Run command: {command}"));
+    let names: Vec<&str> = sources
+        .iter()
+        .filter_map(|p| p.file_name()?.to_str())
+        .collect();
+    let findings = terminal::shell_check::suspicious(command, &names);
+    if findings.is_empty() || trusted {
+        return Ok(());
+    }
+    for finding in &findings {
+        ui::warn(&format!("run command {finding}"));
+    }
+    match ui::confirm("Run it anyway?")? {
+        Some(true) => Ok(()),
+        Some(false) => Err(Error::Validation("run command not confirmed".into())),
+        None => Err(Error::Validation(
+            "run command looks unsafe, check it and pass --trust-run-command to run it anyway"
+                .into(),
+        )),
+    }
+}
+
+/// What a pack is about to produce, shown before anything is compiled or
+/// written so a wrong assignment number is caught before the zip exists.
+struct Summary<'a> {
+    assignment: &'a str,
+    name: &'a str,
+    student_id: &'a str,
+    sources: &'a [PathBuf],
+    theme: Option<&'a str>,
+    docs: &'a [String],
+    folder: &'a Path,
+    zip: &'a Path,
+    question: &'a str,
+}
+
+/// Shows the summary and asks to go ahead. Returns false if the answer was
+/// no, and true without asking when there's no one at the terminal.
+fn confirm_pack(summary: &Summary<'_>) -> Result<bool> {
+    if !ui::is_interactive() {
+        return Ok(true);
+    }
+    let files: Vec<&str> = summary
+        .sources
+        .iter()
+        .filter_map(|p| p.file_name()?.to_str())
+        .collect();
+    ui::blank();
+    ui::kv("assignment", summary.assignment);
+    ui::kv("name", summary.name);
+    ui::kv("id", summary.student_id);
+    ui::kv("files", &files.join(", "));
+    if let Some(theme) = summary.theme {
+        ui::kv("theme", theme);
+    }
+    if !summary.docs.is_empty() {
+        ui::kv("doc", &summary.docs.join(", "));
+    }
+    ui::kv("folder", &summary.folder.display().to_string());
+    ui::kv("zip", &summary.zip.display().to_string());
+    ui::blank();
+    Ok(ui::confirm(summary.question)? != Some(false))
+}
+
+fn config_size(key: &str, value: Option<&str>) -> Result<Option<u64>> {
+    value
+        .map(|v| {
+            terminal::limits::parse_size(v)
+                .map_err(|e| Error::Validation(format!("config {key}: {e}")))
+        })
+        .transpose()
+}
+
+/// The full output of every case, with a heading per named case.
+fn transcript_text(capture: &terminal::RunCapture) -> String {
+    capture
+        .cases
+        .iter()
+        .map(|c| match &c.name {
+            Some(name) => format!("=== {name} ===\n{}\n", c.formatted_output),
+            None => c.formatted_output.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Highlighted image of each source. A file too long to draw gets a warning
+/// and keeps just its text listing.
+fn code_images(
+    sources: &[doc::SourceFile],
+    theme: &theme::Theme,
+    note: Option<&str>,
+    dpi: u32,
+) -> Result<Vec<Option<Vec<u8>>>> {
+    sources
+        .iter()
+        .map(
+            |s| match screenshot::render_code_png(&s.code, &s.name, theme) {
+                Ok(png) => png_meta::scrub(&png, note, dpi).map(Some),
+                Err(e) => {
+                    ui::warn(&format!("skipping the code image for {}: {e}", s.name));
+                    Ok(None)
+                }
+            },
+        )
+        .collect()
+}
+
+/// Hashes of the sources as they go in the submission, after any change to
+/// their line endings.
+fn hash_sources(paths: &[PathBuf], line_endings: LineEndings) -> Result<Vec<history::SourceHash>> {
+    paths
+        .iter()
+        .map(|p| {
+            let bytes = fs::read(p).map_err(|e| io_err(format!("reading {}", p.display()), e))?;
+            Ok(history::SourceHash {
+                file: afs::file_name(p)?.to_string(),
+                sha256: afs::sha256_hex(&afs::convert_line_endings(&bytes, line_endings)),
+            })
+        })
+        .collect()
+}
+
+fn defines_main(code: &str) -> bool {
+    code.lines().any(|line| {
+        let compact: String = line.split_whitespace().collect();
+        compact.starts_with("intmain(") || compact.starts_with("voidmain(")
+    })
+}
+
+/// Pack flags that only do something when the doc is generated, each with
+/// whether it was given.
+fn doc_flags(cli: &Cli) -> Vec<(&'static str, bool)> {
+    vec![
+        ("--run-command", cli.run_command.is_some()),
+        ("--run-display-template", cli.run_display_template.is_some()),
+        ("--input", cli.input.is_some()),
+        ("--stdin-file", cli.stdin_file.is_some()),
+        ("--expected", !cli.expected.is_empty()),
+        ("--error-case", !cli.error_case.is_empty()),
+        ("--test", !cli.tests.is_empty()),
+        ("--tests", cli.tests_file.is_some()),
+        ("--timeout", cli.timeout.is_some()),
+        ("--theme", cli.theme.is_some()),
+        ("--line-numbers", cli.line_numbers),
+        ("--wrap", cli.wrap),
+        ("--paginate", cli.paginate),
+        ("--max-lines", cli.max_lines.is_some()),
+        ("--max-cols", cli.max_cols.is_some()),
+        ("--export-scale", cli.export_scale.is_some()),
+        ("--supersample", cli.supersample.is_some()),
+        ("--image-note", cli.image_note.is_some()),
+        ("--output-lines", cli.output_lines.is_some()),
+        ("--chunk-lines", cli.chunk_lines.is_some()),
+        ("--valgrind", cli.valgrind),
+        ("--sanitize", !cli.sanitize.is_empty()),
+        ("--max-memory", cli.max_memory.is_some()),
+        ("--max-output", cli.max_output.is_some()),
+        ("--pty", cli.pty),
+        ("--no-echo-input", cli.no_echo_input),
+        ("--interact", cli.interact.is_some()),
+        ("--windows-shell", cli.windows_shell.is_some()),
+        ("--trust-run-command", cli.trust_run_command),
+        ("--no-auto-tests", cli.no_auto_tests),
+        ("--expect-exit", cli.expect_exit.is_some()),
+        ("--fail-on-nonzero-exit", cli.fail_on_nonzero_exit),
+        ("--include-compile-log", cli.include_compile_log),
+        ("--no-code", cli.no_code),
+        ("--no-screenshot", cli.no_screenshot),
+        ("--no-text-output", cli.no_text_output),
+        ("--no-environment", cli.no_environment),
+        ("--compile-staged", cli.compile_staged),
+        ("--deny-warnings", cli.deny_warnings),
+        ("--compile-note", cli.compile_note),
+        ("--compat", cli.compat.is_some()),
+        ("--format", !cli.format.is_empty()),
+        ("--strict-rtf", cli.strict_rtf),
+        ("--doc-line-numbers", cli.doc_line_numbers),
+        ("--cover-page", cli.cover_page),
+        ("--appendix", cli.appendix),
+        ("--code-image", cli.code_image),
+        ("--transcript-file", cli.transcript_file),
+        ("--qr", cli.qr.is_some()),
+        ("--link", !cli.link.is_empty()),
+        ("--sections", !cli.sections.is_empty()),
+        ("--notes", cli.notes.is_some()),
+        ("--remote", cli.remote.is_some()),
+        ("--no-run", cli.no_run),
+        ("--save-screenshot", cli.save_screenshot.is_some()),
+    ]
+}
+
+/// Every pack flag with whether it was given, so subcommands can refuse
+/// them.
+pub fn pack_flags(cli: &Cli) -> Vec<(&'static str, bool)> {
+    let mut flags = vec![
+        ("--assignment", cli.assignment.is_some()),
+        ("--name", cli.name.is_some()),
+        ("--id", cli.student_id.is_some()),
+        ("--c-file", cli.c_file.is_some()),
+        ("--sources", cli.sources.is_some()),
+        ("--doc-file", cli.doc_file.is_some()),
+        ("--auto-doc", cli.auto_doc),
+        ("--output-dir", cli.output_dir.is_some()),
+        ("--naming", cli.naming.is_some()),
+        ("--line-endings", cli.line_endings.is_some()),
+        ("--lang", cli.lang.is_some()),
+        ("--bin-dir", cli.bin_dir.is_some()),
+        ("--no-watermark", cli.no_watermark),
+        ("--force", cli.force),
+        ("--yes", cli.yes),
+        ("--profile", cli.profile),
+        ("--profile-json", cli.profile_json.is_some()),
+        ("--porcelain", cli.porcelain),
+    ];
+    flags.extend(doc_flags(cli));
+    flags
+}
+
+/// Rejects flags that don't make sense together, before anything runs.
+fn check_flags(cli: &Cli, auto_doc: bool) -> Result<()> {
+    if !auto_doc && let Some((flag, _)) = doc_flags(cli).into_iter().find(|(_, set)| *set) {
+        return Err(Error::Validation(format!("{flag} requires --auto-doc")));
+    }
+    for (flag, value, (lo, hi)) in [
+        ("--max-lines", cli.max_lines, theme::MAX_LINES_RANGE),
+        ("--max-cols", cli.max_cols, theme::MAX_COLS_RANGE),
+    ] {
+        if let Some(value) = value
+            && !(lo..=hi).contains(&value)
+        {
+            return Err(Error::Validation(format!(
+                "{flag} must be between {lo} and {hi}, got {value}"
+            )));
+        }
+    }
+    terminal::sanitize::validate(&cli.sanitize)?;
+    let rtf_only = !cli.format.is_empty() && !cli.format.contains(&DocFormat::Rtf);
+    if rtf_only {
+        let rtf_flags = [
+            ("--strict-rtf", cli.strict_rtf),
+            ("--compat", cli.compat.is_some()),
+            ("--doc-line-numbers", cli.doc_line_numbers),
+            ("--cover-page", cli.cover_page),
+        ];
+        if let Some((flag, _)) = rtf_flags.iter().find(|(_, set)| *set) {
+            return Err(Error::Validation(format!(
+                "{flag} only applies to --format rtf"
+            )));
+        }
+    }
+    if let Some(Some(url)) = &cli.qr {
+        config::Link {
+            label: "--qr".into(),
+            url: url.clone(),
+        }
+        .validate()?;
+    }
+    // Checked here rather than by clap, which can't mix a global flag with
+    // conflicts on pack-only ones
+    if cli.no_exec {
+        let runs = [
+            ("--run-command", cli.run_command.is_some()),
+            ("--compile-staged", cli.compile_staged),
+            ("--valgrind", cli.valgrind),
+            ("--sanitize", !cli.sanitize.is_empty()),
+            ("--save-screenshot", cli.save_screenshot.is_some()),
+            ("--remote", cli.remote.is_some()),
+            ("--interact", cli.interact.is_some()),
+        ];
+        if let Some((flag, _)) = runs.iter().find(|(_, set)| *set) {
+            return Err(Error::Validation(format!(
+                "{flag} can't be combined with --no-exec"
+            )));
+        }
+    }
+    if let Some(Some(path)) = &cli.save_screenshot
+        && !afs::has_extension(path, &["png"])
+    {
+        return Err(Error::Validation(format!(
+            "--save-screenshot should be a .png file, got '{}'",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// A pack with its flags, config and defaults resolved, and where each
+/// output goes.
+struct Plan {
+    assignment: String,
+    num: u32,
+    name: String,
+    student_id: String,
+    sources: Vec<PathBuf>,
+    backend: &'static dyn lang::LanguageBackend,
+    c_file: PathBuf,
+    cwd: PathBuf,
+    auto_doc: bool,
+    no_run: bool,
+    expect_exit: i32,
+    doc_formats: Vec<DocFormat>,
+    doc_stem: String,
+    doc_names: Vec<String>,
+    /// Copied in as the doc when it isn't generated.
+    manual_doc: Option<PathBuf>,
+    run_command: Option<String>,
+    run_tpl: Option<String>,
+    redactor: Redactor,
+    links: Vec<config::Link>,
+    /// Sections in the order they were listed, empty for the default doc.
+    order: Vec<DocSection>,
+    notes: Vec<notes::NoteBlock>,
+    template: Option<rtf::Template>,
+    cases: Vec<terminal::TestCase>,
+    /// Name of the tests file's compile phase, if it has one.
+    compile_phase: Option<String>,
+    limits: terminal::limits::Limits,
+    run_timeout: Option<u64>,
+    bin_dir: Option<PathBuf>,
+    compile_staged: bool,
+    remote_host: Option<String>,
+    theme_name: Option<String>,
+    out_dir: PathBuf,
+    sub_dir: PathBuf,
+    zip_path: PathBuf,
+    doc_dest: PathBuf,
+    transcript_name: String,
+    transcript_dest: PathBuf,
+    screenshot_dests: Vec<PathBuf>,
+}
+
+impl Plan {
+    fn listed(&self, section: DocSection) -> bool {
+        self.order.is_empty() || self.order.contains(&section)
+    }
+}
+
+/// Works out everything about the pack before any of it is written,
+/// failing early on bad flags or missing files.
+fn plan_pack(cli: &Cli, cfg: &config::AppConfig) -> Result<Plan> {
+    // --no-exec packs the same way --no-run does, without a compiler check
+    let no_run = cli.no_run || cli.no_exec;
 
     if cli.auto_doc && cli.doc_file.is_some() {
         return Err(Error::Validation(
@@ -37,51 +507,59 @@ pub fn run_pack(cli: Cli) -> Result<()> {
         ));
     }
 
-    let (assignment, num) = parse_assignment(
+    let parsed = parse_assignment(
         cli.assignment
             .as_deref()
             .ok_or_else(|| Error::Validation("missing --assignment (-a)".into()))?,
+        cfg.assignment_suffixes.as_deref(),
     )?;
+    let (assignment, num) = (parsed.label(), parsed.number);
     let name = clean_name(
         &cli.name
+            .clone()
             .or_else(|| cfg.name.clone())
             .ok_or_else(|| Error::Validation("missing --name (or set in config)".into()))?,
         "name",
     )?;
     let student_id = clean_name(
         &cli.student_id
+            .clone()
             .or_else(|| cfg.student_id.clone())
             .ok_or_else(|| Error::Validation("missing --id (or set in config)".into()))?,
         "student ID",
     )?;
 
-    let c_file = afs::resolve_c_file(cli.c_file.as_deref())?;
-    afs::check_extension(&c_file, &["c"], "C source")?;
+    let sources = match cli.sources.as_deref() {
+        Some(patterns) => {
+            let mut found = afs::resolve_sources(patterns)?;
+            if let Some(main) = cli.c_file.as_deref()
+                && !found.iter().any(|p| afs::paths_equal(p, main))
+            {
+                found.insert(0, main.to_path_buf());
+            }
+            found
+        }
+        None => vec![afs::resolve_c_file(cli.c_file.as_deref())?],
+    };
+    for source in &sources {
+        afs::check_extension(source, &lang::source_extensions(), "source file")?;
+    }
+    let backend = match cli.lang.as_deref() {
+        Some(id) => lang::by_id(id)?,
+        None => lang::for_sources(&sources),
+    };
+    let c_file = match cli.c_file.as_deref() {
+        Some(p) => p.to_path_buf(),
+        None => main_source(&sources)?,
+    };
 
     let auto_doc = cli.auto_doc || (cli.doc_file.is_none() && cfg.auto_doc.unwrap_or(false));
-
-    if !auto_doc && cli.run_command.is_some() {
-        return Err(Error::Validation(
-            "--run-command requires --auto-doc".into(),
-        ));
-    }
-    if !auto_doc && cli.run_display_template.is_some() {
-        return Err(Error::Validation(
-            "--run-display-template requires --auto-doc".into(),
-        ));
-    }
-    if !auto_doc && cli.input.is_some() {
-        return Err(Error::Validation("--input requires --auto-doc".into()));
-    }
-    if !auto_doc && cli.timeout.is_some() {
-        return Err(Error::Validation("--timeout requires --auto-doc".into()));
-    }
-    if !auto_doc && cli.theme.is_some() {
-        return Err(Error::Validation("--theme requires --auto-doc".into()));
-    }
+    check_flags(cli, auto_doc)?;
+    let expect_exit = cli.expect_exit.unwrap_or(0);
 
     let out_dir = cli
         .output_dir
+        .clone()
         .or_else(|| cfg.output_dir.clone())
         .unwrap_or_else(|| PathBuf::from("."));
     if !out_dir.is_dir() {
@@ -93,12 +571,46 @@ pub fn run_pack(cli: Cli) -> Result<()> {
 
     ui::header(&format!(
         "Packing {} for {} ({})",
-        assignment.bold(),
+        ui::bold(&assignment),
         name,
         student_id,
     ));
 
-    let expected_doc = format!("{assignment}_{name}_{student_id}.doc");
+    if hygiene::is_enabled(&cfg.hygiene) {
+        check_hygiene(&sources, &cfg.hygiene)?;
+    }
+
+    let mut doc_formats = if !auto_doc {
+        Vec::new()
+    } else if cli.format.is_empty() {
+        cfg.format.clone()
+    } else {
+        cli.format.clone()
+    };
+    if doc_formats.is_empty() {
+        doc_formats.push(DocFormat::Rtf);
+    }
+    let mut seen = Vec::new();
+    doc_formats.retain(|f| {
+        let first = !seen.contains(f);
+        seen.push(*f);
+        first
+    });
+    let cwd = env::current_dir().map_err(|e| io_err("current directory", e))?;
+    let names = naming::names(
+        cli.naming.or(cfg.naming_preset).unwrap_or_default(),
+        &assignment,
+        &name,
+        &student_id,
+        &cwd,
+    )?;
+    let doc_stem = names.stem;
+    let doc_names: Vec<String> = doc_formats
+        .iter()
+        .map(|f| format!("{doc_stem}.{}", f.extension()))
+        .collect();
+    // The first format is the one history and `ap open` point at
+    let expected_doc = doc_names[0].clone();
     let manual_doc = if auto_doc {
         None
     } else {
@@ -107,32 +619,863 @@ pub fn run_pack(cli: Cli) -> Result<()> {
         Some(path)
     };
 
-    let run_command = if auto_doc {
-        cli.run_command.or_else(|| cfg.run_command.clone())
+    let mut run_command = if auto_doc && !no_run {
+        cli.run_command.clone().or_else(|| cfg.run_command.clone())
     } else {
         None
     };
-    let run_input = if auto_doc {
-        cli.input.or_else(|| cfg.input.clone())
-    } else {
+    // --input is escape-expanded, an input file is piped exactly as written
+    let run_input = if !auto_doc {
         None
+    } else if let Some(text) = &cli.input {
+        Some(terminal::exec::expand_escapes(text))
+    } else if let Some(path) = cli.stdin_file.clone().or_else(|| cfg.stdin_file.clone()) {
+        if !path.is_file() {
+            return Err(Error::Validation(format!(
+                "stdin file not found: '{}'",
+                path.display()
+            )));
+        }
+        Some(afs::read_text_lossy(&path)?)
+    } else {
+        cfg.input.as_deref().map(terminal::exec::expand_escapes)
+    };
+    let redactor = Redactor::new(&cfg.redact)?;
+    let mut links = cfg.link.clone();
+    for link in &links {
+        link.validate()?;
+    }
+    for arg in &cli.link {
+        links.push(config::Link::parse(arg)?);
+    }
+    let order = if cli.sections.is_empty() {
+        cfg.sections.clone()
+    } else {
+        cli.sections.clone()
+    };
+    DocSection::check_list(&order)?;
+    let listed = |section| order.is_empty() || order.contains(&section);
+    let notes = if !auto_doc || !listed(DocSection::Notes) {
+        Vec::new()
+    } else if let Some(path) = &cli.notes {
+        if !path.is_file() {
+            return Err(Error::Validation(format!(
+                "notes file not found: '{}'",
+                path.display()
+            )));
+        }
+        notes::parse(&afs::read_text_lossy(path)?)
+    } else if Path::new(notes::NOTES_FILE).is_file() {
+        ui::step(&format!(
+            "Adding {} to the doc as {}",
+            notes::NOTES_FILE,
+            notes::HEADING
+        ));
+        notes::parse(&afs::read_text_lossy(Path::new(notes::NOTES_FILE))?)
+    } else {
+        Vec::new()
     };
+    let template = match &cfg.doc_template {
+        Some(path) if doc_formats.contains(&DocFormat::Rtf) => {
+            if !path.is_file() {
+                return Err(Error::Validation(format!(
+                    "doc template not found: '{}'",
+                    path.display()
+                )));
+            }
+            if cli.strict_rtf || cfg.strict_rtf.unwrap_or(false) {
+                return Err(Error::Validation(
+                    "strict RTF checks can't read a doc template, turn one of them off".into(),
+                ));
+            }
+            let rtf = afs::read_text_lossy(path)?;
+            rtf::check_template(&rtf)?;
+            Some(rtf::Template {
+                rtf,
+                date: history::date(history::now()),
+            })
+        }
+        _ => None,
+    };
+    let (cases, compile_phase) = resolve_cases(cli, cfg, auto_doc && !no_run, run_input)?;
+    let limits = resolve_limits(cli, cfg)?;
     let run_timeout = if auto_doc {
         cli.timeout.or(cfg.timeout)
     } else {
         None
     };
+    let bin_dir = if auto_doc {
+        cli.bin_dir.clone().or_else(|| cfg.bin_dir.clone())
+    } else {
+        None
+    };
+    let compile_staged = auto_doc && (cli.compile_staged || cfg.compile_staged.unwrap_or(false));
+    if compile_staged && run_command.is_some() {
+        return Err(Error::Validation(
+            "--compile-staged can't be combined with a run command, which builds on its own".into(),
+        ));
+    }
+    let remote_host = if auto_doc && !no_run {
+        cli.remote.clone().or_else(|| cfg.remote.clone())
+    } else {
+        None
+    };
+    if let Some(host) = &remote_host {
+        terminal::remote::check_host(host)?;
+        if run_command.is_some() {
+            return Err(Error::Validation(
+                "--remote can't be combined with a run command, it uses the built-in compile step"
+                    .into(),
+            ));
+        }
+    }
+    if auto_doc
+        && !no_run
+        && remote_host.is_none()
+        && run_command.is_none()
+        && !compile_staged
+        && cfg.run_script != Some(false)
+    {
+        run_command = detect_run_script(cfg.run_script == Some(true))?;
+    }
+    if let Some(cmd) = &run_command {
+        check_run_command(cmd, &sources, cli.trust_run_command)?;
+    }
     let run_tpl = if auto_doc {
         cli.run_display_template
+            .clone()
             .or_else(|| cfg.run_display_template.clone())
     } else {
         None
     };
 
-    let folder = format!("{assignment}_{name}_{student_id}_Submission");
-    let sub_dir = out_dir.join(&folder);
-    let zip_path = out_dir.join(format!("{folder}.zip"));
+    let sub_dir = out_dir.join(&names.folder);
+    let zip_path = out_dir.join(format!("{}.zip", names.folder));
+    let doc_dest = sub_dir.join(&expected_doc);
+    let transcript_name = format!("{doc_stem}_output.txt");
+    let transcript_dest = sub_dir.join(&transcript_name);
+    // One file per run, named after the case when there's more than one
+    let screenshot_dests: Vec<PathBuf> = match &cli.save_screenshot {
+        Some(path) => {
+            let base = path
+                .clone()
+                .unwrap_or_else(|| sub_dir.join(format!("{doc_stem}_screenshot.png")));
+            if cases.len() == 1 {
+                vec![base]
+            } else {
+                cases
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        let n = (i + 1).to_string();
+                        afs::with_suffix(&base, c.name.as_deref().unwrap_or(&n))
+                    })
+                    .collect()
+            }
+        }
+        None => Vec::new(),
+    };
+
+    Ok(Plan {
+        assignment,
+        num,
+        name,
+        student_id,
+        sources,
+        backend,
+        c_file,
+        cwd,
+        auto_doc,
+        no_run,
+        expect_exit,
+        doc_formats,
+        doc_stem,
+        doc_names,
+        manual_doc,
+        run_command,
+        run_tpl,
+        redactor,
+        links,
+        order,
+        notes,
+        template,
+        cases,
+        compile_phase,
+        limits,
+        run_timeout,
+        bin_dir,
+        compile_staged,
+        remote_host,
+        theme_name: cli.theme.clone().or_else(|| cfg.theme.clone()),
+        out_dir,
+        sub_dir,
+        zip_path,
+        doc_dest,
+        transcript_name,
+        transcript_dest,
+        screenshot_dests,
+    })
+}
 
+/// The runs to capture: defined tests, numbered input files found in the
+/// project, or a single run with the piped input or interact script. Error
+/// cases go on the end.
+fn resolve_cases(
+    cli: &Cli,
+    cfg: &config::AppConfig,
+    runs: bool,
+    run_input: Option<String>,
+) -> Result<(Vec<terminal::TestCase>, Option<String>)> {
+    let (mut defined, compile_phase) = cases::resolve(&cli.tests, cli.tests_file.as_deref())?;
+    let script = cli
+        .interact
+        .as_deref()
+        .map(terminal::interact::load)
+        .transpose()?;
+    let auto_tests = !cli.no_auto_tests && cfg.auto_tests.unwrap_or(true);
+    if runs && auto_tests && defined.is_empty() && run_input.is_none() && script.is_none() {
+        let cwd = env::current_dir().map_err(|e| io_err("current directory", e))?;
+        if let Some((dir, found)) = cases::discover(&cwd)? {
+            ui::step(&format!(
+                "Found {} numbered input files in {}, running each as a test",
+                found.len(),
+                afs::file_name(&dir)?
+            ));
+            defined = found;
+        }
+    }
+    let mut cases = if defined.is_empty() {
+        vec![terminal::TestCase {
+            name: None,
+            input: run_input,
+            expected: None,
+            script,
+            error_case: false,
+        }]
+    } else {
+        defined
+    };
+    cases::apply_expected(&mut cases, &cli.expected)?;
+    for (i, input) in cli.error_case.iter().enumerate() {
+        let name = if cli.error_case.len() == 1 {
+            "error case".to_string()
+        } else {
+            format!("error case {}", i + 1)
+        };
+        cases.push(terminal::TestCase {
+            name: Some(name),
+            input: Some(terminal::exec::expand_escapes(input)),
+            expected: None,
+            script: None,
+            error_case: true,
+        });
+    }
+    Ok((cases, compile_phase))
+}
+
+fn resolve_limits(cli: &Cli, cfg: &config::AppConfig) -> Result<terminal::limits::Limits> {
+    let max_memory = match cli.max_memory {
+        Some(size) => Some(size),
+        None => config_size("max_memory", cfg.max_memory.as_deref())?,
+    };
+    let max_output = match cli.max_output {
+        Some(size) => Some(size),
+        None => config_size("max_output", cfg.max_output.as_deref())?,
+    };
+    let limits = terminal::limits::Limits {
+        max_memory,
+        max_output: max_output.map_or(terminal::limits::DEFAULT_MAX_OUTPUT, |m| m as usize),
+    };
+    if limits.max_memory.is_some() {
+        if cfg!(windows) {
+            ui::warn("--max-memory isn't supported on Windows, running without a memory limit");
+        } else if cli.sanitize.iter().any(|s| s != "undefined") {
+            // ASan and friends reserve terabytes of address space up front
+            return Err(Error::Validation(
+                "--max-memory only works with the undefined sanitizer".into(),
+            ));
+        }
+    }
+    Ok(limits)
+}
+
+/// Copies the project into the submission folder, bringing in any sources
+/// from elsewhere. Returns the files renamed to avoid a case clash.
+fn copy_files(
+    plan: &Plan,
+    past: &history::History,
+    line_endings: LineEndings,
+) -> Result<Vec<(String, String)>> {
+    let sub_dir = &plan.sub_dir;
+    let mut reserved = Vec::new();
+    if plan.auto_doc {
+        reserved.extend(plan.doc_names.iter().cloned());
+        reserved.push(plan.transcript_name.clone());
+        for dest in &plan.screenshot_dests {
+            if dest.parent() == Some(sub_dir.as_path()) {
+                reserved.push(afs::file_name(dest)?.to_string());
+            }
+        }
+    }
+    // A leftover test binary from a project-local --bin-dir shouldn't end
+    // up in the submission
+    let mut skip = past.artifact_names();
+    skip.push(terminal::LOCAL_BIN_NAME.to_string());
+    let report = afs::copy_non_binary_files(&plan.cwd, sub_dir, &skip, &reserved, line_endings)?;
+    if !report.skipped.is_empty() {
+        ui::step(&format!(
+            "Skipped earlier generated files: {}",
+            report.skipped.join(", ")
+        ));
+    }
+    for (from, to) in &report.renamed {
+        ui::warn(&format!(
+            "'{from}' only differs by case from another file, copied as '{to}'"
+        ));
+    }
+
+    for source in &plan.sources {
+        let src_name = afs::file_name(source)?;
+        let dest = sub_dir.join(src_name);
+        if afs::is_in_dir(source, &plan.cwd) {
+            continue;
+        }
+        if dest.exists() {
+            return Err(Error::Validation(format!(
+                "'{src_name}' exists in both the current directory and '{}', rename one of them",
+                source.parent().unwrap_or(Path::new(".")).display()
+            )));
+        }
+        afs::copy_text(source, &dest, line_endings)
+            .map_err(|e| io_err(format!("copying {}", source.display()), e))?;
+    }
+    Ok(report.renamed)
+}
+
+/// What the capture and render stages found, reported once the zip is
+/// written.
+#[derive(Default)]
+struct Outcome {
+    compile_diagnostics: Option<terminal::format::Diagnostics>,
+    run_ms: Option<u64>,
+    warning_flags: Vec<String>,
+    check_results: Vec<(Option<String>, bool)>,
+    memchecks: Vec<(Option<String>, String, bool)>,
+    sanitizer_hits: Vec<Option<String>>,
+    unexpected_exits: Vec<(Option<String>, String)>,
+    saved_screenshots: Vec<PathBuf>,
+    attachments: Vec<PathBuf>,
+}
+
+/// The program's runs, ready to render.
+struct Captured {
+    capture: terminal::RunCapture,
+    /// Why nothing ran, when it didn't.
+    not_run: Option<String>,
+    /// One per case, for those with expected output.
+    checks: Vec<Option<diff::Comparison>>,
+}
+
+/// Compiles and runs the program for every case, or notes why it didn't,
+/// then checks the results against what was expected.
+fn capture_runs(
+    cli: &Cli,
+    cfg: &config::AppConfig,
+    plan: &Plan,
+    sources: &[PathBuf],
+    remote: Option<terminal::remote::Remote>,
+    profile: &mut Profile,
+    outcome: &mut Outcome,
+) -> Result<Captured> {
+    let display_cmd = render_display_command(
+        plan.run_tpl.as_deref(),
+        &plan.assignment,
+        plan.num,
+        &plan.name,
+        &plan.student_id,
+        &plan.c_file,
+    )?;
+
+    let valgrind = cli.valgrind || cfg.valgrind.unwrap_or(false);
+    if valgrind && cli.sanitize.iter().any(|s| s == "address" || s == "thread") {
+        return Err(Error::Validation(
+            "--valgrind can't run a binary built with the address or thread sanitizer".into(),
+        ));
+    }
+    // Without a compiler the doc still gets the listing, and says why
+    // there's no output
+    let skip_flag = if cli.no_exec { "--no-exec" } else { "--no-run" };
+    let not_run = plan.no_run.then(|| {
+        if cli.no_exec || plan.backend.detect().is_some() {
+            format!("skipped with {skip_flag}")
+        } else {
+            format!("no {} compiler found", plan.backend.name())
+        }
+    });
+    let mut capture = if not_run.is_some() {
+        ui::step(&format!("Skipping compile and run ({skip_flag})"));
+        terminal::RunCapture::skipped(display_cmd)
+    } else {
+        terminal::capture_run(&terminal::RunOptions {
+            sources,
+            lang: plan.backend,
+            run_command: plan.run_command.as_deref(),
+            display_command: &display_cmd,
+            cases: &plan.cases,
+            timeout: plan.run_timeout,
+            deny_warnings: cli.deny_warnings || cfg.deny_warnings.unwrap_or(false),
+            bin_dir: plan.bin_dir.as_deref(),
+            valgrind,
+            sanitize: &cli.sanitize,
+            limits: plan.limits,
+            pty: cli.pty || cfg.pty.unwrap_or(false),
+            echo_input: !cli.no_echo_input && cfg.echo_input.unwrap_or(true),
+            remote: remote.as_ref(),
+            shell: cli.windows_shell.or(cfg.windows_shell).unwrap_or_default(),
+        })?
+    };
+    // Cleans up the remote folder
+    drop(remote);
+    if let Some(t) = capture.compile_time {
+        profile.record("compile", t);
+    }
+    outcome.compile_diagnostics = capture.compile.as_ref().map(|c| c.diagnostics);
+    profile.set_diagnostics(outcome.compile_diagnostics);
+    if let Some(info) = &capture.compile {
+        outcome.warning_flags = terminal::format::warning_flags(&info.log);
+    }
+    if not_run.is_none() {
+        outcome.run_ms = Some(capture.run_time.as_millis() as u64);
+        profile.record("run", capture.run_time);
+    }
+    plan.redactor.capture(&mut capture);
+
+    let fail_on_nonzero = cli.fail_on_nonzero_exit || cfg.fail_on_nonzero_exit.unwrap_or(false);
+    for run in &capture.cases {
+        if run.exit_code == Some(plan.expect_exit) || run.error_case {
+            continue;
+        }
+        let which = run
+            .name
+            .as_deref()
+            .map(|n| format!(" in '{n}'"))
+            .unwrap_or_default();
+        let status = terminal::format::describe_exit(run.exit_code, plan.expect_exit);
+        if cli.expect_exit.is_some() || fail_on_nonzero {
+            return Err(Error::Validation(format!(
+                "program exit code{which} was {status}"
+            )));
+        }
+        outcome.unexpected_exits.push((run.name.clone(), status));
+    }
+
+    let checks: Vec<Option<diff::Comparison>> = capture
+        .cases
+        .iter()
+        .zip(&plan.cases)
+        .map(|(run, case)| {
+            // Redacted the same as the output, so the diff in the doc
+            // doesn't leak from either side
+            case.expected
+                .as_deref()
+                .map(|exp| diff::compare(&plan.redactor.apply(exp), &run.stdout))
+        })
+        .collect();
+    outcome.check_results = capture
+        .cases
+        .iter()
+        .zip(&checks)
+        .filter_map(|(run, check)| Some((run.name.clone(), check.as_ref()?.passed)))
+        .collect();
+
+    outcome.memchecks = capture
+        .cases
+        .iter()
+        .filter_map(|run| {
+            let m = run.memcheck.as_ref()?;
+            Some((run.name.clone(), m.summary(), m.is_clean()))
+        })
+        .collect();
+
+    outcome.sanitizer_hits = capture
+        .cases
+        .iter()
+        .filter(|run| run.sanitizer_report.as_ref().is_some_and(|r| !r.is_empty()))
+        .map(|run| run.name.clone())
+        .collect();
+
+    Ok(Captured {
+        capture,
+        not_run,
+        checks,
+    })
+}
+
+/// Renders the screenshots and images, then writes the transcript and a
+/// doc in each format.
+fn write_docs(
+    cli: &Cli,
+    cfg: &config::AppConfig,
+    plan: &mut Plan,
+    mut source_files: Vec<doc::SourceFile>,
+    captured: &Captured,
+    profile: &mut Profile,
+    outcome: &mut Outcome,
+) -> Result<()> {
+    let capture = &captured.capture;
+    let appendix = cli.appendix || cfg.appendix.unwrap_or(false);
+    if appendix {
+        // The main file is the one kept under Code
+        let main_name = afs::file_name(&plan.c_file)?;
+        if let Some(at) = source_files.iter().position(|s| s.name == main_name) {
+            let main = source_files.remove(at);
+            source_files.insert(0, main);
+        }
+    }
+    let mut theme = theme::resolve(plan.theme_name.as_deref(), cfg.theme_namespace.as_deref())?;
+    theme.line_numbers |= cli.line_numbers;
+    theme.max_lines = cli.max_lines.unwrap_or(theme.max_lines);
+    theme.max_cols = cli.max_cols.unwrap_or(theme.max_cols);
+    theme.wrap |= cli.wrap;
+    let compat = cli.compat.or(cfg.compat).unwrap_or_default();
+    let mut export_scale = cli
+        .export_scale
+        .or(cfg.export_scale)
+        .unwrap_or(1)
+        .clamp(1, 3);
+    if compat == DocCompat::Gdocs && export_scale > 1 && plan.doc_formats.contains(&DocFormat::Rtf)
+    {
+        ui::warn(
+            "Google Docs can show images drawn at a larger scale too big, so --compat gdocs embeds them at 1x",
+        );
+        export_scale = 1;
+    }
+    let mut theme = theme.scaled(export_scale);
+    theme.supersample = cli.supersample.or(cfg.supersample).unwrap_or(1).clamp(1, 4);
+    let dpi = png_meta::BASE_DPI * export_scale;
+    let image_note = cli.image_note.as_deref().or(cfg.image_note.as_deref());
+    let paginate = cli.paginate || cfg.paginate.unwrap_or(false);
+    // A sections list says which parts are wanted, though the flags can
+    // still add or drop one for a run
+    let sections = if plan.order.is_empty() {
+        doc::Sections {
+            header: true,
+            code: !cli.no_code && cfg.include_code.unwrap_or(true),
+            screenshot: !cli.no_screenshot && cfg.include_screenshot.unwrap_or(true),
+            text_output: !cli.no_text_output && cfg.include_text_output.unwrap_or(true),
+            compile_log: cli.include_compile_log || cfg.include_compile_log.unwrap_or(false),
+            environment: !cli.no_environment && cfg.include_environment.unwrap_or(true),
+            runs: true,
+        }
+    } else {
+        doc::Sections {
+            header: plan.listed(DocSection::Header),
+            code: !cli.no_code && plan.listed(DocSection::Code),
+            screenshot: !cli.no_screenshot && plan.listed(DocSection::Screenshot),
+            text_output: !cli.no_text_output && plan.listed(DocSection::Output),
+            compile_log: cli.include_compile_log || plan.listed(DocSection::CompileLog),
+            // It's written with each run, so goes when the runs do
+            environment: !cli.no_environment
+                && cfg.include_environment.unwrap_or(true)
+                && (plan.listed(DocSection::Screenshot) || plan.listed(DocSection::Output)),
+            runs: plan.listed(DocSection::Screenshot) || plan.listed(DocSection::Output),
+        }
+    };
+    let pngs = profile.time("render screenshot", || {
+        capture
+            .cases
+            .iter()
+            .map(|c| {
+                if !sections.screenshot && plan.screenshot_dests.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let title = &capture.command_display;
+                let pages = if paginate {
+                    screenshot::render_pages(&c.screenshot, title, &theme)?
+                } else {
+                    vec![screenshot::render_segments(&c.screenshot, title, &theme)?]
+                };
+                pages
+                    .iter()
+                    .map(|png| png_meta::scrub(png, image_note, dpi))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+    let code_images = if sections.code && (cli.code_image || cfg.code_image.unwrap_or(false)) {
+        profile.time("render code", || {
+            code_images(&source_files, &theme, image_note, dpi)
+        })?
+    } else {
+        Vec::new()
+    };
+    let compile_png = match (&plan.compile_phase, &capture.compile) {
+        (Some(_), Some(info)) => {
+            let log = if info.log.is_empty() {
+                "(no output)"
+            } else {
+                &info.log
+            };
+            let text = format!("$ {}\n\n{log}", info.command);
+            let png = screenshot::render_png(&text, &info.command, &theme)?;
+            Some(png_meta::scrub(&png, image_note, dpi)?)
+        }
+        (Some(name), None) => {
+            ui::warn(&format!(
+                "phase '{name}' needs the built-in compile step, leaving it out"
+            ));
+            None
+        }
+        (None, _) => None,
+    };
+    let qr = match &cli.qr {
+        Some(target) => {
+            let (data, caption) = match target {
+                Some(url) => (url.clone(), format!("Scan to open {url}")),
+                None => (
+                    qr::hash_listing(
+                        source_files
+                            .iter()
+                            .map(|s| (s.name.as_str(), s.sha256.as_str())),
+                    ),
+                    "Scan for the SHA-256 of each source file".to_string(),
+                ),
+            };
+            match qr::render_qr(&data) {
+                Ok(png) => {
+                    let png = png_meta::scrub(&png, image_note, png_meta::BASE_DPI)?;
+                    Some((png, caption))
+                }
+                Err(e) => {
+                    ui::warn(&format!("leaving out the QR code: {e}"));
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    profile.mark_render_peak();
+
+    for (pages, dest) in pngs.iter().zip(&plan.screenshot_dests) {
+        if let Some(parent) = dest.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| io_err(format!("creating {}", parent.display()), e))?;
+        }
+        for (i, png) in pages.iter().enumerate() {
+            let dest = if pages.len() == 1 {
+                dest.clone()
+            } else {
+                afs::with_suffix(dest, &(i + 1).to_string())
+            };
+            fs::write(&dest, png).map_err(|e| io_err(format!("writing {}", dest.display()), e))?;
+            outcome.saved_screenshots.push(dest);
+        }
+    }
+
+    let transcript_file = cli.transcript_file || cfg.transcript_file.unwrap_or(false);
+    if transcript_file {
+        fs::write(&plan.transcript_dest, transcript_text(capture))
+            .map_err(|e| io_err(format!("writing {}", plan.transcript_dest.display()), e))?;
+    }
+
+    ui::step("Generating doc...");
+    let doc_started = Instant::now();
+    let doc_opts = doc::DocOptions {
+        assignment: &plan.assignment,
+        name: &plan.name,
+        student_id: &plan.student_id,
+        sources: &source_files,
+        capture,
+        not_run: captured.not_run.as_deref(),
+        screenshots: &pngs,
+        checks: &captured.checks,
+        watermark: !cli.no_watermark && cfg.watermark.unwrap_or(true),
+        output_cap: cli
+            .output_lines
+            .or(cfg.output_lines)
+            .unwrap_or(DEFAULT_OUTPUT_LINES),
+        transcript_name: transcript_file.then_some(plan.transcript_name.as_str()),
+        compile_note: cli.compile_note || cfg.compile_note.unwrap_or(false),
+        code_images: &code_images,
+        image_scale: export_scale,
+        compile_phase: plan.compile_phase.as_deref().zip(compile_png.as_deref()),
+        sections,
+        sanitize: &cli.sanitize,
+        expect_exit: plan.expect_exit,
+        links: &plan.links,
+        qr: qr
+            .as_ref()
+            .map(|(png, caption)| (png.as_slice(), caption.as_str())),
+        notes: &plan.notes,
+        appendix,
+    };
+    let mut template = plan.template.take();
+    for (format, file) in plan.doc_formats.iter().zip(&plan.doc_names) {
+        let builder: Box<dyn DocBuilder> = match format {
+            DocFormat::Rtf => Box::new(rtf::RtfBuilder {
+                chunk_lines: cli
+                    .chunk_lines
+                    .or(cfg.chunk_lines)
+                    .unwrap_or(rtf::DEFAULT_CHUNK_LINES),
+                compat,
+                strict: cli.strict_rtf || cfg.strict_rtf.unwrap_or(false),
+                line_numbers: cli.doc_line_numbers || cfg.doc_line_numbers.unwrap_or(false),
+                cover: (cli.cover_page || cfg.cover_page.unwrap_or(false)).then(|| rtf::Cover {
+                    course: naming::course(&plan.cwd),
+                    date: history::date(history::now()),
+                }),
+                body_font: cfg.doc_font.clone(),
+                code_font: cfg.doc_code_font.clone(),
+                code_size: cfg.doc_code_size.unwrap_or(rtf::DEFAULT_CODE_SIZE),
+                page: cfg.page.unwrap_or_default(),
+                margin: cfg.margin.unwrap_or(rtf::DEFAULT_MARGIN_CM),
+                template: template.take(),
+                order: plan.order.clone(),
+            }),
+            DocFormat::Pdf => Box::new(pdf::PdfBuilder),
+            DocFormat::Md => Box::new(report::ReportBuilder {
+                flavor: report::Flavor::Markdown,
+                image_stem: &plan.doc_stem,
+            }),
+            DocFormat::Html => Box::new(report::ReportBuilder {
+                flavor: report::Flavor::Html,
+                image_stem: &plan.doc_stem,
+            }),
+        };
+        let built = builder.build(&doc_opts)?;
+        for (name, bytes) in &built.attachments {
+            let dest = plan.sub_dir.join(name);
+            fs::write(&dest, bytes)
+                .map_err(|e| io_err(format!("writing {}", dest.display()), e))?;
+            outcome.attachments.push(dest);
+        }
+        let dest = plan.sub_dir.join(file);
+        fs::write(&dest, built.bytes)
+            .map_err(|e| io_err(format!("writing {}", dest.display()), e))?;
+    }
+    profile.record("doc", doc_started.elapsed());
+    Ok(())
+}
+
+/// Sums up the build and the checks once the submission is ready.
+fn print_results(cli: &Cli, plan: &Plan, outcome: &Outcome) {
+    if plan.auto_doc && plan.no_run {
+        ui::warn("packed without running, the doc has no program output");
+    }
+    if let Some(d) = outcome.compile_diagnostics {
+        if d.warnings > 0 {
+            ui::warn(&format!("compiled with {}", d.summary()));
+        } else {
+            ui::success("Compiled with no warnings");
+        }
+    }
+    for (name, summary, clean) in &outcome.memchecks {
+        let which = name
+            .as_deref()
+            .map(|n| format!(" ({n})"))
+            .unwrap_or_default();
+        if *clean {
+            ui::success(&format!("Valgrind{which}: {summary}"));
+        } else {
+            ui::warn(&format!("valgrind{which}: {summary}"));
+        }
+    }
+    for (name, status) in &outcome.unexpected_exits {
+        let which = name
+            .as_deref()
+            .map(|n| format!(" for '{n}'"))
+            .unwrap_or_default();
+        ui::warn(&format!(
+            "exit code{which} was {status}, pass --expect-exit if that's intended"
+        ));
+    }
+    for name in &outcome.sanitizer_hits {
+        let which = name
+            .as_deref()
+            .map(|n| format!(" for '{n}'"))
+            .unwrap_or_default();
+        ui::warn(&format!(
+            "sanitizer reported errors{which}, see the doc's report"
+        ));
+    }
+    if !cli.sanitize.is_empty() && outcome.sanitizer_hits.is_empty() && plan.auto_doc {
+        ui::success("Sanitizers reported no issues");
+    }
+    let results = &outcome.check_results;
+    if !results.is_empty() {
+        let passed = results.iter().filter(|(_, ok)| *ok).count();
+        if passed == results.len() {
+            ui::success(&format!(
+                "Output matched expected ({passed}/{})",
+                results.len()
+            ));
+        } else {
+            for (name, _) in results.iter().filter(|(_, ok)| !ok) {
+                let which = name
+                    .as_deref()
+                    .map(|n| format!(" for '{n}'"))
+                    .unwrap_or_default();
+                ui::warn(&format!(
+                    "output differs from expected{which}, see the doc's diff"
+                ));
+            }
+        }
+    }
+}
+
+pub fn run_pack(cli: Cli) -> Result<()> {
+    run_pack_asking(cli, "Pack it?")
+}
+
+/// [`run_pack`], with `question` asked under the summary instead of the
+/// usual one.
+pub fn run_pack_asking(cli: Cli, question: &str) -> Result<()> {
+    ui::set_porcelain(cli.porcelain);
+    if cli.no_exec {
+        terminal::exec::forbid();
+    }
+    let cfg_path = config::config_path()?;
+    let cfg = config::load(&cfg_path)?;
+    let plan = plan_pack(&cli, &cfg)?;
+
+    if !cli.yes && cfg.confirm != Some(false) {
+        let summary = Summary {
+            assignment: &plan.assignment,
+            name: &plan.name,
+            student_id: &plan.student_id,
+            sources: &plan.sources,
+            theme: plan
+                .auto_doc
+                .then(|| plan.theme_name.as_deref().unwrap_or("default")),
+            docs: if plan.auto_doc { &plan.doc_names } else { &[] },
+            folder: &plan.sub_dir,
+            zip: &plan.zip_path,
+            question,
+        };
+        if !confirm_pack(&summary)? {
+            ui::step("Nothing was written");
+            return Ok(());
+        }
+    }
+
+    // Timings are reported for a failed pack too, since slow failures are
+    // what they're most useful for
+    let mut profile = Profile::default();
+    let packed = pack(&cli, &cfg, plan, &mut profile);
+    if cli.profile {
+        ui::blank();
+        profile.print();
+    }
+    let written = match &cli.profile_json {
+        Some(path) => profile.write_json(path),
+        None => Ok(()),
+    };
+    packed.and(written)
+}
+
+/// Everything from creating the submission folder to recording the pack.
+fn pack(cli: &Cli, cfg: &config::AppConfig, mut plan: Plan, profile: &mut Profile) -> Result<()> {
+    let (sub_dir, zip_path) = (plan.sub_dir.clone(), plan.zip_path.clone());
     afs::prepare_output(&sub_dir, &zip_path, cli.force)?;
     fs::create_dir_all(&sub_dir)
         .map_err(|e| io_err(format!("creating {}", sub_dir.display()), e))?;
@@ -143,78 +1486,175 @@ pub fn run_pack(cli: Cli) -> Result<()> {
         armed: true,
     };
 
+    // Originals are only ever read. Hashing them up front lets us check the
+    // staged copies match them and notice if something touched them
+    let line_endings = cli.line_endings.or(cfg.line_endings).unwrap_or_default();
+    let source_hashes = hash_sources(&plan.sources, line_endings)?;
+
     ui::step("Copying files...");
-    let c_name = afs::file_name(&c_file)?;
-    let cwd = env::current_dir().map_err(|e| io_err("current directory", e))?;
-    afs::copy_non_binary_files(&cwd, &sub_dir)?;
+    let history_path = history::history_path()?;
+    let past = history::load(&history_path).unwrap_or_else(|e| {
+        ui::warn(&format!("ignoring history: {e}"));
+        history::History::default()
+    });
+    let renamed = profile.time("copy", || copy_files(&plan, &past, line_endings))?;
 
-    let c_dest = sub_dir.join(c_name);
-    let c_in_cwd = c_file
-        .parent()
-        .is_some_and(|p| fs::canonicalize(p).ok() == fs::canonicalize(&cwd).ok());
-    if !c_in_cwd {
-        fs::copy(&c_file, &c_dest)
-            .map_err(|e| io_err(format!("copying {}", c_file.display()), e))?;
+    let staged = plan
+        .sources
+        .iter()
+        .map(|p| {
+            let name = afs::file_name(p)?;
+            let staged = renamed
+                .iter()
+                .find(|(from, _)| from == name)
+                .map_or(name, |(_, to)| to.as_str());
+            Ok(sub_dir.join(staged))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (path, hash) in staged.iter().zip(&source_hashes) {
+        if path.exists()
+            && hash_sources(std::slice::from_ref(path), line_endings)?[0].sha256 != hash.sha256
+        {
+            return Err(Error::Validation(format!(
+                "staged copy of '{}' doesn't match the original",
+                hash.file
+            )));
+        }
     }
+    // A remote build always uses the staged copies, they're what gets sent
+    let remote = match &plan.remote_host {
+        Some(host) => {
+            let units = staged
+                .iter()
+                .filter(|p| !lang::is_header(p))
+                .map(|p| afs::file_name(p).map(String::from))
+                .collect::<Result<Vec<_>>>()?;
+            Some(terminal::remote::Remote::stage(
+                host,
+                &sub_dir,
+                &units,
+                plan.backend,
+                plan.limits.max_memory,
+            )?)
+        }
+        None => None,
+    };
+    // Building from the staged copies guarantees the screenshot and listing
+    // match exactly what goes into the zip
+    let sources = if plan.compile_staged {
+        staged
+    } else {
+        plan.sources.clone()
+    };
 
-    let doc_dest = sub_dir.join(&expected_doc);
-    if auto_doc {
-        let display_cmd = render_display_command(
-            run_tpl.as_deref(),
-            &assignment,
-            num,
-            &name,
-            &student_id,
-            &c_file,
-        )?;
-
-        let capture = terminal::capture_run(
-            &c_file,
-            run_command.as_deref(),
-            &display_cmd,
-            run_input.as_deref(),
-            run_timeout,
-        )?;
-
+    let mut outcome = Outcome::default();
+    if plan.auto_doc {
+        let captured = capture_runs(cli, cfg, &plan, &sources, remote, profile, &mut outcome)?;
         ui::step("Rendering screenshot...");
-        let code = afs::read_text_lossy(&c_file)?;
-        let theme_name = cli.theme.as_deref().or(cfg.theme.as_deref());
-        let theme = theme::resolve(theme_name)?;
-        let png = screenshot::render_png(&capture.screenshot_text, &theme)?;
-
-        ui::step("Generating doc...");
-        let doc = rtf::build_rtf(&rtf::RtfOptions {
-            assignment: &assignment,
-            name: &name,
-            student_id: &student_id,
-            c_file_name: c_name,
-            code: &code,
-            capture: &capture,
-            screenshot_png: &png,
-            watermark: !cli.no_watermark && cfg.watermark.unwrap_or(true),
-        })?;
-        fs::write(&doc_dest, doc)
-            .map_err(|e| io_err(format!("writing {}", doc_dest.display()), e))?;
-    } else if let Some(src) = manual_doc {
-        if afs::paths_equal(&src, &doc_dest) {
+        let source_files = sources
+            .iter()
+            .zip(&source_hashes)
+            .map(|(p, hash)| {
+                Ok(doc::SourceFile {
+                    name: hash.file.clone(),
+                    code: afs::read_text_lossy(p)?,
+                    sha256: hash.sha256.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        write_docs(
+            cli,
+            cfg,
+            &mut plan,
+            source_files,
+            &captured,
+            profile,
+            &mut outcome,
+        )?;
+    } else if let Some(src) = &plan.manual_doc {
+        if afs::paths_equal(src, &plan.doc_dest) {
             return Err(Error::Validation(
                 "doc source and destination resolve to the same file".into(),
             ));
         }
-        fs::copy(&src, &doc_dest).map_err(|e| io_err(format!("copying {}", src.display()), e))?;
+        fs::copy(src, &plan.doc_dest)
+            .map_err(|e| io_err(format!("copying {}", src.display()), e))?;
     } else {
         ui::warn("no .doc included, pass --auto-doc or --doc-file");
     }
 
+    check_portable_tree(&sub_dir)?;
+
     ui::step("Zipping...");
-    afs::create_zip(&sub_dir, &zip_path)?;
+    profile.time("zip", || afs::create_zip(&sub_dir, &zip_path))?;
 
-    eprintln!();
-    ui::success(&format!("Created {}", sub_dir.display()));
-    ui::success(&format!("Zipped  {}", zip_path.display()));
-    if auto_doc {
-        ui::success(&format!("Doc     {}", doc_dest.display()));
+    for (changed, _) in hash_sources(&plan.sources, line_endings)?
+        .iter()
+        .zip(&source_hashes)
+        .filter(|(now, then)| now.sha256 != then.sha256)
+    {
+        ui::warn(&format!(
+            "'{}' changed while packing, the submission has the earlier version",
+            changed.file
+        ));
+    }
+
+    ui::blank();
+    ui::success("Submission ready");
+    print_results(cli, &plan, &outcome);
+    ui::tree(&ui::TreeNode {
+        label: format!("{}/", plan.out_dir.display()),
+        children: vec![dir_tree(&sub_dir)?, file_node(&zip_path)?],
+    });
+    let mut artifacts = vec![afs::file_name(&zip_path)?.to_string()];
+    if plan.auto_doc {
+        artifacts.extend(plan.doc_names.iter().cloned());
+    }
+    if plan.transcript_dest.exists() {
+        artifacts.push(plan.transcript_name.clone());
+    }
+    for dest in outcome.saved_screenshots.iter().chain(&outcome.attachments) {
+        artifacts.push(afs::file_name(dest)?.to_string());
+    }
+    let doc_dest = &plan.doc_dest;
+    let entry = history::Entry {
+        timestamp: history::now(),
+        assignment: plan.assignment.clone(),
+        number: plan.num,
+        name: plan.name.clone(),
+        student_id: plan.student_id.clone(),
+        source_dir: plan.cwd.clone(),
+        submission_dir: fs::canonicalize(&sub_dir).unwrap_or_else(|_| sub_dir.clone()),
+        zip: fs::canonicalize(&zip_path).unwrap_or_else(|_| zip_path.clone()),
+        doc: doc_dest
+            .exists()
+            .then(|| fs::canonicalize(doc_dest).unwrap_or_else(|_| doc_dest.clone())),
+        artifacts,
+        sources: source_hashes,
+        run_ms: outcome.run_ms,
+        warnings: outcome.warning_flags,
+        diagnostics: outcome.compile_diagnostics,
+    };
+    if let Err(e) = history::record(entry) {
+        ui::warn(&format!("couldn't update history: {e}"));
+    }
+
+    ui::artifact("dir", &sub_dir);
+    for dest in plan.doc_names.iter().map(|n| sub_dir.join(n)) {
+        if dest.exists() {
+            ui::artifact("doc", &dest);
+        }
+    }
+    if plan.transcript_dest.exists() {
+        ui::artifact("transcript", &plan.transcript_dest);
+    }
+    for dest in &outcome.saved_screenshots {
+        ui::artifact("screenshot", dest);
+    }
+    for dest in &outcome.attachments {
+        ui::artifact("image", dest);
     }
+    ui::artifact("zip", &zip_path);
 
     guard.armed = false;
     Ok(())
@@ -0,0 +1,125 @@
+//! Pages compile errors that don't fit on the screen, through `$PAGER` or a
+//! small built-in pager, so the first error doesn't scroll out of sight.
+
+use crate::terminal::{exec, format};
+use crate::ui;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Shows a failed compile's `log` a screen at a time, with the first error
+/// highlighted and its place repeated after. Returns false without showing
+/// anything when the log fits, or there's no terminal to page in.
+pub fn compile_errors(log: &str) -> bool {
+    if !ui::is_interactive() || !io::stdout().is_terminal() || !io::stderr().is_terminal() {
+        return false;
+    }
+    let Some(rows) = terminal_rows() else {
+        return false;
+    };
+    let lines: Vec<&str> = log.lines().collect();
+    // Room for the error line above and the hint below
+    if lines.len() + 2 <= rows {
+        return false;
+    }
+
+    let first = format::first_error(log);
+    let text: Vec<String> = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| match first {
+            Some((at, _)) if at == i => ui::alert(line),
+            _ => line.to_string(),
+        })
+        .collect();
+    ui::error(&format!("compile failed ({} lines of output)", lines.len()));
+    let paged = env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty() && !exec::is_forbidden())
+        .is_some_and(|pager| external(&pager, &text.join("\n")).is_ok());
+    if !paged {
+        builtin(&text, rows);
+    }
+    match first {
+        Some((_, Some(place))) => ui::step(&format!("First error at {place}")),
+        Some((_, None)) => ui::step("First error is highlighted above"),
+        None => {}
+    }
+    true
+}
+
+/// Pipes `text` into the user's pager. `less` is asked to keep the colors
+/// unless `LESS` already says how it should behave.
+fn external(pager: &str, text: &str) -> io::Result<()> {
+    let argv = shlex::split(pager)
+        .filter(|a| !a.is_empty())
+        .ok_or_else(|| io::Error::other("PAGER can't be parsed"))?;
+    let mut cmd = Command::new(&argv[0]);
+    cmd.args(&argv[1..]).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        cmd.env("LESS", "R");
+    }
+    let mut child = cmd.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager early closes the pipe, which is fine
+        let _ = writeln!(stdin, "{text}");
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// Prints a screenful at a time, asking before each next one.
+fn builtin(lines: &[String], rows: usize) {
+    let page = rows.saturating_sub(2).max(5);
+    let mut start = 0;
+    while start < lines.len() {
+        let end = (start + page).min(lines.len());
+        for line in &lines[start..end] {
+            eprintln!("{line}");
+        }
+        if end == lines.len() {
+            break;
+        }
+        let label = format!(
+            "-- {end}/{} lines, Enter for more, q to stop --",
+            lines.len()
+        );
+        match ui::prompt(&ui::dim(&label)) {
+            Ok(answer) if !answer.eq_ignore_ascii_case("q") => start = end,
+            _ => break,
+        }
+    }
+}
+
+/// Height of the terminal, from `LINES` or by asking the terminal.
+fn terminal_rows() -> Option<usize> {
+    if let Some(lines) = env::var("LINES").ok().and_then(|l| l.parse().ok())
+        && lines > 0
+    {
+        return Some(lines);
+    }
+    #[cfg(unix)]
+    {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+            && size.ws_row > 0
+        {
+            return Some(usize::from(size.ws_row));
+        }
+        None
+    }
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::System::Console::{
+            CONSOLE_SCREEN_BUFFER_INFO, GetConsoleScreenBufferInfo, GetStdHandle, STD_ERROR_HANDLE,
+        };
+        let mut info: CONSOLE_SCREEN_BUFFER_INFO = unsafe { std::mem::zeroed() };
+        if unsafe { GetConsoleScreenBufferInfo(GetStdHandle(STD_ERROR_HANDLE), &mut info) } == 0 {
+            return None;
+        }
+        let rows = i32::from(info.srWindow.Bottom) - i32::from(info.srWindow.Top) + 1;
+        usize::try_from(rows).ok().filter(|&r| r > 0)
+    }
+    #[cfg(not(any(unix, windows)))]
+    None
+}
@@ -0,0 +1,155 @@
+use crate::error::{Result, io_err};
+use crate::terminal::format::Diagnostics;
+use crate::ui;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub struct Profile {
+    stages: Vec<(&'static str, Duration)>,
+    render_peak_rss: Option<u64>,
+    /// Only in the JSON, the terminal already has it in the results.
+    diagnostics: Option<Diagnostics>,
+}
+
+impl Profile {
+    pub fn time<T>(&mut self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let out = f();
+        self.record(stage, start.elapsed());
+        out
+    }
+
+    pub fn record(&mut self, stage: &'static str, elapsed: Duration) {
+        self.stages.push((stage, elapsed));
+    }
+
+    pub fn set_diagnostics(&mut self, diagnostics: Option<Diagnostics>) {
+        self.diagnostics = diagnostics;
+    }
+
+    pub fn mark_render_peak(&mut self) {
+        self.render_peak_rss = peak_rss_bytes();
+    }
+
+    pub fn print(&self) {
+        ui::header("Profile");
+        let total: Duration = self.stages.iter().map(|(_, d)| *d).sum();
+        for (stage, elapsed) in &self.stages {
+            ui::kv(stage, &format_duration(*elapsed));
+        }
+        ui::kv("total", &format_duration(total));
+        if let Some(bytes) = self.render_peak_rss {
+            ui::kv(
+                "peak memory (render)",
+                &format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+            );
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Stage {
+            stage: &'static str,
+            ms: u64,
+        }
+        #[derive(Serialize)]
+        struct Report {
+            stages: Vec<Stage>,
+            total_ms: u64,
+            render_peak_rss: Option<u64>,
+            diagnostics: Option<Diagnostics>,
+        }
+        let stages: Vec<Stage> = self
+            .stages
+            .iter()
+            .map(|(stage, elapsed)| Stage {
+                stage,
+                ms: elapsed.as_millis() as u64,
+            })
+            .collect();
+        let report = Report {
+            total_ms: stages.iter().map(|s| s.ms).sum(),
+            stages,
+            render_peak_rss: self.render_peak_rss,
+            diagnostics: self.diagnostics,
+        };
+        serde_json::to_string_pretty(&report).unwrap_or_default() + "\n"
+    }
+
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json())
+            .map_err(|e| io_err(format!("writing {}", path.display()), e))
+    }
+}
+
+pub fn format_duration(d: Duration) -> String {
+    if d.as_secs() >= 1 {
+        format!("{:.2}s", d.as_secs_f64())
+    } else {
+        format!("{}ms", d.as_millis())
+    }
+}
+
+#[cfg(unix)]
+fn peak_rss_bytes() -> Option<u64> {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let max = usage.ru_maxrss as u64;
+    // Linux reports kilobytes, macOS reports bytes
+    if cfg!(target_os = "macos") {
+        Some(max)
+    } else {
+        Some(max * 1024)
+    }
+}
+
+#[cfg(not(unix))]
+fn peak_rss_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_formatted_by_magnitude() {
+        assert_eq!(format_duration(Duration::from_millis(42)), "42ms");
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1.50s");
+    }
+
+    #[test]
+    fn time_records_stage() {
+        let mut p = Profile::default();
+        let v = p.time("zip", || 5);
+        assert_eq!(v, 5);
+        assert_eq!(p.stages.len(), 1);
+        assert_eq!(p.stages[0].0, "zip");
+    }
+
+    #[test]
+    fn json_lists_stages_in_order() {
+        let mut p = Profile::default();
+        p.record("copy", Duration::from_millis(3));
+        p.record("zip", Duration::from_millis(40));
+        let json: serde_json::Value = serde_json::from_str(&p.to_json()).unwrap();
+        assert_eq!(json["stages"][0]["stage"], "copy");
+        assert_eq!(json["stages"][1]["ms"], 40);
+        assert_eq!(json["total_ms"], 43);
+        assert!(json["render_peak_rss"].is_null());
+        assert!(json["diagnostics"].is_null());
+
+        p.set_diagnostics(Some(Diagnostics {
+            warnings: 2,
+            errors: 0,
+        }));
+        let json: serde_json::Value = serde_json::from_str(&p.to_json()).unwrap();
+        assert_eq!(json["diagnostics"]["warnings"], 2);
+        assert_eq!(json["diagnostics"]["errors"], 0);
+    }
+}
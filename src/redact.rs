@@ -0,0 +1,158 @@
+use crate::config::RedactRule;
+use crate::error::{Error, Result};
+use crate::terminal::RunCapture;
+use regex::Regex;
+
+/// Compiled `[[redact]]` rules from the config, applied in order to
+/// captured output before it's rendered anywhere.
+#[derive(Default)]
+pub struct Redactor {
+    rules: Vec<(Regex, String)>,
+}
+
+impl Redactor {
+    pub fn new(rules: &[RedactRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|r| {
+                let re = Regex::new(&r.pattern).map_err(|e| {
+                    Error::Validation(format!("bad redact pattern '{}': {e}", r.pattern))
+                })?;
+                Ok((re, r.replacement.clone()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for (re, replacement) in &self.rules {
+            out = re.replace_all(&out, replacement.as_str()).into_owned();
+        }
+        out
+    }
+
+    /// Redacts every captured text that can end up in the doc or the
+    /// transcript: the output in each form, the valgrind and sanitizer
+    /// reports, and the compile command and log.
+    pub fn capture(&self, capture: &mut RunCapture) {
+        if self.is_empty() {
+            return;
+        }
+        for case in &mut capture.cases {
+            case.formatted_output = self.apply(&case.formatted_output);
+            case.colored_output = self.apply(&case.colored_output);
+            case.stdout = self.apply(&case.stdout);
+            for segment in &mut case.screenshot {
+                segment.text = self.apply(&segment.text);
+            }
+            if let Some(memcheck) = &mut case.memcheck {
+                memcheck.report = self.apply(&memcheck.report);
+            }
+            if let Some(report) = &mut case.sanitizer_report {
+                *report = self.apply(report);
+            }
+        }
+        if let Some(info) = &mut capture.compile {
+            info.command = self.apply(&info.command);
+            info.log = self.apply(&info.log);
+        }
+        if let Some(env) = &mut capture.environment {
+            *env = self.apply(env);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, replacement: &str) -> RedactRule {
+        RedactRule {
+            pattern: pattern.into(),
+            replacement: replacement.into(),
+        }
+    }
+
+    #[test]
+    fn rules_apply_in_order() {
+        let r = Redactor::new(&[
+            rule(r"/home/\w+", "~"),
+            rule(r"sk-[A-Za-z0-9]+", "[key]"),
+            rule(r"~/(\w+)", "~/<$1>"),
+        ])
+        .unwrap();
+        assert_eq!(
+            r.apply("open /home/joe/data key=sk-abc123"),
+            "open ~/<data> key=[key]"
+        );
+    }
+
+    #[test]
+    fn every_captured_text_is_redacted() {
+        use crate::terminal::format::{Diagnostics, Segment, Stream};
+        use crate::terminal::memcheck::MemCheck;
+        use crate::terminal::{CaseCapture, CompileInfo};
+        use std::time::Duration;
+
+        let leak = "/home/joe/lab3/main.c";
+        let mut capture = RunCapture {
+            command_display: "./main".into(),
+            cases: vec![CaseCapture {
+                name: None,
+                formatted_output: leak.into(),
+                colored_output: leak.into(),
+                stdout: leak.into(),
+                screenshot: vec![Segment::new(Stream::Stdout, leak)],
+                memcheck: Some(MemCheck {
+                    report: format!("at main ({leak}:4)"),
+                    errors: 1,
+                    leaked_bytes: 0,
+                }),
+                sanitizer_report: Some(format!("#0 in main {leak}:4:5")),
+                exit_code: Some(1),
+                error_case: false,
+                duration: Duration::ZERO,
+            }],
+            compile_time: None,
+            run_time: Duration::ZERO,
+            compile: Some(CompileInfo {
+                compiler: "gcc".into(),
+                command: format!("gcc -g {leak}"),
+                diagnostics: Diagnostics::default(),
+                log: format!("{leak}:4:5: warning: unused variable 'x'"),
+            }),
+            environment: None,
+        };
+        Redactor::new(&[rule(r"/home/\w+", "~")])
+            .unwrap()
+            .capture(&mut capture);
+
+        let case = &capture.cases[0];
+        let compile = capture.compile.as_ref().unwrap();
+        let texts = [
+            &case.formatted_output,
+            &case.colored_output,
+            &case.stdout,
+            &case.screenshot[0].text,
+            &case.memcheck.as_ref().unwrap().report,
+            case.sanitizer_report.as_ref().unwrap(),
+            &compile.command,
+            &compile.log,
+        ];
+        for text in texts {
+            assert!(!text.contains("/home/joe"), "{text}");
+            assert!(text.contains("~/lab3/main.c"), "{text}");
+        }
+    }
+
+    #[test]
+    fn bad_pattern_is_reported() {
+        let err = Redactor::new(&[rule("(unclosed", "")]).err().unwrap();
+        assert!(err.to_string().contains("(unclosed"));
+    }
+}
@@ -0,0 +1,195 @@
+//! What every doc format shares: the options describing the doc's content,
+//! the `DocBuilder` each format implements, and the pieces of text the
+//! formats all write the same way.
+
+use crate::config::Link;
+use crate::diff::Comparison;
+use crate::error::Result;
+use crate::profile::format_duration;
+use crate::render::notes::NoteBlock;
+use crate::terminal::format::describe_exit;
+use crate::terminal::{CaseCapture, RunCapture};
+
+pub const WATERMARK: &str = "Packed with assignmentpacker, created by Ian Fogarty (catforgor).";
+
+pub struct SourceFile {
+    pub name: String,
+    pub code: String,
+    pub sha256: String,
+}
+
+/// Hex digits of the SHA-256 shown in the doc, enough for a grader to match
+/// the listing against the zipped file by eye.
+pub const FINGERPRINT_LEN: usize = 16;
+
+/// Everything that goes in the doc, whatever its format.
+pub struct DocOptions<'a> {
+    pub assignment: &'a str,
+    pub name: &'a str,
+    pub student_id: &'a str,
+    pub sources: &'a [SourceFile],
+    pub capture: &'a RunCapture,
+    /// Why the program wasn't run, for a pack made with `--no-run`.
+    pub not_run: Option<&'a str>,
+    /// The screenshot pages of each entry in `capture.cases`, usually one.
+    pub screenshots: &'a [Vec<Vec<u8>>],
+    /// Expected-output comparison per case, where one was requested.
+    pub checks: &'a [Option<Comparison>],
+    pub watermark: bool,
+    pub output_cap: usize,
+    pub transcript_name: Option<&'a str>,
+    pub compile_note: bool,
+    /// Highlighted image per entry in `sources`, or empty when not wanted.
+    /// Files whose image couldn't be drawn have `None`.
+    pub code_images: &'a [Option<Vec<u8>>],
+    /// How many times larger than their display size the images were drawn.
+    pub image_scale: u32,
+    /// Name and screenshot of the tests file's compile phase.
+    pub compile_phase: Option<(&'a str, &'a [u8])>,
+    pub sections: Sections,
+    /// Sanitizers the program was built with, for the report section.
+    pub sanitize: &'a [String],
+    /// Exit code the runs were expected to return.
+    pub expect_exit: i32,
+    pub links: &'a [Link],
+    /// QR code image and the caption under it, placed before the watermark.
+    pub qr: Option<(&'a [u8], &'a str)>,
+    /// The notes file, written after the runs as an Answers / Notes section.
+    pub notes: &'a [NoteBlock],
+    /// List only the first of `sources` under Code and give each of the
+    /// others an appendix at the end.
+    pub appendix: bool,
+}
+
+impl DocOptions<'_> {
+    /// How many of `sources` are listed in the Code section.
+    pub fn in_code(&self) -> usize {
+        if self.appendix {
+            self.sources.len().min(1)
+        } else {
+            self.sources.len()
+        }
+    }
+}
+
+/// Which of the optional parts of the doc get written, so it can be cut down
+/// to what a rubric asks for.
+#[derive(Debug, Clone, Copy)]
+pub struct Sections {
+    /// The title, student, source file names and links at the top.
+    pub header: bool,
+    pub code: bool,
+    pub screenshot: bool,
+    pub text_output: bool,
+    /// The compiler's messages, in a Compiler Output section.
+    pub compile_log: bool,
+    /// The OS, architecture and shell line under each run's command.
+    pub environment: bool,
+    /// Each run with its command, exit code and checks, and the summary
+    /// table. Without it neither the screenshots nor the output are written.
+    pub runs: bool,
+}
+
+impl Default for Sections {
+    fn default() -> Self {
+        Self {
+            header: true,
+            code: true,
+            screenshot: true,
+            text_output: true,
+            compile_log: false,
+            environment: true,
+            runs: true,
+        }
+    }
+}
+
+/// One output format of the doc, see `--format`.
+pub trait DocBuilder {
+    fn build(&self, opts: &DocOptions<'_>) -> Result<Doc>;
+}
+
+/// A built doc, and any files it links to by name, which go next to it.
+pub struct Doc {
+    pub bytes: Vec<u8>,
+    pub attachments: Vec<(String, Vec<u8>)>,
+}
+
+impl Doc {
+    pub fn single(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            attachments: Vec::new(),
+        }
+    }
+}
+
+/// Heading of the appendix holding extra source file `n`, counted from 0:
+/// `Appendix A: utils.h`, running on to `AA` after `Z`.
+pub fn appendix_title(n: usize, file: &str) -> String {
+    let mut letters = Vec::new();
+    let mut n = n + 1;
+    while n > 0 {
+        n -= 1;
+        letters.push(char::from(b'A' + (n % 26) as u8));
+        n /= 26;
+    }
+    let letters: String = letters.iter().rev().collect();
+    format!("Appendix {letters}: {file}")
+}
+
+pub fn exit_label(case: &CaseCapture, expect_exit: i32) -> String {
+    match case.exit_code {
+        // Any status is a fair answer to bad input, so none is "expected"
+        Some(code) if case.error_case => code.to_string(),
+        _ => describe_exit(case.exit_code, expect_exit),
+    }
+}
+
+/// Columns of the test summary table.
+pub const SUMMARY_HEADER: [&str; 4] = ["Case", "Exit code", "Expected", "Time"];
+
+/// Widths of the summary table's columns in twips.
+pub const SUMMARY_WIDTHS: [u32; 4] = [3600, 2200, 1600, 1400];
+
+/// One row per run with its exit code, expected-output verdict and time.
+pub fn summary_rows(
+    capture: &RunCapture,
+    checks: &[Option<Comparison>],
+    expect_exit: i32,
+) -> Vec<Vec<String>> {
+    capture
+        .cases
+        .iter()
+        .enumerate()
+        .map(|(k, case)| {
+            let name = match &case.name {
+                Some(name) => name.clone(),
+                None => format!("Run {}", k + 1),
+            };
+            let verdict = match checks.get(k) {
+                Some(Some(c)) if c.passed => "PASS",
+                Some(Some(_)) => "FAIL",
+                _ => "-",
+            };
+            vec![
+                name,
+                exit_label(case, expect_exit),
+                verdict.to_string(),
+                format_duration(case.duration),
+            ]
+        })
+        .collect()
+}
+
+/// Darkens colors meant for a terminal's dark background, like bright
+/// yellow, until they can be read on a white page.
+pub fn on_page(rgb: [u8; 3]) -> [u8; 3] {
+    const MAX_LUMA: f32 = 140.0;
+    let [r, g, b] = rgb.map(f32::from);
+    let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+    if luma <= MAX_LUMA {
+        return rgb;
+    }
+    rgb.map(|c| (f32::from(c) * MAX_LUMA / luma).round() as u8)
+}
@@ -0,0 +1,187 @@
+//! Finds installed fonts by family name, so a theme can say
+//! `font = "JetBrains Mono"` instead of pointing at a file.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use ttf_parser::{Face, name_id};
+use walkdir::WalkDir;
+
+const EXTENSIONS: [&str; 3] = ["ttf", "otf", "ttc"];
+
+/// A face within a family.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Style {
+    #[default]
+    Regular,
+    Bold,
+    Italic,
+}
+
+impl Style {
+    fn weight(self) -> u16 {
+        match self {
+            Style::Bold => 700,
+            _ => 400,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Style::Regular => "Regular",
+            Style::Bold => "Bold",
+            Style::Italic => "Italic",
+        }
+    }
+}
+
+/// Path of the face of `family` closest to `style`. fontconfig is asked
+/// first where it's installed, then the usual font folders are searched.
+pub fn find_family(family: &str, style: Style) -> Option<PathBuf> {
+    let wanted = squash(family);
+    if wanted.is_empty() {
+        return None;
+    }
+    fontconfig(family, &wanted, style).or_else(|| search(&wanted, style, &font_dirs()))
+}
+
+/// Whether `font` names a file rather than a family.
+pub fn looks_like_path(font: &str) -> bool {
+    font.contains(['/', '\\']) || is_font_file(Path::new(font))
+}
+
+/// `fc-match` always answers with its nearest font, so the answer only counts
+/// when it's the family that was asked for.
+fn fontconfig(family: &str, wanted: &str, style: Style) -> Option<PathBuf> {
+    if cfg!(windows) || crate::terminal::exec::is_forbidden() {
+        return None;
+    }
+    let out = Command::new("fc-match")
+        .args([
+            "-f",
+            "%{family}\n%{file}",
+            &format!("{family}:style={}", style.name()),
+        ])
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&out.stdout);
+    let (families, file) = text.split_once('\n')?;
+    let file = PathBuf::from(file.trim());
+    (families.split(',').any(|f| squash(f) == wanted) && is_font_file(&file)).then_some(file)
+}
+
+fn font_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if cfg!(windows) {
+        if let Some(windir) = std::env::var_os("WINDIR") {
+            dirs.push(PathBuf::from(windir).join("Fonts"));
+        }
+        if let Some(local) = dirs::data_local_dir() {
+            dirs.push(local.join("Microsoft").join("Windows").join("Fonts"));
+        }
+    } else if cfg!(target_os = "macos") {
+        if let Some(home) = dirs::home_dir() {
+            dirs.push(home.join("Library").join("Fonts"));
+        }
+        dirs.push("/Library/Fonts".into());
+        dirs.push("/System/Library/Fonts".into());
+    } else {
+        if let Some(data) = dirs::data_dir() {
+            dirs.push(data.join("fonts"));
+        }
+        if let Some(home) = dirs::home_dir() {
+            dirs.push(home.join(".fonts"));
+        }
+        dirs.push("/usr/local/share/fonts".into());
+        dirs.push("/usr/share/fonts".into());
+    }
+    dirs
+}
+
+/// Reads the family names out of the font files in `dirs`. Files named after
+/// the family, like `JetBrainsMono-Regular.ttf`, are tried first, and the rest
+/// only when none of those match, since Windows shortens names to `consola.ttf`.
+fn search(wanted: &str, style: Style, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let (likely, rest): (Vec<PathBuf>, Vec<PathBuf>) = dirs
+        .iter()
+        .flat_map(|dir| WalkDir::new(dir).max_depth(6).into_iter().flatten())
+        .map(walkdir::DirEntry::into_path)
+        .filter(|path| is_font_file(path))
+        .partition(|path| {
+            path.file_stem()
+                .is_some_and(|stem| squash(&stem.to_string_lossy()).starts_with(wanted))
+        });
+    best_match(&likely, wanted, style).or_else(|| best_match(&rest, wanted, style))
+}
+
+fn best_match(files: &[PathBuf], wanted: &str, style: Style) -> Option<PathBuf> {
+    files
+        .iter()
+        .filter_map(|path| Some((rank(&fs::read(path).ok()?, wanted, style)?, path)))
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, path)| path.clone())
+}
+
+/// How far a face of the `wanted` family is from `style`, lowest first, or
+/// `None` for any other family.
+fn rank(data: &[u8], wanted: &str, style: Style) -> Option<u16> {
+    let face = Face::parse(data, 0).ok()?;
+    let family = face
+        .names()
+        .into_iter()
+        .filter(|n| matches!(n.name_id, name_id::FAMILY | name_id::TYPOGRAPHIC_FAMILY))
+        .filter_map(|n| n.to_string())
+        .any(|n| squash(&n) == wanted);
+    let weight = face.weight().to_number().abs_diff(style.weight());
+    let slant = face.is_italic() != (style == Style::Italic);
+    family.then_some(weight + if slant { 1000 } else { 0 })
+}
+
+/// Lowercase letters and digits only, so "JetBrains Mono" and
+/// `JetBrainsMono` compare equal.
+fn squash(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn is_font_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.iter().any(|x| e.eq_ignore_ascii_case(x)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::scratch_dir;
+
+    #[test]
+    fn names_compare_without_spaces_or_case() {
+        assert_eq!(squash("JetBrains Mono"), squash("JetBrainsMono"));
+        assert_eq!(squash("Fira Code-Regular"), "firacoderegular");
+    }
+
+    #[test]
+    fn paths_and_families_are_told_apart() {
+        assert!(looks_like_path("JetBrainsMono-Regular.ttf"));
+        assert!(looks_like_path("fonts/Mono"));
+        assert!(looks_like_path("FONT.OTF"));
+        assert!(!looks_like_path("JetBrains Mono"));
+    }
+
+    #[test]
+    fn unknown_families_and_bad_files_are_not_found() {
+        let dir = scratch_dir("fonts");
+        fs::write(dir.join("NoSuchFamily-Regular.ttf"), b"not a font").unwrap();
+        assert_eq!(
+            search("nosuchfamily", Style::Regular, &[dir.to_path_buf()]),
+            None
+        );
+        assert_eq!(find_family("  ", Style::Bold), None);
+    }
+}
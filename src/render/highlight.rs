@@ -0,0 +1,247 @@
+//! A small C/C++ tokenizer for coloring source listings. It only needs to be
+//! right enough to look like an editor, so there's no preprocessor or parser
+//! behind it.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Plain,
+    Keyword,
+    String,
+    Comment,
+    Number,
+    Preproc,
+}
+
+const KEYWORDS: &[&str] = &[
+    "alignas",
+    "alignof",
+    "auto",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "constexpr",
+    "continue",
+    "decltype",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "explicit",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "friend",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "mutable",
+    "namespace",
+    "new",
+    "noexcept",
+    "nullptr",
+    "operator",
+    "private",
+    "protected",
+    "public",
+    "register",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "static_assert",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typedef",
+    "typename",
+    "union",
+    "unsigned",
+    "using",
+    "virtual",
+    "void",
+    "volatile",
+    "while",
+    "NULL",
+    "size_t",
+];
+
+/// Splits `code` into colored spans that cover it exactly, newlines included.
+/// Neighbouring spans of the same kind are merged.
+pub fn spans(code: &str) -> Vec<(TokenKind, &str)> {
+    let mut ranges: Vec<(TokenKind, usize, usize)> = Vec::new();
+    let mut push = |kind: TokenKind, start: usize, end: usize| match ranges.last_mut() {
+        Some(last) if last.0 == kind => last.2 = end,
+        _ => ranges.push((kind, start, end)),
+    };
+
+    let bytes = code.as_bytes();
+    let mut i = 0;
+    let mut line_start = true;
+    let mut include = false;
+    while i < bytes.len() {
+        let b = bytes[i];
+        let rest = &code[i..];
+        let (kind, len) = if rest.starts_with("//") {
+            (TokenKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else if let Some(body) = rest.strip_prefix("/*") {
+            (
+                TokenKind::Comment,
+                body.find("*/").map_or(rest.len(), |p| p + 4),
+            )
+        } else if b == b'"' || b == b'\'' {
+            (TokenKind::String, quoted(rest, b))
+        } else if b == b'<' && include {
+            (
+                TokenKind::String,
+                rest.find(['>', '\n']).map_or(rest.len(), |p| p + 1),
+            )
+        } else if b == b'#' && line_start {
+            let word = 1 + ident_len(rest[1..].trim_start_matches([' ', '\t']));
+            let gap = rest[1..].len() - rest[1..].trim_start_matches([' ', '\t']).len();
+            include = rest[1 + gap..].starts_with("include");
+            (TokenKind::Preproc, word + gap)
+        } else if b.is_ascii_digit()
+            || (b == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+        {
+            (TokenKind::Number, number_len(rest))
+        } else if b.is_ascii_alphabetic() || b == b'_' {
+            let len = ident_len(rest);
+            let kind = if KEYWORDS.contains(&&rest[..len]) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Plain
+            };
+            (kind, len)
+        } else {
+            (
+                TokenKind::Plain,
+                rest.chars().next().map_or(1, char::len_utf8),
+            )
+        };
+
+        let len = len.max(1);
+        push(kind, i, i + len);
+        for &c in &bytes[i..i + len] {
+            if c == b'\n' {
+                line_start = true;
+                include = false;
+            } else if !c.is_ascii_whitespace() {
+                line_start = false;
+            }
+        }
+        i += len;
+    }
+    ranges
+        .into_iter()
+        .map(|(kind, start, end)| (kind, &code[start..end]))
+        .collect()
+}
+
+/// Length of a string or char literal, stopping at the closing quote or the
+/// end of the line for unterminated ones.
+fn quoted(text: &str, quote: u8) -> usize {
+    let bytes = text.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' => return i,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+fn ident_len(text: &str) -> usize {
+    text.bytes()
+        .take_while(|b| b.is_ascii_alphanumeric() || *b == b'_')
+        .count()
+}
+
+/// Covers decimal, hex and float literals with suffixes, digit separators
+/// and signed exponents.
+fn number_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        let exponent = matches!(b, b'+' | b'-')
+            && i > 0
+            && matches!(bytes[i - 1], b'e' | b'E' | b'p' | b'P')
+            && !text.starts_with("0x");
+        if b.is_ascii_alphanumeric() || b == b'.' || b == b'\'' || b == b'_' || exponent {
+            i += 1;
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(code: &str) -> Vec<(TokenKind, &str)> {
+        spans(code)
+            .into_iter()
+            .filter(|(k, _)| *k != TokenKind::Plain)
+            .collect()
+    }
+
+    #[test]
+    fn spans_cover_the_whole_input() {
+        let code = "#include <stdio.h>\nint main(void) {\n\tprintf(\"%d\\n\", 42); // hi\n}\n";
+        let joined: String = spans(code).iter().map(|(_, s)| *s).collect();
+        assert_eq!(joined, code);
+    }
+
+    #[test]
+    fn colors_the_usual_suspects() {
+        use TokenKind::*;
+        let code = "#include <stdio.h>\nint x = 0x1F; /* a\nb */ char *s = \"q\\\"\";";
+        assert_eq!(
+            kinds(code),
+            vec![
+                (Preproc, "#include"),
+                (String, "<stdio.h>"),
+                (Keyword, "int"),
+                (Number, "0x1F"),
+                (Comment, "/* a\nb */"),
+                (Keyword, "char"),
+                (String, "\"q\\\"\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_line_start_hash_is_a_directive() {
+        assert_eq!(
+            kinds("  #define N 1e-3")[0],
+            (TokenKind::Preproc, "#define")
+        );
+        assert_eq!(kinds("  #define N 1e-3")[1], (TokenKind::Number, "1e-3"));
+        assert!(kinds("x # y").is_empty());
+    }
+
+    #[test]
+    fn identifiers_containing_keywords_stay_plain() {
+        assert!(kinds("integer format_int").is_empty());
+    }
+}
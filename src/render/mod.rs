@@ -1,3 +1,13 @@
+pub mod doc;
+pub mod fonts;
+pub mod highlight;
+pub mod notes;
+pub mod pdf;
+pub mod png_meta;
+pub mod qr;
+pub mod report;
 pub mod rtf;
+pub mod rtf_check;
 pub mod screenshot;
 pub mod theme;
+pub mod vscode;
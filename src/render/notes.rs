@@ -0,0 +1,172 @@
+//! The notes file: written answers kept next to the code in simple
+//! Markdown, added to the end of the doc as an Answers / Notes section.
+//! Headings, paragraphs, lists and code fences are understood. Anything
+//! inline, like `**bold**`, is kept as typed.
+
+/// Picked up from the project folder when `--notes` isn't given.
+pub const NOTES_FILE: &str = "notes.md";
+
+pub const HEADING: &str = "Answers / Notes";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NoteBlock {
+    /// A heading and its level, 1 for `#`.
+    Heading(usize, String),
+    Para(String),
+    List {
+        ordered: bool,
+        items: Vec<String>,
+    },
+    Code(String),
+}
+
+/// What goes in front of item `index` of a list: its number or a bullet.
+pub fn marker(ordered: bool, index: usize) -> String {
+    if ordered {
+        format!("{}.", index + 1)
+    } else {
+        "\u{2022}".to_string()
+    }
+}
+
+/// Splits Markdown into the blocks the doc knows how to write.
+pub fn parse(text: &str) -> Vec<NoteBlock> {
+    let mut blocks = Vec::new();
+    let mut para: Vec<&str> = Vec::new();
+    let mut fence: Option<(&str, Vec<&str>)> = None;
+    let mut after_blank = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some((marker, code)) = &mut fence {
+            if trimmed.starts_with(*marker) && trimmed.trim_start_matches(*marker).is_empty() {
+                blocks.push(NoteBlock::Code(code.join("\n")));
+                fence = None;
+            } else {
+                code.push(line);
+            }
+            continue;
+        }
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m)) {
+            flush_para(&mut blocks, &mut para);
+            fence = Some((marker, Vec::new()));
+            continue;
+        }
+        if trimmed.is_empty() {
+            flush_para(&mut blocks, &mut para);
+            after_blank = true;
+            continue;
+        }
+        let blank_before = std::mem::replace(&mut after_blank, false);
+        if let Some((level, heading)) = heading(trimmed) {
+            flush_para(&mut blocks, &mut para);
+            blocks.push(NoteBlock::Heading(level, heading.to_string()));
+            continue;
+        }
+        if let Some((ordered, item)) = list_item(trimmed) {
+            flush_para(&mut blocks, &mut para);
+            match blocks.last_mut() {
+                Some(NoteBlock::List {
+                    ordered: same,
+                    items,
+                }) if *same == ordered => items.push(item.to_string()),
+                _ => blocks.push(NoteBlock::List {
+                    ordered,
+                    items: vec![item.to_string()],
+                }),
+            }
+            continue;
+        }
+        // An indented line right after an item carries on with it
+        if !blank_before
+            && para.is_empty()
+            && line.starts_with([' ', '\t'])
+            && let Some(NoteBlock::List { items, .. }) = blocks.last_mut()
+            && let Some(last) = items.last_mut()
+        {
+            last.push(' ');
+            last.push_str(trimmed);
+            continue;
+        }
+        para.push(trimmed);
+    }
+    if let Some((_, code)) = fence {
+        blocks.push(NoteBlock::Code(code.join("\n")));
+    }
+    flush_para(&mut blocks, &mut para);
+    blocks
+}
+
+fn flush_para(blocks: &mut Vec<NoteBlock>, para: &mut Vec<&str>) {
+    if !para.is_empty() {
+        blocks.push(NoteBlock::Para(para.join(" ")));
+        para.clear();
+    }
+}
+
+/// `## Question 2` as level 2 and its text, closing `#`s dropped.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let text = rest.trim().trim_end_matches('#').trim_end();
+    Some((level, text))
+}
+
+/// `- item`, `* item` or `+ item`, or `1. item` or `1) item` for an
+/// ordered list.
+fn list_item(line: &str) -> Option<(bool, &str)> {
+    if let Some(rest) = line
+        .strip_prefix(['-', '*', '+'])
+        .filter(|r| r.starts_with([' ', '\t']))
+    {
+        return Some((false, rest.trim()));
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let rest = line[digits..].strip_prefix(['.', ')'])?;
+    (digits > 0 && rest.starts_with([' ', '\t'])).then(|| (true, rest.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_is_split_into_blocks() {
+        let text = "# Answers #\n\nQ1 asks why\nthe loop stops.\n\n- first\n- second\n  goes on\n\n1. one\n2) two\n\n```c\nint x;\n\n  x++;\n```\n#hashtag\n";
+        assert_eq!(
+            parse(text),
+            [
+                NoteBlock::Heading(1, "Answers".into()),
+                NoteBlock::Para("Q1 asks why the loop stops.".into()),
+                NoteBlock::List {
+                    ordered: false,
+                    items: vec!["first".into(), "second goes on".into()],
+                },
+                NoteBlock::List {
+                    ordered: true,
+                    items: vec!["one".into(), "two".into()],
+                },
+                NoteBlock::Code("int x;\n\n  x++;".into()),
+                NoteBlock::Para("#hashtag".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn an_unclosed_fence_runs_to_the_end() {
+        assert_eq!(
+            parse("text\n~~~\ncode\n"),
+            [
+                NoteBlock::Para("text".into()),
+                NoteBlock::Code("code".into())
+            ]
+        );
+        assert!(parse("\n\n").is_empty());
+    }
+}
@@ -0,0 +1,1063 @@
+//! Writes the doc as a PDF, for courses that only take PDF submissions. It
+//! has the same sections as the RTF doc. Text is set in Helvetica and
+//! Courier, which every PDF reader has built in, so no fonts are embedded,
+//! and images go in as the compressed pixel rows of a PNG.
+
+use crate::error::{Error, Result};
+use crate::render::doc::{self, Doc, DocBuilder, DocOptions};
+use crate::render::notes::{self, NoteBlock};
+use crate::terminal::ansi::{self, Mark, Piece};
+use crate::terminal::format::truncate_middle;
+use crate::terminal::memcheck::VALGRIND_ARGS;
+use crate::terminal::sanitize;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{ExtendedColorType, ImageEncoder, ImageFormat, RgbImage};
+use std::fmt::Write;
+
+/// US Letter, in points.
+const PAGE_W: f32 = 612.0;
+const PAGE_H: f32 = 792.0;
+/// The side margins match the RTF doc's 1.25 inches.
+const MARGIN_X: f32 = 90.0;
+const MARGIN_Y: f32 = 72.0;
+const TEXT_W: f32 = PAGE_W - 2.0 * MARGIN_X;
+/// Space after each paragraph, the RTF doc's `\sa120`.
+const PARA_GAP: f32 = 6.0;
+/// Padding above and below the text in a table cell, and to its left.
+const CELL_PAD_Y: f32 = 3.0;
+const CELL_PAD_X: f32 = 5.4;
+
+const BODY: Style = Style::new(Font::Regular, 12.0);
+const HEADING: Style = Style::new(Font::Bold, 12.0);
+const NOTE: Style = Style::new(Font::Italic, 12.0);
+const SMALL: Style = Style::new(Font::Regular, 8.0);
+const SMALL_NOTE: Style = Style::new(Font::Italic, 9.0);
+const CODE: Style = Style::new(Font::Mono, 9.0);
+
+const LINK_BLUE: [u8; 3] = [0, 0, 255];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    Mono,
+}
+
+impl Font {
+    const ALL: [Font; 4] = [Font::Regular, Font::Bold, Font::Italic, Font::Mono];
+
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+            Font::Italic => "F3",
+            Font::Mono => "F4",
+        }
+    }
+
+    fn base(self) -> &'static str {
+        match self {
+            Font::Regular => "Helvetica",
+            Font::Bold => "Helvetica-Bold",
+            Font::Italic => "Helvetica-Oblique",
+            Font::Mono => "Courier",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Style {
+    font: Font,
+    size: f32,
+}
+
+impl Style {
+    const fn new(font: Font, size: f32) -> Self {
+        Self { font, size }
+    }
+
+    fn line_height(self) -> f32 {
+        self.size * 1.2
+    }
+}
+
+/// Helvetica's advance widths for `' '` to `'~'`, in thousandths of the size.
+const HELVETICA: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+fn char_width(ch: char, style: Style) -> f32 {
+    let units = match style.font {
+        Font::Mono => 600,
+        _ => match ch {
+            ' '..='~' => HELVETICA[ch as usize - 32],
+            _ => 556,
+        },
+    };
+    // Bold is measured with the regular widths plus a margin, which is
+    // enough to keep a heading inside the line
+    let bold = if style.font == Font::Bold { 1.1 } else { 1.0 };
+    f32::from(units) * style.size / 1000.0 * bold
+}
+
+fn text_width(text: &str, style: Style) -> f32 {
+    text.chars().map(|ch| char_width(ch, style)).sum()
+}
+
+/// Splits `text` into lines no wider than `width`, breaking at spaces, or
+/// anywhere in a word too long for a line of its own.
+fn wrap(text: &str, style: Style, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let joined = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{line} {word}")
+        };
+        if text_width(&joined, style) <= width {
+            line = joined;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        let mut used = 0.0;
+        for ch in word.chars() {
+            let w = char_width(ch, style);
+            if !line.is_empty() && used + w > width {
+                lines.push(std::mem::take(&mut line));
+                used = 0.0;
+            }
+            line.push(ch);
+            used += w;
+        }
+    }
+    lines.push(line);
+    lines
+}
+
+/// The byte the standard fonts draw `ch` with, in their WinAnsi encoding,
+/// or `?` when they don't have it.
+fn win_ansi(ch: char) -> u8 {
+    match ch {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => ch as u8,
+        '\u{20ac}' => 0x80,
+        '\u{2026}' => 0x85,
+        '\u{2018}' => 0x91,
+        '\u{2019}' => 0x92,
+        '\u{201c}' => 0x93,
+        '\u{201d}' => 0x94,
+        '\u{2022}' => 0x95,
+        '\u{2013}' => 0x96,
+        '\u{2014}' => 0x97,
+        '\u{2122}' => 0x99,
+        _ => b'?',
+    }
+}
+
+/// Writes `text` as a PDF string literal, escaping its delimiters and
+/// spelling out every byte past ASCII in octal.
+fn push_string(out: &mut String, text: &str) {
+    out.push('(');
+    for ch in text.chars() {
+        match win_ansi(ch) {
+            b @ (b'(' | b')' | b'\\') => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            b @ 0x20..=0x7e => out.push(b as char),
+            b => {
+                let _ = write!(out, "\\{b:03o}");
+            }
+        }
+    }
+    out.push(')');
+}
+
+/// Fill color operator for `rgb`.
+fn fill(rgb: [u8; 3]) -> String {
+    let [r, g, b] = rgb.map(|c| f32::from(c) / 255.0);
+    format!("{r:.3} {g:.3} {b:.3} rg")
+}
+
+/// A run of text in one color, `None` for black.
+type Span = (String, Option<[u8; 3]>);
+
+#[derive(Default)]
+struct Page {
+    content: String,
+    /// Clickable areas as their rectangle and URL.
+    links: Vec<([f32; 4], String)>,
+}
+
+struct Image {
+    width: u32,
+    height: u32,
+    /// PNG-filtered RGB rows in a zlib stream.
+    data: Vec<u8>,
+}
+
+/// Lays the doc out top to bottom, starting a new page whenever the next
+/// line or picture doesn't fit.
+struct Layout {
+    pages: Vec<Page>,
+    images: Vec<Image>,
+    /// Top of the next line, in points up from the bottom of the page.
+    y: f32,
+}
+
+impl Layout {
+    fn new() -> Self {
+        Self {
+            pages: vec![Page::default()],
+            images: Vec::new(),
+            y: PAGE_H - MARGIN_Y,
+        }
+    }
+
+    fn page(&mut self) -> &mut Page {
+        self.pages.last_mut().expect("layout always has a page")
+    }
+
+    /// Moves to a new page unless `height` more fits on this one.
+    fn room(&mut self, height: f32) {
+        if self.y - height < MARGIN_Y {
+            self.pages.push(Page::default());
+            self.y = PAGE_H - MARGIN_Y;
+        }
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    /// An empty paragraph.
+    fn blank(&mut self) {
+        self.gap(BODY.line_height() + PARA_GAP);
+    }
+
+    /// Writes one line starting at `x` and returns its baseline.
+    fn line(&mut self, spans: &[Span], style: Style, x: f32) -> f32 {
+        let height = style.line_height();
+        self.room(height);
+        self.y -= height;
+        let baseline = self.y + style.size * 0.25;
+        let mut ops = format!(
+            "BT /{} {} Tf {x:.2} {baseline:.2} Td ",
+            style.font.resource(),
+            style.size
+        );
+        let mut current = None;
+        for (text, color) in spans.iter().filter(|(text, _)| !text.is_empty()) {
+            if *color != current {
+                ops.push_str(&color.map_or_else(|| "0 g".to_string(), fill));
+                ops.push(' ');
+                current = *color;
+            }
+            push_string(&mut ops, text);
+            ops.push_str(" Tj ");
+        }
+        ops.push_str("ET");
+        if current.is_some() {
+            ops.push_str(" 0 g");
+        }
+        ops.push('\n');
+        self.page().content.push_str(&ops);
+        baseline
+    }
+
+    fn para(&mut self, text: &str, style: Style) {
+        let text = text.replace(['\n', '\r', '\t'], " ");
+        for line in wrap(&text, style, TEXT_W) {
+            self.line(&[(line, None)], style, MARGIN_X);
+        }
+        self.gap(PARA_GAP);
+    }
+
+    fn centered(&mut self, text: &str, style: Style) {
+        for line in wrap(text, style, TEXT_W) {
+            let x = (PAGE_W - text_width(&line, style)) / 2.0;
+            self.line(&[(line, None)], style, x);
+        }
+        self.gap(PARA_GAP);
+    }
+
+    /// Writes `prefix` then `text` as a link to `url`, on one line if they
+    /// fit and otherwise with the link text on lines of its own.
+    fn link(&mut self, prefix: &str, text: &str, url: &str, style: Style, center: bool) {
+        let prefix_w = text_width(prefix, style);
+        let total = prefix_w + text_width(text, style);
+        let mut lines = vec![(prefix.to_string(), text.to_string())];
+        if total > TEXT_W {
+            lines = wrap(text, style, TEXT_W)
+                .into_iter()
+                .map(|l| (String::new(), l))
+                .collect();
+            if !prefix.is_empty() {
+                lines.insert(0, (prefix.to_string(), String::new()));
+            }
+        }
+        for (prefix, text) in lines {
+            let prefix_w = text_width(&prefix, style);
+            let text_w = text_width(&text, style);
+            let x = if center {
+                (PAGE_W - prefix_w - text_w) / 2.0
+            } else {
+                MARGIN_X
+            };
+            let spans = [(prefix, None), (text, Some(LINK_BLUE))];
+            let baseline = self.line(&spans, style, x);
+            if text_w > 0.0 {
+                let left = x + prefix_w;
+                let bottom = baseline - style.size * 0.25;
+                // Underlined in the link blue, like the RTF doc's links
+                self.page().content.push_str(&format!(
+                    "0 0 1 RG 0.6 w {left:.2} {u:.2} m {right:.2} {u:.2} l S 0 G\n",
+                    u = baseline - 1.2,
+                    right = left + text_w,
+                ));
+                let rect = [left, bottom, left + text_w, baseline + style.size * 0.9];
+                self.page().links.push((rect, url.to_string()));
+            }
+        }
+        self.gap(PARA_GAP);
+    }
+
+    /// Writes a monospace listing, wrapping lines too long for the page.
+    /// With `colored`, color markers in the text set the color of what
+    /// follows them.
+    fn block(&mut self, text: &str, colored: bool) {
+        let cols = (TEXT_W / char_width(' ', CODE)).floor() as usize;
+        let marked = if colored {
+            text.to_string()
+        } else {
+            ansi::escape(text)
+        };
+        let mut color = None;
+        for source_line in marked.split('\n') {
+            let mut spans: Vec<Span> = Vec::new();
+            let mut count = 0;
+            for piece in ansi::pieces(source_line) {
+                let ch = match piece {
+                    Piece::Text(ch) => ch,
+                    Piece::Marker(ch) => {
+                        match ansi::mark(ch) {
+                            Some(Mark::Color(n)) => color = Some(doc::on_page(ansi::rgb(n))),
+                            Some(Mark::Reset) => color = None,
+                            None => {}
+                        }
+                        continue;
+                    }
+                };
+                let (ch, times) = match ch {
+                    '\r' => continue,
+                    '\t' => (' ', 4),
+                    _ => (ch, 1),
+                };
+                for _ in 0..times {
+                    if count == cols {
+                        self.line(&spans, CODE, MARGIN_X);
+                        spans.clear();
+                        count = 0;
+                    }
+                    match spans.last_mut() {
+                        Some((text, c)) if *c == color => text.push(ch),
+                        _ => spans.push((ch.to_string(), color)),
+                    }
+                    count += 1;
+                }
+            }
+            self.line(&spans, CODE, MARGIN_X);
+        }
+        self.gap(PARA_GAP);
+    }
+
+    /// Places a PNG drawn at `scale` times its display size, shrunk if
+    /// needed to fit the page.
+    fn image(&mut self, png: &[u8], scale: u32, center: bool) -> Result<()> {
+        let image = image_data(png)?;
+        let scale = scale.max(1) as f32;
+        // 72 points per inch over 96 pixels
+        let mut w = image.width as f32 * 0.75 / scale;
+        let mut h = image.height as f32 * 0.75 / scale;
+        let fit = (TEXT_W / w).min((PAGE_H - 2.0 * MARGIN_Y) / h).min(1.0);
+        w *= fit;
+        h *= fit;
+        self.room(h);
+        self.y -= h;
+        let x = if center { (PAGE_W - w) / 2.0 } else { MARGIN_X };
+        let y = self.y;
+        let n = self.images.len();
+        self.images.push(image);
+        self.page().content.push_str(&format!(
+            "q {w:.2} 0 0 {h:.2} {x:.2} {y:.2} cm /Im{n} Do Q\n"
+        ));
+        self.gap(PARA_GAP);
+        Ok(())
+    }
+
+    /// Writes a bordered table with a bold header on a grey background,
+    /// repeated at the top of each page the table runs onto. `widths` are
+    /// in twips, like the RTF doc's. Cells too wide for their column are cut
+    /// short.
+    fn table(&mut self, widths: &[u32], header: &[&str], rows: &[Vec<String>]) {
+        let widths: Vec<f32> = widths.iter().map(|&w| w as f32 / 20.0).collect();
+        let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+        let height = BODY.line_height() + 2.0 * CELL_PAD_Y;
+        self.table_row(&widths, &header, true);
+        for row in rows {
+            if self.y - height < MARGIN_Y {
+                self.room(height);
+                self.table_row(&widths, &header, true);
+            }
+            self.table_row(&widths, row, false);
+        }
+        self.blank();
+    }
+
+    fn table_row(&mut self, widths: &[f32], cells: &[String], header: bool) {
+        let style = if header { HEADING } else { BODY };
+        let height = style.line_height() + 2.0 * CELL_PAD_Y;
+        self.room(height);
+        self.y -= height;
+        let y = self.y;
+        let baseline = y + CELL_PAD_Y + style.size * 0.25;
+        let mut ops = String::new();
+        let mut x = MARGIN_X;
+        for (k, &w) in widths.iter().enumerate() {
+            if header {
+                let _ = write!(ops, "0.902 g {x:.2} {y:.2} {w:.2} {height:.2} re f 0 g ");
+            }
+            let _ = writeln!(ops, "0.5 w {x:.2} {y:.2} {w:.2} {height:.2} re S");
+            let mut text = cells.get(k).cloned().unwrap_or_default();
+            while !text.is_empty() && text_width(&text, style) > w - 2.0 * CELL_PAD_X {
+                text.pop();
+            }
+            let _ = write!(
+                ops,
+                "BT /{} {} Tf {:.2} {baseline:.2} Td ",
+                style.font.resource(),
+                style.size,
+                x + CELL_PAD_X
+            );
+            push_string(&mut ops, &text);
+            ops.push_str(" Tj ET\n");
+            x += w;
+        }
+        self.page().content.push_str(&ops);
+    }
+
+    /// The finished file. Objects 1 to 3 are the catalog, the page tree and
+    /// the resources every page shares, then come the fonts, the images and
+    /// a page and content stream for each page.
+    fn finish(self) -> Vec<u8> {
+        let first_font = 4;
+        let first_image = first_font + Font::ALL.len();
+        let first_page = first_image + self.images.len();
+        let page_id = |i: usize| first_page + 2 * i;
+
+        let mut out: Vec<u8> = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+        let mut offsets = Vec::new();
+        let mut object = |out: &mut Vec<u8>, body: &[u8]| {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", offsets.len()).as_bytes());
+            out.extend_from_slice(body);
+            out.extend_from_slice(b"\nendobj\n");
+        };
+
+        object(&mut out, b"<< /Type /Catalog /Pages 2 0 R >>");
+        let kids: Vec<String> = (0..self.pages.len())
+            .map(|i| format!("{} 0 R", page_id(i)))
+            .collect();
+        object(
+            &mut out,
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                self.pages.len()
+            )
+            .as_bytes(),
+        );
+        let mut resources = String::from("<< /Font <<");
+        for (i, font) in Font::ALL.iter().enumerate() {
+            let _ = write!(resources, " /{} {} 0 R", font.resource(), first_font + i);
+        }
+        resources.push_str(" >> /XObject <<");
+        for i in 0..self.images.len() {
+            let _ = write!(resources, " /Im{i} {} 0 R", first_image + i);
+        }
+        resources.push_str(" >> >>");
+        object(&mut out, resources.as_bytes());
+
+        for font in Font::ALL {
+            object(
+                &mut out,
+                format!(
+                    "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                    font.base()
+                )
+                .as_bytes(),
+            );
+        }
+        for image in &self.images {
+            let mut body = format!(
+                "<< /Type /XObject /Subtype /Image /Width {w} /Height {h} /ColorSpace /DeviceRGB \
+                 /BitsPerComponent 8 /Filter /FlateDecode /DecodeParms << /Predictor 15 \
+                 /Colors 3 /BitsPerComponent 8 /Columns {w} >> /Length {} >>\nstream\n",
+                image.data.len(),
+                w = image.width,
+                h = image.height,
+            )
+            .into_bytes();
+            body.extend_from_slice(&image.data);
+            body.extend_from_slice(b"\nendstream");
+            object(&mut out, &body);
+        }
+        for (i, page) in self.pages.iter().enumerate() {
+            let mut annots = String::new();
+            for ([x0, y0, x1, y1], url) in &page.links {
+                let _ = write!(
+                    annots,
+                    "<< /Type /Annot /Subtype /Link /Rect [{x0:.2} {y0:.2} {x1:.2} {y1:.2}] \
+                     /Border [0 0 0] /A << /S /URI /URI "
+                );
+                push_string(&mut annots, url);
+                annots.push_str(" >> >> ");
+            }
+            object(
+                &mut out,
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_W} {PAGE_H}] \
+                     /Resources 3 0 R /Contents {} 0 R /Annots [{}] >>",
+                    page_id(i) + 1,
+                    annots.trim_end()
+                )
+                .as_bytes(),
+            );
+            object(
+                &mut out,
+                format!(
+                    "<< /Length {} >>\nstream\n{}\nendstream",
+                    page.content.len(),
+                    page.content
+                )
+                .as_bytes(),
+            );
+        }
+
+        let xref = out.len();
+        let mut tail = format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1);
+        for offset in &offsets {
+            let _ = writeln!(tail, "{offset:010} 00000 n ");
+        }
+        let _ = write!(
+            tail,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            offsets.len() + 1
+        );
+        out.extend_from_slice(tail.as_bytes());
+        out
+    }
+}
+
+/// The pixels of `png` in the form a PDF image with the PNG predictors
+/// reads: RGB rows, each with its filter byte, in a zlib stream. That's a
+/// PNG's own `IDAT` data, so the image is re-encoded as plain 8-bit RGB and
+/// its `IDAT` chunks joined. Transparent pixels are laid over white.
+fn image_data(png: &[u8]) -> Result<Image> {
+    let img = image::load_from_memory_with_format(png, ImageFormat::Png)
+        .map_err(|e| Error::Image(format!("reading image: {e}")))?
+        .to_rgba8();
+    let rgb = RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        let over_white =
+            |c: u8| ((u32::from(c) * u32::from(a) + 255 * (255 - u32::from(a))) / 255) as u8;
+        image::Rgb([over_white(r), over_white(g), over_white(b)])
+    });
+    let mut encoded = Vec::new();
+    PngEncoder::new_with_quality(&mut encoded, CompressionType::Default, FilterType::Adaptive)
+        .write_image(
+            rgb.as_raw(),
+            rgb.width(),
+            rgb.height(),
+            ExtendedColorType::Rgb8,
+        )
+        .map_err(|e| Error::Image(format!("encoding image: {e}")))?;
+
+    // Chunks follow the 8-byte signature as length, type, data and CRC
+    let mut data = Vec::new();
+    let mut rest = &encoded[8..];
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        if rest.len() < len + 12 {
+            break;
+        }
+        if &rest[4..8] == b"IDAT" {
+            data.extend_from_slice(&rest[8..8 + len]);
+        }
+        rest = &rest[len + 12..];
+    }
+    Ok(Image {
+        width: rgb.width(),
+        height: rgb.height(),
+        data,
+    })
+}
+
+/// The PDF doc.
+pub struct PdfBuilder;
+
+impl DocBuilder for PdfBuilder {
+    fn build(&self, opts: &DocOptions<'_>) -> Result<Doc> {
+        build_pdf(opts).map(Doc::single)
+    }
+}
+
+pub fn build_pdf(opts: &DocOptions<'_>) -> Result<Vec<u8>> {
+    let DocOptions {
+        assignment,
+        name,
+        student_id,
+        sources,
+        capture,
+        not_run,
+        screenshots,
+        checks,
+        watermark,
+        output_cap,
+        transcript_name,
+        compile_note,
+        code_images,
+        image_scale,
+        compile_phase,
+        sections,
+        sanitize,
+        expect_exit,
+        links,
+        qr,
+        notes,
+        ..
+    } = opts;
+    let mut layout = Layout::new();
+
+    if sections.header {
+        layout.para(&format!("{assignment} Submission"), HEADING);
+        layout.para(&format!("Student: {name} ({student_id})"), BODY);
+        let names: Vec<&str> = sources.iter().map(|s| s.name.as_str()).collect();
+        let label = if names.len() == 1 {
+            "Source file"
+        } else {
+            "Source files"
+        };
+        layout.para(&format!("{label}: {}", names.join(", ")), BODY);
+        let checked: Vec<_> = checks.iter().flatten().collect();
+        if !checked.is_empty() {
+            let passed = checked.iter().filter(|c| c.passed).count();
+            let verdict = if passed == checked.len() {
+                "PASS"
+            } else {
+                "FAIL"
+            };
+            layout.para(
+                &format!(
+                    "Expected output: {verdict} ({passed} of {} runs matched)",
+                    checked.len()
+                ),
+                BODY,
+            );
+        }
+        layout.blank();
+
+        if !links.is_empty() {
+            layout.para("Links", HEADING);
+            for link in links.iter() {
+                layout.link(
+                    &format!("{}: ", link.label),
+                    &link.url,
+                    &link.url,
+                    BODY,
+                    false,
+                );
+            }
+            layout.blank();
+        }
+    }
+
+    if sections.code {
+        layout.para("Code", HEADING);
+    }
+    let (listed, appendices) = sources.split_at(opts.in_code());
+    for (i, source) in listed.iter().enumerate().filter(|_| sections.code) {
+        if sources.len() > 1 {
+            layout.para(&source.name, NOTE);
+        }
+        layout.block(&source.code, false);
+        layout.blank();
+        if let Some(Some(png)) = code_images.get(i) {
+            layout.image(png, *image_scale, false)?;
+            layout.blank();
+        }
+    }
+    if *compile_note && let Some(info) = &capture.compile {
+        layout.para(
+            &format!(
+                "Compiled with {}: {}.",
+                info.compiler,
+                info.diagnostics.summary()
+            ),
+            SMALL_NOTE,
+        );
+        layout.blank();
+    }
+
+    if let Some((phase, png)) = compile_phase
+        && let Some(info) = &capture.compile
+    {
+        layout.para(&format!("Compile Screenshot - {phase}"), HEADING);
+        layout.para(&format!("Command: {}", info.command), BODY);
+        layout.image(png, *image_scale, false)?;
+        layout.blank();
+    }
+
+    if (sections.compile_log || compile_phase.is_some())
+        && let Some(info) = &capture.compile
+    {
+        let suffix = compile_phase
+            .map(|(phase, _)| format!(" - {phase}"))
+            .unwrap_or_default();
+        layout.para(&format!("Compiler Output{suffix}"), HEADING);
+        layout.para(&format!("Command: {}", info.command), BODY);
+        if info.log.is_empty() {
+            layout.para(
+                &format!("{} reported no warnings or errors.", info.compiler),
+                BODY,
+            );
+        } else {
+            layout.block(&info.log, false);
+        }
+        layout.blank();
+    }
+
+    if sections.runs && capture.cases.len() > 1 {
+        layout.para("Test Summary", HEADING);
+        layout.table(
+            &doc::SUMMARY_WIDTHS,
+            &doc::SUMMARY_HEADER,
+            &doc::summary_rows(capture, checks, *expect_exit),
+        );
+    }
+
+    let runs = capture.cases.iter().zip(screenshots.iter());
+    for (k, (case, pages)) in runs.enumerate().filter(|_| sections.runs) {
+        if case.error_case && !capture.cases[..k].iter().any(|c| c.error_case) {
+            layout.para("Error Handling Evidence", HEADING);
+            layout.para("The program was run with deliberately invalid input.", BODY);
+            layout.blank();
+        }
+        let suffix = case
+            .name
+            .as_deref()
+            .map(|n| format!(" - {n}"))
+            .unwrap_or_default();
+        let heading = if sections.screenshot {
+            "Program Run Screenshot"
+        } else {
+            "Program Run"
+        };
+        layout.para(&format!("{heading}{suffix}"), HEADING);
+        layout.para(&format!("Command: {}", capture.command_display), BODY);
+        if sections.environment
+            && let Some(env) = &capture.environment
+        {
+            layout.para(&format!("Environment: {env}"), BODY);
+        }
+        for (i, png) in pages.iter().enumerate().filter(|_| sections.screenshot) {
+            if pages.len() > 1 {
+                layout.para(&format!("Screenshot {}/{}", i + 1, pages.len()), NOTE);
+            }
+            layout.image(png, *image_scale, false)?;
+        }
+
+        if sections.text_output {
+            layout.para(&format!("Captured Output (Text){suffix}"), HEADING);
+            let truncated = truncate_middle(&case.colored_output, *output_cap);
+            if truncated.is_some()
+                && let Some(file) = transcript_name
+            {
+                layout.para(&format!("Full transcript: {file}"), BODY);
+            }
+            let output = truncated.as_deref().unwrap_or(&case.colored_output);
+            layout.block(output, true);
+        }
+        layout.para(
+            &format!("Exit code: {}", doc::exit_label(case, *expect_exit)),
+            BODY,
+        );
+        layout.blank();
+
+        if let Some(m) = &case.memcheck {
+            layout.para(&format!("Memory Check{suffix}"), HEADING);
+            layout.para(
+                &format!(
+                    "Command: valgrind {} {}",
+                    VALGRIND_ARGS.join(" "),
+                    capture.command_display
+                ),
+                BODY,
+            );
+            layout.para(&format!("Result: {}.", m.summary()), BODY);
+            layout.block(&m.report, false);
+            layout.blank();
+        }
+
+        if let Some(report) = &case.sanitizer_report {
+            layout.para(&format!("Sanitizer Report{suffix}"), HEADING);
+            layout.para(
+                &format!(
+                    "Built with: {}",
+                    sanitize::compile_flags(sanitize).join(" ")
+                ),
+                BODY,
+            );
+            if report.is_empty() {
+                layout.para("Result: no issues reported.", BODY);
+            } else {
+                layout.block(report, false);
+            }
+            layout.blank();
+        }
+
+        if let Some(Some(check)) = checks.get(k) {
+            layout.para(&format!("Expected Output Check{suffix}"), HEADING);
+            if check.passed {
+                layout.para("Result: PASS, output matches the expected output.", BODY);
+            } else {
+                layout.para("Result: FAIL, differences from the expected output:", BODY);
+                layout.block(check.unified.trim_end(), false);
+            }
+            layout.blank();
+        }
+    }
+
+    if sections.runs
+        && let Some(reason) = not_run
+    {
+        layout.para("Program Run", HEADING);
+        layout.para(&format!("Output not captured ({reason})."), NOTE);
+        layout.blank();
+    }
+
+    if !notes.is_empty() {
+        layout.para(notes::HEADING, HEADING);
+        for block in notes.iter() {
+            match block {
+                NoteBlock::Heading(_, text) => layout.para(text, HEADING),
+                NoteBlock::Para(text) => layout.para(text, BODY),
+                NoteBlock::List { ordered, items } => {
+                    for (i, item) in items.iter().enumerate() {
+                        layout.para(&format!("{} {item}", notes::marker(*ordered, i)), BODY);
+                    }
+                }
+                NoteBlock::Code(text) => layout.block(text, false),
+            }
+        }
+        layout.blank();
+    }
+
+    for (n, source) in appendices.iter().enumerate().filter(|_| sections.code) {
+        layout.para(&doc::appendix_title(n, &source.name), HEADING);
+        layout.block(&source.code, false);
+        layout.blank();
+        if let Some(Some(png)) = code_images.get(listed.len() + n) {
+            layout.image(png, *image_scale, false)?;
+            layout.blank();
+        }
+    }
+
+    for source in sources.iter() {
+        let short = &source.sha256[..doc::FINGERPRINT_LEN.min(source.sha256.len())];
+        layout.line(
+            &[(format!("SHA-256 {}: {short}", source.name), None)],
+            SMALL,
+            MARGIN_X,
+        );
+    }
+    layout.gap(PARA_GAP);
+
+    if let Some((png, caption)) = qr {
+        layout.image(png, 1, true)?;
+        layout.centered(caption, SMALL);
+        layout.blank();
+    }
+
+    if *watermark {
+        layout.link(
+            "",
+            doc::WATERMARK,
+            "https://github.com/cat-forgor/AssignmentPacker",
+            Style::new(Font::Italic, 8.0),
+            true,
+        );
+    }
+
+    Ok(layout.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::doc::{Sections, SourceFile};
+    use crate::terminal::{CaseCapture, RunCapture};
+
+    fn case(output: String) -> CaseCapture {
+        CaseCapture {
+            name: None,
+            formatted_output: ansi::strip(&output),
+            stdout: ansi::strip(&output),
+            colored_output: output,
+            screenshot: Vec::new(),
+            memcheck: None,
+            sanitizer_report: None,
+            exit_code: Some(0),
+            error_case: false,
+            duration: std::time::Duration::ZERO,
+        }
+    }
+
+    fn build(capture: &RunCapture, screenshots: &[Vec<Vec<u8>>], sections: Sections) -> Vec<u8> {
+        let sources = [SourceFile {
+            name: "main.c".into(),
+            code: "int main(void) { return 0; }".into(),
+            sha256: "ab".repeat(32),
+        }];
+        build_pdf(&DocOptions {
+            assignment: "A1",
+            name: "Joe",
+            student_id: "1",
+            sources: &sources,
+            capture,
+            not_run: None,
+            screenshots,
+            checks: &[],
+            watermark: true,
+            output_cap: 0,
+            transcript_name: None,
+            compile_note: false,
+            code_images: &[],
+            image_scale: 1,
+            compile_phase: None,
+            sections,
+            sanitize: &[],
+            expect_exit: 0,
+            links: &[],
+            qr: None,
+            notes: &[],
+            appendix: false,
+        })
+        .unwrap()
+    }
+
+    fn capture(cases: Vec<CaseCapture>) -> RunCapture {
+        RunCapture {
+            command_display: "./a.out".into(),
+            cases,
+            compile_time: None,
+            run_time: std::time::Duration::ZERO,
+            compile: None,
+            environment: None,
+        }
+    }
+
+    #[test]
+    fn strings_are_escaped_and_encoded() {
+        let mut out = String::new();
+        push_string(&mut out, "a(b)\\ \u{e9}\u{2014}\u{2713}");
+        assert_eq!(out, "(a\\(b\\)\\\\ \\351\\227?)");
+    }
+
+    #[test]
+    fn wrapping_stays_inside_the_margin() {
+        let text = format!("{} {}", "word ".repeat(60).trim_end(), "x".repeat(200));
+        let lines = wrap(&text, BODY, TEXT_W);
+        assert!(lines.len() > 3);
+        assert!(lines.iter().all(|l| text_width(l, BODY) <= TEXT_W));
+        assert_eq!(lines.concat().replace(' ', ""), text.replace(' ', ""));
+    }
+
+    #[test]
+    fn cross_references_point_at_each_object() {
+        let png = crate::render::qr::render_qr("x").unwrap();
+        let output = format!("hi {}there{}", ansi::marker(Some(11)), ansi::COLOR_RESET);
+        let doc = build(
+            &capture(vec![case(output)]),
+            &[vec![png]],
+            Sections::default(),
+        );
+        assert!(doc.starts_with(b"%PDF-1.4\n"));
+        assert!(doc.ends_with(b"%%EOF\n"));
+
+        // Offsets are counted in bytes, and the image data isn't text
+        let at = doc.windows(10).rposition(|w| w == b"startxref\n").unwrap();
+        let tail = std::str::from_utf8(&doc[at + 10..]).unwrap();
+        let start: usize = tail.lines().next().unwrap().parse().unwrap();
+        let xref = std::str::from_utf8(&doc[start..]).unwrap();
+        assert!(xref.starts_with("xref\n"));
+        let offsets: Vec<usize> = xref
+            .lines()
+            .skip(3)
+            .take_while(|l| l.ends_with(" n "))
+            .map(|l| l[..10].parse().unwrap())
+            .collect();
+        for (i, &offset) in offsets.iter().enumerate() {
+            assert!(doc[offset..].starts_with(format!("{} 0 obj\n", i + 1).as_bytes()));
+        }
+        assert_eq!(offsets.len(), 10);
+
+        let text = String::from_utf8_lossy(&doc);
+
+        assert!(text.contains("/Subtype /Image"));
+        assert!(text.contains("/Im0 Do"));
+        assert!(text.contains("(int main\\(void\\) { return 0; }) Tj"));
+        // Bright yellow, darkened to read on the page
+        assert!(text.contains("(hi ) Tj 0.600 0.600 0.165 rg (there) Tj ET 0 g"));
+        assert!(text.contains("/URI (https://github.com/cat-forgor/AssignmentPacker)"));
+    }
+
+    #[test]
+    fn long_output_runs_onto_more_pages() {
+        let output: String = (1..=500).map(|n| format!("line {n}\n")).collect();
+        let doc = build(
+            &capture(vec![case(output)]),
+            &[Vec::new()],
+            Sections::default(),
+        );
+        let text = String::from_utf8_lossy(&doc);
+        let count = text.split("/Count ").nth(1).unwrap();
+        let pages: usize = count[..count.find(' ').unwrap()].parse().unwrap();
+        assert!(pages >= 8);
+        assert!(text.contains("(line 500) Tj"));
+    }
+
+    #[test]
+    fn sections_leave_out_the_header_and_runs() {
+        let sections = Sections {
+            header: false,
+            runs: false,
+            ..Sections::default()
+        };
+        let doc = build(&capture(vec![case("hi".into())]), &[Vec::new()], sections);
+        let text = String::from_utf8_lossy(&doc);
+        assert!(text.contains("(Code) Tj"));
+        assert!(!text.contains("A1 Submission"));
+        assert!(!text.contains("Student:"));
+        assert!(!text.contains("Program Run"));
+        assert!(!text.contains("Command: ./a.out"));
+        assert!(!text.contains("Exit code"));
+    }
+}
@@ -0,0 +1,152 @@
+//! Controls what metadata goes into embedded PNGs. Only the chunks needed to
+//! display the image are kept, so nothing about the machine that drew it ends
+//! up in the doc unless the user asks for a note. Every image gets a `pHYs`
+//! chunk so apps that size pictures by DPI show it at the intended size.
+
+use crate::error::{Error, Result};
+
+const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Chunks that affect how the image looks. Everything else, like `tEXt`,
+/// `tIME` or `eXIf`, is dropped.
+const KEEP: &[&[u8; 4]] = &[
+    b"IHDR", b"PLTE", b"IDAT", b"IEND", b"tRNS", b"gAMA", b"cHRM", b"sRGB",
+];
+
+/// Resolution of an image drawn at `--export-scale 1`, one pixel per screen
+/// pixel on a standard display.
+pub const BASE_DPI: u32 = 96;
+
+/// Keyword of the provenance chunk added by `scrub`.
+const NOTE_KEYWORD: &str = "Comment";
+
+/// Rewrites `png` with only display chunks, a `pHYs` chunk saying it's
+/// `dpi` dots per inch, and a `tEXt` comment holding `note` when one is given.
+pub fn scrub(png: &[u8], note: Option<&str>, dpi: u32) -> Result<Vec<u8>> {
+    let bad = || Error::Image("embedded image isn't a valid PNG".into());
+    let mut rest = png.strip_prefix(SIGNATURE).ok_or_else(bad)?;
+    let mut out = Vec::with_capacity(png.len());
+    out.extend_from_slice(SIGNATURE);
+
+    while !rest.is_empty() {
+        if rest.len() < 12 {
+            return Err(bad());
+        }
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let total = len
+            .checked_add(12)
+            .filter(|&t| t <= rest.len())
+            .ok_or_else(bad)?;
+        let (chunk, tail) = rest.split_at(total);
+        let kind = &chunk[4..8];
+        if KEEP.iter().any(|k| k.as_slice() == kind) {
+            out.extend_from_slice(chunk);
+        }
+        if kind == b"IHDR" {
+            push_chunk(&mut out, b"pHYs", &phys_chunk(dpi));
+            if let Some(text) = note {
+                push_chunk(&mut out, b"tEXt", &text_chunk(text)?);
+            }
+        }
+        rest = tail;
+    }
+    Ok(out)
+}
+
+/// Body of a `pHYs` chunk: pixels per meter on both axes, then unit 1 for meters.
+fn phys_chunk(dpi: u32) -> Vec<u8> {
+    let ppm = (f64::from(dpi.max(1)) / 0.0254).round() as u32;
+    let mut data = Vec::with_capacity(9);
+    data.extend_from_slice(&ppm.to_be_bytes());
+    data.extend_from_slice(&ppm.to_be_bytes());
+    data.push(1);
+    data
+}
+
+/// Body of a `tEXt` chunk: keyword, a null byte, then Latin-1 text.
+fn text_chunk(text: &str) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(NOTE_KEYWORD.len() + 1 + text.len());
+    data.extend_from_slice(NOTE_KEYWORD.as_bytes());
+    data.push(0);
+    for ch in text.chars() {
+        match u8::try_from(u32::from(ch)) {
+            Ok(b) if b != 0 => data.push(b),
+            _ => {
+                return Err(Error::Validation(format!(
+                    "image note can only use Latin-1 characters, found '{ch}'"
+                )));
+            }
+        }
+    }
+    Ok(data)
+}
+
+fn push_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_kinds(png: &[u8]) -> Vec<String> {
+        let mut rest = &png[8..];
+        let mut kinds = Vec::new();
+        while rest.len() >= 12 {
+            let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            kinds.push(String::from_utf8_lossy(&rest[4..8]).into_owned());
+            rest = &rest[len + 12..];
+        }
+        kinds
+    }
+
+    fn sample() -> Vec<u8> {
+        let png = crate::render::screenshot::render_png("hi", "", &Default::default()).unwrap();
+        // Slip a timestamp and a software tag in after IHDR
+        let mut tagged = png[..33].to_vec();
+        push_chunk(&mut tagged, b"tIME", &[0x07, 0xea, 10, 16, 12, 0, 0]);
+        push_chunk(&mut tagged, b"tEXt", b"Software\0laptop-of-joe");
+        tagged.extend_from_slice(&png[33..]);
+        tagged
+    }
+
+    #[test]
+    fn drops_metadata_chunks() {
+        let clean = scrub(&sample(), None, BASE_DPI).unwrap();
+        assert_eq!(chunk_kinds(&clean), ["IHDR", "pHYs", "IDAT", "IEND"]);
+        assert!(image::load_from_memory(&clean).is_ok());
+    }
+
+    #[test]
+    fn adds_a_note_that_decoders_accept() {
+        let noted = scrub(&sample(), Some("Joe Bloggs, CS101"), BASE_DPI).unwrap();
+        assert_eq!(
+            chunk_kinds(&noted),
+            ["IHDR", "pHYs", "tEXt", "IDAT", "IEND"]
+        );
+        assert!(image::load_from_memory(&noted).is_ok());
+        assert!(noted.windows(7).any(|w| w == b"Comment"));
+    }
+
+    #[test]
+    fn rejects_text_outside_latin1() {
+        assert!(scrub(&sample(), Some("日本"), BASE_DPI).is_err());
+        assert!(scrub(b"not a png", None, BASE_DPI).is_err());
+    }
+
+    #[test]
+    fn writes_one_phys_with_the_dpi() {
+        let once = scrub(&sample(), None, 192).unwrap();
+        let twice = scrub(&once, None, 192).unwrap();
+        assert_eq!(chunk_kinds(&twice), ["IHDR", "pHYs", "IDAT", "IEND"]);
+        // 192 DPI is 7559 pixels per meter
+        let at = twice.windows(4).position(|w| w == b"pHYs").unwrap() + 4;
+        assert_eq!(&twice[at..at + 9], &[0, 0, 0x1d, 0x87, 0, 0, 0x1d, 0x87, 1]);
+    }
+}
@@ -0,0 +1,688 @@
+//! `--format md` and `--format html`: the doc as a report for places that
+//! take Markdown or a web page rather than a Word file, like GitHub
+//! Classroom or a web form. Both have the same sections as the RTF doc.
+//! Markdown links to its images, which are saved next to it, while the HTML
+//! page carries them inline so it's a single file.
+
+use crate::base64;
+use crate::error::Result;
+use crate::render::doc::{self, Doc, DocBuilder, DocOptions};
+use crate::render::notes::{self, NoteBlock};
+use crate::terminal::ansi::{self, Mark, Piece};
+use crate::terminal::format::truncate_middle;
+use crate::terminal::memcheck::VALGRIND_ARGS;
+use crate::terminal::sanitize;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flavor {
+    Markdown,
+    Html,
+}
+
+/// A Markdown or HTML report.
+pub struct ReportBuilder<'a> {
+    pub flavor: Flavor,
+    /// Start of the file names of a Markdown report's images.
+    pub image_stem: &'a str,
+}
+
+impl DocBuilder for ReportBuilder<'_> {
+    fn build(&self, opts: &DocOptions<'_>) -> Result<Doc> {
+        build_report(opts, self.flavor, self.image_stem)
+    }
+}
+
+const STYLE: &str = "body{font-family:Calibri,Arial,sans-serif;max-width:48em;margin:2em auto;padding:0 1em}\
+pre{font-family:Consolas,'Courier New',monospace;font-size:.8em;white-space:pre-wrap}\
+table{border-collapse:collapse}th,td{border:1px solid #999;padding:.2em .5em;text-align:left}\
+th{background:#e6e6e6}.small{font-size:.7em}.center{text-align:center}img{max-width:100%}";
+
+/// Markdown characters that would otherwise start formatting.
+const MD_SPECIAL: &[char] = &['\\', '`', '*', '_', '[', ']', '<', '>', '#', '|', '!'];
+
+fn md_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\n' | '\r' | '\t' => out.push(' '),
+            _ if MD_SPECIAL.contains(&ch) => {
+                out.push('\\');
+                out.push(ch);
+            }
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            '\r' => {}
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Language named on a fenced code block, so sites that highlight code do.
+fn fence_lang(file: &str) -> &'static str {
+    match file
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+    {
+        Some(ext) if ext == "c" || ext == "h" => "c",
+        Some(ext) if ["cpp", "cc", "cxx", "hpp", "hh", "hxx"].contains(&ext.as_str()) => "cpp",
+        _ => "",
+    }
+}
+
+/// Width and height of a PNG from its header, 0 if it has none.
+fn png_size(png: &[u8]) -> (u32, u32) {
+    let read = |at: usize| {
+        png.get(at..at + 4)
+            .map_or(0, |b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    (read(16), read(20))
+}
+
+struct Writer<'a> {
+    flavor: Flavor,
+    out: String,
+    images: Vec<(String, Vec<u8>)>,
+    /// Start of the file names of a Markdown report's images.
+    image_stem: &'a str,
+}
+
+impl Writer<'_> {
+    fn block(&mut self, md: String, html: String) {
+        match self.flavor {
+            Flavor::Markdown => self.out.push_str(&md),
+            Flavor::Html => self.out.push_str(&html),
+        }
+        self.out.push('\n');
+    }
+
+    fn title(&mut self, text: &str) {
+        self.block(
+            format!("# {}\n", md_escape(text)),
+            format!("<h1>{}</h1>", html_escape(text)),
+        );
+    }
+
+    fn heading(&mut self, text: &str) {
+        self.block(
+            format!("## {}\n", md_escape(text)),
+            format!("<h2>{}</h2>", html_escape(text)),
+        );
+    }
+
+    fn para(&mut self, text: &str) {
+        self.block(
+            format!("{}\n", md_escape(text)),
+            format!("<p>{}</p>", html_escape(text)),
+        );
+    }
+
+    fn note(&mut self, text: &str) {
+        self.block(
+            format!("*{}*\n", md_escape(text)),
+            format!("<p><em>{}</em></p>", html_escape(text)),
+        );
+    }
+
+    /// Lines in small print, like the source fingerprints.
+    fn small(&mut self, lines: &[String]) {
+        let md: Vec<String> = lines.iter().map(|l| md_escape(l)).collect();
+        let html: Vec<String> = lines.iter().map(|l| html_escape(l)).collect();
+        self.block(
+            format!("<sub>{}</sub>\n", md.join("<br>")),
+            format!("<p class=\"small\">{}</p>", html.join("<br>")),
+        );
+    }
+
+    fn link(&mut self, prefix: &str, text: &str, url: &str) {
+        self.block(
+            format!("{}[{}](<{url}>)\n", md_escape(prefix), md_escape(text)),
+            format!(
+                "<p>{}<a href=\"{}\">{}</a></p>",
+                html_escape(prefix),
+                html_escape(url),
+                html_escape(text)
+            ),
+        );
+    }
+
+    /// A monospace listing. With `colored`, color markers in the text set
+    /// the color of what follows them in HTML, Markdown drops them.
+    fn code(&mut self, text: &str, lang: &str, colored: bool) {
+        let marked = if colored {
+            text.to_string()
+        } else {
+            ansi::escape(text)
+        };
+        let plain = ansi::strip(&marked).replace('\r', "");
+        // The fence has to be longer than any run of backticks in the text
+        let mut longest = 0;
+        let mut run = 0;
+        for ch in plain.chars() {
+            run = if ch == '`' { run + 1 } else { 0 };
+            longest = longest.max(run);
+        }
+        let fence = "`".repeat((longest + 1).max(3));
+        let md = format!("{fence}{lang}\n{}\n{fence}\n", plain.trim_end_matches('\n'));
+
+        let mut html = String::from("<pre><code>");
+        let mut open = false;
+        let mut pending = String::new();
+        for piece in ansi::pieces(&marked) {
+            let ch = match piece {
+                Piece::Text(ch) => {
+                    pending.push(ch);
+                    continue;
+                }
+                Piece::Marker(ch) => ch,
+            };
+            let Some(mark) = ansi::mark(ch) else {
+                continue;
+            };
+            html.push_str(&html_escape(&pending));
+            pending.clear();
+            if open {
+                html.push_str("</span>");
+                open = false;
+            }
+            if let Mark::Color(n) = mark {
+                let [r, g, b] = doc::on_page(ansi::rgb(n));
+                let _ = write!(html, "<span style=\"color:#{r:02x}{g:02x}{b:02x}\">");
+                open = true;
+            }
+        }
+        html.push_str(&html_escape(&pending));
+        if open {
+            html.push_str("</span>");
+        }
+        html.push_str("</code></pre>");
+        self.block(md, html);
+    }
+
+    /// An image drawn at `scale` times its display size, linked as a file
+    /// next to a Markdown report or inlined in an HTML one.
+    fn image(&mut self, png: &[u8], scale: u32, alt: &str, center: bool) {
+        match self.flavor {
+            Flavor::Markdown => {
+                let file = format!("{}_image{}.png", self.image_stem, self.images.len() + 1);
+                self.out
+                    .push_str(&format!("![{}]({file})\n\n", md_escape(alt)));
+                self.images.push((file, png.to_vec()));
+            }
+            Flavor::Html => {
+                let (w, _) = png_size(png);
+                let class = if center { " class=\"center\"" } else { "" };
+                self.out.push_str(&format!(
+                    "<p{class}><img src=\"data:image/png;base64,{}\" width=\"{}\" alt=\"{}\"></p>\n",
+                    base64::encode(png),
+                    w / scale.max(1),
+                    html_escape(alt)
+                ));
+            }
+        }
+    }
+
+    fn list(&mut self, ordered: bool, items: &[String]) {
+        let md: String = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let marker = if ordered {
+                    format!("{}.", i + 1)
+                } else {
+                    "-".into()
+                };
+                format!("{marker} {}\n", md_escape(item))
+            })
+            .collect();
+        let tag = if ordered { "ol" } else { "ul" };
+        let html: String = items
+            .iter()
+            .map(|item| format!("<li>{}</li>", html_escape(item)))
+            .collect();
+        self.block(md, format!("<{tag}>{html}</{tag}>"));
+    }
+
+    fn table(&mut self, header: &[&str], rows: &[Vec<String>]) {
+        let md_row = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+        let mut md = md_row(header.iter().map(|h| md_escape(h)).collect());
+        md.push_str(&md_row(vec!["---".to_string(); header.len()]));
+        let mut html = String::from("<table>\n<tr>");
+        for h in header {
+            let _ = write!(html, "<th>{}</th>", html_escape(h));
+        }
+        html.push_str("</tr>\n");
+        for row in rows {
+            md.push_str(&md_row(row.iter().map(|c| md_escape(c)).collect()));
+            html.push_str("<tr>");
+            for c in row {
+                let _ = write!(html, "<td>{}</td>", html_escape(c));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>");
+        self.block(md, html);
+    }
+}
+
+/// Builds a Markdown or HTML report. A Markdown report's images are named
+/// `<image_stem>_image<n>.png`.
+pub fn build_report(opts: &DocOptions<'_>, flavor: Flavor, image_stem: &str) -> Result<Doc> {
+    let DocOptions {
+        assignment,
+        name,
+        student_id,
+        sources,
+        capture,
+        not_run,
+        screenshots,
+        checks,
+        watermark,
+        output_cap,
+        transcript_name,
+        compile_note,
+        code_images,
+        image_scale,
+        compile_phase,
+        sections,
+        sanitize,
+        expect_exit,
+        links,
+        qr,
+        notes,
+        ..
+    } = opts;
+    let mut w = Writer {
+        flavor,
+        out: String::new(),
+        images: Vec::new(),
+        image_stem,
+    };
+    let title = format!("{assignment} Submission");
+    if flavor == Flavor::Html {
+        w.out.push_str(&format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n",
+            html_escape(&title)
+        ));
+    }
+
+    if sections.header {
+        w.title(&title);
+        w.para(&format!("Student: {name} ({student_id})"));
+        let names: Vec<&str> = sources.iter().map(|s| s.name.as_str()).collect();
+        let label = if names.len() == 1 {
+            "Source file"
+        } else {
+            "Source files"
+        };
+        w.para(&format!("{label}: {}", names.join(", ")));
+        let checked: Vec<_> = checks.iter().flatten().collect();
+        if !checked.is_empty() {
+            let passed = checked.iter().filter(|c| c.passed).count();
+            let verdict = if passed == checked.len() {
+                "PASS"
+            } else {
+                "FAIL"
+            };
+            w.para(&format!(
+                "Expected output: {verdict} ({passed} of {} runs matched)",
+                checked.len()
+            ));
+        }
+
+        if !links.is_empty() {
+            w.heading("Links");
+            for link in links.iter() {
+                w.link(&format!("{}: ", link.label), &link.url, &link.url);
+            }
+        }
+    }
+
+    if sections.code {
+        w.heading("Code");
+    }
+    let (listed, appendices) = sources.split_at(opts.in_code());
+    for (i, source) in listed.iter().enumerate().filter(|_| sections.code) {
+        if sources.len() > 1 {
+            w.note(&source.name);
+        }
+        w.code(&source.code, fence_lang(&source.name), false);
+        if let Some(Some(png)) = code_images.get(i) {
+            w.image(
+                png,
+                *image_scale,
+                &format!("Highlighted {}", source.name),
+                false,
+            );
+        }
+    }
+    if *compile_note && let Some(info) = &capture.compile {
+        w.note(&format!(
+            "Compiled with {}: {}.",
+            info.compiler,
+            info.diagnostics.summary()
+        ));
+    }
+
+    if let Some((phase, png)) = compile_phase
+        && let Some(info) = &capture.compile
+    {
+        w.heading(&format!("Compile Screenshot - {phase}"));
+        w.para(&format!("Command: {}", info.command));
+        w.image(png, *image_scale, "Compile screenshot", false);
+    }
+
+    if (sections.compile_log || compile_phase.is_some())
+        && let Some(info) = &capture.compile
+    {
+        let suffix = compile_phase
+            .map(|(phase, _)| format!(" - {phase}"))
+            .unwrap_or_default();
+        w.heading(&format!("Compiler Output{suffix}"));
+        w.para(&format!("Command: {}", info.command));
+        if info.log.is_empty() {
+            w.para(&format!(
+                "{} reported no warnings or errors.",
+                info.compiler
+            ));
+        } else {
+            w.code(&info.log, "", false);
+        }
+    }
+
+    if sections.runs && capture.cases.len() > 1 {
+        w.heading("Test Summary");
+        w.table(
+            &doc::SUMMARY_HEADER,
+            &doc::summary_rows(capture, checks, *expect_exit),
+        );
+    }
+
+    let runs = capture.cases.iter().zip(screenshots.iter());
+    for (k, (case, pages)) in runs.enumerate().filter(|_| sections.runs) {
+        if case.error_case && !capture.cases[..k].iter().any(|c| c.error_case) {
+            w.heading("Error Handling Evidence");
+            w.para("The program was run with deliberately invalid input.");
+        }
+        let suffix = case
+            .name
+            .as_deref()
+            .map(|n| format!(" - {n}"))
+            .unwrap_or_default();
+        let heading = if sections.screenshot {
+            "Program Run Screenshot"
+        } else {
+            "Program Run"
+        };
+        w.heading(&format!("{heading}{suffix}"));
+        w.para(&format!("Command: {}", capture.command_display));
+        if sections.environment
+            && let Some(env) = &capture.environment
+        {
+            w.para(&format!("Environment: {env}"));
+        }
+        for (i, png) in pages.iter().enumerate().filter(|_| sections.screenshot) {
+            if pages.len() > 1 {
+                w.note(&format!("Screenshot {}/{}", i + 1, pages.len()));
+            }
+            w.image(png, *image_scale, &format!("Program run{suffix}"), false);
+        }
+
+        if sections.text_output {
+            w.heading(&format!("Captured Output (Text){suffix}"));
+            let truncated = truncate_middle(&case.colored_output, *output_cap);
+            if truncated.is_some()
+                && let Some(file) = transcript_name
+            {
+                w.para(&format!("Full transcript: {file}"));
+            }
+            let output = truncated.as_deref().unwrap_or(&case.colored_output);
+            w.code(output, "", true);
+        }
+        w.para(&format!(
+            "Exit code: {}",
+            doc::exit_label(case, *expect_exit)
+        ));
+
+        if let Some(m) = &case.memcheck {
+            w.heading(&format!("Memory Check{suffix}"));
+            w.para(&format!(
+                "Command: valgrind {} {}",
+                VALGRIND_ARGS.join(" "),
+                capture.command_display
+            ));
+            w.para(&format!("Result: {}.", m.summary()));
+            w.code(&m.report, "", false);
+        }
+
+        if let Some(report) = &case.sanitizer_report {
+            w.heading(&format!("Sanitizer Report{suffix}"));
+            w.para(&format!(
+                "Built with: {}",
+                sanitize::compile_flags(sanitize).join(" ")
+            ));
+            if report.is_empty() {
+                w.para("Result: no issues reported.");
+            } else {
+                w.code(report, "", false);
+            }
+        }
+
+        if let Some(Some(check)) = checks.get(k) {
+            w.heading(&format!("Expected Output Check{suffix}"));
+            if check.passed {
+                w.para("Result: PASS, output matches the expected output.");
+            } else {
+                w.para("Result: FAIL, differences from the expected output:");
+                w.code(check.unified.trim_end(), "diff", false);
+            }
+        }
+    }
+
+    if sections.runs
+        && let Some(reason) = not_run
+    {
+        w.heading("Program Run");
+        w.note(&format!("Output not captured ({reason})."));
+    }
+
+    if !notes.is_empty() {
+        w.heading(notes::HEADING);
+        for block in notes.iter() {
+            match block {
+                NoteBlock::Heading(_, text) => w.block(
+                    format!("**{}**\n", md_escape(text)),
+                    format!("<p><strong>{}</strong></p>", html_escape(text)),
+                ),
+                NoteBlock::Para(text) => w.para(text),
+                NoteBlock::List { ordered, items } => w.list(*ordered, items),
+                NoteBlock::Code(text) => w.code(text, "", false),
+            }
+        }
+    }
+
+    for (n, source) in appendices.iter().enumerate().filter(|_| sections.code) {
+        w.heading(&doc::appendix_title(n, &source.name));
+        w.code(&source.code, fence_lang(&source.name), false);
+        if let Some(Some(png)) = code_images.get(listed.len() + n) {
+            w.image(
+                png,
+                *image_scale,
+                &format!("Highlighted {}", source.name),
+                false,
+            );
+        }
+    }
+
+    let fingerprints: Vec<String> = sources
+        .iter()
+        .map(|s| {
+            let short = &s.sha256[..doc::FINGERPRINT_LEN.min(s.sha256.len())];
+            format!("SHA-256 {}: {short}", s.name)
+        })
+        .collect();
+    w.small(&fingerprints);
+
+    if let Some((png, caption)) = qr {
+        w.image(png, 1, caption, true);
+        w.small(&[caption.to_string()]);
+    }
+
+    if *watermark {
+        w.block(
+            format!(
+                "<sub>*[{}](https://github.com/cat-forgor/AssignmentPacker)*</sub>\n",
+                md_escape(doc::WATERMARK)
+            ),
+            format!(
+                "<p class=\"small center\"><em><a href=\"https://github.com/cat-forgor/AssignmentPacker\">{}</a></em></p>",
+                html_escape(doc::WATERMARK)
+            ),
+        );
+    }
+    if flavor == Flavor::Html {
+        w.out.push_str("</body>\n</html>\n");
+    }
+
+    Ok(Doc {
+        bytes: w.out.into_bytes(),
+        attachments: w.images,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::doc::{Sections, SourceFile};
+    use crate::terminal::{CaseCapture, RunCapture};
+
+    fn build(flavor: Flavor, code: &str, png: &[u8], sections: Sections) -> Doc {
+        let output = format!("hi {}<there>{}", ansi::marker(Some(1)), ansi::COLOR_RESET);
+        let capture = RunCapture {
+            command_display: "./a.out".into(),
+            cases: vec![CaseCapture {
+                name: None,
+                formatted_output: ansi::strip(&output),
+                stdout: ansi::strip(&output),
+                colored_output: output,
+                screenshot: Vec::new(),
+                memcheck: None,
+                sanitizer_report: None,
+                exit_code: Some(0),
+                error_case: false,
+                duration: std::time::Duration::ZERO,
+            }],
+            compile_time: None,
+            run_time: std::time::Duration::ZERO,
+            compile: None,
+            environment: None,
+        };
+        let sources = [SourceFile {
+            name: "main.c".into(),
+            code: code.into(),
+            sha256: "ab".repeat(32),
+        }];
+        build_report(
+            &DocOptions {
+                assignment: "A1",
+                name: "Joe",
+                student_id: "1",
+                sources: &sources,
+                capture: &capture,
+                not_run: None,
+                screenshots: &[vec![png.to_vec()]],
+                checks: &[None],
+                watermark: false,
+                output_cap: 0,
+                transcript_name: None,
+                compile_note: false,
+                code_images: &[],
+                image_scale: 2,
+                compile_phase: None,
+                sections,
+                sanitize: &[],
+                expect_exit: 0,
+                links: &[],
+                qr: None,
+                notes: &[],
+                appendix: false,
+            },
+            flavor,
+            "A1_Joe_1",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn markdown_links_its_images_and_fences_code() {
+        let png = crate::render::qr::render_qr("x").unwrap();
+        let report = build(
+            Flavor::Markdown,
+            "char *s = \"```\";",
+            &png,
+            Sections::default(),
+        );
+        let doc = String::from_utf8(report.bytes).unwrap();
+        assert!(doc.starts_with("# A1 Submission\n"));
+        assert!(doc.contains("````c\nchar *s = \"```\";\n````\n"));
+        assert!(doc.contains("![Program run](A1_Joe_1_image1.png)"));
+        assert!(doc.contains("```\nhi <there>\n```"));
+        assert_eq!(report.attachments.len(), 1);
+        assert_eq!(report.attachments[0].0, "A1_Joe_1_image1.png");
+    }
+
+    #[test]
+    fn html_inlines_images_and_escapes_text() {
+        let png = crate::render::qr::render_qr("x").unwrap();
+        let report = build(
+            Flavor::Html,
+            "if (a < b && c) {}",
+            &png,
+            Sections::default(),
+        );
+        let doc = String::from_utf8(report.bytes).unwrap();
+        assert!(doc.starts_with("<!DOCTYPE html>"));
+        assert!(doc.ends_with("</html>\n"));
+        assert!(doc.contains("if (a &lt; b &amp;&amp; c) {}"));
+        assert!(doc.contains(&format!(
+            "src=\"data:image/png;base64,{}\"",
+            base64::encode(&png)
+        )));
+        assert!(doc.contains(&format!("width=\"{}\"", png_size(&png).0 / 2)));
+        assert!(doc.contains("hi <span style=\"color:#cd3131\">&lt;there&gt;</span>"));
+        assert!(report.attachments.is_empty());
+    }
+
+    #[test]
+    fn sections_leave_out_the_header_and_runs() {
+        let sections = Sections {
+            header: false,
+            runs: false,
+            ..Sections::default()
+        };
+        for flavor in [Flavor::Markdown, Flavor::Html] {
+            let report = build(flavor, "int main;", &[], sections);
+            let doc = String::from_utf8(report.bytes).unwrap();
+            assert!(doc.contains("int main;"));
+            assert!(!doc.contains("Student:"));
+            assert!(!doc.contains("Program Run"));
+            assert!(!doc.contains("Command: ./a.out"));
+            assert!(!doc.contains("Exit code"));
+            assert!(report.attachments.is_empty());
+        }
+    }
+}
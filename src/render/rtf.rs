@@ -1,44 +1,216 @@
+use crate::config::{DocCompat, DocSection, PageSize};
+use crate::diff::Comparison;
 use crate::error::{Error, Result};
+use crate::render::doc::{
+    Doc, DocBuilder, DocOptions, FINGERPRINT_LEN, SUMMARY_HEADER, SUMMARY_WIDTHS, WATERMARK,
+    appendix_title, exit_label, on_page, summary_rows,
+};
+use crate::render::highlight::{self, TokenKind};
+use crate::render::notes::{self, NoteBlock};
+use crate::render::rtf_check;
 use crate::terminal::RunCapture;
+use crate::terminal::ansi::{self, Mark, Piece};
+use crate::terminal::format::truncate_middle;
+use crate::terminal::memcheck::VALGRIND_ARGS;
+use crate::terminal::sanitize;
 use image::ImageFormat;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::ops::Range;
 
-const WATERMARK: &str = "Packed with assignmentpacker, created by Ian Fogarty (catforgor).";
+/// Point size of code and output listings unless configured otherwise.
+pub const DEFAULT_CODE_SIZE: u32 = 9;
 
-pub struct RtfOptions<'a> {
-    pub assignment: &'a str,
-    pub name: &'a str,
-    pub student_id: &'a str,
-    pub c_file_name: &'a str,
-    pub code: &'a str,
-    pub capture: &'a RunCapture,
-    pub screenshot_png: &'a [u8],
-    pub watermark: bool,
+/// Page margins in cm unless configured otherwise: 1.25 in, what RTF
+/// readers assume when a doc doesn't say.
+pub const DEFAULT_MARGIN_CM: f64 = 3.175;
+
+/// Lines per listing paragraph unless configured otherwise. Well under where
+/// viewers start to struggle with one group, and long enough that the split
+/// points are rare.
+pub const DEFAULT_CHUNK_LINES: usize = 200;
+
+/// What a doc template can ask for, each as a `{{key}}` placeholder.
+pub const TEMPLATE_KEYS: [&str; 7] = [
+    "assignment",
+    "name",
+    "student_id",
+    "date",
+    "code",
+    "screenshot",
+    "output",
+];
+
+/// A placeholder as typed in a text editor, or as Word saves it with the
+/// braces escaped.
+const PLACEHOLDER: &str = r"\\\{\\\{([a-z_]+)\\\}\\\}|\{\{([a-z_]+)\}\}";
+
+/// The RTF doc, saved as `.doc` so Word opens it directly.
+pub struct RtfBuilder {
+    /// Lines per paragraph in code and output listings, 0 for no limit.
+    pub chunk_lines: usize,
+    pub compat: DocCompat,
+    /// Read the doc back with `rtf_check` and fail if it's malformed.
+    pub strict: bool,
+    /// Number the lines of the code listing.
+    pub line_numbers: bool,
+    /// Start the doc with a title page.
+    pub cover: Option<Cover>,
+    /// Font of the text, in place of the one picked for `compat`.
+    pub body_font: Option<String>,
+    /// Font of the code and output listings, in place of the one picked for
+    /// `compat`.
+    pub code_font: Option<String>,
+    /// Point size of the listings.
+    pub code_size: u32,
+    pub page: PageSize,
+    /// Margins in cm, the same on every side.
+    pub margin: f64,
+    /// Fill in the user's own layout in place of the usual doc.
+    pub template: Option<Template>,
+    /// The order of the doc's parts, empty for the usual one.
+    pub order: Vec<DocSection>,
+}
+
+/// What the title page shows besides the assignment and student.
+pub struct Cover {
+    pub course: Option<String>,
+    /// The day the doc was made, as `YYYY-MM-DD`.
+    pub date: String,
+}
+
+/// The user's own doc layout: an RTF file with placeholders, see
+/// `TEMPLATE_KEYS`, for the parts to fill in.
+pub struct Template {
+    pub rtf: String,
+    /// The day the doc was made, as `YYYY-MM-DD`.
+    pub date: String,
 }
 
-pub fn build_rtf(opts: &RtfOptions<'_>) -> Result<Vec<u8>> {
-    let RtfOptions {
+impl Default for RtfBuilder {
+    fn default() -> Self {
+        Self {
+            chunk_lines: DEFAULT_CHUNK_LINES,
+            compat: DocCompat::Word,
+            strict: false,
+            line_numbers: false,
+            cover: None,
+            body_font: None,
+            code_font: None,
+            code_size: DEFAULT_CODE_SIZE,
+            page: PageSize::Letter,
+            margin: DEFAULT_MARGIN_CM,
+            template: None,
+            order: Vec::new(),
+        }
+    }
+}
+
+impl DocBuilder for RtfBuilder {
+    fn build(&self, opts: &DocOptions<'_>) -> Result<Doc> {
+        let doc = match &self.template {
+            Some(template) => fill_template(template, opts, self)?,
+            None => build_rtf(opts, self)?,
+        };
+        if self.strict {
+            rtf_check::check(&doc)?;
+        }
+        Ok(Doc::single(doc))
+    }
+}
+
+pub fn build_rtf(opts: &DocOptions<'_>, settings: &RtfBuilder) -> Result<Vec<u8>> {
+    let DocOptions {
         assignment,
         name,
         student_id,
-        c_file_name,
-        code,
+        sources,
         capture,
-        screenshot_png,
+        not_run,
+        screenshots,
+        checks,
         watermark,
+        output_cap,
+        transcript_name,
+        compile_note,
+        code_images,
+        image_scale,
+        compile_phase,
+        sections,
+        sanitize,
+        expect_exit,
+        links,
+        qr,
+        notes,
+        ..
     } = opts;
-    let img = image::load_from_memory_with_format(screenshot_png, ImageFormat::Png)
-        .map_err(|e| Error::Image(format!("reading screenshot: {e}")))?;
-    let pw = img.width().max(1) as u64;
-    let ph = img.height().max(1) as u64;
-    let goal_w = pw.saturating_mul(15);
-    let goal_h = ph.saturating_mul(15);
-    let hex = hex_wrap(screenshot_png, 64);
-
-    let mut r = String::with_capacity(screenshot_png.len() * 2 + code.len() + 4096);
+    let RtfBuilder {
+        chunk_lines,
+        compat,
+        line_numbers,
+        cover,
+        body_font,
+        code_font,
+        code_size,
+        page,
+        margin,
+        order,
+        ..
+    } = settings;
+    let body_font = body_font.as_deref().map(check_font).transpose()?;
+    let code_font = code_font.as_deref().map(check_font).transpose()?;
+    check_code_size(*code_size)?;
+    let mono = Mono {
+        font: 1,
+        fs: code_size * 2,
+    };
+    check_margin(*margin)?;
+    let (paper_w, paper_h) = paper_size(*page);
+    let margin = cm_to_twips(*margin);
+    let text_width = paper_w - 2 * margin;
+    let code_len: usize = sources.iter().map(|s| s.code.len()).sum();
+    let png_len: usize = screenshots
+        .iter()
+        .flatten()
+        .chain(code_images.iter().flatten())
+        .map(Vec::len)
+        .sum();
+    let mut r = String::with_capacity(png_len * 2 + code_len + 4096);
     r.push_str("{\\rtf1\\ansi\\deff0\n");
-    r.push_str("{\\fonttbl{\\f0 Calibri;}{\\f1 Consolas;}}\n");
-    r.push_str("{\\colortbl ;\\red0\\green0\\blue255;}\n");
-    r.push_str("\\viewkind4\\uc1\\pard\\sa120\\sl240\\slmult1\\f0\\fs24\n");
+    let [(body_family, body_default), (code_family, code_default)] = default_fonts(*compat);
+    r.push_str(&format!("{{\\fonttbl{{\\f0{body_family} "));
+    rtf_escape(&mut r, body_font.unwrap_or(body_default), Mode::Inline);
+    r.push_str(&format!(";}}{{\\f1{code_family} "));
+    rtf_escape(&mut r, code_font.unwrap_or(code_default), Mode::Inline);
+    r.push_str(";}}\n");
+    let palette = if sections.text_output {
+        ansi_palette(capture)
+    } else {
+        Vec::new()
+    };
+    r.push_str("{\\colortbl ;\\red0\\green0\\blue255;\\red230\\green230\\blue230;");
+    for [red, green, blue] in CODE_COLORS {
+        r.push_str(&format!("\\red{red}\\green{green}\\blue{blue};"));
+    }
+    for &n in &palette {
+        let [red, green, blue] = on_page(ansi::rgb(n));
+        r.push_str(&format!("\\red{red}\\green{green}\\blue{blue};"));
+    }
+    r.push_str("}\n");
+    // Stated outright, since a Word left to pick falls back on whatever its
+    // own template says
+    r.push_str(&format!(
+        "\\paperw{paper_w}\\paperh{paper_h}\\margl{margin}\\margr{margin}\\margt{margin}\\margb{margin}\n"
+    ));
+    r.push_str("\\viewkind4\\uc1\n");
+    push_header_footer(&mut r, assignment, name, student_id, cover.is_some());
+    r.push_str("\\pard\\sa120\\sl240\\slmult1\\f0\\fs24\n");
+    if let Some(cover) = cover {
+        push_cover(&mut r, assignment, name, student_id, cover);
+    }
+    // Each part is written on its own, then put together in the order asked for
+    let mut doc = std::mem::take(&mut r);
+    let mut parts = Vec::new();
 
     r.push_str("\\b ");
     rtf_escape(&mut r, &format!("{assignment} Submission"), Mode::Inline);
@@ -49,46 +221,1020 @@ pub fn build_rtf(opts: &RtfOptions<'_>) -> Result<Vec<u8>> {
         Mode::Inline,
     );
     r.push_str("\\par\n");
-    rtf_escape(&mut r, &format!("Source file: {c_file_name}"), Mode::Inline);
-    r.push_str("\\par\n\\par\n");
-
-    r.push_str("\\b Code\\b0\\par\n");
-    r.push_str("{\\pard\\f1\\fs18 ");
-    rtf_escape(&mut r, code, Mode::Block);
-    r.push_str("\\par}\n\\pard\\f0\\fs24\\par\n");
-
-    r.push_str("\\b Program Run Screenshot\\b0\\par\n");
+    let names: Vec<&str> = sources.iter().map(|s| s.name.as_str()).collect();
+    let label = if names.len() == 1 {
+        "Source file"
+    } else {
+        "Source files"
+    };
     rtf_escape(
         &mut r,
-        &format!("Command: {}", capture.command_display),
+        &format!("{label}: {}", names.join(", ")),
         Mode::Inline,
     );
     r.push_str("\\par\n");
-    r.push_str(&format!(
-        "{{\\pict\\pngblip\\picw{pw}\\pich{ph}\\picwgoal{goal_w}\\pichgoal{goal_h}\n{hex}}}\n\\par\n"
-    ));
+    let checked: Vec<&Comparison> = checks.iter().flatten().collect();
+    if !checked.is_empty() {
+        let passed = checked.iter().filter(|c| c.passed).count();
+        let verdict = if passed == checked.len() {
+            "PASS"
+        } else {
+            "FAIL"
+        };
+        rtf_escape(
+            &mut r,
+            &format!(
+                "Expected output: {verdict} ({passed} of {} runs matched)",
+                checked.len()
+            ),
+            Mode::Inline,
+        );
+        r.push_str("\\par\n");
+    }
+    r.push_str("\\par\n");
 
-    r.push_str("\\b Captured Output (Text)\\b0\\par\n");
-    r.push_str("{\\pard\\f1\\fs18 ");
-    rtf_escape(&mut r, &capture.formatted_output, Mode::Block);
-    r.push_str("\\par}\n");
+    if !links.is_empty() {
+        r.push_str("\\b Links\\b0\\par\n");
+        for link in links.iter() {
+            rtf_escape(&mut r, &format!("{}: ", link.label), Mode::Inline);
+            push_link(&mut r, &link.url, &link.url);
+            r.push_str("\\par\n");
+        }
+        r.push_str("\\par\n");
+    }
+
+    parts.push((Part::Header, std::mem::take(&mut r)));
+
+    if sections.code {
+        r.push_str("\\b Code\\b0\\par\n");
+    }
+    let (listed, appendices) = sources.split_at(opts.in_code());
+    for (i, source) in listed.iter().enumerate().filter(|_| sections.code) {
+        if sources.len() > 1 {
+            r.push_str("\\i ");
+            rtf_escape(&mut r, &source.name, Mode::Inline);
+            r.push_str("\\i0\\par\n");
+        }
+        push_block(
+            &mut r,
+            &source.code,
+            *chunk_lines,
+            mono,
+            Coloring::Syntax {
+                numbered: *line_numbers,
+            },
+        );
+        r.push_str("\\pard\\f0\\fs24\\par\n");
+        if let Some(Some(png)) = code_images.get(i) {
+            push_png(&mut r, png, *image_scale, *compat, text_width)?;
+            r.push_str("\\par\n");
+        }
+    }
+    if *compile_note && let Some(info) = &capture.compile {
+        r.push_str("{\\i\\fs18 ");
+        rtf_escape(
+            &mut r,
+            &format!(
+                "Compiled with {}: {}.",
+                info.compiler,
+                info.diagnostics.summary()
+            ),
+            Mode::Inline,
+        );
+        r.push_str("\\par}\n\\par\n");
+    }
+
+    parts.push((Part::Code, std::mem::take(&mut r)));
+
+    if let Some((phase, png)) = compile_phase
+        && let Some(info) = &capture.compile
+    {
+        r.push_str("\\b ");
+        rtf_escape(
+            &mut r,
+            &format!("Compile Screenshot - {phase}"),
+            Mode::Inline,
+        );
+        r.push_str("\\b0\\par\n");
+        rtf_escape(&mut r, &format!("Command: {}", info.command), Mode::Inline);
+        r.push_str("\\par\n");
+        push_png(&mut r, png, *image_scale, *compat, text_width)?;
+        r.push_str("\\par\n");
+    }
+
+    if (sections.compile_log || compile_phase.is_some())
+        && let Some(info) = &capture.compile
+    {
+        let suffix = compile_phase
+            .map(|(phase, _)| format!(" - {phase}"))
+            .unwrap_or_default();
+        r.push_str("\\b ");
+        rtf_escape(&mut r, &format!("Compiler Output{suffix}"), Mode::Inline);
+        r.push_str("\\b0\\par\n");
+        rtf_escape(&mut r, &format!("Command: {}", info.command), Mode::Inline);
+        r.push_str("\\par\n");
+        if info.log.is_empty() {
+            rtf_escape(
+                &mut r,
+                &format!("{} reported no warnings or errors.", info.compiler),
+                Mode::Inline,
+            );
+            r.push_str("\\par\n\\par\n");
+        } else {
+            push_block(&mut r, &info.log, *chunk_lines, mono, Coloring::Plain);
+            r.push_str("\\pard\\f0\\fs24\\par\n");
+        }
+    }
+
+    parts.push((Part::Compile, std::mem::take(&mut r)));
+
+    let position = |section| order.iter().position(|&s| s == section);
+    let output_first = matches!(
+        (position(DocSection::Output), position(DocSection::Screenshot)),
+        (Some(output), Some(shot)) if output < shot
+    );
+
+    if capture.cases.len() > 1 {
+        push_summary_table(&mut r, capture, checks, *expect_exit);
+    }
+
+    for (k, (case, pages)) in capture.cases.iter().zip(screenshots.iter()).enumerate() {
+        if case.error_case && !capture.cases[..k].iter().any(|c| c.error_case) {
+            r.push_str("\\b Error Handling Evidence\\b0\\par\n");
+            r.push_str("The program was run with deliberately invalid input.\\par\n\\par\n");
+        }
+        let suffix = case
+            .name
+            .as_deref()
+            .map(|n| format!(" - {n}"))
+            .unwrap_or_default();
+        let heading = if sections.screenshot {
+            "Program Run Screenshot"
+        } else {
+            "Program Run"
+        };
+        r.push_str("\\b ");
+        rtf_escape(&mut r, &format!("{heading}{suffix}"), Mode::Inline);
+        r.push_str("\\b0\\par\n");
+        rtf_escape(
+            &mut r,
+            &format!("Command: {}", capture.command_display),
+            Mode::Inline,
+        );
+        r.push_str("\\par\n");
+        if sections.environment
+            && let Some(env) = &capture.environment
+        {
+            rtf_escape(&mut r, &format!("Environment: {env}"), Mode::Inline);
+            r.push_str("\\par\n");
+        }
+        let mut shots = String::new();
+        for (i, png) in pages.iter().enumerate().filter(|_| sections.screenshot) {
+            if pages.len() > 1 {
+                shots.push_str("\\i ");
+                rtf_escape(
+                    &mut shots,
+                    &format!("Screenshot {}/{}", i + 1, pages.len()),
+                    Mode::Inline,
+                );
+                shots.push_str("\\i0\\par\n");
+            }
+            push_png(&mut shots, png, *image_scale, *compat, text_width)?;
+        }
+        if !output_first {
+            r.push_str(&shots);
+        }
+
+        if sections.text_output {
+            r.push_str("\\b ");
+            rtf_escape(
+                &mut r,
+                &format!("Captured Output (Text){suffix}"),
+                Mode::Inline,
+            );
+            r.push_str("\\b0\\par\n");
+            let truncated = truncate_middle(&case.colored_output, *output_cap);
+            if truncated.is_some()
+                && let Some(file) = transcript_name
+            {
+                rtf_escape(&mut r, &format!("Full transcript: {file}"), Mode::Inline);
+                r.push_str("\\par\n");
+            }
+            let output = truncated.as_deref().unwrap_or(&case.colored_output);
+            push_block(&mut r, output, *chunk_lines, mono, Coloring::Ansi(&palette));
+        }
+        if output_first {
+            r.push_str(&shots);
+        }
+        r.push_str("\\pard\\f0\\fs24 ");
+        rtf_escape(
+            &mut r,
+            &format!("Exit code: {}", exit_label(case, *expect_exit)),
+            Mode::Inline,
+        );
+        r.push_str("\\par\n\\par\n");
+
+        if let Some(m) = &case.memcheck {
+            r.push_str("\\b ");
+            rtf_escape(&mut r, &format!("Memory Check{suffix}"), Mode::Inline);
+            r.push_str("\\b0\\par\n");
+            rtf_escape(
+                &mut r,
+                &format!(
+                    "Command: valgrind {} {}",
+                    VALGRIND_ARGS.join(" "),
+                    capture.command_display
+                ),
+                Mode::Inline,
+            );
+            r.push_str("\\par\n");
+            rtf_escape(&mut r, &format!("Result: {}.", m.summary()), Mode::Inline);
+            r.push_str("\\par\n");
+            push_block(&mut r, &m.report, *chunk_lines, mono, Coloring::Plain);
+            r.push_str("\\pard\\f0\\fs24\\par\n");
+        }
+
+        if let Some(report) = &case.sanitizer_report {
+            r.push_str("\\b ");
+            rtf_escape(&mut r, &format!("Sanitizer Report{suffix}"), Mode::Inline);
+            r.push_str("\\b0\\par\n");
+            rtf_escape(
+                &mut r,
+                &format!(
+                    "Built with: {}",
+                    sanitize::compile_flags(sanitize).join(" ")
+                ),
+                Mode::Inline,
+            );
+            r.push_str("\\par\n");
+            if report.is_empty() {
+                r.push_str("Result: no issues reported.\\par\n\\par\n");
+            } else {
+                push_block(&mut r, report, *chunk_lines, mono, Coloring::Plain);
+                r.push_str("\\pard\\f0\\fs24\\par\n");
+            }
+        }
+
+        if let Some(Some(check)) = checks.get(k) {
+            r.push_str("\\b ");
+            rtf_escape(
+                &mut r,
+                &format!("Expected Output Check{suffix}"),
+                Mode::Inline,
+            );
+            r.push_str("\\b0\\par\n");
+            if check.passed {
+                r.push_str("Result: PASS, output matches the expected output.\\par\n\\par\n");
+            } else {
+                r.push_str("Result: FAIL, differences from the expected output:\\par\n");
+                push_block(
+                    &mut r,
+                    check.unified.trim_end(),
+                    *chunk_lines,
+                    mono,
+                    Coloring::Plain,
+                );
+                r.push_str("\\pard\\f0\\fs24\\par\n");
+            }
+        }
+    }
+
+    if let Some(reason) = not_run {
+        r.push_str("\\b Program Run\\b0\\par\n\\i ");
+        rtf_escape(
+            &mut r,
+            &format!("Output not captured ({reason})."),
+            Mode::Inline,
+        );
+        r.push_str("\\i0\\par\n\\par\n");
+    }
+
+    parts.push((Part::Runs, std::mem::take(&mut r)));
+
+    if !notes.is_empty() {
+        push_notes(&mut r, notes, *chunk_lines, mono);
+    }
+    parts.push((Part::Notes, std::mem::take(&mut r)));
+    let mut layout = part_order(order);
+    // --include-compile-log still adds the log to a list that leaves it out
+    if sections.compile_log && !layout.contains(&Part::Compile) {
+        layout.push(Part::Compile);
+    }
+    for part in layout {
+        if let Some((_, text)) = parts.iter().find(|(p, _)| *p == part) {
+            doc.push_str(text);
+        }
+    }
+    r = doc;
+
+    for (n, source) in appendices.iter().enumerate().filter(|_| sections.code) {
+        r.push_str("\\b ");
+        rtf_escape(&mut r, &appendix_title(n, &source.name), Mode::Inline);
+        r.push_str("\\b0\\par\n");
+        push_block(
+            &mut r,
+            &source.code,
+            *chunk_lines,
+            mono,
+            Coloring::Syntax {
+                numbered: *line_numbers,
+            },
+        );
+        r.push_str("\\pard\\f0\\fs24\\par\n");
+        if let Some(Some(png)) = code_images.get(listed.len() + n) {
+            push_png(&mut r, png, *image_scale, *compat, text_width)?;
+            r.push_str("\\par\n");
+        }
+    }
+
+    r.push_str("\\pard\\f0\\fs16 ");
+    for source in sources.iter() {
+        let short = &source.sha256[..FINGERPRINT_LEN.min(source.sha256.len())];
+        rtf_escape(
+            &mut r,
+            &format!("SHA-256 {}: {short}", source.name),
+            Mode::Inline,
+        );
+        r.push_str("\\line\n");
+    }
+    r.push_str("\\par\n");
+
+    if let Some((png, caption)) = qr {
+        r.push_str("\\pard\\qc ");
+        push_png(&mut r, png, 1, *compat, text_width)?;
+        r.push_str("\\fs16 ");
+        rtf_escape(&mut r, caption, Mode::Inline);
+        r.push_str("\\par\n\\pard\\f0\\fs24\\par\n");
+    }
 
     if *watermark {
-        r.push_str("\\pard\\qc\\f0\\fs16\\i {\\field{\\*\\fldinst{HYPERLINK \"https://github.com/cat-forgor/AssignmentPacker\"}}{\\fldrslt \\cf1\\ul ");
-        rtf_escape(&mut r, WATERMARK, Mode::Inline);
-        r.push_str("\\ul0\\cf0 }}\\i0\\par\n");
+        r.push_str("\\pard\\qc\\f0\\fs16\\i ");
+        push_link(
+            &mut r,
+            "https://github.com/cat-forgor/AssignmentPacker",
+            WATERMARK,
+        );
+        r.push_str("\\i0\\par\n");
     }
     r.push_str("}\n");
 
     Ok(r.into_bytes())
 }
 
+/// The parts of the doc that can be moved around.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Part {
+    Header,
+    Code,
+    Compile,
+    Runs,
+    Notes,
+}
+
+/// The parts to write, in order. `order` is the `sections` list, where the
+/// screenshot and the output both stand for the runs, and a part left off it
+/// isn't written. An empty list is the usual doc.
+fn part_order(order: &[DocSection]) -> Vec<Part> {
+    if order.is_empty() {
+        return vec![
+            Part::Header,
+            Part::Code,
+            Part::Compile,
+            Part::Runs,
+            Part::Notes,
+        ];
+    }
+    let mut parts: Vec<Part> = Vec::new();
+    for section in order {
+        let part = match section {
+            DocSection::Header => Part::Header,
+            DocSection::Code => Part::Code,
+            DocSection::CompileLog => Part::Compile,
+            DocSection::Screenshot | DocSection::Output => Part::Runs,
+            DocSection::Notes => Part::Notes,
+        };
+        if !parts.contains(&part) {
+            parts.push(part);
+        }
+    }
+    parts
+}
+
+/// The family and name of the text font, then of the listing font. Google
+/// Docs has neither of Word's and falls back to a proportional one for the
+/// code, so it gets the closest fonts it does have, along with the family to
+/// fall back on for any the config names instead.
+fn default_fonts(compat: DocCompat) -> [(&'static str, &'static str); 2] {
+    match compat {
+        DocCompat::Word => [("", "Calibri"), ("", "Consolas")],
+        DocCompat::Gdocs => [("\\fswiss", "Arial"), ("\\fmodern", "Courier New")],
+    }
+}
+
+/// Checks a doc template is RTF and only asks for what can be filled in.
+pub fn check_template(rtf: &str) -> Result<()> {
+    if !rtf.trim_start_matches('\u{feff}').starts_with("{\\rtf") {
+        return Err(Error::Validation(
+            "the doc template isn't an RTF file".into(),
+        ));
+    }
+    template_font_table(rtf)?;
+    let keys = template_keys(rtf);
+    if keys.is_empty() {
+        return Err(Error::Validation(format!(
+            "the doc template has no placeholders, add some of {}",
+            key_list()
+        )));
+    }
+    if let Some(key) = keys.iter().find(|k| !TEMPLATE_KEYS.contains(k)) {
+        return Err(Error::Validation(format!(
+            "the doc template has an unknown placeholder {{{{{key}}}}}, use {}",
+            key_list()
+        )));
+    }
+    Ok(())
+}
+
+fn key_list() -> String {
+    let keys: Vec<String> = TEMPLATE_KEYS
+        .iter()
+        .map(|k| format!("{{{{{k}}}}}"))
+        .collect();
+    keys.join(", ")
+}
+
+fn placeholder_re() -> Regex {
+    Regex::new(PLACEHOLDER).expect("placeholder pattern is valid")
+}
+
+fn template_keys(rtf: &str) -> Vec<&str> {
+    placeholder_re()
+        .captures_iter(rtf)
+        .filter_map(|c| c.get(1).or(c.get(2)))
+        .map(|m| m.as_str())
+        .collect()
+}
+
+/// Where the template's font table is, up to its closing brace.
+fn template_font_table(rtf: &str) -> Result<Range<usize>> {
+    let missing = || Error::Validation("the doc template has no font table".into());
+    let start = rtf.find("{\\fonttbl").ok_or_else(missing)?;
+    let mut depth = 0;
+    let mut bytes = rtf.bytes().enumerate().skip(start);
+    while let Some((i, b)) = bytes.next() {
+        match b {
+            b'\\' => {
+                bytes.next();
+            }
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(start..i);
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(missing())
+}
+
+/// The doc as the template lays it out. The listing font is added to the
+/// template's own fonts so the code lines up whatever those are. Listings
+/// are left uncolored, since the template's color table is its own.
+fn fill_template(
+    template: &Template,
+    opts: &DocOptions<'_>,
+    settings: &RtfBuilder,
+) -> Result<Vec<u8>> {
+    let rtf = template.rtf.as_str();
+    check_template(rtf)?;
+    let code_font = settings.code_font.as_deref().map(check_font).transpose()?;
+    check_code_size(settings.code_size)?;
+    check_margin(settings.margin)?;
+    let (paper_w, _) = paper_size(settings.page);
+    let text_width = paper_w - 2 * cm_to_twips(settings.margin);
+
+    let table = template_font_table(rtf)?;
+    let font = Regex::new(r"\\f(\d+)")
+        .expect("font pattern is valid")
+        .captures_iter(&rtf[table.clone()])
+        .filter_map(|c| c[1].parse::<usize>().ok())
+        .max()
+        .map_or(0, |n| n + 1);
+    let mono = Mono {
+        font,
+        fs: settings.code_size * 2,
+    };
+    let [_, (family, default)] = default_fonts(settings.compat);
+    let mut with_font = rtf[..table.end].to_string();
+    with_font.push_str(&format!("{{\\f{font}{family} "));
+    rtf_escape(&mut with_font, code_font.unwrap_or(default), Mode::Inline);
+    with_font.push_str(";}");
+    with_font.push_str(&rtf[table.end..]);
+
+    let mut fills = HashMap::new();
+    for key in template_keys(rtf) {
+        if !fills.contains_key(key) {
+            fills.insert(
+                key,
+                template_fill(key, template, opts, settings, mono, text_width)?,
+            );
+        }
+    }
+    // One pass, so a placeholder in the filled-in code is left as it is
+    let doc = placeholder_re().replace_all(&with_font, |c: &Captures| {
+        let key = c.get(1).or(c.get(2)).map_or("", |m| m.as_str());
+        fills.get(key).cloned().unwrap_or_default()
+    });
+    Ok(doc.into_owned().into_bytes())
+}
+
+/// What a template's `{{key}}` is replaced with.
+fn template_fill(
+    key: &str,
+    template: &Template,
+    opts: &DocOptions<'_>,
+    settings: &RtfBuilder,
+    mono: Mono,
+    text_width: u64,
+) -> Result<String> {
+    let mut r = String::new();
+    let chunk_lines = settings.chunk_lines;
+    match key {
+        "assignment" => rtf_escape(&mut r, opts.assignment, Mode::Inline),
+        "name" => rtf_escape(&mut r, opts.name, Mode::Inline),
+        "student_id" => rtf_escape(&mut r, opts.student_id, Mode::Inline),
+        "date" => rtf_escape(&mut r, &template.date, Mode::Inline),
+        "code" => {
+            for source in opts.sources {
+                if opts.sources.len() > 1 {
+                    r.push_str("{\\i ");
+                    rtf_escape(&mut r, &source.name, Mode::Inline);
+                    r.push_str("\\par}\n");
+                }
+                push_block(&mut r, &source.code, chunk_lines, mono, Coloring::Plain);
+            }
+        }
+        "screenshot" => {
+            for png in opts.screenshots.iter().flatten() {
+                push_png(&mut r, png, opts.image_scale, settings.compat, text_width)?;
+            }
+        }
+        "output" => {
+            let cases = &opts.capture.cases;
+            for (k, case) in cases.iter().enumerate() {
+                if cases.len() > 1 {
+                    let label = case.name.clone().unwrap_or(format!("Run {}", k + 1));
+                    r.push_str("{\\i ");
+                    rtf_escape(&mut r, &label, Mode::Inline);
+                    r.push_str("\\par}\n");
+                }
+                let output = &case.formatted_output;
+                let truncated = truncate_middle(output, opts.output_cap);
+                let output = truncated.as_deref().unwrap_or(output);
+                push_block(&mut r, output, chunk_lines, mono, Coloring::Plain);
+            }
+            if let Some(reason) = opts.not_run {
+                r.push_str("{\\i ");
+                rtf_escape(
+                    &mut r,
+                    &format!("Output not captured ({reason})."),
+                    Mode::Inline,
+                );
+                r.push_str("\\par}\n");
+            }
+        }
+        _ => {}
+    }
+    Ok(r)
+}
+
+/// The notes file's blocks under their own heading. List items hang off
+/// their number or bullet.
+fn push_notes(r: &mut String, notes: &[NoteBlock], chunk_lines: usize, mono: Mono) {
+    r.push_str("\\b ");
+    rtf_escape(r, notes::HEADING, Mode::Inline);
+    r.push_str("\\b0\\par\n");
+    for block in notes {
+        match block {
+            NoteBlock::Heading(_, text) => {
+                r.push_str("\\b ");
+                rtf_escape(r, text, Mode::Inline);
+                r.push_str("\\b0\\par\n");
+            }
+            NoteBlock::Para(text) => {
+                rtf_escape(r, text, Mode::Inline);
+                r.push_str("\\par\n");
+            }
+            NoteBlock::List { ordered, items } => {
+                for (i, item) in items.iter().enumerate() {
+                    r.push_str("\\pard\\li360\\fi-360\\f0\\fs24 ");
+                    rtf_escape(
+                        r,
+                        &format!("{} {item}", notes::marker(*ordered, i)),
+                        Mode::Inline,
+                    );
+                    r.push_str("\\par\n");
+                }
+                r.push_str("\\pard\\f0\\fs24 ");
+            }
+            NoteBlock::Code(text) => {
+                push_block(r, text, chunk_lines, mono, Coloring::Plain);
+                r.push_str("\\pard\\f0\\fs24 ");
+            }
+        }
+    }
+    r.push_str("\\par\n");
+}
+
+/// A blue, underlined hyperlink field. `url` must already be free of quotes
+/// and RTF control characters, see `Link::validate`.
+fn push_link(r: &mut String, url: &str, text: &str) {
+    r.push_str(&format!(
+        "{{\\field{{\\*\\fldinst{{HYPERLINK \"{url}\"}}}}{{\\fldrslt \\cf1\\ul "
+    ));
+    rtf_escape(r, text, Mode::Inline);
+    r.push_str("\\ul0\\cf0 }}");
+}
+
+fn push_summary_table(
+    r: &mut String,
+    capture: &RunCapture,
+    checks: &[Option<Comparison>],
+    expect_exit: i32,
+) {
+    r.push_str("\\b Test Summary\\b0\\par\n");
+    push_table(
+        r,
+        &SUMMARY_WIDTHS,
+        &SUMMARY_HEADER,
+        &summary_rows(capture, checks, expect_exit),
+    );
+}
+
+/// Cell borders, the same single thin line on every side.
+const CELL_BORDERS: &str = "\\clbrdrt\\brdrs\\brdrw10\\clbrdrl\\brdrs\\brdrw10\\clbrdrb\\brdrs\\brdrw10\\clbrdrr\\brdrs\\brdrw10";
+
+/// Writes a bordered table. `widths` are column widths in twips. The header
+/// row is bold on a grey background and repeats at the top of each page the
+/// table runs onto. Cells are escaped, and missing cells are left blank.
+fn push_table(r: &mut String, widths: &[u32], header: &[&str], rows: &[Vec<String>]) {
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    push_table_row(r, widths, &header, true);
+    for row in rows {
+        push_table_row(r, widths, row, false);
+    }
+    r.push_str("\\pard\\f0\\fs24\\par\n");
+}
+
+fn push_table_row(r: &mut String, widths: &[u32], cells: &[String], header: bool) {
+    r.push_str(if header {
+        "\\trowd\\trhdr\\trgaph108"
+    } else {
+        "\\trowd\\trgaph108"
+    });
+    let mut right = 0;
+    for w in widths {
+        right += w;
+        r.push_str(CELL_BORDERS);
+        if header {
+            r.push_str("\\clcbpat2");
+        }
+        r.push_str(&format!("\\cellx{right}"));
+    }
+    r.push('\n');
+    for k in 0..widths.len() {
+        let (open, close) = if header {
+            ("\\pard\\intbl\\b ", "\\b0\\cell\n")
+        } else {
+            ("\\pard\\intbl ", "\\cell\n")
+        };
+        r.push_str(open);
+        rtf_escape(r, cells.get(k).map_or("", String::as_str), Mode::Inline);
+        r.push_str(close);
+    }
+    r.push_str("\\row\n");
+}
+
+/// Checks a font name from the config, which ends at the first `;` in the
+/// font table.
+pub fn check_font(name: &str) -> Result<&str> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(';') || name.chars().any(char::is_control) {
+        return Err(Error::Validation(format!(
+            "'{name}' isn't a usable font name"
+        )));
+    }
+    Ok(name)
+}
+
+pub fn check_code_size(points: u32) -> Result<()> {
+    if !(6..=24).contains(&points) {
+        return Err(Error::Validation(
+            "doc-code-size must be 6-24 points".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Puts the assignment, student and page number on every page, so a page
+/// that comes loose from a printout can still be told apart. A cover page
+/// is left without them.
+fn push_header_footer(r: &mut String, assignment: &str, name: &str, student_id: &str, cover: bool) {
+    if cover {
+        r.push_str("\\titlepg{\\headerf\\pard\\par}{\\footerf\\pard\\par}\n");
+    }
+    r.push_str("{\\header\\pard\\qr\\f0\\fs18 ");
+    rtf_escape(
+        r,
+        &format!("{assignment} \u{2014} {name} ({student_id})"),
+        Mode::Inline,
+    );
+    r.push_str("\\par}\n");
+    r.push_str(
+        "{\\footer\\pard\\qc\\f0\\fs18 Page {\\field{\\*\\fldinst{PAGE}}{\\fldrslt 1}} \
+         of {\\field{\\*\\fldinst{NUMPAGES}}{\\fldrslt 1}}\\par}\n",
+    );
+}
+
+/// A title page: the assignment in large type about a third of the way
+/// down, then the course, student and date, centered, and a page break.
+fn push_cover(r: &mut String, assignment: &str, name: &str, student_id: &str, cover: &Cover) {
+    r.push_str("\\pard\\qc\\sb3600\\sa480\\b\\fs56 ");
+    rtf_escape(r, assignment, Mode::Inline);
+    r.push_str("\\b0\\par\n\\pard\\qc\\sa240\\fs32 ");
+    if let Some(course) = &cover.course {
+        rtf_escape(r, course, Mode::Inline);
+        r.push_str("\\par\n");
+    }
+    rtf_escape(r, &format!("{name} ({student_id})"), Mode::Inline);
+    r.push_str("\\par\n\\fs28 ");
+    rtf_escape(r, &cover.date, Mode::Inline);
+    r.push_str("\\par\n\\page\n\\pard\\sa120\\sl240\\slmult1\\f0\\fs24\n");
+}
+
+/// Embeds a PNG. `scale` is how many pixels make up one display pixel, so a
+/// high-resolution image keeps the size of a normal one on the page.
+/// `text_width` is the room between the margins, in twips.
+fn push_png(
+    r: &mut String,
+    png: &[u8],
+    scale: u32,
+    compat: DocCompat,
+    text_width: u64,
+) -> Result<()> {
+    let img = image::load_from_memory_with_format(png, ImageFormat::Png)
+        .map_err(|e| Error::Image(format!("reading screenshot: {e}")))?;
+    let pw = img.width().max(1) as u64;
+    let ph = img.height().max(1) as u64;
+    let (goal_w, goal_h) = goal_size(pw, ph, scale);
+    // Google Docs doesn't always honor the goal size and lets a wide picture
+    // run off the page, so it gets a size that fits and says 100% outright
+    let sizing = match compat {
+        DocCompat::Word => format!("\\picwgoal{goal_w}\\pichgoal{goal_h}"),
+        DocCompat::Gdocs => {
+            let (w, h) = fit_width(goal_w, goal_h, text_width);
+            format!("\\picwgoal{w}\\pichgoal{h}\\picscalex100\\picscaley100")
+        }
+    };
+    let hex = hex_wrap(png, 64);
+    r.push_str(&format!(
+        "{{\\pict\\pngblip\\picw{pw}\\pich{ph}{sizing}\n{hex}}}\n\\par\n"
+    ));
+    Ok(())
+}
+
+/// Width and height of the paper in twips.
+fn paper_size(page: PageSize) -> (u64, u64) {
+    match page {
+        PageSize::Letter => (12_240, 15_840),
+        PageSize::A4 => (11_906, 16_838),
+    }
+}
+
+fn cm_to_twips(cm: f64) -> u64 {
+    (cm * 1440.0 / 2.54).round() as u64
+}
+
+pub fn check_margin(cm: f64) -> Result<()> {
+    if !(0.5..=5.0).contains(&cm) {
+        return Err(Error::Validation("margin must be 0.5-5 cm".into()));
+    }
+    Ok(())
+}
+
+/// Shrinks a `w` by `h` size to at most `max_w` wide, keeping its shape.
+fn fit_width(w: u64, h: u64, max_w: u64) -> (u64, u64) {
+    if w <= max_w {
+        return (w, h);
+    }
+    (max_w, h.saturating_mul(max_w) / w.max(1))
+}
+
+/// Display size in twips (15 per pixel at 96 DPI) of a `scale`x image.
+fn goal_size(pw: u64, ph: u64, scale: u32) -> (u64, u64) {
+    let scale = u64::from(scale.max(1));
+    (pw.saturating_mul(15) / scale, ph.saturating_mul(15) / scale)
+}
+
+/// The font listings are set in: its number in the font table and its size
+/// in half-points, as RTF measures it.
+#[derive(Clone, Copy)]
+struct Mono {
+    font: usize,
+    fs: u32,
+}
+
 #[derive(Clone, Copy, PartialEq)]
 enum Mode {
     Inline,
     Block,
 }
 
+/// How a listing's text is colored.
+#[derive(Clone, Copy)]
+enum Coloring<'a> {
+    Plain,
+    /// Program output, its ANSI color markers looked up in the palette.
+    Ansi(&'a [u8]),
+    /// Source code, highlighted like an editor would, optionally with a
+    /// dimmed line number in front of each line.
+    Syntax {
+        numbered: bool,
+    },
+}
+
+/// Writes a monospace listing as paragraphs of at most `chunk_lines` lines,
+/// since some viewers choke on one huge group. Each paragraph after the
+/// first opens with an ignorable `\\apcontinued` marker, and with no space
+/// between them the listing reads as one block.
+fn push_block(r: &mut String, text: &str, chunk_lines: usize, mono: Mono, coloring: Coloring<'_>) {
+    // Highlighted as a whole, so a comment running past a split keeps its color
+    let spans = match coloring {
+        Coloring::Syntax { .. } => highlight::spans(text),
+        _ => Vec::new(),
+    };
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let size = if chunk_lines == 0 {
+        lines.len()
+    } else {
+        chunk_lines
+    };
+    let chunks: Vec<&[&str]> = match lines.len() {
+        0 => vec![&[]],
+        _ => lines.chunks(size.max(1)).collect(),
+    };
+    let last = chunks.len() - 1;
+    let width = lines.len().to_string().len();
+    let mut start = 0;
+    let mut line = 1;
+    for (i, chunk) in chunks.iter().enumerate() {
+        r.push_str(&format!("{{\\pard\\f{}\\fs{} ", mono.font, mono.fs));
+        if i > 0 {
+            r.push_str("{\\*\\apcontinued}");
+        }
+        let len: usize = chunk.iter().map(|line| line.len()).sum();
+        let mut end = start + len;
+        // The paragraph break stands in for the newline it was split at
+        if i < last {
+            end -= 1;
+        }
+        let body = &text[start..end];
+        match coloring {
+            Coloring::Plain => rtf_escape(r, body, Mode::Block),
+            Coloring::Ansi(palette) => push_colored(r, body, palette),
+            Coloring::Syntax { numbered } => {
+                let gutter = numbered.then_some((line, width));
+                push_highlighted(r, &spans, start..end, gutter);
+            }
+        }
+        r.push_str("\\par}\n");
+        start += len;
+        line += chunk.len();
+    }
+}
+
+/// Token colors for code on a white page, the light theme's, then the gray
+/// of the line numbers. They follow the link blue and the table gray in the
+/// color table.
+const CODE_COLORS: [[u8; 3]; 6] = [
+    [0, 0, 200],
+    [160, 30, 30],
+    [0, 128, 0],
+    [9, 134, 88],
+    [128, 0, 128],
+    [150, 150, 150],
+];
+
+const LINE_NUMBER_COLOR: usize = 8;
+
+/// Color table entry of the first ANSI color, after the code colors.
+const FIRST_ANSI_COLOR: usize = 3 + CODE_COLORS.len();
+
+fn syntax_color(kind: TokenKind) -> usize {
+    match kind {
+        TokenKind::Plain => 0,
+        TokenKind::Keyword => 3,
+        TokenKind::String => 4,
+        TokenKind::Comment => 5,
+        TokenKind::Number => 6,
+        TokenKind::Preproc => 7,
+    }
+}
+
+/// Writes the part of a highlighted listing that falls in `range`, each
+/// token in its color. `spans` covers the whole listing. With a `gutter` of
+/// the first line's number and the width to pad to, each line opens with
+/// its number.
+fn push_highlighted(
+    buf: &mut String,
+    spans: &[(TokenKind, &str)],
+    range: Range<usize>,
+    gutter: Option<(usize, usize)>,
+) {
+    let mut pos = 0;
+    let mut current = 0;
+    let mut line_start = true;
+    let mut line = gutter.map_or(0, |(first, _)| first);
+    for &(kind, text) in spans {
+        let (from, to) = (pos, pos + text.len());
+        pos = to;
+        if to <= range.start {
+            continue;
+        }
+        if from >= range.end {
+            break;
+        }
+        // Chunks end at newlines, so the cuts land on char boundaries
+        let cut = range.start.saturating_sub(from)..text.len() - to.saturating_sub(range.end);
+        for piece in text[cut].split_inclusive('\n') {
+            if let Some((_, width)) = gutter
+                && line_start
+            {
+                // A group, so the token's color carries on after it
+                buf.push_str(&format!("{{\\cf{LINE_NUMBER_COLOR} {line:>width$}  }}"));
+                line += 1;
+            }
+            let cf = syntax_color(kind);
+            if cf != current {
+                buf.push_str(&format!("\\cf{cf} "));
+                current = cf;
+            }
+            rtf_escape(buf, piece, Mode::Block);
+            line_start = piece.ends_with('\n');
+        }
+    }
+}
+
+/// The ANSI colors the runs' text listings use, in color table order.
+fn ansi_palette(capture: &RunCapture) -> Vec<u8> {
+    let mut used: Vec<u8> = capture
+        .cases
+        .iter()
+        .flat_map(|c| ansi::pieces(&c.colored_output))
+        .filter_map(|piece| match piece {
+            Piece::Marker(ch) => match ansi::mark(ch) {
+                Some(Mark::Color(n)) => Some(n),
+                _ => None,
+            },
+            Piece::Text(_) => None,
+        })
+        .collect();
+    used.sort_unstable();
+    used.dedup();
+    used
+}
+
+/// Writes a block of program output in the colors it was printed in, each
+/// looked up in `palette`.
+fn push_colored(buf: &mut String, text: &str, palette: &[u8]) {
+    let mut plain = String::new();
+    for piece in ansi::pieces(text) {
+        let mark = match piece {
+            Piece::Text(ch) => {
+                plain.push(ch);
+                continue;
+            }
+            Piece::Marker(ch) => ansi::mark(ch),
+        };
+        let Some(mark) = mark else {
+            continue;
+        };
+        rtf_escape(buf, &plain, Mode::Block);
+        plain.clear();
+        let cf = match mark {
+            Mark::Color(n) => palette
+                .iter()
+                .position(|&p| p == n)
+                .map_or(0, |i| i + FIRST_ANSI_COLOR),
+            Mark::Reset => 0,
+        };
+        buf.push_str(&format!("\\cf{cf} "));
+    }
+    rtf_escape(buf, &plain, Mode::Block);
+}
+
 fn rtf_escape(buf: &mut String, text: &str, mode: Mode) {
     for ch in text.chars() {
         match ch {
@@ -140,6 +1286,50 @@ fn hex_wrap(bytes: &[u8], per_line: usize) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::render::doc::{Sections, SourceFile};
+    use crate::render::rtf_check;
+    use crate::terminal::CaseCapture;
+    use proptest::prelude::*;
+
+    const MONO: Mono = Mono { font: 1, fs: 18 };
+
+    /// A `main.c` holding `code`.
+    fn main_c(code: impl Into<String>) -> [SourceFile; 1] {
+        [SourceFile {
+            name: "main.c".into(),
+            code: code.into(),
+            sha256: "ab".repeat(32),
+        }]
+    }
+
+    /// A plain doc for `sources` and `capture`, for each test to change the
+    /// fields it's about.
+    fn opts<'a>(sources: &'a [SourceFile], capture: &'a RunCapture) -> DocOptions<'a> {
+        DocOptions {
+            assignment: "A1",
+            name: "Joe",
+            student_id: "1",
+            sources,
+            capture,
+            not_run: None,
+            screenshots: &[],
+            checks: &[],
+            watermark: false,
+            output_cap: 0,
+            transcript_name: None,
+            compile_note: false,
+            code_images: &[],
+            image_scale: 1,
+            compile_phase: None,
+            sections: Sections::default(),
+            sanitize: &[],
+            expect_exit: 0,
+            links: &[],
+            qr: None,
+            notes: &[],
+            appendix: false,
+        }
+    }
 
     #[test]
     fn rtf_escapes_special_chars() {
@@ -183,6 +1373,27 @@ mod tests {
         assert_eq!(buf, "\\u233?");
     }
 
+    #[test]
+    fn scaled_images_keep_their_display_size() {
+        assert_eq!(goal_size(200, 100, 1), (3000, 1500));
+        assert_eq!(goal_size(400, 200, 2), (3000, 1500));
+        assert_eq!(goal_size(600, 300, 3), (3000, 1500));
+    }
+
+    #[test]
+    fn gdocs_images_fit_the_page() {
+        let mut png = Vec::new();
+        image::RgbImage::new(1000, 10)
+            .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let mut word = String::new();
+        push_png(&mut word, &png, 1, DocCompat::Word, 8640).unwrap();
+        assert!(word.contains("\\picwgoal15000\\pichgoal150\n"));
+        let mut gdocs = String::new();
+        push_png(&mut gdocs, &png, 1, DocCompat::Gdocs, 8640).unwrap();
+        assert!(gdocs.contains("\\picwgoal8640\\pichgoal86\\picscalex100\\picscaley100\n"));
+    }
+
     #[test]
     fn hex_wraps_at_boundary() {
         let out = hex_wrap(&[0xAB, 0xCD, 0xEF, 0x01], 2);
@@ -194,4 +1405,567 @@ mod tests {
         let out = hex_wrap(&[0xFF], 4);
         assert_eq!(out, "ff\n");
     }
+
+    #[test]
+    fn links_are_hyperlink_fields() {
+        let mut r = String::new();
+        push_link(&mut r, "https://x.dev/a?b=1", "x {dev}");
+        assert_eq!(
+            r,
+            "{\\field{\\*\\fldinst{HYPERLINK \"https://x.dev/a?b=1\"}}{\\fldrslt \\cf1\\ul x \\{dev\\}\\ul0\\cf0 }}"
+        );
+    }
+
+    #[test]
+    fn table_escapes_cells_and_pads_short_rows() {
+        let mut r = String::new();
+        push_table(&mut r, &[1000, 2000], &["A", "B"], &[vec!["{x}".into()]]);
+        assert!(r.starts_with("\\trowd\\trhdr"));
+        assert_eq!(r.matches("\\cellx1000").count(), 2);
+        assert_eq!(r.matches("\\cellx3000").count(), 2);
+        assert!(r.contains("\\pard\\intbl\\b A\\b0\\cell"));
+        assert!(r.contains("\\pard\\intbl \\{x\\}\\cell\n\\pard\\intbl \\cell\n\\row"));
+    }
+
+    #[test]
+    fn summary_table_has_a_row_per_case() {
+        let case = |name: &str, exit_code, error_case| CaseCapture {
+            name: Some(name.into()),
+            formatted_output: String::new(),
+            colored_output: String::new(),
+            stdout: String::new(),
+            screenshot: Vec::new(),
+            memcheck: None,
+            sanitizer_report: None,
+            exit_code,
+            error_case,
+            duration: std::time::Duration::from_millis(12),
+        };
+        let capture = RunCapture {
+            command_display: "./a.out".into(),
+            cases: vec![case("small", Some(0), false), case("bad", Some(1), true)],
+            compile_time: None,
+            run_time: std::time::Duration::ZERO,
+            compile: None,
+            environment: None,
+        };
+        let checks = [
+            Some(Comparison {
+                passed: true,
+                unified: String::new(),
+            }),
+            None,
+        ];
+        let mut r = String::new();
+        push_summary_table(&mut r, &capture, &checks, 0);
+        assert_eq!(r.matches("\\row").count(), 3);
+        assert!(
+            r.contains("\\intbl small\\cell\n\\pard\\intbl 0 (success)\\cell\n\\pard\\intbl PASS")
+        );
+        assert!(r.contains(
+            "\\intbl bad\\cell\n\\pard\\intbl 1\\cell\n\\pard\\intbl -\\cell\n\\pard\\intbl 12ms"
+        ));
+    }
+
+    #[test]
+    fn sections_can_be_left_out() {
+        let capture = RunCapture {
+            command_display: "./a.out".into(),
+            cases: vec![CaseCapture {
+                name: None,
+                formatted_output: "STDOUT\nhi".into(),
+                colored_output: format!(
+                    "STDOUT\n{}hi{}",
+                    ansi::marker(Some(11)),
+                    ansi::COLOR_RESET
+                ),
+                stdout: "hi".into(),
+                screenshot: Vec::new(),
+                memcheck: None,
+                sanitizer_report: None,
+                exit_code: Some(0),
+                error_case: false,
+                duration: std::time::Duration::ZERO,
+            }],
+            compile_time: None,
+            run_time: std::time::Duration::ZERO,
+            compile: None,
+            environment: Some("Ubuntu 24.04 LTS, x86_64, bash".into()),
+        };
+        let sources = main_c("int main;");
+        let png = crate::render::qr::render_qr("x").unwrap();
+        let build = |sections| {
+            let doc = build_rtf(
+                &DocOptions {
+                    screenshots: &[vec![png.clone()]],
+                    checks: &[None],
+                    sections,
+                    ..opts(&sources, &capture)
+                },
+                &RtfBuilder::default(),
+            )
+            .unwrap();
+            String::from_utf8(doc).unwrap()
+        };
+
+        let full = build(Sections::default());
+        assert!(full.contains("\\cf3 int\\cf0  main;"));
+        assert!(full.contains("\\pict"));
+        assert!(full.contains("Captured Output"));
+        // Bright yellow, darkened to read on the page
+        assert!(full.contains("\\blue150;\\red153\\green153\\blue42;}"));
+        assert!(full.contains("STDOUT\\line\n\\cf9 hi\\cf0 \\par}"));
+        assert!(full.contains("Environment: Ubuntu 24.04 LTS, x86_64, bash\\par"));
+        assert!(full.contains("{\\header\\pard\\qr\\f0\\fs18 A1 \\u8212? Joe (1)\\par}"));
+        assert!(full.contains("{\\fldrslt 1}} of {\\field{\\*\\fldinst{NUMPAGES}}"));
+        assert!(!full.contains("\\titlepg"));
+        assert!(rtf_check::check(full.as_bytes()).is_ok());
+
+        let slim = build(Sections {
+            code: false,
+            screenshot: false,
+            text_output: false,
+            compile_log: false,
+            environment: false,
+            ..Sections::default()
+        });
+        assert!(!slim.contains("\\cf3 int\\cf0  main;"));
+        assert!(!slim.contains("\\pict"));
+        assert!(!slim.contains("Captured Output"));
+        assert!(!slim.contains("Environment:"));
+        assert!(slim.contains("Program Run\\b0"));
+        assert!(slim.contains("Exit code: 0 (success)"));
+        assert!(slim.contains("SHA-256 main.c"));
+    }
+
+    #[test]
+    fn code_is_highlighted_across_paragraphs() {
+        let code = "#include <stdio.h>\n/* one\ntwo */ int x = 1; // \"{}\"\n";
+        let mut r = String::new();
+        push_block(&mut r, code, 2, MONO, Coloring::Syntax { numbered: false });
+        assert_eq!(
+            r,
+            "{\\pard\\f1\\fs18 \\cf7 #include\\cf0  \\cf4 <stdio.h>\\cf0 \\line\n\\cf5 /* one\\par}\n\
+             {\\pard\\f1\\fs18 {\\*\\apcontinued}\\cf5 two */\\cf0  \\cf3 int\\cf0  x = \\cf6 1\\cf0 ; \\cf5 // \"\\{\\}\"\\cf0 \\line\n\\par}\n"
+        );
+        assert!(rtf_check::check(format!("{{\\rtf1 {r}}}").as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn line_numbers_run_on_across_paragraphs() {
+        let code: String = (1..=10).map(|n| format!("x{n}\n")).collect();
+        let mut r = String::new();
+        push_block(&mut r, &code, 4, MONO, Coloring::Syntax { numbered: true });
+        assert!(r.starts_with("{\\pard\\f1\\fs18 {\\cf8  1  }x1\\line\n{\\cf8  2  }x2"));
+        assert!(r.contains("{\\*\\apcontinued}{\\cf8  5  }x5"));
+        assert!(r.contains("{\\cf8 10  }x10\\line\n\\par}"));
+        assert!(!r.contains("11"));
+
+        let mut r = String::new();
+        push_block(
+            &mut r,
+            "/* a\n\nb */ int\n",
+            0,
+            MONO,
+            Coloring::Syntax { numbered: true },
+        );
+        assert_eq!(
+            r,
+            "{\\pard\\f1\\fs18 {\\cf8 1  }\\cf5 /* a\\line\n{\\cf8 2  }\\line\n{\\cf8 3  }b */\\cf0  \\cf3 int\\cf0 \\line\n\\par}\n"
+        );
+    }
+
+    #[test]
+    fn cover_page_comes_before_a_page_break() {
+        let mut cover = Cover {
+            course: Some("CS101".into()),
+            date: "2026-10-16".into(),
+        };
+        let mut r = String::new();
+        push_cover(&mut r, "Assignment7", "Joe {B}", "123", &cover);
+        assert!(r.starts_with("\\pard\\qc\\sb3600\\sa480\\b\\fs56 Assignment7\\b0\\par\n"));
+        assert!(r.contains(
+            "\\fs32 CS101\\par\nJoe \\{B\\} (123)\\par\n\\fs28 2026-10-16\\par\n\\page\n"
+        ));
+        assert!(r.ends_with("\\pard\\sa120\\sl240\\slmult1\\f0\\fs24\n"));
+        assert!(rtf_check::check(format!("{{\\rtf1 {r}}}").as_bytes()).is_ok());
+
+        cover.course = None;
+        let mut r = String::new();
+        push_cover(&mut r, "Assignment7", "Joe", "123", &cover);
+        assert!(r.contains("\\fs32 Joe (123)\\par\n"));
+
+        // The cover page gets an empty header and footer of its own
+        let mut r = String::new();
+        push_header_footer(&mut r, "Assignment7", "Joe", "123", true);
+        assert!(r.starts_with("\\titlepg{\\headerf\\pard\\par}{\\footerf\\pard\\par}\n{\\header"));
+    }
+
+    #[test]
+    fn extra_sources_get_appendices() {
+        let capture = RunCapture::skipped("./a.out".into());
+        let sources: Vec<SourceFile> = ["main.c", "utils.h", "utils.c"]
+            .iter()
+            .map(|name| SourceFile {
+                name: name.to_string(),
+                code: format!("// {name}\n"),
+                sha256: "ab".repeat(32),
+            })
+            .collect();
+        let build = |appendix| {
+            let doc = build_rtf(
+                &DocOptions {
+                    not_run: Some("no C compiler found"),
+                    appendix,
+                    ..opts(&sources, &capture)
+                },
+                &RtfBuilder::default(),
+            )
+            .unwrap();
+            String::from_utf8(doc).unwrap()
+        };
+
+        let doc = build(true);
+        let code = doc.find("\\b Code\\b0").unwrap();
+        let a = doc.find("\\b Appendix A: utils.h\\b0").unwrap();
+        let b = doc.find("\\b Appendix B: utils.c\\b0").unwrap();
+        assert!(code < a && a < b);
+        assert!(doc[code..a].contains("// main.c"));
+        assert!(!doc[code..a].contains("// utils"));
+        assert!(doc[b..].contains("// utils.c"));
+        assert!(!build(false).contains("Appendix"));
+        assert_eq!(appendix_title(26, "x.h"), "Appendix AA: x.h");
+    }
+
+    #[test]
+    fn sections_can_be_reordered() {
+        use DocSection::*;
+        assert_eq!(
+            part_order(&[Header, Screenshot, Code, Output]),
+            [Part::Header, Part::Runs, Part::Code]
+        );
+        assert_eq!(part_order(&[Notes, Header]), [Part::Notes, Part::Header]);
+        assert_eq!(part_order(&[Code]), [Part::Code]);
+        assert_eq!(part_order(&[]).len(), 5);
+
+        let capture = RunCapture::skipped("./a.out".into());
+        let sources = main_c("int main;");
+        let opts = DocOptions {
+            not_run: Some("no C compiler found"),
+            ..opts(&sources, &capture)
+        };
+        let settings = RtfBuilder {
+            order: vec![Header, Screenshot, Code],
+            ..Default::default()
+        };
+        let doc = String::from_utf8(build_rtf(&opts, &settings).unwrap()).unwrap();
+        let title = doc.find("A1 Submission").unwrap();
+        let run = doc.find("\\b Program Run\\b0").unwrap();
+        let code = doc.find("\\b Code\\b0").unwrap();
+        let hash = doc.find("SHA-256 main.c").unwrap();
+        assert!(title < run && run < code && code < hash);
+        assert!(rtf_check::check(doc.as_bytes()).is_ok());
+
+        let settings = RtfBuilder {
+            order: vec![Header, Code],
+            ..Default::default()
+        };
+        let doc = String::from_utf8(build_rtf(&opts, &settings).unwrap()).unwrap();
+        assert!(doc.contains("\\b Code\\b0"));
+        assert!(!doc.contains("Program Run"));
+        assert!(!doc.contains("Command: ./a.out"));
+        let settings = RtfBuilder {
+            order: vec![Code],
+            ..Default::default()
+        };
+        let doc = String::from_utf8(build_rtf(&opts, &settings).unwrap()).unwrap();
+        assert!(!doc.contains("A1 Submission"));
+
+        assert!(DocSection::check_list(&[Code, Output, Code]).is_err());
+    }
+
+    #[test]
+    fn notes_become_their_own_section() {
+        let blocks = notes::parse("## Q1 {a}\n\nBecause.\n\n1. one\n2. two\n\n```\nx\n```\n");
+        let mut r = String::new();
+        push_notes(&mut r, &blocks, 200, MONO);
+        assert!(
+            r.starts_with("\\b Answers / Notes\\b0\\par\n\\b Q1 \\{a\\}\\b0\\par\nBecause.\\par\n")
+        );
+        assert!(r.contains("\\pard\\li360\\fi-360\\f0\\fs24 2. two\\par\n"));
+        assert!(r.contains("{\\pard\\f1\\fs18 x\\par}\n"));
+        assert!(rtf_check::check(format!("{{\\rtf1 {r}}}").as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn long_listings_are_split_into_paragraphs() {
+        let text: String = (1..=5000).map(|n| format!("line {n}\n")).collect();
+        let paragraphs = |chunk_lines| {
+            let mut r = String::new();
+            push_block(&mut r, &text, chunk_lines, MONO, Coloring::Plain);
+            r.split("\\par}\n")
+                .filter(|p| !p.is_empty())
+                .map(|p| {
+                    p.trim_start_matches("{\\pard\\f1\\fs18 ")
+                        .trim_start_matches("{\\*\\apcontinued}")
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let split = paragraphs(200);
+        assert_eq!(split.len(), 25);
+        assert!(split.iter().all(|p| p.matches("\\line").count() <= 200));
+        let joined: Vec<String> = split.iter().map(|p| unescape(p)).collect();
+        assert_eq!(joined.join("\n"), text);
+        assert_eq!(paragraphs(0).len(), 1);
+
+        let mut empty = String::new();
+        push_block(&mut empty, "", 200, MONO, Coloring::Plain);
+        assert_eq!(empty, "{\\pard\\f1\\fs18 \\par}\n");
+    }
+
+    #[test]
+    fn thousands_of_lines_keep_the_doc_valid() {
+        let code: String = (1..=3000)
+            .map(|n| format!("int x{n} = {{{n}}};\n"))
+            .collect();
+        let output: String = (1..=4000).map(|n| format!("{n}\\{n}\n")).collect();
+        let capture = RunCapture {
+            command_display: "./a.out".into(),
+            cases: vec![CaseCapture {
+                name: None,
+                formatted_output: output.clone(),
+                colored_output: format!("{}{output}", ansi::marker(Some(4))),
+                stdout: output.clone(),
+                screenshot: Vec::new(),
+                memcheck: None,
+                sanitizer_report: Some(output.clone()),
+                exit_code: Some(0),
+                error_case: false,
+                duration: std::time::Duration::ZERO,
+            }],
+            compile_time: None,
+            run_time: std::time::Duration::ZERO,
+            compile: None,
+            environment: None,
+        };
+        let sources = main_c(code);
+        let doc = build_rtf(
+            &DocOptions {
+                screenshots: &[Vec::new()],
+                checks: &[None],
+                ..opts(&sources, &capture)
+            },
+            &RtfBuilder::default(),
+        )
+        .unwrap();
+        assert!(rtf_check::check(&doc).is_ok());
+        let doc = String::from_utf8(doc).unwrap();
+        // 15 paragraphs of code, then 20 each of output and sanitizer report
+        assert_eq!(doc.matches("{\\pard\\f1\\fs18 ").count(), 55);
+        assert_eq!(doc.matches("{\\*\\apcontinued}").count(), 52);
+        for paragraph in doc.split("{\\pard\\f1\\fs18 ").skip(1) {
+            let body = &paragraph[..paragraph.find("\\par}").unwrap()];
+            assert!(body.matches("\\line").count() <= DEFAULT_CHUNK_LINES);
+        }
+    }
+
+    #[test]
+    fn skipped_run_is_noted() {
+        let capture = RunCapture::skipped("./a.out".into());
+        let sources = main_c("int main;");
+        let doc = build_rtf(
+            &DocOptions {
+                not_run: Some("no C compiler found"),
+                compile_note: true,
+                ..opts(&sources, &capture)
+            },
+            &RtfBuilder::default(),
+        )
+        .unwrap();
+        let doc = String::from_utf8(doc).unwrap();
+        assert!(doc.contains("\\cf3 int\\cf0  main;"));
+        assert!(doc.contains("Output not captured (no C compiler found)."));
+        assert!(!doc.contains("Exit code"));
+    }
+
+    #[test]
+    fn fonts_and_page_can_be_configured() {
+        let capture = RunCapture::skipped("./a.out".into());
+        let sources = main_c("int main;");
+        let opts = DocOptions {
+            not_run: Some("no C compiler found"),
+            ..opts(&sources, &capture)
+        };
+        let build = |settings| String::from_utf8(build_rtf(&opts, &settings).unwrap()).unwrap();
+
+        let doc = build(RtfBuilder {
+            body_font: Some("Times New Roman".into()),
+            code_size: 10,
+            compat: DocCompat::Gdocs,
+            ..Default::default()
+        });
+        assert!(
+            doc.contains("{\\fonttbl{\\f0\\fswiss Times New Roman;}{\\f1\\fmodern Courier New;}}")
+        );
+        assert!(doc.contains("{\\pard\\f1\\fs20 \\cf3 int"));
+        assert!(doc.contains("\\paperw12240\\paperh15840\\margl1800\\margr1800"));
+
+        let doc = build(RtfBuilder {
+            code_font: Some("Fira Code".into()),
+            page: PageSize::A4,
+            margin: 2.0,
+            ..Default::default()
+        });
+        assert!(doc.contains("\\paperw11906\\paperh16838\\margl1134\\margr1134"));
+        assert!(doc.contains("{\\fonttbl{\\f0 Calibri;}{\\f1 Fira Code;}}"));
+        assert!(rtf_check::check(doc.as_bytes()).is_ok());
+
+        assert!(check_font("Arial;Bold").is_err());
+        assert!(check_font("  ").is_err());
+        assert!(check_code_size(40).is_err());
+        assert!(check_margin(0.1).is_err());
+    }
+
+    #[test]
+    fn template_placeholders_are_filled() {
+        let capture = RunCapture::skipped("./a.out".into());
+        let sources = main_c("// {{name}}\n");
+        let opts = DocOptions {
+            name: "Jo {B}",
+            not_run: Some("no C compiler found"),
+            ..opts(&sources, &capture)
+        };
+        let rtf = "{\\rtf1{\\fonttbl{\\f0 Times;}{\\f1{\\*\\panose 0}Arial;}}\n\\pard \\{\\{name\\}\\} ({{student_id}}), {{date}}\\par\n\\{\\{code\\}\\}{{output}}\\par}";
+        let settings = RtfBuilder {
+            template: Some(Template {
+                rtf: rtf.into(),
+                date: "2026-10-16".into(),
+            }),
+            ..Default::default()
+        };
+        let doc = String::from_utf8(settings.build(&opts).unwrap().bytes).unwrap();
+        assert!(doc.starts_with(
+            "{\\rtf1{\\fonttbl{\\f0 Times;}{\\f1{\\*\\panose 0}Arial;}{\\f2 Consolas;}}"
+        ));
+        assert!(doc.contains("\\pard Jo \\{B\\} (1), 2026-10-16\\par"));
+        // Placeholders in the code itself are left alone
+        assert!(doc.contains("{\\pard\\f2\\fs18 // \\{\\{name\\}\\}"));
+        assert!(doc.contains("{\\i Output not captured (no C compiler found).\\par}"));
+
+        assert!(check_template("plain text {{code}}").is_err());
+        assert!(check_template("{\\rtf1{\\fonttbl{\\f0 Times;}} no placeholders}").is_err());
+        let unknown = check_template("{\\rtf1{\\fonttbl{\\f0 Times;}}{{grade}}}").unwrap_err();
+        assert!(unknown.to_string().contains("{{grade}}"));
+    }
+
+    /// Reads `rtf_escape` output back into text.
+    fn unescape(rtf: &str) -> String {
+        let b = rtf.as_bytes();
+        let mut units: Vec<u16> = Vec::new();
+        let mut i = 0;
+        while i < b.len() {
+            if b[i] != b'\\' {
+                units.push(u16::from(b[i]));
+                i += 1;
+                continue;
+            }
+            if matches!(b[i + 1], b'\\' | b'{' | b'}') {
+                units.push(u16::from(b[i + 1]));
+                i += 2;
+            } else if rtf[i..].starts_with("\\line\n") {
+                units.push(u16::from(b'\n'));
+                i += "\\line\n".len();
+            } else {
+                let end = rtf[i..].find('?').unwrap() + i;
+                let code: i32 = rtf[i + 2..end].parse().unwrap();
+                units.push(code as i16 as u16);
+                i = end + 1;
+            }
+        }
+        String::from_utf16(&units).unwrap()
+    }
+
+    /// Text leaning on what RTF treats specially, mixed with anything at all.
+    fn hostile() -> impl Strategy<Value = String> {
+        let piece = prop_oneof![
+            Just("\\".to_string()),
+            Just("{".to_string()),
+            Just("}".to_string()),
+            Just("\\u233?".to_string()),
+            Just("\\par".to_string()),
+            Just("\r\n\t".to_string()),
+            any::<char>().prop_map(String::from),
+            "\\PC{0,8}",
+        ];
+        prop::collection::vec(piece, 0..24).prop_map(|v| v.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn escaped_text_reads_back(text in prop_oneof![any::<String>(), hostile()]) {
+            let mut block = String::new();
+            rtf_escape(&mut block, &text, Mode::Block);
+            let expected = text.replace('\r', "").replace('\t', "    ");
+            prop_assert_eq!(unescape(&block), expected);
+            let doc = format!("{{\\rtf1 {block}}}");
+            prop_assert!(rtf_check::check(doc.as_bytes()).is_ok(), "{}", doc);
+
+            let mut inline = String::new();
+            rtf_escape(&mut inline, &text, Mode::Inline);
+            prop_assert_eq!(unescape(&inline), text.replace(['\n', '\r', '\t'], " "));
+        }
+
+        #[test]
+        fn odd_output_keeps_the_doc_intact(
+            output in hostile(),
+            code in hostile(),
+            name in hostile(),
+            case_name in hostile(),
+            report in any::<String>(),
+        ) {
+            let capture = RunCapture {
+                command_display: name.clone(),
+                cases: vec![CaseCapture {
+                    name: Some(case_name),
+                    colored_output: format!("{}{output}", ansi::marker(Some(1))),
+                    stdout: output.clone(),
+                    formatted_output: output,
+                    screenshot: Vec::new(),
+                    memcheck: None,
+                    sanitizer_report: Some(report),
+                    exit_code: Some(3),
+                    error_case: false,
+                    duration: std::time::Duration::ZERO,
+                }],
+                compile_time: None,
+                run_time: std::time::Duration::ZERO,
+                compile: None,
+                environment: Some(name.clone()),
+            };
+            let sources = [SourceFile {
+                name: name.clone(),
+                code,
+                sha256: "ab".repeat(32),
+            }];
+            let doc = build_rtf(&DocOptions {
+                assignment: &name,
+                name: &name,
+                student_id: &name,
+                not_run: Some(&name),
+                screenshots: &[Vec::new()],
+                checks: &[None],
+                watermark: true,
+                output_cap: 40,
+                transcript_name: Some(&name),
+                ..opts(&sources, &capture)
+            },
+            &RtfBuilder::default(),
+        )
+            .unwrap();
+            let checked = rtf_check::check(&doc);
+            prop_assert!(checked.is_ok(), "{:?}", checked);
+        }
+    }
 }
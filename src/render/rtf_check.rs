@@ -0,0 +1,239 @@
+//! `--strict-rtf`: reads the generated doc back the way a word processor
+//! would and rejects anything that could end a group early, run text into a
+//! control word or carry bytes RTF doesn't allow, so odd program output
+//! can't break the doc's structure.
+
+use crate::error::{Error, Result};
+
+/// Control words the doc is built from. Text that ran into one would read
+/// as a longer word, so anything else means the escaping went wrong.
+const WORDS: &[&str] = &[
+    "ansi",
+    "apcontinued",
+    "b",
+    "blue",
+    "brdrs",
+    "brdrw",
+    "cell",
+    "cellx",
+    "cf",
+    "clbrdrb",
+    "clbrdrl",
+    "clbrdrr",
+    "clbrdrt",
+    "clcbpat",
+    "colortbl",
+    "deff",
+    "f",
+    "fi",
+    "field",
+    "fldinst",
+    "fldrslt",
+    "fonttbl",
+    "fs",
+    "fmodern",
+    "footer",
+    "footerf",
+    "fswiss",
+    "green",
+    "header",
+    "headerf",
+    "i",
+    "intbl",
+    "li",
+    "line",
+    "margb",
+    "margl",
+    "margr",
+    "margt",
+    "page",
+    "paperh",
+    "paperw",
+    "par",
+    "pard",
+    "pich",
+    "pichgoal",
+    "pict",
+    "picw",
+    "picwgoal",
+    "picscalex",
+    "picscaley",
+    "pngblip",
+    "qc",
+    "qr",
+    "red",
+    "row",
+    "rtf",
+    "sa",
+    "sb",
+    "sl",
+    "slmult",
+    "titlepg",
+    "trgaph",
+    "trhdr",
+    "trowd",
+    "u",
+    "uc",
+    "ul",
+    "viewkind",
+];
+
+/// Control symbols: the escaped `\`, `{` and `}`, plus the few that stand
+/// for something themselves.
+const SYMBOLS: &[u8] = b"\\{}~-_*|:";
+
+/// Digits a numeric parameter may have, RTF keeps them to 32-bit values.
+const MAX_PARAM_DIGITS: usize = 10;
+
+type Found = (usize, String);
+
+pub fn check(doc: &[u8]) -> Result<()> {
+    check_at(doc).map_err(|(at, why)| {
+        Error::Validation(format!("generated doc isn't valid RTF at byte {at}: {why}"))
+    })
+}
+
+fn check_at(doc: &[u8]) -> std::result::Result<(), Found> {
+    if !doc.starts_with(b"{\\rtf1") {
+        return Err((0, "doesn't start with {\\rtf1".into()));
+    }
+    // One entry per open group, true once it's a picture's hex data
+    let mut groups: Vec<bool> = Vec::new();
+    let mut i = 0;
+    while i < doc.len() {
+        let b = doc[i];
+        if groups.is_empty() && i > 0 && !b.is_ascii_whitespace() {
+            return Err((i, "text after the document's closing brace".into()));
+        }
+        match b {
+            b'{' => groups.push(false),
+            b'}' => {
+                groups.pop();
+            }
+            b'\\' => {
+                i = control(doc, i, &mut groups)?;
+                continue;
+            }
+            b'\n' | b'\r' => {}
+            0x20..=0x7e => {
+                if groups.last() == Some(&true) && !b.is_ascii_hexdigit() && b != b' ' {
+                    return Err((i, format!("'{}' in picture data", b as char)));
+                }
+            }
+            _ => {
+                return Err((
+                    i,
+                    format!("raw byte 0x{b:02x}, anything else must be escaped"),
+                ));
+            }
+        }
+        i += 1;
+    }
+    if !groups.is_empty() {
+        return Err((doc.len(), format!("{} group(s) left open", groups.len())));
+    }
+    Ok(())
+}
+
+/// Reads the control word or symbol whose backslash is at `at`, and returns
+/// where the text after it starts.
+fn control(doc: &[u8], at: usize, groups: &mut [bool]) -> std::result::Result<usize, Found> {
+    let mut i = at + 1;
+    let Some(&c) = doc.get(i) else {
+        return Err((at, "backslash at the very end".into()));
+    };
+    if c == b'\'' {
+        let hex = doc.get(i + 1..i + 3);
+        return match hex {
+            Some(h) if h.iter().all(u8::is_ascii_hexdigit) => Ok(i + 3),
+            _ => Err((at, "\\' needs two hex digits".into())),
+        };
+    }
+    if !c.is_ascii_alphabetic() {
+        return if SYMBOLS.contains(&c) {
+            Ok(i + 1)
+        } else {
+            Err((at, format!("unknown control symbol {:?}", c as char)))
+        };
+    }
+
+    while doc.get(i).is_some_and(u8::is_ascii_alphabetic) {
+        i += 1;
+    }
+    let word = std::str::from_utf8(&doc[at + 1..i]).unwrap_or_default();
+    let sign = i;
+    if doc.get(i) == Some(&b'-') {
+        i += 1;
+    }
+    let digits = i;
+    while doc.get(i).is_some_and(u8::is_ascii_digit) {
+        i += 1;
+    }
+    if i - digits > MAX_PARAM_DIGITS {
+        return Err((at, format!("\\{word} has an oversized parameter")));
+    }
+    if i == digits && digits > sign {
+        return Err((at, format!("\\{word} has a '-' with no number")));
+    }
+    let param: Option<i64> = std::str::from_utf8(&doc[sign..i])
+        .ok()
+        .and_then(|p| p.parse().ok());
+    if doc.get(i) == Some(&b' ') {
+        i += 1;
+    }
+
+    if !WORDS.contains(&word) {
+        return Err((at, format!("unexpected control word \\{word}")));
+    }
+    match word {
+        "u" => {
+            if !param.is_some_and(|n| (-32768..=32767).contains(&n)) {
+                return Err((at, "\\u needs a 16-bit character code".into()));
+            }
+            // The stand-in shown by readers without Unicode, a plain character
+            match doc.get(i) {
+                Some(b'\\' | b'{' | b'}' | b'\n' | b'\r') | None => {
+                    Err((at, "\\u is missing its fallback character".into()))
+                }
+                Some(_) => Ok(i + 1),
+            }
+        }
+        "pict" => {
+            if let Some(top) = groups.last_mut() {
+                *top = true;
+            }
+            Ok(i)
+        }
+        _ => Ok(i),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn err(doc: &str) -> String {
+        check_at(doc.as_bytes()).unwrap_err().1
+    }
+
+    #[test]
+    fn accepts_what_the_doc_uses() {
+        let doc = "{\\rtf1\\ansi{\\fonttbl{\\f0 Calibri;}}\\pard\\b A1\\b0\\par\nJos\\u233?\\line\n\
+                   a\\\\b\\{c\\}{\\pict\\pngblip\\picw2\\pich2\n89ab\ncdef\n}}\n";
+        assert!(check_at(doc.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn rejects_broken_structure() {
+        assert!(err("{\\rtf1 a}}").contains("after the document"));
+        assert!(err("{\\rtf1 {a}").contains("left open"));
+        assert!(err("{\\rtf1 caf\u{e9}}").contains("raw byte 0xc3"));
+        assert!(err("{\\rtf1 \\bHello}").contains("\\bHello"));
+        assert!(err("{\\rtf1 \\u233\\par}").contains("fallback"));
+        assert!(err("{\\rtf1 \\u99999?}").contains("16-bit"));
+        assert!(err("{\\rtf1 {\\pict\\pngblip zz}}").contains("picture data"));
+        assert!(err("{\\rtf1 \\'4}").contains("two hex digits"));
+        assert!(err("{\\rtf1 \\").contains("very end"));
+        assert!(err("plain").contains("{\\rtf1"));
+    }
+}
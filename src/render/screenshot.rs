@@ -1,30 +1,174 @@
 use crate::error::{Error, Result};
-use crate::render::theme::Theme;
+use crate::render::fonts::Style;
+use crate::render::highlight;
+use crate::render::theme::{Background, Theme};
+use crate::terminal::ansi::{self, Mark, Piece};
+use crate::terminal::echo::{INPUT_END, INPUT_START};
+use crate::terminal::format::{Segment, Stream};
 use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
-use font8x8::{BASIC_FONTS, UnicodeFonts};
-use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb};
+use font8x8::{
+    BASIC_FONTS, BLOCK_FONTS, BOX_FONTS, GREEK_FONTS, LATIN_FONTS, MISC_FONTS, UnicodeFonts,
+};
+use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb, imageops};
 use std::io::Cursor;
 
-const MAX_LINES: usize = 80;
-const MAX_COLS: usize = 120;
+/// Longer listings are cut off in the code image, the text listing has it all.
+const MAX_CODE_LINES: usize = 400;
+const TAB_WIDTH: usize = 4;
 const GLYPH: u32 = 8;
 
-pub fn render_png(text: &str, theme: &Theme) -> Result<Vec<u8>> {
-    let mut lines = prepare_lines(text);
+/// One row of colored characters, ready to draw.
+type Row = Vec<(char, Rgb<u8>)>;
+
+/// How far a synthetic italic leans, in pixels across per pixel up.
+const ITALIC_SLANT: f32 = 0.2;
+
+/// Ends a row that a wrapped line continues on the next one. Drawn as a
+/// dimmed backslash.
+const WRAP_MARK: char = '\u{E002}';
+
+/// Most images one run's output is split into with pagination on.
+pub const MAX_PAGES: usize = 10;
+
+/// Renders plain text, like a compiler log. `title` goes in the title bar
+/// when the theme draws window chrome.
+pub fn render_png(text: &str, title: &str, theme: &Theme) -> Result<Vec<u8>> {
+    render_segments(&[Segment::new(Stream::Stdout, text)], title, theme)
+}
+
+/// Renders a run's captured output, each stream in its theme color.
+pub fn render_segments(segments: &[Segment], title: &str, theme: &Theme) -> Result<Vec<u8>> {
+    render_rows(&output_rows(segments, theme, theme.max_lines), title, theme)
+}
+
+/// Renders captured output as one image per `max_lines` rows instead of
+/// cutting it off after the first, up to `MAX_PAGES` images. Line numbers
+/// carry on from one image to the next.
+pub fn render_pages(segments: &[Segment], title: &str, theme: &Theme) -> Result<Vec<Vec<u8>>> {
+    // Leaves room for the truncation note on the last page
+    let limit = theme.max_lines * MAX_PAGES - 1;
+    output_rows(segments, theme, limit)
+        .chunks(theme.max_lines)
+        .map(|page| render_rows(page, title, theme))
+        .collect()
+}
+
+/// Output rows with the style each is drawn in: bold for the command line
+/// and headings, italic for the screenshot's own notes.
+fn output_rows(segments: &[Segment], theme: &Theme, max_lines: usize) -> Vec<(Row, Style)> {
+    let mut lines = prepare_lines(segments, max_lines, theme.max_cols, theme.wrap);
     if lines.is_empty() {
-        lines.push("(no output)".into());
+        lines.push(("(no output)".into(), Stream::Stdout, Style::Italic));
+    }
+    let mut rows: Vec<Row> = lines
+        .iter()
+        .map(|(l, stream, style)| {
+            let base = match style {
+                Style::Italic => theme.syntax.comment,
+                _ => theme.stream_color(*stream),
+            };
+            cells(l, base, theme)
+        })
+        .collect();
+    if theme.line_numbers {
+        let continued: Vec<bool> = std::iter::once(false)
+            .chain(
+                lines
+                    .iter()
+                    .map(|(l, _, _)| ansi::pieces(l).last() == Some(Piece::Marker(WRAP_MARK))),
+            )
+            .collect();
+        number_rows(&mut rows, &continued, theme);
+    }
+    rows.into_iter()
+        .zip(lines.iter().map(|(_, _, style)| *style))
+        .collect()
+}
+
+/// Renders source code as a themed image with syntax coloring.
+pub fn render_code_png(code: &str, title: &str, theme: &Theme) -> Result<Vec<u8>> {
+    let rows: Vec<(Row, Style)> = code_rows(code, theme)
+        .into_iter()
+        .map(|row| (row, Style::Regular))
+        .collect();
+    render_rows(&rows, title, theme)
+}
+
+/// The theme's TrueType faces. Bold and italic are faked from the regular
+/// face when the theme doesn't give them.
+struct Fonts<'a> {
+    regular: FontRef<'a>,
+    bold: Option<FontRef<'a>>,
+    italic: Option<FontRef<'a>>,
+}
+
+impl Fonts<'_> {
+    /// The font to draw `style` with, and the style still to fake on top.
+    fn face(&self, style: Style) -> (&FontRef<'_>, Style) {
+        match (style, &self.bold, &self.italic) {
+            (Style::Bold, Some(bold), _) => (bold, Style::Regular),
+            (Style::Italic, _, Some(italic)) => (italic, Style::Regular),
+            _ => (&self.regular, style),
+        }
     }
+}
+
+fn parse_font(data: &[u8]) -> Result<FontRef<'_>> {
+    FontRef::try_from_slice(data).map_err(|e| Error::Image(format!("invalid font: {e}")))
+}
+
+fn render_rows(rows: &[(Row, Style)], title: &str, theme: &Theme) -> Result<Vec<u8>> {
+    let factor = theme.supersample.max(1);
+    let img = if factor > 1 {
+        downsample(
+            &draw_rows(rows, title, &theme.clone().scaled(factor))?,
+            factor,
+        )
+    } else {
+        draw_rows(rows, title, theme)?
+    };
+
+    let mut buf = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(img)
+        .write_to(&mut buf, ImageFormat::Png)
+        .map_err(|e| Error::Image(format!("encoding screenshot: {e}")))?;
+    Ok(buf.into_inner())
+}
+
+/// Shrinks a supersampled image by `factor`, averaging each block of pixels
+/// so the edges of the text come out smooth.
+fn downsample(img: &ImageBuffer<Rgb<u8>, Vec<u8>>, factor: u32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let (w, h) = img.dimensions();
+    let size = |n: u32| (n.div_ceil(factor)).max(1);
+    imageops::thumbnail(img, size(w), size(h))
+}
 
-    let max_cols = lines.iter().map(|l| l.chars().count()).max().unwrap_or(1);
+fn draw_rows(
+    rows: &[(Row, Style)],
+    title: &str,
+    theme: &Theme,
+) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>> {
+    let max_cols = rows.iter().map(|(r, _)| r.len()).max().unwrap_or(1).max(1);
 
-    let ttf_font = theme
-        .font_data
-        .as_ref()
-        .map(|data| FontRef::try_from_slice(data))
-        .transpose()
-        .map_err(|e| Error::Image(format!("invalid font: {e}")))?;
+    let fonts = match &theme.font_data {
+        Some(data) => Some(Fonts {
+            regular: parse_font(data)?,
+            bold: theme
+                .font_bold_data
+                .as_deref()
+                .map(parse_font)
+                .transpose()?,
+            italic: theme
+                .font_italic_data
+                .as_deref()
+                .map(parse_font)
+                .transpose()?,
+        }),
+        None => None,
+    };
 
-    let (cell_w, cell_h) = if let Some(ref font) = ttf_font {
+    let (cell_w, cell_h) = if let Some(fonts) = &fonts {
+        let font = &fonts.regular;
         let scaled = font.as_scaled(PxScale::from(theme.font_size));
         let advance = scaled.h_advance(font.glyph_id('M'));
         let height = scaled.height();
@@ -32,56 +176,279 @@ pub fn render_png(text: &str, theme: &Theme) -> Result<Vec<u8>> {
     } else {
         (GLYPH * theme.scale, GLYPH * theme.scale)
     };
+    let metrics = Metrics {
+        cell_w,
+        cell_h,
+        bar_h: if theme.chrome {
+            cell_h + theme.padding
+        } else {
+            0
+        },
+        smooth: theme.supersample > 1,
+    };
 
-    let w = theme.padding * 2 + (max_cols as u32) * cell_w;
-    let h = theme.padding * 2 + (lines.len() as u32) * cell_h;
+    let mut w = theme.padding * 2 + (max_cols as u32) * cell_w;
+    if theme.chrome {
+        // Room for the buttons on both sides and a few characters of title
+        w = w.max(buttons_width(&metrics, theme) * 2 + 12 * cell_w);
+    }
+    let h = metrics.bar_h + theme.padding * 2 + (rows.len() as u32) * cell_h;
 
     const MAX_DIM: u32 = 8192;
     if w > MAX_DIM || h > MAX_DIM {
+        let less = if metrics.smooth {
+            ", scale or supersample"
+        } else {
+            " or scale"
+        };
         return Err(Error::Image(format!(
-            "screenshot dimensions {w}x{h} exceed limit {MAX_DIM}x{MAX_DIM}, use a smaller font size or scale"
+            "screenshot dimensions {w}x{h} exceed limit {MAX_DIM}x{MAX_DIM}, use a smaller font size{less}"
         )));
     }
 
-    let mut img: ImageBuffer<Rgb<u8>, Vec<u8>> = ImageBuffer::from_pixel(w, h, theme.bg);
+    let mut img = paint_background(w, h, theme);
+    let fonts = fonts.as_ref();
+    if theme.chrome {
+        draw_chrome(&mut img, fonts, title, &metrics, theme);
+    }
+    for (row, (line, style)) in rows.iter().enumerate() {
+        let y = metrics.bar_h + theme.padding + (row as u32) * cell_h;
+        let at = (theme.padding, y);
+        draw_cells(&mut img, fonts, at, line, *style, &metrics, theme);
+    }
+    Ok(decorate(img, theme))
+}
 
-    if let Some(ref font) = ttf_font {
-        let scaled = font.as_scaled(PxScale::from(theme.font_size));
-        let ascent = scaled.ascent();
-        for (row, line) in lines.iter().enumerate() {
-            for (col, ch) in line.chars().enumerate() {
-                let x = theme.padding as f32 + (col as f32) * cell_w as f32;
-                let y = theme.padding as f32 + (row as f32) * cell_h as f32 + ascent;
-                stamp_glyph_ttf(&mut img, font, theme.font_size, x, y, ch, theme.fg);
-            }
+/// A `w` by `h` image filled with the theme's background.
+fn paint_background(w: u32, h: u32, theme: &Theme) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    match &theme.background {
+        Background::Solid => ImageBuffer::from_pixel(w, h, theme.bg),
+        Background::Gradient(stops) => {
+            let spans = (stops.len() - 1) as f32;
+            let rows: Vec<Rgb<u8>> = (0..h)
+                .map(|y| {
+                    let at = y as f32 / h.saturating_sub(1).max(1) as f32 * spans;
+                    let i = (at as usize).min(stops.len() - 2);
+                    blend(stops[i], stops[i + 1], at - i as f32)
+                })
+                .collect();
+            ImageBuffer::from_fn(w, h, |_, y| rows[y as usize])
+        }
+        Background::Image(pic) => {
+            let (pw, ph) = pic.dimensions();
+            let zoom = (w as f32 / pw as f32).max(h as f32 / ph as f32);
+            let sw = ((pw as f32 * zoom).ceil() as u32).max(w);
+            let sh = ((ph as f32 * zoom).ceil() as u32).max(h);
+            let cover = imageops::resize(pic, sw, sh, imageops::FilterType::Triangle);
+            imageops::crop_imm(&cover, (sw - w) / 2, (sh - h) / 2, w, h).to_image()
+        }
+    }
+}
+
+struct Metrics {
+    cell_w: u32,
+    cell_h: u32,
+    /// Height of the title bar, zero without chrome.
+    bar_h: u32,
+    /// Round off the bitmap font's diagonals, for images drawn large to be
+    /// shrunk back down.
+    smooth: bool,
+}
+
+/// Draws a row of characters with its top-left corner at `(x, y)`.
+fn draw_cells(
+    img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    fonts: Option<&Fonts>,
+    (x, y): (u32, u32),
+    cells: &[(char, Rgb<u8>)],
+    style: Style,
+    metrics: &Metrics,
+    theme: &Theme,
+) {
+    if let Some(fonts) = fonts {
+        // The regular face sets the baseline so styled rows line up
+        let ascent = fonts
+            .regular
+            .as_scaled(PxScale::from(theme.font_size))
+            .ascent();
+        let (font, fake) = fonts.face(style);
+        for (col, &(ch, color)) in cells.iter().enumerate() {
+            let gx = x as f32 + (col as f32) * metrics.cell_w as f32;
+            let gy = y as f32 + ascent;
+            stamp_glyph_ttf(img, font, theme.font_size, (gx, gy), ch, color, fake);
         }
     } else {
-        for (row, line) in lines.iter().enumerate() {
-            for (col, ch) in line.chars().enumerate() {
-                let x = theme.padding + (col as u32) * cell_w;
-                let y = theme.padding + (row as u32) * cell_h;
-                stamp_glyph(&mut img, x, y, ch, theme.scale, theme.fg);
+        for (col, &(ch, color)) in cells.iter().enumerate() {
+            let gx = x + (col as u32) * metrics.cell_w;
+            let scale = theme.scale;
+            stamp_glyph(img, (gx, y), ch, scale, color, style, metrics.smooth);
+        }
+    }
+}
+
+/// Close, minimize and zoom, in the usual order and colors.
+const BUTTONS: [Rgb<u8>; 3] = [Rgb([255, 95, 86]), Rgb([255, 189, 46]), Rgb([39, 201, 63])];
+
+fn button_radius(metrics: &Metrics) -> u32 {
+    (metrics.bar_h / 5).max(2)
+}
+
+/// Width taken by the buttons from the left edge, gap included.
+fn buttons_width(metrics: &Metrics, theme: &Theme) -> u32 {
+    let r = button_radius(metrics);
+    theme.padding + r * 2 * 3 + r * 2 + r * 2
+}
+
+/// Title bar with traffic-light buttons and a centered title, so the image
+/// reads as a terminal window.
+fn draw_chrome(
+    img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    fonts: Option<&Fonts>,
+    title: &str,
+    metrics: &Metrics,
+    theme: &Theme,
+) {
+    let bar = blend(theme.bg, theme.fg, 0.12);
+    for y in 0..metrics.bar_h {
+        for x in 0..img.width() {
+            img.put_pixel(x, y, bar);
+        }
+    }
+
+    let r = button_radius(metrics);
+    let cy = metrics.bar_h as f32 / 2.0;
+    for (i, color) in BUTTONS.iter().enumerate() {
+        let cx = (theme.padding + r + i as u32 * r * 3) as f32;
+        fill_circle(img, cx, cy, r as f32, *color);
+    }
+
+    // Kept clear of the buttons on both sides so it stays centered
+    let side = buttons_width(metrics, theme);
+    let fits = (img.width().saturating_sub(side * 2) / metrics.cell_w) as usize;
+    let mut text: String = title.chars().filter(|c| !c.is_control()).collect();
+    if text.chars().count() > fits {
+        text = text
+            .chars()
+            .take(fits.saturating_sub(3))
+            .collect::<String>()
+            + "...";
+    }
+    let dim = blend(theme.fg, bar, 0.35);
+    let cells: Row = text.chars().map(|c| (c, dim)).collect();
+    let x = (img.width() - cells.len() as u32 * metrics.cell_w) / 2;
+    let y = (metrics.bar_h - metrics.cell_h) / 2;
+    draw_cells(img, fonts, (x, y), &cells, Style::Regular, metrics, theme);
+}
+
+/// Rounds the corners and adds a drop shadow, on a canvas colored like the
+/// page the image will sit on. Returns the image as-is when neither is on.
+fn decorate(img: ImageBuffer<Rgb<u8>, Vec<u8>>, theme: &Theme) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    if theme.corner_radius == 0 && !theme.shadow {
+        return img;
+    }
+    let (w, h) = img.dimensions();
+    let radius = (theme.corner_radius as f32).min(w.min(h) as f32 / 2.0);
+    let blur = if theme.shadow {
+        theme.padding.max(8)
+    } else {
+        0
+    };
+    let drop = blur / 3;
+    let margin = blur;
+
+    let mut canvas = ImageBuffer::from_pixel(w + margin * 2, h + margin * 2 + drop, theme.canvas);
+    let (cw, ch) = canvas.dimensions();
+    let half = (w as f32 / 2.0, h as f32 / 2.0);
+    let center = (margin as f32 + half.0, margin as f32 + half.1);
+
+    if theme.shadow {
+        let shadow_center = (center.0, center.1 + drop as f32);
+        for y in 0..ch {
+            for x in 0..cw {
+                let d = rounded_rect_distance(x, y, shadow_center, half, radius);
+                let fade = 1.0 - (d / blur as f32).clamp(0.0, 1.0);
+                let alpha = SHADOW_ALPHA * fade * fade;
+                if alpha > 0.0 {
+                    let under = *canvas.get_pixel(x, y);
+                    canvas.put_pixel(x, y, blend(under, Rgb([0, 0, 0]), alpha));
+                }
             }
         }
     }
 
-    let mut buf = Cursor::new(Vec::new());
-    DynamicImage::ImageRgb8(img)
-        .write_to(&mut buf, ImageFormat::Png)
-        .map_err(|e| Error::Image(format!("encoding screenshot: {e}")))?;
-    Ok(buf.into_inner())
+    for y in 0..h {
+        for x in 0..w {
+            let (cx, cy) = (x + margin, y + margin);
+            let d = rounded_rect_distance(cx, cy, center, half, radius);
+            let coverage = (0.5 - d).clamp(0.0, 1.0);
+            if coverage > 0.0 {
+                let under = *canvas.get_pixel(cx, cy);
+                canvas.put_pixel(cx, cy, blend(under, *img.get_pixel(x, y), coverage));
+            }
+        }
+    }
+    canvas
+}
+
+/// Darkness of the shadow right under the image's edge.
+const SHADOW_ALPHA: f32 = 0.35;
+
+/// Signed distance from the middle of pixel `x`, `y` to a rounded rectangle,
+/// negative inside.
+fn rounded_rect_distance(x: u32, y: u32, center: (f32, f32), half: (f32, f32), r: f32) -> f32 {
+    let qx = (x as f32 + 0.5 - center.0).abs() - (half.0 - r);
+    let qy = (y as f32 + 0.5 - center.1).abs() - (half.1 - r);
+    let outside = (qx.max(0.0).powi(2) + qy.max(0.0).powi(2)).sqrt();
+    outside + qx.max(qy).min(0.0) - r
 }
 
+/// Antialiased filled circle.
+fn fill_circle(img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, cx: f32, cy: f32, r: f32, color: Rgb<u8>) {
+    let x0 = (cx - r - 1.0).max(0.0) as u32;
+    let y0 = (cy - r - 1.0).max(0.0) as u32;
+    let x1 = ((cx + r + 1.0) as u32).min(img.width());
+    let y1 = ((cy + r + 1.0) as u32).min(img.height());
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let dx = x as f32 + 0.5 - cx;
+            let dy = y as f32 + 0.5 - cy;
+            let coverage = (r + 0.5 - (dx * dx + dy * dy).sqrt()).clamp(0.0, 1.0);
+            if coverage > 0.0 {
+                let under = *img.get_pixel(x, y);
+                img.put_pixel(x, y, blend(under, color, coverage));
+            }
+        }
+    }
+}
+
+/// Draws `ch` with its baseline origin at `(x, y)`. A `fake` bold is drawn
+/// twice a pixel apart, and a fake italic is sheared to the right.
 fn stamp_glyph_ttf(
     img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
     font: &FontRef,
     font_size: f32,
-    x: f32,
-    y: f32,
+    (x, y): (f32, f32),
     ch: char,
     fg: Rgb<u8>,
+    fake: Style,
 ) {
-    let glyph_id = font.glyph_id(ch);
+    if fake == Style::Bold {
+        let offset = (font_size / 16.0).max(1.0).round();
+        for dx in [0.0, offset] {
+            stamp_glyph_ttf(img, font, font_size, (x + dx, y), ch, fg, Style::Regular);
+        }
+        return;
+    }
+    let slant = if fake == Style::Italic {
+        ITALIC_SLANT
+    } else {
+        0.0
+    };
+    // Glyph 0 is the font's "missing" box, a question mark reads better
+    let glyph_id = match font.glyph_id(ch) {
+        id if id.0 == 0 => font.glyph_id('?'),
+        id => id,
+    };
     let glyph = glyph_id.with_scale_and_position(PxScale::from(font_size), ab_glyph::point(x, y));
 
     if let Some(outlined) = font.outline_glyph(glyph) {
@@ -90,8 +457,11 @@ fn stamp_glyph_ttf(
             if coverage < 0.1 {
                 return;
             }
-            let ix = (bounds.min.x as u32) + px;
             let iy = (bounds.min.y as u32) + py;
+            let shift = ((y - iy as f32) * slant).round() as i64;
+            let Ok(ix) = u32::try_from(bounds.min.x as i64 + px as i64 + shift) else {
+                return;
+            };
             if ix < img.width() && iy < img.height() {
                 if coverage >= 0.5 {
                     img.put_pixel(ix, iy, fg);
@@ -108,61 +478,269 @@ fn stamp_glyph_ttf(
     }
 }
 
-fn prepare_lines(text: &str) -> Vec<String> {
-    let processed = crate::terminal::emulate::process(text);
-    let mut lines: Vec<String> = processed.lines().map(clamp_line).collect();
-    if lines.len() > MAX_LINES {
-        lines.truncate(MAX_LINES);
-        lines.push("(output truncated)".into());
+/// Screen rows of the captured output, each with the stream it came from
+/// and the style it's drawn in.
+fn prepare_lines(
+    segments: &[Segment],
+    max_lines: usize,
+    max_cols: usize,
+    wrap: bool,
+) -> Vec<(String, Stream, Style)> {
+    let mut lines: Vec<(String, Stream, Style)> = Vec::new();
+    for segment in segments {
+        let style = if segment.marker || segment.stream == Stream::Command {
+            Style::Bold
+        } else {
+            Style::Regular
+        };
+        let processed = crate::terminal::emulate::process(&segment.text);
+        for line in processed.lines() {
+            lines.extend(
+                split_line(line, max_cols, wrap)
+                    .into_iter()
+                    .map(|row| (row, segment.stream, style)),
+            );
+        }
+        // The emulator trims trailing blank lines, but the gap between one
+        // segment and the next is part of the layout.
+        let gap = segment.text.trim_end_matches([' ', '\t']).ends_with("\n\n");
+        if gap && lines.last().is_some_and(|(line, _, _)| !line.is_empty()) {
+            lines.push((String::new(), segment.stream, style));
+        }
+    }
+    while lines.last().is_some_and(|(line, _, _)| line.is_empty()) {
+        lines.pop();
+    }
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        lines.push(("(output truncated)".into(), Stream::Stdout, Style::Italic));
     }
     lines
 }
 
-fn clamp_line(line: &str) -> String {
-    let mut out = String::new();
-    for (i, ch) in line.chars().enumerate() {
-        if i >= MAX_COLS {
-            out.push_str("...");
-            break;
+/// Each visible character of a prepared line with its color, starting from
+/// `base`. Text the program colored keeps its ANSI color, and echoed input
+/// is drawn in the theme's input color.
+fn cells(line: &str, base: Rgb<u8>, theme: &Theme) -> Row {
+    let mut color = base;
+    let mut in_input = false;
+    let mut out = Vec::new();
+    for piece in ansi::pieces(line) {
+        match piece {
+            Piece::Marker(INPUT_START) => in_input = true,
+            Piece::Marker(INPUT_END) => in_input = false,
+            Piece::Marker(WRAP_MARK) => out.push(('\\', blend(theme.fg, theme.bg, 0.5))),
+            Piece::Text(ch) if in_input => out.push((ch, theme.input)),
+            Piece::Text(ch) => out.push((ch, color)),
+            Piece::Marker(ch) => match ansi::mark(ch) {
+                Some(Mark::Color(n)) => color = Rgb(ansi::rgb(n)),
+                Some(Mark::Reset) => color = base,
+                None => {}
+            },
         }
-        if ch.is_control() {
-            continue;
-        }
-        if ch.is_ascii() {
-            out.push(ch);
+    }
+    out
+}
+
+/// Prefixes each row with its right-aligned line number, in a color halfway
+/// between the text and the background. Rows marked in `continued` carry on
+/// a wrapped line, so they get a blank gutter instead of a number.
+fn number_rows(rows: &mut [Row], continued: &[bool], theme: &Theme) {
+    let dim = blend(theme.fg, theme.bg, 0.5);
+    let total = rows.len() - continued.iter().filter(|&&c| c).count();
+    let digits = total.to_string().len();
+    let mut n = 0;
+    for (i, row) in rows.iter_mut().enumerate() {
+        let gutter = if continued.get(i).copied().unwrap_or(false) {
+            " ".repeat(digits + 1)
         } else {
-            out.push('?');
+            n += 1;
+            format!("{n:>digits$} ")
+        };
+        row.splice(0..0, gutter.chars().map(|c| (c, dim)));
+    }
+}
+
+fn blend(a: Rgb<u8>, b: Rgb<u8>, t: f32) -> Rgb<u8> {
+    let mix = |x: u8, y: u8| (x as f32 * (1.0 - t) + y as f32 * t).round() as u8;
+    Rgb([mix(a[0], b[0]), mix(a[1], b[1]), mix(a[2], b[2])])
+}
+
+/// Splits highlighted source into rows, expanding tabs and clamping long
+/// lines the same way as the run screenshot.
+fn code_rows(code: &str, theme: &Theme) -> Vec<Row> {
+    let mut rows: Vec<Row> = vec![Vec::new()];
+    let mut clamped = false;
+    for (kind, text) in highlight::spans(code) {
+        let color = theme.syntax.color(kind, theme.fg);
+        for ch in text.chars() {
+            let row = rows.last_mut().expect("rows is never empty");
+            match ch {
+                '\n' => {
+                    rows.push(Vec::new());
+                    clamped = false;
+                }
+                _ if clamped => {}
+                _ if row.len() >= theme.max_cols => {
+                    row.extend("...".chars().map(|c| (c, theme.fg)));
+                    clamped = true;
+                }
+                '\t' => {
+                    let next = (row.len() / TAB_WIDTH + 1) * TAB_WIDTH;
+                    row.resize(next.min(theme.max_cols), (' ', color));
+                }
+                _ if ch.is_control() => {}
+                _ => row.push((ch, color)),
+            }
         }
     }
-    out
+    while rows.len() > 1 && rows.last().is_some_and(Vec::is_empty) {
+        rows.pop();
+    }
+    if rows.len() > MAX_CODE_LINES {
+        let more = rows.len() - MAX_CODE_LINES;
+        rows.truncate(MAX_CODE_LINES);
+        let note = format!("({more} more lines)");
+        rows.push(note.chars().map(|c| (c, theme.syntax.comment)).collect());
+    }
+    rows
+}
+
+/// Cuts a line into rows of at most `max_cols` characters. Without `wrap`
+/// the rest of the line becomes `...`. With it the line carries on over as
+/// many rows as it needs, each but the last ending in `WRAP_MARK`. Echoed
+/// input split across rows keeps its markers on both sides, and colored
+/// text picks its color back up on the next row.
+fn split_line(line: &str, max_cols: usize, wrap: bool) -> Vec<String> {
+    let mut rows = vec![String::new()];
+    let mut cols = 0;
+    let mut in_input = false;
+    let mut color = None;
+    for piece in ansi::pieces(line) {
+        let row = rows.last_mut().expect("rows is never empty");
+        let ch = match piece {
+            Piece::Text(ch) => ch,
+            Piece::Marker(ch) => {
+                if ch == INPUT_START || ch == INPUT_END {
+                    in_input = ch == INPUT_START;
+                } else if let Some(mark) = ansi::mark(ch) {
+                    color = (mark != Mark::Reset).then_some(ch);
+                }
+                row.push(ch);
+                continue;
+            }
+        };
+        if cols >= max_cols {
+            if !wrap {
+                row.push_str("...");
+                break;
+            }
+            if in_input {
+                row.push(INPUT_END);
+            }
+            row.push(WRAP_MARK);
+            let mut next: String = color.into_iter().collect();
+            if in_input {
+                next.push(INPUT_START);
+            }
+            rows.push(next);
+            cols = 0;
+        }
+        let row = rows.last_mut().expect("rows is never empty");
+        cols += 1;
+        if !ch.is_control() {
+            ansi::push_text(row, ch);
+        }
+    }
+    rows
+}
+
+/// The 8x8 bitmap for `ch`, covering ASCII, Latin-1, Greek, box drawing
+/// and block elements. Anything else has no glyph and is drawn as `?`.
+fn bitmap_glyph(ch: char) -> Option<[u8; 8]> {
+    BASIC_FONTS
+        .get(ch)
+        .or_else(|| LATIN_FONTS.get(ch))
+        .or_else(|| BOX_FONTS.get(ch))
+        .or_else(|| BLOCK_FONTS.get(ch))
+        .or_else(|| GREEK_FONTS.get(ch))
+        .or_else(|| MISC_FONTS.get(ch))
 }
 
+/// Draws `ch` from the bitmap font. Bold widens every stroke by a column,
+/// italic shifts the upper rows to the right. With `smooth`, each step of a
+/// staircase is filled in half-way so slanted strokes stay slanted when the
+/// image is shrunk.
 fn stamp_glyph(
     img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
-    ox: u32,
-    oy: u32,
+    (ox, oy): (u32, u32),
     ch: char,
     scale: u32,
     fg: Rgb<u8>,
+    style: Style,
+    smooth: bool,
 ) {
-    let glyph = BASIC_FONTS
-        .get(ch)
-        .or_else(|| BASIC_FONTS.get('?'))
+    let glyph = bitmap_glyph(ch)
+        .or_else(|| bitmap_glyph('?'))
         .unwrap_or([0; 8]);
+    let rows = glyph.map(|bits| {
+        if style == Style::Bold {
+            bits | (bits << 1)
+        } else {
+            bits
+        }
+    });
+    let on = |row: usize, col: usize| row < 8 && col < 8 && rows[row] & (1 << col) != 0;
 
-    for (row, bits) in glyph.iter().enumerate() {
+    // Fills the pixels of cell `row`, `col` that `inside` picks out
+    let mut fill = |row: usize, col: usize, inside: &dyn Fn(u32, u32) -> bool| {
+        let shift = if style == Style::Italic {
+            (GLYPH - 1 - row as u32) * scale / 3
+        } else {
+            0
+        };
+        for sy in 0..scale {
+            for sx in 0..scale {
+                if !inside(sx, sy) {
+                    continue;
+                }
+                let x = ox + shift + (col as u32) * scale + sx;
+                let y = oy + (row as u32) * scale + sy;
+                if x < img.width() && y < img.height() {
+                    img.put_pixel(x, y, fg);
+                }
+            }
+        }
+    };
+
+    for row in 0..8 {
         for col in 0..8 {
-            if bits & (1 << col) == 0 {
-                continue;
+            if on(row, col) {
+                fill(row, col, &|_, _| true);
             }
-            for sy in 0..scale {
-                for sx in 0..scale {
-                    let x = ox + (col as u32) * scale + sx;
-                    let y = oy + (row as u32) * scale + sy;
-                    if x < img.width() && y < img.height() {
-                        img.put_pixel(x, y, fg);
-                    }
-                }
+        }
+    }
+    if !smooth {
+        return;
+    }
+    // In each 2x2 block, an empty cell whose two neighbours in the block are
+    // both set gets the half facing the block's middle
+    let last = scale - 1;
+    for row in 0..7 {
+        for col in 0..7 {
+            let (tl, tr) = (on(row, col), on(row, col + 1));
+            let (bl, br) = (on(row + 1, col), on(row + 1, col + 1));
+            if !tl && tr && bl {
+                fill(row, col, &|sx, sy| sx + sy > last);
+            }
+            if !tr && tl && br {
+                fill(row, col + 1, &|sx, sy| sx < sy);
+            }
+            if !bl && tl && br {
+                fill(row + 1, col, &|sx, sy| sx > sy);
+            }
+            if !br && tr && bl {
+                fill(row + 1, col + 1, &|sx, sy| sx + sy < last);
             }
         }
     }
@@ -171,43 +749,303 @@ fn stamp_glyph(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::render::theme::{DEFAULT_MAX_COLS, DEFAULT_MAX_LINES};
+
+    fn plain(text: &str) -> Vec<Segment> {
+        vec![Segment::new(Stream::Stdout, text)]
+    }
 
     #[test]
     fn clamp_short_line() {
-        assert_eq!(clamp_line("hello"), "hello");
+        assert_eq!(split_line("hello", DEFAULT_MAX_COLS, false)[0], "hello");
     }
 
     #[test]
     fn clamp_tabs_expand() {
-        assert_eq!(clamp_line("a\tb"), "ab");
+        assert_eq!(split_line("a\tb", DEFAULT_MAX_COLS, false)[0], "ab");
     }
 
     #[test]
     fn clamp_long_line_truncated() {
         let long = "x".repeat(200);
-        let out = clamp_line(&long);
+        let out = split_line(&long, DEFAULT_MAX_COLS, false).remove(0);
         assert!(out.ends_with("..."));
-        assert!(out.len() <= MAX_COLS + 3);
+        assert!(out.len() <= DEFAULT_MAX_COLS + 3);
+    }
+
+    #[test]
+    fn limits_come_from_the_caller() {
+        let wide = "x".repeat(200);
+        let lines = prepare_lines(&plain(&format!("{wide}\n{wide}\n{wide}\n")), 2, 150, false);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].0.len(), 153);
+    }
+
+    #[test]
+    fn wrap_continues_long_lines() {
+        let rows = split_line(&"x".repeat(45), 20, true);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], format!("{}{WRAP_MARK}", "x".repeat(20)));
+        assert_eq!(rows[2], "x".repeat(5));
+        assert_eq!(split_line(&"x".repeat(20), 20, true).len(), 1);
+    }
+
+    #[test]
+    fn wrapped_input_keeps_its_color() {
+        let theme = Theme::default();
+        let rows = split_line(&format!("> {INPUT_START}abcd{INPUT_END}"), 4, true);
+        assert_eq!(rows.len(), 2);
+        let second = cells(&rows[1], theme.fg, &theme);
+        assert_eq!(second.len(), 2);
+        assert!(second.iter().all(|c| c.1 == theme.input));
     }
 
     #[test]
     fn prepare_lines_caps_at_max() {
-        let text = "line\n".repeat(MAX_LINES + 50);
-        let lines = prepare_lines(&text);
-        assert_eq!(lines.len(), MAX_LINES + 1);
-        assert_eq!(lines.last().unwrap(), "(output truncated)");
+        let text = "line\n".repeat(DEFAULT_MAX_LINES + 50);
+        let lines = prepare_lines(&plain(&text), DEFAULT_MAX_LINES, DEFAULT_MAX_COLS, false);
+        assert_eq!(lines.len(), DEFAULT_MAX_LINES + 1);
+        assert_eq!(lines.last().unwrap().0, "(output truncated)");
     }
 
     #[test]
     fn render_png_produces_bytes() {
-        let png = render_png("hello world", &Theme::default()).unwrap();
+        let png = render_png("hello world", "", &Theme::default()).unwrap();
         assert!(png.len() > 100);
         assert_eq!(&png[1..4], b"PNG");
     }
 
+    #[test]
+    fn pages_split_at_max_lines_and_stop_at_the_cap() {
+        let theme = Theme {
+            max_lines: 5,
+            ..Theme::default()
+        };
+        let text = "line\n".repeat(12);
+        assert_eq!(render_pages(&plain(&text), "", &theme).unwrap().len(), 3);
+        let text = "line\n".repeat(500);
+        assert_eq!(
+            render_pages(&plain(&text), "", &theme).unwrap().len(),
+            MAX_PAGES
+        );
+        let rows = output_rows(&plain(&text), &theme, theme.max_lines * MAX_PAGES - 1);
+        let (last, style) = rows.last().unwrap();
+        assert_eq!(
+            last.iter().map(|c| c.0).collect::<String>(),
+            "(output truncated)"
+        );
+        assert_eq!(*style, Style::Italic);
+        assert_eq!(last[0].1, theme.syntax.comment);
+    }
+
+    #[test]
+    fn streams_get_their_own_colors() {
+        let theme = Theme::default();
+        let segments = [
+            Segment::new(Stream::Command, "$ ./a\n\n"),
+            Segment::new(Stream::Stdout, "ok\n"),
+            Segment::new(Stream::Stderr, "oops"),
+        ];
+        let rows = output_rows(&segments, &theme, theme.max_lines);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0].0[0], ('$', theme.syntax.prompt));
+        assert!(rows[1].0.is_empty());
+        assert_eq!(rows[2].0[0], ('o', theme.fg));
+        assert_eq!(rows[3].0[0], ('o', theme.syntax.stderr));
+    }
+
+    #[test]
+    fn command_and_headings_are_bold() {
+        let segments = [
+            Segment::new(Stream::Command, "$ ./a\n\n"),
+            Segment::marker(Stream::Stderr, "STDERR\n"),
+            Segment::new(Stream::Stderr, "oops\n\n"),
+            Segment::marker(Stream::Stdout, "Exit code: 1"),
+        ];
+        let styles: Vec<Style> = prepare_lines(&segments, 10, 80, false)
+            .into_iter()
+            .map(|(_, _, style)| style)
+            .collect();
+        use Style::{Bold, Regular};
+        assert_eq!(styles, [Bold, Bold, Bold, Regular, Regular, Bold]);
+    }
+
+    #[test]
+    fn fake_bold_and_italic_change_the_bitmap() {
+        let draw = |style| {
+            let mut img = ImageBuffer::from_pixel(16, 8, Rgb([0, 0, 0]));
+            stamp_glyph(&mut img, (0, 0), 'l', 1, Rgb([255, 255, 255]), style, false);
+            img.pixels().map(|p| p.0[0] > 0).collect::<Vec<_>>()
+        };
+        let lit = |pixels: &[bool]| pixels.iter().filter(|&&p| p).count();
+        let regular = draw(Style::Regular);
+        assert!(lit(&draw(Style::Bold)) > lit(&regular));
+        let italic = draw(Style::Italic);
+        assert_eq!(lit(&italic), lit(&regular));
+        assert_ne!(italic, regular);
+    }
+
+    #[test]
+    fn supersampling_smooths_the_bitmap_font() {
+        let theme = Theme::default();
+        let draw = |supersample| {
+            let theme = Theme {
+                supersample,
+                ..theme.clone()
+            };
+            let png = render_png("/\\x", "", &theme).unwrap();
+            image::load_from_memory(&png).unwrap().to_rgb8()
+        };
+        let (plain, smooth) = (draw(1), draw(2));
+        assert_eq!(plain.dimensions(), smooth.dimensions());
+        // Blending the filled-in corners leaves shades between fg and bg
+        let shades = |img: &ImageBuffer<Rgb<u8>, Vec<u8>>| {
+            img.pixels()
+                .filter(|&&p| p != theme.bg && p != theme.fg)
+                .count()
+        };
+        assert_eq!(shades(&plain), 0);
+        assert!(shades(&smooth) > 0);
+    }
+
+    #[test]
+    fn gradient_runs_top_to_bottom() {
+        let (top, bottom) = (Rgb([0, 0, 0]), Rgb([200, 0, 100]));
+        let theme = Theme {
+            background: Background::Gradient(vec![top, bottom]),
+            ..Theme::default()
+        };
+        let png = render_png("hi", "", &theme).unwrap();
+        let img = image::load_from_memory(&png).unwrap().to_rgb8();
+        let (w, h) = img.dimensions();
+        assert_eq!(*img.get_pixel(w - 1, 0), top);
+        assert_eq!(*img.get_pixel(w - 1, h - 1), bottom);
+        assert_eq!(*img.get_pixel(0, h / 2), *img.get_pixel(w - 1, h / 2));
+    }
+
+    #[test]
+    fn echoed_input_takes_the_input_color() {
+        let theme = Theme::default();
+        let line = split_line(
+            &format!("n: {INPUT_START}42{INPUT_END}"),
+            DEFAULT_MAX_COLS,
+            false,
+        )
+        .remove(0);
+        let colors: Vec<_> = cells(&line, theme.fg, &theme)
+            .into_iter()
+            .map(|c| c.1)
+            .collect();
+        assert_eq!(colors.len(), 5);
+        assert_eq!(colors[2], theme.fg);
+        assert_eq!(colors[3], theme.input);
+        assert_eq!(colors[4], theme.input);
+    }
+
+    #[test]
+    fn line_numbers_are_right_aligned_and_dimmed() {
+        let theme = Theme::default();
+        let mut rows: Vec<Row> = (0..10).map(|_| vec![('x', theme.fg)]).collect();
+        number_rows(&mut rows, &[false; 10], &theme);
+        let first: String = rows[0].iter().map(|c| c.0).collect();
+        let last: String = rows[9].iter().map(|c| c.0).collect();
+        assert_eq!(first, " 1 x");
+        assert_eq!(last, "10 x");
+        assert_ne!(rows[0][1].1, theme.fg);
+        assert_eq!(rows[0][3].1, theme.fg);
+    }
+
+    #[test]
+    fn wrapped_rows_skip_a_number() {
+        let theme = Theme::default();
+        let mut rows: Vec<Row> = (0..3).map(|_| vec![('x', theme.fg)]).collect();
+        number_rows(&mut rows, &[false, true, false], &theme);
+        let text: Vec<String> = rows
+            .iter()
+            .map(|r| r.iter().map(|c| c.0).collect())
+            .collect();
+        assert_eq!(text, ["1 x", "  x", "2 x"]);
+    }
+
+    #[test]
+    fn code_rows_color_tokens_and_expand_tabs() {
+        let theme = Theme::default();
+        let rows = code_rows("int x;\n\treturn 0;\n", &theme);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][0], ('i', theme.syntax.keyword));
+        assert_eq!(rows[0][4], ('x', theme.fg));
+        assert_eq!(rows[1][4], ('r', theme.syntax.keyword));
+        assert_eq!(rows[1][11], ('0', theme.syntax.number));
+    }
+
+    #[test]
+    fn code_rows_cap_long_listings() {
+        let code = "x\n".repeat(MAX_CODE_LINES + 5);
+        let rows = code_rows(&code, &Theme::default());
+        assert_eq!(rows.len(), MAX_CODE_LINES + 1);
+        let last: String = rows.last().unwrap().iter().map(|c| c.0).collect();
+        assert_eq!(last, "(5 more lines)");
+    }
+
+    #[test]
+    fn chrome_adds_a_title_bar() {
+        let plain = Theme::default();
+        let chrome = Theme {
+            chrome: true,
+            ..Theme::default()
+        };
+        let size = |theme: &Theme| {
+            let png = render_png("hello", "./main", theme).unwrap();
+            let img = image::load_from_memory(&png).unwrap();
+            (img.width(), img.height())
+        };
+        let (pw, ph) = size(&plain);
+        let (cw, ch) = size(&chrome);
+        assert_eq!(ch, ph + 16 + plain.padding);
+        assert!(cw >= pw);
+    }
+
+    #[test]
+    fn rounded_corners_show_the_canvas() {
+        let theme = Theme {
+            corner_radius: 8,
+            ..Theme::default()
+        };
+        let png = render_png("hello", "", &theme).unwrap();
+        let img = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(*img.get_pixel(0, 0), theme.canvas);
+        assert_eq!(*img.get_pixel(img.width() / 2, 1), theme.bg);
+    }
+
+    #[test]
+    fn shadow_grows_the_canvas() {
+        let plain = render_png("hello", "", &Theme::default()).unwrap();
+        let theme = Theme {
+            shadow: true,
+            ..Theme::default()
+        };
+        let shadowed = render_png("hello", "", &theme).unwrap();
+        let size = |png: &[u8]| image::load_from_memory(png).unwrap().width();
+        assert_eq!(size(&shadowed), size(&plain) + theme.padding * 2);
+    }
+
+    #[test]
+    fn non_ascii_text_is_kept() {
+        let line = "┌─┐ José │ 90%";
+        assert_eq!(split_line(line, DEFAULT_MAX_COLS, false)[0], line);
+    }
+
+    #[test]
+    fn bitmap_font_covers_box_drawing_and_latin1() {
+        for ch in ['─', '│', '┌', '╬', '█', 'é', 'ñ', 'ß', 'λ'] {
+            assert!(bitmap_glyph(ch).is_some(), "no glyph for {ch}");
+        }
+        assert!(bitmap_glyph('中').is_none());
+    }
+
     #[test]
     fn clamp_skips_control_chars() {
-        assert_eq!(clamp_line("a\x01b"), "ab");
+        assert_eq!(split_line("a\x01b", DEFAULT_MAX_COLS, false)[0], "ab");
     }
 
     #[test]
@@ -218,7 +1056,7 @@ mod tests {
             ..Theme::default()
         };
         let long = ("x".repeat(120) + "\n").repeat(80);
-        let result = render_png(&long, &theme);
+        let result = render_png(&long, "", &theme);
         assert!(result.is_err());
     }
 }
@@ -1,61 +1,261 @@
+use crate::cli::ThemeCommand;
 use crate::config;
 use crate::error::{Error, Result, io_err};
-use image::Rgb;
+use crate::render::fonts::{self, Style};
+use crate::render::highlight::TokenKind;
+use crate::render::screenshot;
+use crate::terminal::echo::{INPUT_END, INPUT_START};
+use crate::terminal::format::{Segment, Stream};
+use crate::ui;
+use image::{Rgb, RgbImage, imageops};
 use serde::Deserialize;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct Theme {
+    /// Background color, also the base the title bar and dimmed text are
+    /// mixed from when `background` paints something else.
     pub bg: Rgb<u8>,
+    pub background: Background,
     pub fg: Rgb<u8>,
+    /// Color of piped input echoed after the prompts.
+    pub input: Rgb<u8>,
     pub padding: u32,
     pub scale: u32,
     pub font_data: Option<Vec<u8>>,
+    /// Faces for the command line and section headings, and for notes like
+    /// `(output truncated)`. Without them `font_data` is thickened or slanted.
+    pub font_bold_data: Option<Vec<u8>>,
+    pub font_italic_data: Option<Vec<u8>>,
     pub font_size: f32,
+    /// Draw at this many times the size and shrink back down, so text edges
+    /// come out smooth instead of blocky.
+    pub supersample: u32,
+    pub syntax: Syntax,
+    /// Draw a gutter of line numbers down the left of the run screenshot.
+    pub line_numbers: bool,
+    /// Frame the image in a title bar with window buttons.
+    pub chrome: bool,
+    /// Radius of the image's rounded corners in pixels, 0 for square.
+    pub corner_radius: u32,
+    /// Draw a soft drop shadow under the image.
+    pub shadow: bool,
+    /// Color around rounded corners and behind the shadow, usually the page.
+    pub canvas: Rgb<u8>,
+    /// Output lines shown before the screenshot is cut off.
+    pub max_lines: usize,
+    /// Characters per line before the rest is replaced with `...`.
+    pub max_cols: usize,
+    /// Continue long lines on the next row instead of cutting them off.
+    pub wrap: bool,
+}
+
+/// What's painted behind the text.
+#[derive(Debug, Clone, Default)]
+pub enum Background {
+    /// Plain `bg`.
+    #[default]
+    Solid,
+    /// Colors spread evenly from the top of the image to the bottom.
+    Gradient(Vec<Rgb<u8>>),
+    /// A picture scaled to cover the image, with the overflow cropped off.
+    Image(RgbImage),
+}
+
+impl Background {
+    /// The color it looks like from a distance, for a theme that sets no `bg`.
+    fn average(&self) -> Option<Rgb<u8>> {
+        match self {
+            Background::Solid => None,
+            Background::Gradient(stops) => {
+                let n = stops.len().max(1) as u32;
+                let channel =
+                    |i: usize| (stops.iter().map(|c| u32::from(c[i])).sum::<u32>() / n) as u8;
+                Some(Rgb([channel(0), channel(1), channel(2)]))
+            }
+            Background::Image(pic) => Some(*imageops::thumbnail(pic, 1, 1).get_pixel(0, 0)),
+        }
+    }
+}
+
+pub const DEFAULT_MAX_LINES: usize = 80;
+pub const DEFAULT_MAX_COLS: usize = 120;
+/// Bounds for `max_lines` and `max_cols`, shared with the CLI flags.
+pub const MAX_LINES_RANGE: (usize, usize) = (1, 2000);
+pub const MAX_COLS_RANGE: (usize, usize) = (20, 1000);
+
+/// Token colors, shared by the highlighted code image and the run
+/// screenshot. Comments double as the color of the images' own notes, like
+/// `(output truncated)`.
+#[derive(Debug, Clone, Copy)]
+pub struct Syntax {
+    pub keyword: Rgb<u8>,
+    pub string: Rgb<u8>,
+    pub comment: Rgb<u8>,
+    pub number: Rgb<u8>,
+    pub preproc: Rgb<u8>,
+    /// The `$ command` line at the top of the run screenshot.
+    pub prompt: Rgb<u8>,
+    /// Anything the program wrote to stderr.
+    pub stderr: Rgb<u8>,
+}
+
+impl Syntax {
+    pub fn color(&self, kind: TokenKind, plain: Rgb<u8>) -> Rgb<u8> {
+        match kind {
+            TokenKind::Plain => plain,
+            TokenKind::Keyword => self.keyword,
+            TokenKind::String => self.string,
+            TokenKind::Comment => self.comment,
+            TokenKind::Number => self.number,
+            TokenKind::Preproc => self.preproc,
+        }
+    }
+}
+
+impl Default for Syntax {
+    fn default() -> Self {
+        Self {
+            keyword: Rgb([120, 190, 255]),
+            string: Rgb([255, 200, 120]),
+            comment: Rgb([110, 125, 140]),
+            number: Rgb([220, 160, 255]),
+            preproc: Rgb([255, 130, 170]),
+            prompt: Rgb([120, 190, 255]),
+            stderr: Rgb([255, 110, 110]),
+        }
+    }
 }
 
 #[derive(Deserialize)]
 struct ThemeFile {
+    /// Theme whose settings this one starts from instead of the defaults.
+    extends: Option<String>,
     bg: Option<String>,
+    bg_gradient: Option<Vec<String>>,
+    bg_image: Option<String>,
     fg: Option<String>,
+    input: Option<String>,
+    /// Older spellings of `[syntax] stderr` and `[syntax] prompt`.
+    fg_err: Option<String>,
+    prompt: Option<String>,
     padding: Option<u32>,
     scale: Option<u32>,
     font: Option<String>,
+    font_bold: Option<String>,
+    font_italic: Option<String>,
     font_size: Option<f32>,
+    line_numbers: Option<bool>,
+    chrome: Option<bool>,
+    corner_radius: Option<u32>,
+    shadow: Option<bool>,
+    canvas: Option<String>,
+    max_lines: Option<usize>,
+    max_cols: Option<usize>,
+    wrap: Option<bool>,
+    #[serde(default)]
+    syntax: SyntaxFile,
+}
+
+#[derive(Deserialize, Default)]
+struct SyntaxFile {
+    keyword: Option<String>,
+    string: Option<String>,
+    comment: Option<String>,
+    number: Option<String>,
+    preproc: Option<String>,
+    prompt: Option<String>,
+    stderr: Option<String>,
+}
+
+impl Theme {
+    pub fn stream_color(&self, stream: Stream) -> Rgb<u8> {
+        match stream {
+            Stream::Command => self.syntax.prompt,
+            Stream::Stdout => self.fg,
+            Stream::Stderr => self.syntax.stderr,
+        }
+    }
+
+    /// The same theme drawn `factor` times larger, for high-resolution export.
+    pub fn scaled(self, factor: u32) -> Self {
+        Self {
+            padding: self.padding * factor,
+            corner_radius: self.corner_radius * factor,
+            scale: self.scale * factor,
+            font_size: self.font_size * factor as f32,
+            ..self
+        }
+    }
 }
 
 impl Default for Theme {
     fn default() -> Self {
         Self {
             bg: Rgb([15, 18, 24]),
+            background: Background::Solid,
             fg: Rgb([128, 255, 170]),
+            input: Rgb([235, 235, 235]),
             padding: 16,
             scale: 2,
             font_data: None,
+            font_bold_data: None,
+            font_italic_data: None,
             font_size: 16.0,
+            supersample: 1,
+            syntax: Syntax::default(),
+            line_numbers: false,
+            chrome: false,
+            corner_radius: 0,
+            shadow: false,
+            canvas: Rgb([255, 255, 255]),
+            max_lines: DEFAULT_MAX_LINES,
+            max_cols: DEFAULT_MAX_COLS,
+            wrap: false,
         }
     }
 }
 
-pub fn resolve(name: Option<&str>) -> Result<Theme> {
+/// Finds a theme by name. With a namespace, a bare name is looked up in
+/// `themes/<namespace>/` first, so a course folder can override built-ins,
+/// and its `default.toml` is used when no theme is given.
+pub fn resolve(name: Option<&str>, namespace: Option<&str>) -> Result<Theme> {
+    let namespace = namespace.map(str::trim).filter(|n| !n.is_empty());
+    if let Some(ns) = namespace {
+        check_name(ns)?;
+    }
     let name = match name {
         Some(n) => n.trim(),
-        None => return Ok(Theme::default()),
+        None => {
+            if let Some(ns) = namespace {
+                let file = themes_dir()?.join(ns).join("default.toml");
+                if file.exists() {
+                    return load_file(&file);
+                }
+            }
+            return Ok(Theme::default());
+        }
     };
     if name.is_empty() {
         return Err(Error::Validation("theme name cannot be empty".into()));
     }
+    check_name(name)?;
+
+    let themes_dir = themes_dir()?;
+    if let Some(ns) = namespace
+        && !name.contains('/')
+    {
+        let file = themes_dir.join(ns).join(format!("{name}.toml"));
+        if file.exists() {
+            return load_file(&file);
+        }
+    }
 
     if let Some(theme) = builtin(name) {
         return Ok(theme);
     }
 
-    let themes_dir = config::config_path()?
-        .parent()
-        .map(|p| p.join("themes"))
-        .ok_or_else(|| Error::Validation("can't determine themes directory".into()))?;
-
     let file = themes_dir.join(format!("{name}.toml"));
     if !file.exists() {
         let available = list_available(&themes_dir);
@@ -67,78 +267,302 @@ pub fn resolve(name: Option<&str>) -> Result<Theme> {
     load_file(&file)
 }
 
+/// Theme names are paths under the themes folder, so they can't climb out of it.
+pub fn check_name(name: &str) -> Result<()> {
+    let escapes = Path::new(name)
+        .components()
+        .any(|c| !matches!(c, std::path::Component::Normal(_)));
+    if escapes || name.starts_with(['/', '\\']) {
+        return Err(Error::Validation(format!(
+            "bad theme name '{name}', use a name or folder/name inside the themes folder"
+        )));
+    }
+    Ok(())
+}
+
 fn builtin(name: &str) -> Option<Theme> {
     Some(match name {
         "default" => Theme::default(),
         "light" => Theme {
             bg: Rgb([255, 255, 255]),
             fg: Rgb([30, 30, 30]),
+            input: Rgb([0, 90, 200]),
+            syntax: Syntax {
+                keyword: Rgb([0, 0, 200]),
+                string: Rgb([160, 30, 30]),
+                comment: Rgb([0, 128, 0]),
+                number: Rgb([9, 134, 88]),
+                preproc: Rgb([128, 0, 128]),
+                prompt: Rgb([0, 110, 0]),
+                stderr: Rgb([190, 20, 20]),
+            },
             ..Theme::default()
         },
         "dracula" => Theme {
             bg: Rgb([40, 42, 54]),
             fg: Rgb([248, 248, 242]),
+            input: Rgb([139, 233, 253]),
+            syntax: Syntax {
+                keyword: Rgb([255, 121, 198]),
+                string: Rgb([241, 250, 140]),
+                comment: Rgb([98, 114, 164]),
+                number: Rgb([189, 147, 249]),
+                preproc: Rgb([80, 250, 123]),
+                prompt: Rgb([80, 250, 123]),
+                stderr: Rgb([255, 85, 85]),
+            },
             ..Theme::default()
         },
         "monokai" => Theme {
             bg: Rgb([39, 40, 34]),
             fg: Rgb([248, 248, 240]),
+            input: Rgb([230, 219, 116]),
+            syntax: Syntax {
+                keyword: Rgb([249, 38, 114]),
+                string: Rgb([230, 219, 116]),
+                comment: Rgb([117, 113, 94]),
+                number: Rgb([174, 129, 255]),
+                preproc: Rgb([166, 226, 46]),
+                prompt: Rgb([166, 226, 46]),
+                stderr: Rgb([249, 38, 114]),
+            },
             ..Theme::default()
         },
         "solarized" => Theme {
             bg: Rgb([0, 43, 54]),
             fg: Rgb([131, 148, 150]),
+            input: Rgb([181, 137, 0]),
+            syntax: Syntax {
+                keyword: Rgb([133, 153, 0]),
+                string: Rgb([42, 161, 152]),
+                comment: Rgb([88, 110, 117]),
+                number: Rgb([211, 54, 130]),
+                preproc: Rgb([203, 75, 22]),
+                prompt: Rgb([38, 139, 210]),
+                stderr: Rgb([220, 50, 47]),
+            },
             ..Theme::default()
         },
         _ => return None,
     })
 }
 
-fn load_file(path: &std::path::Path) -> Result<Theme> {
+/// Reads the PNG named by `bg_image`, relative to the theme at `theme_path`
+/// unless absolute. Transparent parts show `under`.
+fn read_bg_image(file: &str, theme_path: &Path, under: Rgb<u8>) -> Result<RgbImage> {
+    let resolved = match theme_path.parent() {
+        Some(dir) if !Path::new(file).is_absolute() => dir.join(file),
+        _ => PathBuf::from(file),
+    };
+    let data = fs::read(&resolved)
+        .map_err(|e| io_err(format!("reading bg_image '{}'", resolved.display()), e))?;
+    let pic = image::load_from_memory_with_format(&data, image::ImageFormat::Png)
+        .map_err(|e| {
+            Error::Validation(format!(
+                "bg_image '{}' isn't a readable PNG: {e}",
+                resolved.display()
+            ))
+        })?
+        .to_rgba8();
+    Ok(RgbImage::from_fn(pic.width(), pic.height(), |x, y| {
+        let [r, g, b, a] = pic.get_pixel(x, y).0;
+        let mix = |top: u8, bottom: u8| {
+            ((u32::from(top) * u32::from(a) + u32::from(bottom) * (255 - u32::from(a))) / 255) as u8
+        };
+        Rgb([mix(r, under[0]), mix(g, under[1]), mix(b, under[2])])
+    }))
+}
+
+/// The background a theme file sets with `bg_gradient` or `bg_image`. A
+/// file that sets only `bg` paints it plain, one that sets none of the
+/// three keeps what it extends.
+fn background(raw: &ThemeFile, path: &Path, base: &Theme) -> Result<Background> {
+    match (&raw.bg_gradient, &raw.bg_image) {
+        (Some(_), Some(_)) => Err(Error::Validation(
+            "set bg_gradient or bg_image, not both".into(),
+        )),
+        (Some(stops), None) => {
+            if stops.len() < 2 {
+                return Err(Error::Validation(
+                    "bg_gradient needs at least two colors".into(),
+                ));
+            }
+            let colors = stops.iter().map(|c| parse_hex(c)).collect::<Result<_>>()?;
+            Ok(Background::Gradient(colors))
+        }
+        (None, Some(file)) => {
+            let under = color_or(&raw.bg, base.bg)?;
+            Ok(Background::Image(read_bg_image(file, path, under)?))
+        }
+        (None, None) if raw.bg.is_some() => Ok(Background::Solid),
+        (None, None) => Ok(base.background.clone()),
+    }
+}
+
+/// Reads a font named in the theme at `theme_path`: a file relative to the
+/// theme, an absolute path, or the name of an installed family, in which
+/// case its `style` face is used.
+fn read_font(font_path: &str, theme_path: &Path, style: Style) -> Result<Vec<u8>> {
+    let mut resolved = if Path::new(font_path).is_absolute() {
+        std::path::PathBuf::from(font_path)
+    } else {
+        theme_path
+            .parent()
+            .ok_or_else(|| Error::Validation("can't resolve font path".into()))?
+            .join(font_path)
+    };
+    if !resolved.exists() && !fonts::looks_like_path(font_path) {
+        resolved = fonts::find_family(font_path, style).ok_or_else(|| {
+            Error::Validation(format!(
+                "font '{font_path}' isn't a file next to the theme or an installed font family"
+            ))
+        })?;
+    }
+    fs::read(&resolved).map_err(|e| io_err(format!("reading font '{}'", resolved.display()), e))
+}
+
+fn load_file(path: &Path) -> Result<Theme> {
+    load_chain(path, &mut Vec::new())
+}
+
+/// Loads the theme at `path` on top of the one it `extends`. `chain` holds
+/// the files already being loaded, so a loop is an error rather than a hang.
+fn load_chain(path: &Path, chain: &mut Vec<PathBuf>) -> Result<Theme> {
+    let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if chain.contains(&key) {
+        let names: Vec<String> = chain
+            .iter()
+            .chain([&key])
+            .map(|p| {
+                p.file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        return Err(Error::Validation(format!(
+            "themes extend each other in a loop: {}",
+            names.join(" -> ")
+        )));
+    }
+    chain.push(key);
+
     let content = fs::read_to_string(path).map_err(|e| io_err("reading theme", e))?;
     let raw: ThemeFile =
         toml::from_str(&content).map_err(|e| Error::Validation(format!("bad theme file: {e}")))?;
 
-    let base = Theme::default();
+    let base = match raw.extends.as_deref().map(str::trim) {
+        Some(parent) => parent_theme(parent, path, chain)?,
+        None => Theme::default(),
+    };
     let scale = raw.scale.unwrap_or(base.scale).clamp(1, 4);
     let padding = raw.padding.unwrap_or(base.padding).min(64);
     let font_size = raw.font_size.unwrap_or(base.font_size).clamp(8.0, 72.0);
+    let background = background(&raw, path, &base)?;
+    let bg = match &raw.bg {
+        Some(hex) => parse_hex(hex)?,
+        None if raw.bg_gradient.is_some() || raw.bg_image.is_some() => {
+            background.average().unwrap_or(base.bg)
+        }
+        None => base.bg,
+    };
 
-    let font_data = if let Some(ref font_path) = raw.font {
-        let resolved = if std::path::Path::new(font_path).is_absolute() {
-            std::path::PathBuf::from(font_path)
-        } else {
-            path.parent()
-                .ok_or_else(|| Error::Validation("can't resolve font path".into()))?
-                .join(font_path)
-        };
-        let data = fs::read(&resolved)
-            .map_err(|e| io_err(format!("reading font '{}'", resolved.display()), e))?;
-        Some(data)
-    } else {
-        None
+    let font = |name: &Option<String>, style| {
+        name.as_deref()
+            .map(|f| read_font(f, path, style))
+            .transpose()
+    };
+    // A new regular font drops the inherited bold and italic faces, which
+    // belong to the old one
+    let (font_data, inherited_bold, inherited_italic) = match font(&raw.font, Style::Regular)? {
+        Some(data) => (Some(data), None, None),
+        None => (base.font_data, base.font_bold_data, base.font_italic_data),
     };
+    if font_data.is_none() && (raw.font_bold.is_some() || raw.font_italic.is_some()) {
+        return Err(Error::Validation(
+            "font_bold and font_italic need a regular font too, set font".into(),
+        ));
+    }
+    let font_bold_data = font(&raw.font_bold, Style::Bold)?.or(inherited_bold);
+    let font_italic_data = font(&raw.font_italic, Style::Italic)?.or(inherited_italic);
 
     Ok(Theme {
-        bg: raw
-            .bg
-            .as_deref()
-            .map(parse_hex)
-            .transpose()?
-            .unwrap_or(base.bg),
+        bg,
+        background,
         fg: raw
             .fg
             .as_deref()
             .map(parse_hex)
             .transpose()?
             .unwrap_or(base.fg),
+        input: raw
+            .input
+            .as_deref()
+            .map(parse_hex)
+            .transpose()?
+            .unwrap_or(base.input),
         padding,
         scale,
         font_data,
+        font_bold_data,
+        font_italic_data,
         font_size,
+        supersample: base.supersample,
+        line_numbers: raw.line_numbers.unwrap_or(base.line_numbers),
+        chrome: raw.chrome.unwrap_or(base.chrome),
+        corner_radius: raw.corner_radius.unwrap_or(base.corner_radius).min(64),
+        shadow: raw.shadow.unwrap_or(base.shadow),
+        canvas: color_or(&raw.canvas, base.canvas)?,
+        max_lines: raw
+            .max_lines
+            .unwrap_or(base.max_lines)
+            .clamp(MAX_LINES_RANGE.0, MAX_LINES_RANGE.1),
+        max_cols: raw
+            .max_cols
+            .unwrap_or(base.max_cols)
+            .clamp(MAX_COLS_RANGE.0, MAX_COLS_RANGE.1),
+        wrap: raw.wrap.unwrap_or(base.wrap),
+        syntax: Syntax {
+            keyword: color_or(&raw.syntax.keyword, base.syntax.keyword)?,
+            string: color_or(&raw.syntax.string, base.syntax.string)?,
+            comment: color_or(&raw.syntax.comment, base.syntax.comment)?,
+            number: color_or(&raw.syntax.number, base.syntax.number)?,
+            preproc: color_or(&raw.syntax.preproc, base.syntax.preproc)?,
+            prompt: color_or(&raw.syntax.prompt.or(raw.prompt), base.syntax.prompt)?,
+            stderr: color_or(&raw.syntax.stderr.or(raw.fg_err), base.syntax.stderr)?,
+        },
     })
 }
 
+/// Finds the theme named in `extends`: a built-in, a file next to `child`,
+/// or one in the themes folder.
+fn parent_theme(name: &str, child: &Path, chain: &mut Vec<PathBuf>) -> Result<Theme> {
+    if name.is_empty() {
+        return Err(Error::Validation("extends can't be empty".into()));
+    }
+    check_name(name)?;
+    if let Some(theme) = builtin(name) {
+        return Ok(theme);
+    }
+    let file = format!("{name}.toml");
+    let sibling = child.parent().map(|dir| dir.join(&file));
+    let found = match sibling.filter(|p| p.exists()) {
+        Some(path) => path,
+        None => themes_dir()?.join(&file),
+    };
+    if !found.exists() {
+        return Err(Error::Validation(format!(
+            "'{}' extends unknown theme '{name}'",
+            child.display()
+        )));
+    }
+    load_chain(&found, chain)
+}
+
+fn color_or(value: &Option<String>, fallback: Rgb<u8>) -> Result<Rgb<u8>> {
+    value.as_deref().map_or(Ok(fallback), parse_hex)
+}
+
 fn parse_hex(s: &str) -> Result<Rgb<u8>> {
     let s = s.strip_prefix('#').unwrap_or(s);
     if s.len() != 6 {
@@ -194,34 +618,221 @@ fn custom_theme_names(themes_dir: &Path) -> Vec<String> {
     names
 }
 
+pub fn themes_dir() -> Result<std::path::PathBuf> {
+    config::config_path()?
+        .parent()
+        .map(|p| p.join("themes"))
+        .ok_or_else(|| Error::Validation("can't determine themes directory".into()))
+}
+
+/// Commented theme files to copy from, written by `ap theme install-examples`.
+const EXAMPLES: &[(&str, &str)] = &[
+    ("nord", include_str!("../../assets/themes/nord.toml")),
+    ("gruvbox", include_str!("../../assets/themes/gruvbox.toml")),
+    (
+        "catppuccin",
+        include_str!("../../assets/themes/catppuccin.toml"),
+    ),
+    (
+        "high-contrast",
+        include_str!("../../assets/themes/high-contrast.toml"),
+    ),
+];
+
+pub fn run_theme_command(command: ThemeCommand) -> Result<()> {
+    match command {
+        ThemeCommand::InstallExamples { force } => install_examples(force),
+        ThemeCommand::List => run_list(),
+        ThemeCommand::Preview { name, output } => preview(&name, output.as_deref()),
+        ThemeCommand::New { name, force } => new_theme(&name, force),
+        ThemeCommand::Import { file, name, force } => {
+            crate::render::vscode::import(&file, name.as_deref(), force)
+        }
+    }
+}
+
+fn install_examples(force: bool) -> Result<()> {
+    let dir = themes_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| io_err(format!("creating '{}'", dir.display()), e))?;
+
+    let mut skipped = Vec::new();
+    for (name, content) in EXAMPLES {
+        let file = dir.join(format!("{name}.toml"));
+        if file.exists() && !force {
+            skipped.push(*name);
+            continue;
+        }
+        fs::write(&file, content)
+            .map_err(|e| io_err(format!("writing '{}'", file.display()), e))?;
+        ui::success(&format!("{name} -> {}", file.display()));
+    }
+    if !skipped.is_empty() {
+        ui::warn(&format!(
+            "kept existing {}, pass --force to overwrite",
+            skipped.join(", ")
+        ));
+    }
+    ui::kv(
+        "try",
+        &format!("ap -a 7 --auto-doc --theme {}", EXAMPLES[0].0),
+    );
+    Ok(())
+}
+
 const BUILTIN_NAMES: &[&str] = &["default", "light", "dracula", "monokai", "solarized"];
 
 pub fn run_list() -> Result<()> {
+    let themes_dir = themes_dir()?;
+    let custom = custom_theme_names(&themes_dir);
+    let width = BUILTIN_NAMES
+        .iter()
+        .map(|n| n.len())
+        .chain(custom.iter().map(String::len))
+        .max()
+        .unwrap_or(0);
+
     println!("Built-in:");
-    println!("  {}", BUILTIN_NAMES.join("  "));
+    for name in BUILTIN_NAMES {
+        let theme = builtin(name).expect("built-in theme");
+        println!(
+            "{}",
+            format!("  {name:width$}  {}", swatches(&theme)).trim_end()
+        );
+    }
     println!();
 
-    let themes_dir = config::config_path()?
-        .parent()
-        .map(|p| p.join("themes"))
-        .ok_or_else(|| Error::Validation("can't determine themes directory".into()))?;
+    let cfg = config::load(&config::config_path()?)?;
+    if let Some(ns) = cfg.theme_namespace {
+        println!("Namespace: {ns} (bare names look in {ns}/ first)");
+        println!();
+    }
 
     println!("Custom:");
-    let custom = custom_theme_names(&themes_dir);
     if custom.is_empty() {
-        println!("  (none)");
-    } else {
-        for name in custom {
-            println!("  {name}");
+        println!("  (none, start one with `ap theme new <name>`)");
+    }
+    for name in custom {
+        match load_file(&themes_dir.join(format!("{name}.toml"))) {
+            Ok(theme) => {
+                println!(
+                    "{}",
+                    format!("  {name:width$}  {}", swatches(&theme)).trim_end()
+                )
+            }
+            Err(e) => println!("  {name:width$}  {}", ui::dim(&format!("({e})"))),
         }
     }
 
     Ok(())
 }
 
+/// The background, text and input colors, then the token colors.
+fn swatches(theme: &Theme) -> String {
+    let s = theme.syntax;
+    [
+        theme.bg,
+        theme.fg,
+        theme.input,
+        s.keyword,
+        s.string,
+        s.comment,
+    ]
+    .into_iter()
+    .chain([s.number, s.preproc, s.prompt, s.stderr])
+    .map(|c| ui::swatch(c.0))
+    .collect()
+}
+
+const SAMPLE_CODE: &str = r#"#include <stdio.h>
+
+// Reads a number and doubles it
+int main(void) {
+    int n;
+    printf("Number: ");
+    if (scanf("%d", &n) != 1)
+        return 1;
+    printf("Twice that is %d\n", n * 2);
+    return 0;
+}
+"#;
+
+/// Renders the sample program and a run of it, one above the other, and
+/// saves it to `output` or opens it.
+fn preview(name: &str, output: Option<&Path>) -> Result<()> {
+    let cfg = config::load(&config::config_path()?)?;
+    let theme = resolve(Some(name), cfg.theme_namespace.as_deref())?;
+
+    let code =
+        image::load_from_memory(&screenshot::render_code_png(SAMPLE_CODE, "main.c", &theme)?)
+            .map_err(|e| Error::Validation(format!("decoding preview: {e}")))?;
+    let stdout = format!("Number: {INPUT_START}21{INPUT_END}\nTwice that is 42\n");
+    let run = [
+        Segment::new(Stream::Command, "$ ./Assignment7\n\n"),
+        Segment::new(Stream::Stdout, stdout),
+        Segment::new(Stream::Stderr, "warning: something went to stderr\n"),
+    ];
+    let run = image::load_from_memory(&screenshot::render_segments(&run, "Assignment7", &theme)?)
+        .map_err(|e| Error::Validation(format!("decoding preview: {e}")))?;
+
+    let (code, run) = (code.to_rgb8(), run.to_rgb8());
+    let gap = theme.padding.max(8);
+    let mut sheet = image::RgbImage::from_pixel(
+        code.width().max(run.width()),
+        code.height() + gap + run.height(),
+        theme.canvas,
+    );
+    image::imageops::replace(&mut sheet, &code, 0, 0);
+    image::imageops::replace(&mut sheet, &run, 0, (code.height() + gap) as i64);
+
+    let path = match output {
+        Some(path) => path.to_path_buf(),
+        None => std::env::temp_dir().join(format!("ap_theme_{}.png", name.replace('/', "_"))),
+    };
+    sheet
+        .save_with_format(&path, image::ImageFormat::Png)
+        .map_err(|e| Error::Validation(format!("writing '{}': {e}", path.display())))?;
+    ui::success(&format!("{name} -> {}", path.display()));
+    if output.is_none() {
+        crate::open::open_path(&path)?;
+    }
+    Ok(())
+}
+
+/// Commented theme with every default spelled out, `{name}` filled in.
+const TEMPLATE: &str = include_str!("../../assets/themes/template.toml");
+
+fn new_theme(name: &str, force: bool) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::Validation("theme name cannot be empty".into()));
+    }
+    check_name(name)?;
+    if BUILTIN_NAMES.contains(&name) {
+        return Err(Error::Validation(format!(
+            "'{name}' is a built-in theme and would hide the file, pick another name"
+        )));
+    }
+    let file = themes_dir()?.join(format!("{name}.toml"));
+    if file.exists() && !force {
+        return Err(Error::Validation(format!(
+            "theme '{name}' already exists at {}, pass --force to overwrite",
+            file.display()
+        )));
+    }
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).map_err(|e| io_err(format!("creating '{}'", dir.display()), e))?;
+    }
+    fs::write(&file, TEMPLATE.replace("{name}", name))
+        .map_err(|e| io_err(format!("writing '{}'", file.display()), e))?;
+    ui::success(&format!("{name} -> {}", file.display()));
+    ui::kv("preview", &format!("ap theme preview {name}"));
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fs::scratch_dir;
 
     #[test]
     fn parse_hex_valid() {
@@ -257,15 +868,140 @@ mod tests {
 
     #[test]
     fn resolve_none_gives_default() {
-        let t = resolve(None).unwrap();
+        let t = resolve(None, None).unwrap();
         let d = Theme::default();
         assert_eq!(t.bg, d.bg);
         assert_eq!(t.fg, d.fg);
     }
 
+    #[test]
+    fn examples_are_valid_themes() {
+        for (name, content) in EXAMPLES {
+            let raw: ThemeFile = toml::from_str(content).unwrap();
+            let syntax = raw.syntax;
+            let colors = [raw.bg, raw.fg, raw.input, syntax.keyword, syntax.string]
+                .into_iter()
+                .chain([syntax.comment, syntax.number, syntax.preproc])
+                .chain([syntax.prompt, syntax.stderr]);
+            for color in colors.flatten() {
+                assert!(parse_hex(&color).is_ok(), "{name}: {color}");
+            }
+            assert!(raw.font.is_none(), "{name} shouldn't need a font file");
+        }
+    }
+
+    #[test]
+    fn template_spells_out_the_defaults() {
+        let dir = scratch_dir("template");
+        let path = dir.join("mine.toml");
+        fs::write(&path, TEMPLATE.replace("{name}", "mine")).unwrap();
+        let theme = load_file(&path).unwrap();
+        let base = Theme::default();
+        assert_eq!(
+            (theme.bg, theme.fg, theme.input),
+            (base.bg, base.fg, base.input)
+        );
+        let (s, d) = (theme.syntax, base.syntax);
+        assert_eq!(
+            [
+                s.keyword, s.string, s.comment, s.number, s.preproc, s.prompt, s.stderr
+            ],
+            [
+                d.keyword, d.string, d.comment, d.number, d.preproc, d.prompt, d.stderr
+            ]
+        );
+    }
+
+    #[test]
+    fn extends_overrides_only_what_it_sets() {
+        let dir = scratch_dir("extends");
+        fs::write(
+            dir.join("base.toml"),
+            "extends = \"dracula\"\npadding = 4\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("mine.toml"),
+            "extends = \"base\"\n[syntax]\nkeyword = \"#010203\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("a.toml"), "extends = \"b\"\n").unwrap();
+        fs::write(dir.join("b.toml"), "extends = \"a\"\n").unwrap();
+
+        let theme = load_file(&dir.join("mine.toml")).unwrap();
+        let looped = load_file(&dir.join("a.toml")).map(|_| ()).unwrap_err();
+        let dracula = builtin("dracula").unwrap();
+        assert_eq!(theme.syntax.keyword, Rgb([1, 2, 3]));
+        assert_eq!(theme.syntax.string, dracula.syntax.string);
+        assert_eq!(theme.bg, dracula.bg);
+        assert_eq!(theme.padding, 4);
+        assert!(looped.to_string().contains("a -> b -> a"), "{looped}");
+    }
+
+    #[test]
+    fn backgrounds_load_and_set_bg() {
+        let dir = scratch_dir("background");
+        RgbImage::from_pixel(4, 4, Rgb([200, 100, 0]))
+            .save(dir.join("paper.png"))
+            .unwrap();
+        let load = |content: &str| {
+            fs::write(dir.join("t.toml"), content).unwrap();
+            load_file(&dir.join("t.toml"))
+        };
+        let gradient = load("bg_gradient = [\"#000000\", \"#202020\"]\n").unwrap();
+        let picture = load("bg_image = \"paper.png\"\n").unwrap();
+        let both = load("bg_gradient = [\"#000000\", \"#ffffff\"]\nbg_image = \"paper.png\"\n");
+        let one = load("bg_gradient = [\"#000000\"]\n");
+        fs::write(dir.join("t2.toml"), "bg_image = \"paper.png\"\n").unwrap();
+        let inherited = load("extends = \"t2\"\nfg = \"#ffffff\"\n").unwrap();
+        let replaced = load("extends = \"t2\"\nbg = \"#010203\"\n").unwrap();
+
+        assert!(matches!(gradient.background, Background::Gradient(ref s) if s.len() == 2));
+        assert_eq!(gradient.bg, Rgb([16, 16, 16]));
+        assert!(matches!(picture.background, Background::Image(_)));
+        assert_eq!(picture.bg, Rgb([200, 100, 0]));
+        assert!(
+            both.map(|_| ())
+                .unwrap_err()
+                .to_string()
+                .contains("not both")
+        );
+        assert!(
+            one.map(|_| ())
+                .unwrap_err()
+                .to_string()
+                .contains("two colors")
+        );
+        assert!(matches!(inherited.background, Background::Image(_)));
+        assert!(matches!(replaced.background, Background::Solid));
+        assert_eq!(replaced.bg, Rgb([1, 2, 3]));
+    }
+
+    #[test]
+    fn old_prompt_keys_still_load() {
+        let dir = scratch_dir("palette");
+        let path = dir.join("old.toml");
+        fs::write(
+            &path,
+            "fg_err = \"#010203\"\n[syntax]\nprompt = \"#040506\"\n",
+        )
+        .unwrap();
+        let theme = load_file(&path).unwrap();
+        assert_eq!(theme.syntax.stderr, Rgb([1, 2, 3]));
+        assert_eq!(theme.syntax.prompt, Rgb([4, 5, 6]));
+    }
+
     #[test]
     fn resolve_builtin_works() {
-        let t = resolve(Some("dracula")).unwrap();
+        let t = resolve(Some("dracula"), None).unwrap();
         assert_eq!(t.bg, Rgb([40, 42, 54]));
     }
+
+    #[test]
+    fn names_stay_inside_the_themes_folder() {
+        assert!(check_name("cs101/nord").is_ok());
+        assert!(check_name("../secrets").is_err());
+        assert!(check_name("cs101/../../x").is_err());
+        assert!(check_name("/etc/theme").is_err());
+    }
 }
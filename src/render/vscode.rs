@@ -0,0 +1,359 @@
+//! `ap theme import`: turns a VS Code color theme into a theme file, taking
+//! the terminal colors for the run screenshot and the token colors for the
+//! code image.
+
+use crate::error::{Error, Result, io_err};
+use crate::render::theme;
+use crate::ui;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// Where each theme key is looked for in the `colors` table, first found wins.
+const WORKBENCH: &[(&str, &[&str])] = &[
+    ("bg", &["terminal.background", "editor.background"]),
+    ("fg", &["terminal.foreground", "editor.foreground"]),
+    ("input", &["terminal.ansiCyan", "terminal.ansiBrightCyan"]),
+];
+
+/// Keys of the `[syntax]` table and the TextMate scopes they're colored by,
+/// most specific first.
+const TOKENS: &[(&str, &[&str])] = &[
+    ("keyword", &["keyword.control", "keyword", "storage.type"]),
+    ("string", &["string.quoted", "string"]),
+    ("comment", &["comment.line", "comment"]),
+    ("number", &["constant.numeric", "constant"]),
+    (
+        "preproc",
+        &[
+            "meta.preprocessor",
+            "keyword.control.directive",
+            "keyword.control.import",
+        ],
+    ),
+];
+
+/// Workbench colors for the `[syntax]` keys that aren't tokens.
+const TERMINAL: &[(&str, &[&str])] = &[
+    (
+        "prompt",
+        &["terminal.ansiGreen", "terminal.ansiBrightGreen"],
+    ),
+    (
+        "stderr",
+        &[
+            "terminal.ansiRed",
+            "errorForeground",
+            "editorError.foreground",
+        ],
+    ),
+];
+
+/// Converts the theme at `file` and writes it to the themes folder, named
+/// `name` or after the theme.
+pub fn import(file: &Path, name: Option<&str>, force: bool) -> Result<()> {
+    let text =
+        fs::read_to_string(file).map_err(|e| io_err(format!("reading '{}'", file.display()), e))?;
+    let source = file.file_name().unwrap_or_default().to_string_lossy();
+    let converted = convert(&text, &source)?;
+
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match name {
+        Some(n) => n.to_string(),
+        None => slug(converted.name.as_deref().unwrap_or(&stem)),
+    };
+    if name.is_empty() {
+        return Err(Error::Validation(
+            "can't make a theme name from that file, pass --name".into(),
+        ));
+    }
+    theme::check_name(&name)?;
+
+    let dest = theme::themes_dir()?.join(format!("{name}.toml"));
+    if dest.exists() && !force {
+        return Err(Error::Validation(format!(
+            "theme '{name}' already exists, pass --force to overwrite it"
+        )));
+    }
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| io_err(format!("creating '{}'", parent.display()), e))?;
+    }
+    fs::write(&dest, &converted.toml)
+        .map_err(|e| io_err(format!("writing '{}'", dest.display()), e))?;
+
+    if let Some(include) = &converted.include {
+        ui::warn(&format!(
+            "the theme builds on '{include}', colors only set there were left at the defaults"
+        ));
+    }
+    ui::success(&format!("{name} -> {}", dest.display()));
+    ui::kv("try", &format!("ap -a 7 --auto-doc --theme {name}"));
+    Ok(())
+}
+
+pub struct Converted {
+    /// The theme's own name, if it gives one.
+    pub name: Option<String>,
+    /// Another theme file this one extends, which isn't followed.
+    pub include: Option<String>,
+    pub toml: String,
+}
+
+/// Builds theme TOML from VS Code theme JSON. Keys the theme has no color for
+/// are left out, so they fall back to the defaults.
+pub fn convert(json: &str, source: &str) -> Result<Converted> {
+    let root: Value = serde_json::from_str(&strip_jsonc(json))
+        .map_err(|e| Error::Validation(format!("not a VS Code theme: {e}")))?;
+    let colors = root.get("colors");
+    let bg = colors.and_then(|c| workbench(c, &["terminal.background", "editor.background"]));
+    let rules: Vec<(Vec<&str>, &str)> = root
+        .get("tokenColors")
+        .and_then(Value::as_array)
+        .map(|rules| rules.iter().filter_map(token_rule).collect())
+        .unwrap_or_default();
+
+    let mut top = Vec::new();
+    let mut syntax = Vec::new();
+    if let Some(colors) = colors {
+        for (key, names) in WORKBENCH {
+            if let Some(color) = workbench(colors, names) {
+                top.push((*key, hex(color, bg)));
+            }
+        }
+    }
+    for (key, scopes) in TOKENS {
+        if let Some(color) = scopes.iter().find_map(|s| token_color(&rules, s)) {
+            syntax.push((*key, hex(color, bg)));
+        }
+    }
+    if let Some(colors) = colors {
+        for (key, names) in TERMINAL {
+            if let Some(color) = workbench(colors, names) {
+                syntax.push((*key, hex(color, bg)));
+            }
+        }
+    }
+    let (top, syntax) = (flatten(top), flatten(syntax));
+    if top.is_empty() && syntax.is_empty() {
+        return Err(Error::Validation(
+            "no colors found, is that a VS Code color theme?".into(),
+        ));
+    }
+
+    let name = root.get("name").and_then(Value::as_str).map(String::from);
+    let mut toml = format!(
+        "# Imported from {source}{}\n",
+        name.as_deref()
+            .map(|n| format!(" ({n})"))
+            .unwrap_or_default()
+    );
+    for (key, color) in &top {
+        toml.push_str(&format!("{key} = \"{color}\"\n"));
+    }
+    if !syntax.is_empty() {
+        toml.push_str("\n[syntax]\n");
+        for (key, color) in &syntax {
+            toml.push_str(&format!("{key} = \"{color}\"\n"));
+        }
+    }
+    Ok(Converted {
+        name,
+        include: root
+            .get("include")
+            .and_then(Value::as_str)
+            .map(String::from),
+        toml,
+    })
+}
+
+/// Drops keys whose color couldn't be read.
+fn flatten(pairs: Vec<(&'static str, Option<String>)>) -> Vec<(&'static str, String)> {
+    pairs
+        .into_iter()
+        .filter_map(|(key, color)| Some((key, color?)))
+        .collect()
+}
+
+fn workbench<'a>(colors: &'a Value, names: &[&str]) -> Option<&'a str> {
+    names
+        .iter()
+        .find_map(|n| colors.get(*n).and_then(Value::as_str))
+}
+
+/// The selectors of a `tokenColors` rule and its foreground. Selectors with
+/// a space only apply inside another scope, so they're skipped.
+fn token_rule(rule: &Value) -> Option<(Vec<&str>, &str)> {
+    let foreground = rule.get("settings")?.get("foreground")?.as_str()?;
+    let selectors: Vec<&str> = match rule.get("scope")? {
+        Value::String(s) => s.split(',').collect(),
+        Value::Array(list) => list.iter().filter_map(Value::as_str).collect(),
+        _ => return None,
+    };
+    let selectors = selectors
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !s.is_empty() && !s.contains(' '))
+        .collect();
+    Some((selectors, foreground))
+}
+
+/// Color of `scope` the way TextMate picks it: the rule whose selector is the
+/// longest prefix of the scope, the later rule on a tie.
+fn token_color<'a>(rules: &[(Vec<&str>, &'a str)], scope: &str) -> Option<&'a str> {
+    rules
+        .iter()
+        .flat_map(|(selectors, color)| selectors.iter().map(move |s| (*s, *color)))
+        .filter(|(s, _)| {
+            scope == *s
+                || scope
+                    .strip_prefix(s)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+        .max_by_key(|(s, _)| s.len())
+        .map(|(_, color)| color)
+}
+
+/// `#RRGGBB` for a VS Code color, which may be short (`#abc`) or carry alpha.
+/// A translucent color is blended over `bg`, as the editor would draw it.
+fn hex(color: &str, bg: Option<&str>) -> Option<String> {
+    let (rgb, alpha) = rgba(color)?;
+    let under = bg.and_then(rgba).map_or([0, 0, 0], |(rgb, _)| rgb);
+    let a = f32::from(alpha) / 255.0;
+    let [r, g, b] =
+        [0, 1, 2].map(|i| (f32::from(rgb[i]) * a + f32::from(under[i]) * (1.0 - a)).round() as u8);
+    Some(format!("#{r:02X}{g:02X}{b:02X}"))
+}
+
+fn rgba(color: &str) -> Option<([u8; 3], u8)> {
+    let digits = color.trim().strip_prefix('#')?;
+    let expanded: String = match digits.len() {
+        3 | 4 => digits.chars().flat_map(|c| [c, c]).collect(),
+        6 | 8 => digits.to_string(),
+        _ => return None,
+    };
+    let channel = |i: usize| u8::from_str_radix(expanded.get(i..i + 2)?, 16).ok();
+    let alpha = if expanded.len() == 8 {
+        channel(6)?
+    } else {
+        255
+    };
+    Some(([channel(0)?, channel(2)?, channel(4)?], alpha))
+}
+
+/// Lowercase letters, digits and dashes, so "Night Owl (No Italics)" becomes
+/// `night-owl-no-italics`.
+fn slug(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            out.push(c.to_ascii_lowercase());
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out.trim_end_matches('-').to_string()
+}
+
+/// VS Code theme files are JSON with comments and trailing commas. This
+/// removes both, leaving strings alone.
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => while chars.next_if(|&n| n != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for n in chars.by_ref() {
+                    if prev == '*' && n == '/' {
+                        break;
+                    }
+                    prev = n;
+                }
+            }
+            (']' | '}', _) => {
+                let kept = out.trim_end().len();
+                if out[..kept].ends_with(',') {
+                    out.replace_range(kept - 1..kept, "");
+                }
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THEME: &str = r##"{
+        // a comment, with a "quote"
+        "name": "Night Owl",
+        "type": "dark",
+        "colors": {
+            "editor.background": "#011627",
+            "editor.foreground": "#d6deeb",
+            "terminal.ansiRed": "#EF5350",
+            "terminal.ansiGreen": "#22da6e", /* trailing comma next */
+        },
+        "tokenColors": [
+            { "scope": "comment", "settings": { "foreground": "#637777", "fontStyle": "italic" } },
+            { "scope": ["keyword", "storage.type"], "settings": { "foreground": "#c792ea" } },
+            { "scope": "keyword.control", "settings": { "foreground": "#ff0000" } },
+            { "scope": "string, string.quoted", "settings": { "foreground": "#ecc48d" } },
+            { "scope": "constant.numeric", "settings": { "foreground": "#F78C6C80" } },
+            { "scope": "source.c keyword", "settings": { "foreground": "#000000" } },
+        ],
+    }"##;
+
+    #[test]
+    fn vscode_theme_converts() {
+        let converted = convert(THEME, "night-owl.json").unwrap();
+        assert_eq!(converted.name.as_deref(), Some("Night Owl"));
+        let parsed: toml::Value = toml::from_str(&converted.toml).unwrap();
+        assert_eq!(parsed["bg"].as_str(), Some("#011627"));
+        assert_eq!(parsed["fg"].as_str(), Some("#D6DEEB"));
+        assert!(parsed.get("input").is_none());
+        let syntax = &parsed["syntax"];
+        assert_eq!(syntax["keyword"].as_str(), Some("#FF0000"));
+        assert_eq!(syntax["string"].as_str(), Some("#ECC48D"));
+        assert_eq!(syntax["comment"].as_str(), Some("#637777"));
+        // half-transparent, blended over the background
+        assert_eq!(syntax["number"].as_str(), Some("#7C514A"));
+        assert_eq!(syntax["preproc"].as_str(), Some("#FF0000"));
+        assert_eq!(syntax["prompt"].as_str(), Some("#22DA6E"));
+        assert_eq!(syntax["stderr"].as_str(), Some("#EF5350"));
+    }
+
+    #[test]
+    fn colorless_json_is_rejected() {
+        assert!(convert("{\"name\": \"x\"}", "x.json").is_err());
+        assert!(convert("not json", "x.json").is_err());
+    }
+
+    #[test]
+    fn short_colors_expand_and_names_slug() {
+        assert_eq!(hex("#abc", None).as_deref(), Some("#AABBCC"));
+        assert_eq!(hex("red", None), None);
+        assert_eq!(slug("Night Owl (No Italics)"), "night-owl-no-italics");
+        assert_eq!(strip_jsonc("[\"a//b\", 1,]"), "[\"a//b\", 1]");
+    }
+}
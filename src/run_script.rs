@@ -0,0 +1,70 @@
+use std::fs;
+use std::path::Path;
+
+const MAKEFILES: &[&str] = &["GNUmakefile", "makefile", "Makefile"];
+const RUN_SCRIPT: &str = "run.sh";
+
+/// A run command the project prescribes itself.
+pub struct RunScript {
+    pub command: String,
+    /// Where it came from, for the confirmation prompt.
+    pub source: &'static str,
+}
+
+/// Looks for a `run` make target, then a `run.sh`, in `dir`.
+pub fn detect(dir: &Path) -> Option<RunScript> {
+    for name in MAKEFILES {
+        if let Ok(text) = fs::read_to_string(dir.join(name))
+            && has_run_target(&text)
+        {
+            return Some(RunScript {
+                command: "make run".into(),
+                source: name,
+            });
+        }
+    }
+    dir.join(RUN_SCRIPT).is_file().then(|| RunScript {
+        command: format!("sh ./{RUN_SCRIPT}"),
+        source: RUN_SCRIPT,
+    })
+}
+
+fn has_run_target(makefile: &str) -> bool {
+    makefile.lines().any(|line| {
+        // Recipe lines are tab-indented, and `run := ...` is a variable
+        if line.starts_with('\t') {
+            return false;
+        }
+        line.split_once(':').is_some_and(|(targets, rest)| {
+            !rest.starts_with('=') && targets.split_whitespace().any(|t| t == "run")
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::scratch_dir;
+
+    #[test]
+    fn finds_run_target() {
+        assert!(has_run_target("all: main\n\nrun: main\n\t./main\n"));
+        assert!(has_run_target(".PHONY: all\nbuild run: main\n"));
+        assert!(!has_run_target("run := ./main\nall:\n\t$(run)\n"));
+        assert!(!has_run_target("all:\n\trun: not a target\nrunner: x\n"));
+    }
+
+    #[test]
+    fn make_target_preferred_over_script() {
+        let dir = scratch_dir("runscript");
+        assert!(detect(&dir).is_none());
+
+        fs::write(dir.join(RUN_SCRIPT), "./main\n").unwrap();
+        assert_eq!(detect(&dir).unwrap().command, "sh ./run.sh");
+
+        fs::write(dir.join("Makefile"), "run: main\n\t./main\n").unwrap();
+        let found = detect(&dir).unwrap();
+        assert_eq!(found.command, "make run");
+        assert_eq!(found.source, "Makefile");
+    }
+}
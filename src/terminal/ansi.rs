@@ -0,0 +1,226 @@
+//! Colors a program sets with ANSI escape codes. The emulator keeps them as
+//! private-use marker characters in its output, the same way echoed input is
+//! marked, so the screenshot and the doc's text listing can both show them.
+//! A program can print those characters too (icon fonts put glyphs there),
+//! so in marked text its own are escaped with `LITERAL`, along with any of
+//! the echo and screenshot markers at U+E000..U+E002.
+
+/// Marks where the 256-color palette entry `n` starts as `COLOR_BASE + n`.
+const COLOR_BASE: u32 = 0xF0000;
+/// Marks where the text goes back to its default color.
+pub const COLOR_RESET: char = '\u{F0100}';
+/// Put before a marker character the program printed itself, so it's read
+/// as text.
+pub const LITERAL: char = '\u{F0101}';
+
+/// A character of marked text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Piece {
+    /// The program's own text.
+    Text(char),
+    /// A marker, like the ones `marker` makes.
+    Marker(char),
+}
+
+/// What a marker character stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mark {
+    /// Entry in the xterm 256-color palette.
+    Color(u8),
+    Reset,
+}
+
+pub fn marker(color: Option<u8>) -> char {
+    match color {
+        Some(n) => char::from_u32(COLOR_BASE + u32::from(n)).unwrap_or(COLOR_RESET),
+        None => COLOR_RESET,
+    }
+}
+
+pub fn mark(ch: char) -> Option<Mark> {
+    if ch == COLOR_RESET {
+        return Some(Mark::Reset);
+    }
+    let n = u32::from(ch).checked_sub(COLOR_BASE)?;
+    u8::try_from(n).ok().map(Mark::Color)
+}
+
+/// Whether the program's `ch` has to be escaped in marked text.
+pub fn is_reserved(ch: char) -> bool {
+    let n = u32::from(ch);
+    (0xE000..=0xE002).contains(&n) || (COLOR_BASE..=u32::from(LITERAL)).contains(&n)
+}
+
+/// Appends the program's `ch` to marked text.
+pub fn push_text(out: &mut String, ch: char) {
+    if is_reserved(ch) {
+        out.push(LITERAL);
+    }
+    out.push(ch);
+}
+
+/// The program's `text` made safe to put markers around.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        push_text(&mut out, ch);
+    }
+    out
+}
+
+/// Splits marked text into the program's characters and the markers.
+pub fn pieces(text: &str) -> impl Iterator<Item = Piece> + '_ {
+    let mut chars = text.chars();
+    std::iter::from_fn(move || {
+        let ch = chars.next()?;
+        Some(match ch {
+            LITERAL => Piece::Text(chars.next().unwrap_or(LITERAL)),
+            _ if is_reserved(ch) => Piece::Marker(ch),
+            _ => Piece::Text(ch),
+        })
+    })
+}
+
+/// The program's text in `text`, without the markers.
+pub fn strip(text: &str) -> String {
+    pieces(text)
+        .filter_map(|piece| match piece {
+            Piece::Text(ch) => Some(ch),
+            Piece::Marker(_) => None,
+        })
+        .collect()
+}
+
+/// The foreground color after the SGR (`ESC [ ... m`) parameters `params`,
+/// starting from `color`. `None` is the terminal's default. Backgrounds and
+/// text attributes are ignored.
+pub fn apply_sgr(params: &[u32], mut color: Option<u8>) -> Option<u8> {
+    if params.is_empty() {
+        return None;
+    }
+    let mut i = 0;
+    while i < params.len() {
+        match params[i] {
+            0 | 39 => color = None,
+            n @ 30..=37 => color = Some((n - 30) as u8),
+            n @ 90..=97 => color = Some((n - 90 + 8) as u8),
+            38 => match params.get(i + 1) {
+                Some(5) => {
+                    color = params.get(i + 2).and_then(|&n| u8::try_from(n).ok());
+                    i += 2;
+                }
+                Some(2) => {
+                    if let [r, g, b] = params.get(i + 2..i + 5).unwrap_or_default() {
+                        let channel = |c: u32| c.min(255) as u8;
+                        color = Some(nearest([channel(*r), channel(*g), channel(*b)]));
+                    }
+                    i += 4;
+                }
+                _ => {}
+            },
+            // Skip the extra parameters of a 256 or true color background
+            48 => match params.get(i + 1) {
+                Some(5) => i += 2,
+                Some(2) => i += 4,
+                _ => {}
+            },
+            _ => {}
+        }
+        i += 1;
+    }
+    color
+}
+
+/// The standard sixteen colors, as xterm draws them.
+const BASIC: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 49, 49],
+    [13, 188, 121],
+    [229, 229, 16],
+    [36, 114, 200],
+    [188, 63, 188],
+    [17, 168, 205],
+    [229, 229, 229],
+    [102, 102, 102],
+    [241, 76, 76],
+    [35, 209, 139],
+    [245, 245, 67],
+    [59, 142, 234],
+    [214, 112, 214],
+    [41, 184, 219],
+    [255, 255, 255],
+];
+
+/// Levels of each channel in the 6x6x6 color cube.
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// RGB of palette entry `n`.
+pub fn rgb(n: u8) -> [u8; 3] {
+    match n {
+        0..=15 => BASIC[n as usize],
+        16..=231 => {
+            let i = n - 16;
+            [
+                CUBE[(i / 36) as usize],
+                CUBE[(i / 6 % 6) as usize],
+                CUBE[(i % 6) as usize],
+            ]
+        }
+        _ => {
+            let level = 8 + (n - 232) * 10;
+            [level; 3]
+        }
+    }
+}
+
+/// The color cube entry closest to a 24-bit color.
+fn nearest(color: [u8; 3]) -> u8 {
+    let step = |c: u8| {
+        CUBE.iter()
+            .enumerate()
+            .min_by_key(|&(_, &level)| level.abs_diff(c))
+            .map_or(0, |(i, _)| i as u8)
+    };
+    16 + 36 * step(color[0]) + 6 * step(color[1]) + step(color[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sgr_sets_and_resets_the_color() {
+        assert_eq!(apply_sgr(&[31], None), Some(1));
+        assert_eq!(apply_sgr(&[1, 92], None), Some(10));
+        assert_eq!(apply_sgr(&[38, 5, 208], None), Some(208));
+        assert_eq!(apply_sgr(&[38, 2, 255, 0, 0], None), Some(196));
+        assert_eq!(apply_sgr(&[48, 5, 1, 4], Some(2)), Some(2));
+        assert_eq!(apply_sgr(&[39], Some(2)), None);
+        assert_eq!(apply_sgr(&[], Some(2)), None);
+    }
+
+    #[test]
+    fn markers_round_trip() {
+        for color in [Some(0), Some(255), None] {
+            let expected = color.map_or(Mark::Reset, Mark::Color);
+            assert_eq!(mark(marker(color)), Some(expected));
+        }
+        assert_eq!(mark('a'), None);
+        assert_eq!(rgb(196), [255, 0, 0]);
+        assert_eq!(rgb(244), [128; 3]);
+    }
+
+    #[test]
+    fn printed_marker_characters_stay_text() {
+        let icons = "\u{F0001} \u{F0100}\u{F0101}";
+        let marked = format!("{}{}{COLOR_RESET}", marker(Some(1)), escape(icons));
+        assert_eq!(strip(&marked), icons);
+        let marks: Vec<Piece> = pieces(&marked)
+            .filter(|p| matches!(p, Piece::Marker(_)))
+            .collect();
+        assert_eq!(
+            marks,
+            [Piece::Marker(marker(Some(1))), Piece::Marker(COLOR_RESET)]
+        );
+    }
+}
@@ -1,6 +1,9 @@
+use super::limits::Limits;
 use crate::error::{Error, Result, io_err};
 use portable_pty::{CommandBuilder, PtySize, native_pty_system};
+use std::ffi::OsString;
 use std::io::{Read, Write};
+use std::path::Path;
 use std::process::Output;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, mpsc};
@@ -81,9 +84,12 @@ impl Drop for RawModeGuard {
     }
 }
 
-const MAX_TRANSCRIPT: usize = 4 * 1024 * 1024;
-
-pub fn run_interactive(bin: &std::path::Path, timeout: Duration) -> Result<Output> {
+pub fn run_interactive(
+    argv: &[OsString],
+    env: &[(&str, OsString)],
+    timeout: Duration,
+    limits: &Limits,
+) -> Result<Output> {
     let eof_key = if cfg!(windows) { "Ctrl+Z" } else { "Ctrl+D" };
     eprintln!("  Program is running. If it doesn't exit on its own, press {eof_key}.\n");
 
@@ -100,12 +106,20 @@ pub fn run_interactive(bin: &std::path::Path, timeout: Duration) -> Result<Outpu
         .openpty(pty_size)
         .map_err(|e| Error::Validation(format!("opening PTY: {e}")))?;
 
-    let mut cmd = CommandBuilder::new(bin.to_string_lossy().as_ref());
+    let program = Path::new(&argv[0]);
+    super::exec::check_allowed(&format!("'{}'", program.display()))?;
+    let argv = limits.wrap(argv);
+    let cap = limits.max_output;
+    let mut cmd = CommandBuilder::new(&argv[0]);
+    cmd.args(&argv[1..]);
+    for (key, value) in env {
+        cmd.env(key, value);
+    }
     cmd.cwd(env::current_dir().map_err(|e| io_err("current directory", e))?);
     let mut child = pair
         .slave
         .spawn_command(cmd)
-        .map_err(|e| Error::Validation(format!("spawning '{}': {e}", bin.display())))?;
+        .map_err(|e| Error::Validation(format!("spawning '{}': {e}", program.display())))?;
     drop(pair.slave);
 
     let absolute_deadline = Instant::now() + timeout.saturating_mul(3);
@@ -128,8 +142,14 @@ pub fn run_interactive(bin: &std::path::Path, timeout: Duration) -> Result<Outpu
                 Ok(n) => {
                     let chunk = strip_cpr(&buf[..n]);
                     if !chunk.is_empty() {
-                        io::stdout().write_all(&chunk)?;
-                        io::stdout().flush()?;
+                        // Keep stdout free for artifact lines in porcelain mode
+                        if crate::ui::is_porcelain() {
+                            io::stderr().write_all(&chunk)?;
+                            io::stderr().flush()?;
+                        } else {
+                            io::stdout().write_all(&chunk)?;
+                            io::stdout().flush()?;
+                        }
                     }
                     if tx.send(chunk.clone()).is_err() {
                         break;
@@ -169,14 +189,17 @@ pub fn run_interactive(bin: &std::path::Path, timeout: Duration) -> Result<Outpu
     let mut transcript = Vec::new();
     let mut timeout_start: Option<Instant> = None;
     let status = loop {
-        drain_chunks(&rx, &mut transcript);
+        drain_chunks(&rx, &mut transcript, cap);
+        if transcript.len() >= cap {
+            let _ = child.kill();
+        }
         match child.try_wait() {
             Ok(Some(status)) => break status,
             Ok(None) => {
                 if Instant::now() >= absolute_deadline {
                     let _ = child.kill();
                     let _ = child.wait();
-                    drain_chunks_for(&rx, &mut transcript, Duration::from_millis(120));
+                    drain_chunks_for(&rx, &mut transcript, cap, Duration::from_millis(120));
                     return Err(Error::Validation(format!(
                         "program timed out after {}s (absolute limit)",
                         timeout.as_secs() * 3
@@ -189,7 +212,7 @@ pub fn run_interactive(bin: &std::path::Path, timeout: Duration) -> Result<Outpu
                     if started.elapsed() >= timeout {
                         let _ = child.kill();
                         let _ = child.wait();
-                        drain_chunks_for(&rx, &mut transcript, Duration::from_millis(120));
+                        drain_chunks_for(&rx, &mut transcript, cap, Duration::from_millis(120));
                         return Err(Error::Validation(format!(
                             "program timed out after {}s",
                             timeout.as_secs()
@@ -200,18 +223,18 @@ pub fn run_interactive(bin: &std::path::Path, timeout: Duration) -> Result<Outpu
             }
             Err(e) => {
                 let _ = child.kill();
-                drain_chunks_for(&rx, &mut transcript, Duration::from_millis(120));
+                drain_chunks_for(&rx, &mut transcript, cap, Duration::from_millis(120));
                 return Err(Error::Validation(format!("waiting for PTY process: {e}")));
             }
         }
     };
 
-    drain_chunks_for(&rx, &mut transcript, Duration::from_millis(180));
+    drain_chunks_for(&rx, &mut transcript, cap, Duration::from_millis(180));
 
     // Give the reader thread a moment to finish, then join it
     let join_deadline = Instant::now() + Duration::from_secs(2);
     while !output_handle.is_finished() && Instant::now() < join_deadline {
-        drain_chunks(&rx, &mut transcript);
+        drain_chunks(&rx, &mut transcript, cap);
         thread::sleep(Duration::from_millis(20));
     }
     if output_handle.is_finished() {
@@ -221,32 +244,34 @@ pub fn run_interactive(bin: &std::path::Path, timeout: Duration) -> Result<Outpu
         reader_result.map_err(|e| io_err("reading PTY output", e))?;
     }
 
-    if transcript.len() >= MAX_TRANSCRIPT {
-        crate::ui::warn("program output exceeded 4 MB, transcript was truncated");
-    }
+    let stderr = if transcript.len() >= cap {
+        limits.output_note().into_bytes()
+    } else {
+        Vec::new()
+    };
 
     Ok(Output {
         status: portable_status_to_std(status.exit_code()),
         stdout: transcript,
-        stderr: Vec::new(),
+        stderr,
     })
 }
 
 #[cfg(windows)]
-fn portable_status_to_std(code: u32) -> std::process::ExitStatus {
+pub(super) fn portable_status_to_std(code: u32) -> std::process::ExitStatus {
     use std::os::windows::process::ExitStatusExt;
     std::process::ExitStatus::from_raw(code)
 }
 
 #[cfg(unix)]
-fn portable_status_to_std(code: u32) -> std::process::ExitStatus {
+pub(super) fn portable_status_to_std(code: u32) -> std::process::ExitStatus {
     use std::os::unix::process::ExitStatusExt;
     std::process::ExitStatus::from_raw((code as i32) << 8)
 }
 
-fn drain_chunks(rx: &mpsc::Receiver<Vec<u8>>, transcript: &mut Vec<u8>) {
+pub(super) fn drain_chunks(rx: &mpsc::Receiver<Vec<u8>>, transcript: &mut Vec<u8>, cap: usize) {
     while let Ok(chunk) = rx.try_recv() {
-        let remaining = MAX_TRANSCRIPT.saturating_sub(transcript.len());
+        let remaining = cap.saturating_sub(transcript.len());
         if remaining > 0 {
             let take = chunk.len().min(remaining);
             transcript.extend_from_slice(&chunk[..take]);
@@ -254,10 +279,15 @@ fn drain_chunks(rx: &mpsc::Receiver<Vec<u8>>, transcript: &mut Vec<u8>) {
     }
 }
 
-fn drain_chunks_for(rx: &mpsc::Receiver<Vec<u8>>, transcript: &mut Vec<u8>, budget: Duration) {
+pub(super) fn drain_chunks_for(
+    rx: &mpsc::Receiver<Vec<u8>>,
+    transcript: &mut Vec<u8>,
+    cap: usize,
+    budget: Duration,
+) {
     let start = Instant::now();
     loop {
-        drain_chunks(rx, transcript);
+        drain_chunks(rx, transcript, cap);
         if start.elapsed() >= budget {
             break;
         }
@@ -265,7 +295,7 @@ fn drain_chunks_for(rx: &mpsc::Receiver<Vec<u8>>, transcript: &mut Vec<u8>, budg
     }
 }
 
-fn strip_cpr(data: &[u8]) -> Vec<u8> {
+pub(super) fn strip_cpr(data: &[u8]) -> Vec<u8> {
     let mut out = Vec::with_capacity(data.len());
     let mut i = 0;
     while i < data.len() {
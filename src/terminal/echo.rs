@@ -0,0 +1,100 @@
+//! Puts piped input back into the screenshot. A pipe never echoes, so a run
+//! fed from a file shows its prompts with no answers after them.
+
+use super::ansi;
+
+/// Marks the start of echoed input in screenshot text. These are private-use
+/// characters, so `ansi::escape` sets apart any the program prints itself.
+pub const INPUT_START: char = '\u{E000}';
+/// Marks the end of echoed input.
+pub const INPUT_END: char = '\u{E001}';
+
+/// Characters that usually end a prompt, as in `Enter a number: `.
+const PROMPT_ENDS: [char; 4] = [':', '?', '>', ']'];
+
+/// Inserts each input line after the next prompt in `stdout`, wrapped in the
+/// input markers and followed by the newline the user would have typed. The
+/// result is marked text, with the program's output and input escaped.
+///
+/// A prompt is a prompt-ending character plus optional spaces, followed by
+/// either the end of the output or more text with another prompt on the same
+/// line. That's how piped prompts run together (`First: Second: Sum: 7`),
+/// while a labelled value like `Total: 7` is left alone. Input lines left over
+/// once the prompts run out are dropped.
+pub fn interleave(stdout: &str, input: &str) -> String {
+    let mut lines = input.lines();
+    let mut next = lines.next();
+    let mut out = String::with_capacity(stdout.len() + input.len());
+    let mut rest = stdout;
+
+    while let Some(line) = next {
+        let Some(at) = find_prompt(rest) else { break };
+        out.push_str(&ansi::escape(&rest[..at]));
+        out.push(INPUT_START);
+        out.push_str(&ansi::escape(line));
+        out.push(INPUT_END);
+        out.push('\n');
+        rest = &rest[at..];
+        next = lines.next();
+    }
+    out.push_str(&ansi::escape(rest));
+    out
+}
+
+/// Byte offset just past the first prompt in `text`, spaces included.
+fn find_prompt(text: &str) -> Option<usize> {
+    for (i, c) in text.char_indices() {
+        if !PROMPT_ENDS.contains(&c) {
+            continue;
+        }
+        let after = i + c.len_utf8();
+        let end = after + text[after..].len() - text[after..].trim_start_matches(' ').len();
+        let line = text[end..].split(['\n', '\r']).next().unwrap_or("");
+        let at_end = text.len() == end;
+        if at_end || (!line.is_empty() && line.contains(PROMPT_ENDS)) {
+            return Some(end);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::ansi::strip;
+
+    #[test]
+    fn answers_follow_prompts() {
+        let out = interleave("First: Second: Sum: 7\n", "3\n4\n");
+        assert_eq!(strip(&out), "First: 3\nSecond: 4\nSum: 7\n");
+        assert!(out.contains(&format!("{INPUT_START}3{INPUT_END}")));
+    }
+
+    #[test]
+    fn colons_at_line_end_are_not_prompts() {
+        let out = interleave("Results:\n> ", "quit\n");
+        assert_eq!(strip(&out), "Results:\n> quit\n");
+    }
+
+    #[test]
+    fn labelled_values_are_not_prompts() {
+        let out = interleave("tty: no\nFirst: Second: Sum: 7\n", "3\n4\n");
+        assert_eq!(strip(&out), "tty: no\nFirst: 3\nSecond: 4\nSum: 7\n");
+    }
+
+    #[test]
+    fn extra_input_is_dropped() {
+        assert_eq!(strip(&interleave("Name? ", "a\nb\n")), "Name? a\n");
+        assert_eq!(interleave("no prompts here\n", "a\n"), "no prompts here\n");
+    }
+
+    #[test]
+    fn printed_input_markers_stay_text() {
+        let out = interleave("\u{E000}Name: ", "\u{E001}a\n");
+        assert_eq!(strip(&out), "\u{E000}Name: \u{E001}a\n");
+        let starts = ansi::pieces(&out)
+            .filter(|&p| p == ansi::Piece::Marker(INPUT_START))
+            .count();
+        assert_eq!(starts, 1);
+    }
+}
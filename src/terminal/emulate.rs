@@ -1,20 +1,47 @@
+use super::ansi;
+
 const MAX_ROWS: usize = 500;
 const DEFAULT_COLS: usize = 10000;
 
+/// A character on the emulated screen, the ANSI color it was written in,
+/// and whether it's a marker that was already in the text.
+type Cell = (char, Option<u8>, bool);
+
 pub fn process(text: &str) -> String {
     process_with_cols(text, DEFAULT_COLS)
 }
 
 pub fn process_with_cols(text: &str, cols: usize) -> String {
+    emulate(text, cols, false)
+}
+
+/// Like `process_with_cols`, but each run of colored text is wrapped in
+/// `ansi` markers. Every line closes its own color, so lines can be split
+/// up without losing it.
+///
+/// The text in and out is marked text, so a program's output has to go
+/// through `ansi::escape` first.
+pub fn process_colored(text: &str, cols: Option<usize>) -> String {
+    emulate(text, cols.unwrap_or(DEFAULT_COLS), true)
+}
+
+fn emulate(text: &str, cols: usize, colored: bool) -> String {
     let cols = cols.max(1);
-    let mut screen: Vec<Vec<char>> = vec![Vec::new()];
+    let mut screen: Vec<Vec<Cell>> = vec![Vec::new()];
     let mut row: usize = 0;
     let mut col: usize = 0;
+    let mut color: Option<u8> = None;
     let mut chars = text.chars().peekable();
 
     while let Some(c) = chars.next() {
         match c {
-            '\x1b' => handle_escape(&mut chars, &mut screen, &mut row, &mut col, cols),
+            '\x1b' => handle_escape(
+                &mut chars,
+                &mut screen,
+                (&mut row, &mut col),
+                &mut color,
+                cols,
+            ),
             '\n' => {
                 row += 1;
                 col = 0;
@@ -31,11 +58,15 @@ pub fn process_with_cols(text: &str, cols: usize) -> String {
                 let target = next_tab.min(cols);
                 ensure_row(&mut screen, row);
                 while col < target {
-                    put_char(&mut screen[row], col, ' ');
+                    put_char(&mut screen[row], col, (' ', color, false));
                     col += 1;
                 }
             }
             _ => {
+                let cell = match c {
+                    ansi::LITERAL => (chars.next().unwrap_or(c), color, false),
+                    _ => (c, color, ansi::is_reserved(c)),
+                };
                 if row < MAX_ROWS {
                     if col >= cols {
                         row += 1;
@@ -43,7 +74,7 @@ pub fn process_with_cols(text: &str, cols: usize) -> String {
                     }
                     if row < MAX_ROWS {
                         ensure_row(&mut screen, row);
-                        put_char(&mut screen[row], col, c);
+                        put_char(&mut screen[row], col, cell);
                         col += 1;
                     }
                 }
@@ -53,7 +84,13 @@ pub fn process_with_cols(text: &str, cols: usize) -> String {
 
     let lines: Vec<String> = screen
         .iter()
-        .map(|line| line.iter().collect::<String>().trim_end().to_string())
+        .map(|line| {
+            let end = line
+                .iter()
+                .rposition(|&(c, _, _)| c != ' ')
+                .map_or(0, |i| i + 1);
+            render_line(&line[..end], colored)
+        })
         .collect();
 
     let mut result: Vec<String> = Vec::with_capacity(lines.len());
@@ -78,28 +115,48 @@ pub fn process_with_cols(text: &str, cols: usize) -> String {
     result.join("\n")
 }
 
-fn ensure_row(screen: &mut Vec<Vec<char>>, row: usize) {
+fn render_line(cells: &[Cell], colored: bool) -> String {
+    let mut out = String::with_capacity(cells.len());
+    let mut current = None;
+    for &(c, color, marker) in cells {
+        if colored && color != current {
+            out.push(ansi::marker(color));
+            current = color;
+        }
+        if marker {
+            out.push(c);
+        } else {
+            ansi::push_text(&mut out, c);
+        }
+    }
+    if current.is_some() {
+        out.push(ansi::COLOR_RESET);
+    }
+    out
+}
+
+fn ensure_row(screen: &mut Vec<Vec<Cell>>, row: usize) {
     while screen.len() <= row {
         screen.push(Vec::new());
     }
 }
 
-fn put_char(line: &mut Vec<char>, col: usize, ch: char) {
+fn put_char(line: &mut Vec<Cell>, col: usize, cell: Cell) {
     if col < line.len() {
-        line[col] = ch;
+        line[col] = cell;
     } else {
         while line.len() < col {
-            line.push(' ');
+            line.push((' ', None, false));
         }
-        line.push(ch);
+        line.push(cell);
     }
 }
 
 fn handle_escape(
     chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
-    screen: &mut Vec<Vec<char>>,
-    row: &mut usize,
-    col: &mut usize,
+    screen: &mut Vec<Vec<Cell>>,
+    (row, col): (&mut usize, &mut usize),
+    color: &mut Option<u8>,
     cols: usize,
 ) {
     match chars.peek() {
@@ -147,6 +204,7 @@ fn handle_escape(
                         screen[*row].truncate(*col);
                     }
                 }
+                Some('m') => *color = ansi::apply_sgr(&params, *color),
                 _ => {}
             }
         }
@@ -162,7 +220,7 @@ fn handle_escape(
                 }
             }
         }
-        Some(&fe) if fe >= '@' && fe <= '_' => {
+        Some(&fe) if ('@'..='_').contains(&fe) => {
             chars.next();
         }
         _ => {}
@@ -269,4 +327,14 @@ mod tests {
     fn mixed_ansi_and_text() {
         assert_eq!(process("\x1b[32mgreen\x1b[0m plain"), "green plain");
     }
+
+    #[test]
+    fn colors_are_marked_line_by_line() {
+        let (red, green) = (ansi::marker(Some(1)), ansi::marker(Some(2)));
+        let reset = ansi::COLOR_RESET;
+        assert_eq!(
+            process_colored("\x1b[32mgo\non  \x1b[m done\r\x1b[31mX", None),
+            format!("{green}go{reset}\n{red}X{green}n  {reset} done")
+        );
+    }
 }
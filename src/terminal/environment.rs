@@ -0,0 +1,79 @@
+//! The "Environment" line in the doc's run section: the OS, architecture and
+//! shell the program ran under, for output that differs between platforms.
+
+use crate::config::WindowsShell;
+use std::path::Path;
+use std::process::Command;
+
+/// Describes this machine, like `Ubuntu 24.04 LTS, x86_64, bash`.
+pub fn local() -> String {
+    describe(&os_name(), std::env::consts::ARCH, &shell())
+}
+
+/// Like `local`, but on Windows names the shell that ran the run command.
+pub fn with_shell(run_shell: WindowsShell) -> String {
+    if cfg!(windows) {
+        return describe(&os_name(), std::env::consts::ARCH, run_shell.name());
+    }
+    local()
+}
+
+pub fn describe(os: &str, arch: &str, shell: &str) -> String {
+    format!("{os}, {arch}, {shell}")
+}
+
+fn os_name() -> String {
+    match std::env::consts::OS {
+        "linux" => std::fs::read_to_string("/etc/os-release")
+            .ok()
+            .and_then(|s| pretty_name(&s))
+            .unwrap_or_else(|| "Linux".into()),
+        "macos" if crate::terminal::exec::is_forbidden() => "macOS".into(),
+        "macos" => Command::new("sw_vers")
+            .arg("-productVersion")
+            .output()
+            .ok()
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+            .filter(|v| !v.is_empty())
+            .map_or_else(|| "macOS".into(), |v| format!("macOS {v}")),
+        "windows" => "Windows".into(),
+        other => other.into(),
+    }
+}
+
+/// The distro's display name from `/etc/os-release`.
+fn pretty_name(os_release: &str) -> Option<String> {
+    os_release
+        .lines()
+        .find_map(|l| l.strip_prefix("PRETTY_NAME="))
+        .map(|v| v.trim().trim_matches('"').to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// The user's login shell, taken to be PowerShell on Windows.
+fn shell() -> String {
+    if cfg!(windows) {
+        return "PowerShell".into();
+    }
+    std::env::var_os("SHELL")
+        .as_deref()
+        .and_then(|s| Path::new(s).file_name())
+        .map_or_else(|| "sh".into(), |s| s.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_distro_name() {
+        let release = "NAME=\"Ubuntu\"\nPRETTY_NAME=\"Ubuntu 24.04 LTS\"\nID=ubuntu\n";
+        assert_eq!(pretty_name(release).as_deref(), Some("Ubuntu 24.04 LTS"));
+        assert_eq!(pretty_name("PRETTY_NAME=\"\"\n"), None);
+        assert_eq!(pretty_name("ID=arch\n"), None);
+        assert_eq!(
+            describe("Arch Linux", "x86_64", "zsh"),
+            "Arch Linux, x86_64, zsh"
+        );
+    }
+}
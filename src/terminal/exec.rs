@@ -1,9 +1,38 @@
-use crate::error::{Result, io_err};
+use super::limits::Limits;
+use crate::base64;
+use crate::config::WindowsShell;
+use crate::error::{Error, Result, io_err};
+use std::ffi::OsString;
 use std::io::{Read, Write};
-use std::process::{Command, Output, Stdio};
+use std::path::Path;
+use std::process::{ChildStdin, Command, Output, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 use std::{io, thread};
 
+/// Set by `--no-exec`. Everything that starts another program checks it
+/// first, so the flag can't be missed by one code path.
+static NO_EXEC: AtomicBool = AtomicBool::new(false);
+
+pub fn forbid() {
+    NO_EXEC.store(true, Ordering::Relaxed);
+}
+
+pub fn is_forbidden() -> bool {
+    NO_EXEC.load(Ordering::Relaxed)
+}
+
+/// Errors instead of running `what` when `--no-exec` is set.
+pub fn check_allowed(what: &str) -> Result<()> {
+    if is_forbidden() {
+        return Err(Error::Validation(format!(
+            "--no-exec is set, not running {what}"
+        )));
+    }
+    Ok(())
+}
+
 pub fn expand_escapes(s: &str) -> String {
     let mut out = String::with_capacity(s.len());
     let mut chars = s.chars();
@@ -47,46 +76,108 @@ pub fn expand_escapes(s: &str) -> String {
     out
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Environment variable that carries the run command to `cmd`.
+const CMD_COMMAND_VAR: &str = "AP_RUN_COMMAND";
 
-    #[test]
-    fn expand_basic() {
-        assert_eq!(expand_escapes("a\\nb"), "a\nb");
-        assert_eq!(expand_escapes("a\\tb"), "a\tb");
-        assert_eq!(expand_escapes("a\\\\b"), "a\\b");
-    }
+/// How to start the shell that runs a command: its argv, and anything it
+/// needs in the environment.
+pub struct ShellCall {
+    pub argv: Vec<OsString>,
+    pub env: Vec<(&'static str, OsString)>,
+}
 
-    #[test]
-    fn expand_cr() {
-        assert_eq!(expand_escapes("a\\rb"), "a\rb");
+/// The platform shell invocation that runs `command`. `shell` picks the
+/// shell on Windows, everywhere else it's `sh -c`.
+pub fn shell_call(command: &str, shell: WindowsShell) -> ShellCall {
+    if cfg!(windows) {
+        return windows_call(command, shell, |name| std::env::var(name).ok());
     }
-
-    #[test]
-    fn expand_nul() {
-        assert_eq!(expand_escapes("a\\0b"), "a\0b");
+    ShellCall {
+        argv: vec!["sh".into(), "-c".into(), command.into()],
+        env: Vec::new(),
     }
+}
 
-    #[test]
-    fn expand_hex() {
-        assert_eq!(expand_escapes("\\x41"), "A");
-        assert_eq!(expand_escapes("\\x0a"), "\n");
-    }
+/// Windows quotes each argument for the C runtime's rules, which neither
+/// shell follows, so the command never goes through them. PowerShell takes
+/// it base64 encoded and `cmd` reads it out of a variable, expanding the
+/// variable before it looks for pipes and redirects, just like a typed line.
+fn windows_call(
+    command: &str,
+    shell: WindowsShell,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> ShellCall {
+    let (argv, env): (Vec<OsString>, _) = match shell {
+        WindowsShell::Powershell | WindowsShell::Pwsh => {
+            let utf16: Vec<u8> = command.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            let exe = if shell == WindowsShell::Pwsh {
+                "pwsh"
+            } else {
+                "powershell"
+            };
+            (
+                vec![
+                    exe.into(),
+                    "-NoProfile".into(),
+                    "-EncodedCommand".into(),
+                    base64::encode(&utf16).into(),
+                ],
+                Vec::new(),
+            )
+        }
+        WindowsShell::Cmd => (
+            vec![
+                "cmd".into(),
+                "/d".into(),
+                "/c".into(),
+                format!("%{CMD_COMMAND_VAR}%").into(),
+            ],
+            // The one expansion pass is spent on our variable, so the
+            // command's own %VARS% are filled in here instead
+            vec![(CMD_COMMAND_VAR, expand_cmd_vars(command, lookup).into())],
+        ),
+    };
+    ShellCall { argv, env }
+}
 
-    #[test]
-    fn expand_invalid_hex_passthrough() {
-        assert_eq!(expand_escapes("\\xZZ"), "\\xZZ");
-        assert_eq!(expand_escapes("\\x4"), "\\x4");
+/// Expands `%NAME%` the way `cmd` does on a typed line: names it can't find
+/// are left as they are.
+fn expand_cmd_vars(command: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(command.len());
+    let mut rest = command;
+    while let Some(start) = rest.find('%') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after
+            .find('%')
+            .map(|end| &after[..end])
+            .filter(|name| !name.is_empty())
+            .and_then(|name| Some((name.len(), lookup(name)?)));
+        match value {
+            Some((len, value)) => {
+                out.push_str(&value);
+                rest = &after[len + 1..];
+            }
+            None => {
+                out.push('%');
+                rest = after;
+            }
+        }
     }
+    out.push_str(rest);
+    out
 }
 
-pub fn shell_exec_with_input(command: &str, input: Option<&str>, timeout: Duration) -> Result<Output> {
-    let (shell, flag): (&str, &[&str]) = if cfg!(windows) {
-        ("powershell", &["-NoProfile", "-Command"])
-    } else {
-        ("sh", &["-c"])
-    };
+pub fn shell_exec_with_input(
+    command: &str,
+    shell: WindowsShell,
+    input: Option<&str>,
+    timeout: Duration,
+    limits: &Limits,
+) -> Result<Output> {
+    let limited = format!("{}{command}", limits.shell_prefix().unwrap_or_default());
+    check_allowed("the run command")?;
+    let ShellCall { argv, env } = shell_call(&limited, shell);
 
     let stdin_mode = if input.is_some() {
         Stdio::piped()
@@ -94,54 +185,78 @@ pub fn shell_exec_with_input(command: &str, input: Option<&str>, timeout: Durati
         Stdio::null()
     };
 
-    let mut child = Command::new(shell)
-        .args(flag)
-        .arg(command)
+    let child = Command::new(&argv[0])
+        .args(&argv[1..])
+        .envs(env)
         .stdin(stdin_mode)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
         .map_err(|e| io_err(format!("running '{command}'"), e))?;
 
-    if let Some(input_str) = input {
-        let expanded = expand_escapes(input_str);
-        if let Some(mut stdin) = child.stdin.take() {
-            stdin
-                .write_all(expanded.as_bytes())
-                .map_err(|e| io_err("writing command input to stdin", e))?;
-        }
-    }
-
-    wait_with_timeout(child, timeout)
+    wait_with_timeout(child, input, timeout, limits)
 }
 
-pub fn run_with_input(bin: &std::path::Path, input: &str, timeout: Duration) -> Result<Output> {
-    let mut child = Command::new(bin)
+pub fn run_with_input(
+    argv: &[OsString],
+    env: &[(&str, OsString)],
+    input: &str,
+    timeout: Duration,
+    limits: &Limits,
+) -> Result<Output> {
+    let program = Path::new(&argv[0]);
+    check_allowed(&format!("'{}'", program.display()))?;
+    let argv = limits.wrap(argv);
+    let child = Command::new(&argv[0])
+        .args(&argv[1..])
+        .envs(env.iter().map(|(k, v)| (k, v)))
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .map_err(|e| io_err(format!("spawning '{}'", bin.display()), e))?;
-
-    let expanded = expand_escapes(input);
-    if let Some(mut stdin) = child.stdin.take() {
-        stdin
-            .write_all(expanded.as_bytes())
-            .map_err(|e| io_err("writing program input to stdin", e))?;
-    }
+        .map_err(|e| io_err(format!("spawning '{}'", program.display()), e))?;
 
-    wait_with_timeout(child, timeout)
+    wait_with_timeout(child, Some(input), timeout, limits)
 }
 
-fn wait_with_timeout(mut child: std::process::Child, timeout: Duration) -> Result<Output> {
-    let mut stdout_reader = spawn_reader(child.stdout.take());
-    let mut stderr_reader = spawn_reader(child.stderr.take());
+/// Feeds `input` to the child and collects its output. The input is written
+/// alongside the readers, so a program that prints while it reads can't
+/// stall on a full pipe, and the timeout covers the write too.
+fn wait_with_timeout(
+    mut child: std::process::Child,
+    input: Option<&str>,
+    timeout: Duration,
+    limits: &Limits,
+) -> Result<Output> {
+    let overflowed = Arc::new(AtomicBool::new(false));
+    let mut stdout_reader = spawn_reader(child.stdout.take(), limits.max_output, &overflowed);
+    let mut stderr_reader = spawn_reader(child.stderr.take(), limits.max_output, &overflowed);
+    let mut writer = spawn_writer(child.stdin.take(), input.unwrap_or_default());
     let start = Instant::now();
     loop {
+        // A program that keeps printing past the cap is stopped rather than
+        // left to run until the timeout
+        if overflowed.load(Ordering::Relaxed) {
+            let _ = child.kill();
+        }
+        if let Some(handle) = writer.take_if(|w| w.is_finished())
+            && let Err(e) = join_writer(Some(handle))
+        {
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = join_reader(stdout_reader.take(), "stdout");
+            let _ = join_reader(stderr_reader.take(), "stderr");
+            return Err(e);
+        }
         match child.try_wait() {
             Ok(Some(status)) => {
+                join_writer(writer.take())?;
                 let stdout = join_reader(stdout_reader.take(), "stdout")?;
-                let stderr = join_reader(stderr_reader.take(), "stderr")?;
+                let mut stderr = join_reader(stderr_reader.take(), "stderr")?;
+                // On stderr so it survives the screen emulation's row limit
+                if overflowed.load(Ordering::Relaxed) {
+                    stderr.extend_from_slice(limits.output_note().as_bytes());
+                }
                 return Ok(Output {
                     status,
                     stdout,
@@ -151,6 +266,7 @@ fn wait_with_timeout(mut child: std::process::Child, timeout: Duration) -> Resul
             Ok(None) if start.elapsed() >= timeout => {
                 let _ = child.kill();
                 let _ = child.wait();
+                let _ = join_writer(writer.take());
                 let _ = join_reader(stdout_reader.take(), "stdout");
                 let _ = join_reader(stderr_reader.take(), "stderr");
                 return Err(crate::error::Error::Validation(format!(
@@ -162,6 +278,7 @@ fn wait_with_timeout(mut child: std::process::Child, timeout: Duration) -> Resul
             Err(e) => {
                 let _ = child.kill();
                 let _ = child.wait();
+                let _ = join_writer(writer.take());
                 let _ = join_reader(stdout_reader.take(), "stdout");
                 let _ = join_reader(stderr_reader.take(), "stderr");
                 return Err(io_err("waiting for process", e));
@@ -170,14 +287,50 @@ fn wait_with_timeout(mut child: std::process::Child, timeout: Duration) -> Resul
     }
 }
 
-fn spawn_reader<R>(reader: Option<R>) -> Option<thread::JoinHandle<io::Result<Vec<u8>>>>
+/// Writes the whole input and closes the pipe. A program that exits without
+/// reading all of it isn't an error.
+fn spawn_writer(
+    stdin: Option<ChildStdin>,
+    input: &str,
+) -> Option<thread::JoinHandle<io::Result<()>>> {
+    let input = input.as_bytes().to_vec();
+    stdin.map(|mut w| {
+        thread::spawn(move || match w.write_all(&input) {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        })
+    })
+}
+
+fn join_writer(handle: Option<thread::JoinHandle<io::Result<()>>>) -> Result<()> {
+    let Some(handle) = handle else {
+        return Ok(());
+    };
+    let result = handle
+        .join()
+        .map_err(|_| Error::Validation("stdin writer thread panicked".into()))?;
+    result.map_err(|e| io_err("writing program input to stdin", e))
+}
+
+/// Reads a stream until EOF or until `cap` bytes, then flags the overflow
+/// and drops the pipe so the program's next write fails.
+fn spawn_reader<R>(
+    reader: Option<R>,
+    cap: usize,
+    overflowed: &Arc<AtomicBool>,
+) -> Option<thread::JoinHandle<io::Result<Vec<u8>>>>
 where
     R: Read + Send + 'static,
 {
-    reader.map(|mut r| {
+    let overflowed = Arc::clone(overflowed);
+    reader.map(|r| {
         thread::spawn(move || {
             let mut buf = Vec::new();
-            r.read_to_end(&mut buf)?;
+            r.take(cap as u64 + 1).read_to_end(&mut buf)?;
+            if buf.len() > cap {
+                buf.truncate(cap);
+                overflowed.store(true, Ordering::Relaxed);
+            }
             Ok(buf)
         })
     })
@@ -190,14 +343,161 @@ fn join_reader(
     let Some(handle) = handle else {
         return Ok(Vec::new());
     };
-    let result = handle
-        .join()
-        .map_err(|_| crate::error::Error::Validation(format!("{stream_name} reader thread panicked")))?;
+    let result = handle.join().map_err(|_| {
+        crate::error::Error::Validation(format!("{stream_name} reader thread panicked"))
+    })?;
     result.map_err(|e| io_err(format!("reading {stream_name}"), e))
 }
 
-pub fn detect_compiler() -> Option<&'static str> {
-    ["gcc", "clang"]
-        .into_iter()
-        .find(|c| Command::new(c).arg("--version").output().is_ok())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_basic() {
+        assert_eq!(expand_escapes("a\\nb"), "a\nb");
+        assert_eq!(expand_escapes("a\\tb"), "a\tb");
+        assert_eq!(expand_escapes("a\\\\b"), "a\\b");
+    }
+
+    #[test]
+    fn expand_cr() {
+        assert_eq!(expand_escapes("a\\rb"), "a\rb");
+    }
+
+    #[test]
+    fn expand_nul() {
+        assert_eq!(expand_escapes("a\\0b"), "a\0b");
+    }
+
+    #[test]
+    fn expand_hex() {
+        assert_eq!(expand_escapes("\\x41"), "A");
+        assert_eq!(expand_escapes("\\x0a"), "\n");
+    }
+
+    #[test]
+    fn expand_invalid_hex_passthrough() {
+        assert_eq!(expand_escapes("\\xZZ"), "\\xZZ");
+        assert_eq!(expand_escapes("\\x4"), "\\x4");
+    }
+
+    fn strings(argv: &[OsString]) -> Vec<&str> {
+        argv.iter().map(|a| a.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn powershell_gets_the_command_encoded() {
+        let call = windows_call("echo \"a | b\"", WindowsShell::Powershell, |_| None);
+        assert_eq!(
+            strings(&call.argv),
+            [
+                "powershell",
+                "-NoProfile",
+                "-EncodedCommand",
+                "ZQBjAGgAbwAgACIAYQAgAHwAIABiACIA"
+            ]
+        );
+        assert!(call.env.is_empty());
+        let call = windows_call("echo", WindowsShell::Pwsh, |_| None);
+        assert_eq!(call.argv[0], "pwsh");
+    }
+
+    #[test]
+    fn cmd_reads_the_command_from_a_variable() {
+        let lookup = |name: &str| (name == "USER").then(|| "joe".to_string());
+        let call = windows_call("echo \"%USER%\" | find \"j\"", WindowsShell::Cmd, lookup);
+        assert_eq!(strings(&call.argv), ["cmd", "/d", "/c", "%AP_RUN_COMMAND%"]);
+        assert_eq!(
+            call.env,
+            [("AP_RUN_COMMAND", "echo \"joe\" | find \"j\"".into())]
+        );
+    }
+
+    #[test]
+    fn cmd_vars_expand_like_a_typed_line() {
+        let lookup = |name: &str| match name {
+            "A" => Some("1".to_string()),
+            "B C" => Some("2".to_string()),
+            _ => None,
+        };
+        assert_eq!(expand_cmd_vars("%A%%A%", lookup), "11");
+        assert_eq!(expand_cmd_vars("%B C% 50%", lookup), "2 50%");
+        assert_eq!(expand_cmd_vars("%NOPE% %A%", lookup), "%NOPE% 1");
+        assert_eq!(expand_cmd_vars("100%% %", lookup), "100%% %");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sh_runs_pipes_and_quotes() {
+        let out = shell_exec_with_input(
+            "printf '%s|%s\\n' \"a b\" 'c\"d' | tr a-z A-Z; read x; echo \"got $x\"",
+            WindowsShell::default(),
+            Some("in put\n"),
+            Duration::from_secs(10),
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&out.stdout),
+            "A B|C\"D\ngot in put\n"
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_shells_run_pipes_and_quotes() {
+        let run = |command: &str, shell| {
+            let out = shell_exec_with_input(
+                command,
+                shell,
+                None,
+                Duration::from_secs(30),
+                &Limits::default(),
+            )
+            .unwrap();
+            String::from_utf8_lossy(&out.stdout).trim_end().to_string()
+        };
+        assert_eq!(
+            run(
+                "Write-Output 'a \"b\" | c' | Select-Object -First 1",
+                WindowsShell::Powershell
+            ),
+            "a \"b\" | c"
+        );
+        assert_eq!(
+            run("echo \"a | b\" & echo c| findstr c", WindowsShell::Cmd),
+            "\"a | b\" \r\nc"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn input_bigger_than_the_pipe_buffer_goes_through() {
+        let input = "0123456789abcdef\n".repeat(16 * 1024);
+        let out = run_with_input(
+            &["cat".into()],
+            &[],
+            &input,
+            Duration::from_secs(30),
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(out.stdout.len(), input.len());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn program_that_ignores_its_input_still_finishes() {
+        let input = "x".repeat(256 * 1024);
+        let out = run_with_input(
+            &["true".into()],
+            &[],
+            &input,
+            Duration::from_secs(30),
+            &Limits::default(),
+        )
+        .unwrap();
+        assert!(out.status.success());
+    }
 }
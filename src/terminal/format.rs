@@ -1,38 +1,109 @@
+use serde::{Deserialize, Serialize};
 use std::process::Output;
 
 pub fn format_output(output: &Output) -> String {
     format_output_with_cols(output, None)
 }
 
-pub fn format_output_with_cols(output: &Output, cols: Option<usize>) -> String {
-    let process = |text: &[u8]| -> String {
-        let s = String::from_utf8_lossy(text);
-        match cols {
-            Some(c) => super::emulate::process_with_cols(&s, c),
-            None => super::emulate::process(&s),
+/// Where a stretch of screenshot text came from, so each can get its own
+/// color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// The `$ command` line above the output.
+    Command,
+    Stdout,
+    Stderr,
+}
+
+/// Whole lines of captured text from one stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub stream: Stream,
+    pub text: String,
+    /// A heading like `STDOUT` or `Exit code: 1` rather than program output.
+    pub marker: bool,
+}
+
+impl Segment {
+    pub fn new(stream: Stream, text: impl Into<String>) -> Self {
+        Self {
+            stream,
+            text: text.into(),
+            marker: false,
         }
-    };
-    let stdout = process(&output.stdout).trim_end().to_string();
-    let stderr = process(&output.stderr).trim_end().to_string();
+    }
+
+    pub fn marker(stream: Stream, text: impl Into<String>) -> Self {
+        Self {
+            marker: true,
+            ..Self::new(stream, text)
+        }
+    }
+}
+
+pub fn format_output_with_cols(output: &Output, cols: Option<usize>) -> String {
+    super::ansi::strip(&colored_output(output, cols))
+}
+
+/// Program output as a terminal would show it, without colors.
+pub fn plain_text(bytes: &[u8], cols: Option<usize>) -> String {
+    super::ansi::strip(&super::emulate::process_colored(
+        &super::ansi::escape(&String::from_utf8_lossy(bytes)),
+        cols,
+    ))
+}
+
+/// The formatted output with the program's colors kept as `ansi` markers.
+pub fn colored_output(output: &Output, cols: Option<usize>) -> String {
+    output_segments(output, cols)
+        .into_iter()
+        .map(|s| s.text)
+        .collect()
+}
+
+/// The formatted output split by stream, with its colors marked. Joining
+/// the texts gives the same string as `colored_output`.
+pub fn output_segments(output: &Output, cols: Option<usize>) -> Vec<Segment> {
+    let stdout = super::ansi::escape(&String::from_utf8_lossy(&output.stdout));
+    segments(&stdout, output, cols)
+}
+
+/// Like `output_segments`, with `input` echoed after the prompts in stdout.
+pub fn echoed_segments(output: &Output, input: &str, cols: Option<usize>) -> Vec<Segment> {
+    let stdout = super::echo::interleave(&String::from_utf8_lossy(&output.stdout), input);
+    segments(&stdout, output, cols)
+}
+
+/// The segments for `output`, with its stdout already in marked text.
+fn segments(stdout: &str, output: &Output, cols: Option<usize>) -> Vec<Segment> {
+    let stdout = super::emulate::process_colored(stdout, cols)
+        .trim_end()
+        .to_string();
+    let stderr = super::ansi::escape(&String::from_utf8_lossy(&output.stderr));
+    let stderr = super::emulate::process_colored(&stderr, cols)
+        .trim_end()
+        .to_string();
 
     let success = output.status.code() == Some(0);
 
     if success && stderr.is_empty() {
         if stdout.is_empty() {
-            return "(no output)".into();
+            return vec![Segment::new(Stream::Stdout, "(no output)")];
         }
-        return stdout;
+        return vec![Segment::new(Stream::Stdout, stdout)];
     }
 
     let mut parts = Vec::new();
     if !stdout.is_empty() {
-        parts.push(format!("STDOUT\n{stdout}"));
+        parts.push(Segment::marker(Stream::Stdout, "STDOUT\n"));
+        parts.push(Segment::new(Stream::Stdout, format!("{stdout}\n\n")));
     }
     if !stderr.is_empty() {
-        parts.push(format!("STDERR\n{stderr}"));
+        parts.push(Segment::marker(Stream::Stderr, "STDERR\n"));
+        parts.push(Segment::new(Stream::Stderr, format!("{stderr}\n\n")));
     }
     if parts.is_empty() {
-        parts.push("(no output)".into());
+        parts.push(Segment::new(Stream::Stdout, "(no output)\n\n"));
     }
 
     let exit = output
@@ -40,8 +111,127 @@ pub fn format_output_with_cols(output: &Output, cols: Option<usize>) -> String {
         .code()
         .map(|c| c.to_string())
         .unwrap_or_else(|| "killed".into());
-    parts.push(format!("Exit code: {exit}"));
-    parts.join("\n\n")
+    parts.push(Segment::marker(
+        Stream::Stdout,
+        format!("Exit code: {exit}"),
+    ));
+    parts
+}
+
+/// Spells out what an exit code means next to the code the run should have
+/// returned, e.g. "1 (expected)" or "3, expected 0".
+pub fn describe_exit(code: Option<i32>, expected: i32) -> String {
+    match code {
+        None => "none, the program was killed by a signal".into(),
+        Some(0) if expected == 0 => "0 (success)".into(),
+        Some(c) if c == expected => format!("{c} (expected)"),
+        Some(c) => format!("{c}, expected {expected}"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostics {
+    pub warnings: usize,
+    pub errors: usize,
+}
+
+impl Diagnostics {
+    pub fn summary(&self) -> String {
+        let plural = |n: usize, word: &str| {
+            if n == 1 {
+                format!("{n} {word}")
+            } else {
+                format!("{n} {word}s")
+            }
+        };
+        match (self.warnings, self.errors) {
+            (0, 0) => "no warnings".into(),
+            (w, 0) => plural(w, "warning"),
+            (0, e) => plural(e, "error"),
+            (w, e) => format!("{}, {}", plural(w, "warning"), plural(e, "error")),
+        }
+    }
+}
+
+/// Counts gcc/clang style `file:line:col: warning:` and `error:` lines.
+pub fn count_diagnostics(compiler_output: &str) -> Diagnostics {
+    let mut d = Diagnostics::default();
+    for line in compiler_output.lines() {
+        if line.contains(": warning:") {
+            d.warnings += 1;
+        } else if line.contains(": error:") || line.contains(": fatal error:") {
+            d.errors += 1;
+        }
+    }
+    d
+}
+
+/// The first gcc/clang error: its line's index in `compiler_output`, and
+/// `file:line` when the error names a place in a source file.
+pub fn first_error(compiler_output: &str) -> Option<(usize, Option<String>)> {
+    compiler_output.lines().enumerate().find_map(|(i, line)| {
+        let (place, _) = line
+            .split_once(": fatal error:")
+            .or_else(|| line.split_once(": error:"))?;
+        // Peel `:col` and `:line` off the right, the path may have colons
+        let mut rest = place;
+        let mut line_no = None;
+        while let Some((head, tail)) = rest.rsplit_once(':')
+            && !tail.is_empty()
+            && tail.bytes().all(|b| b.is_ascii_digit())
+        {
+            line_no = Some(tail);
+            rest = head;
+        }
+        Some((i, line_no.map(|n| format!("{rest}:{n}"))))
+    })
+}
+
+/// The flag of each gcc/clang warning, like `-Wunused-variable`, or just
+/// `warning` for one printed without a flag.
+pub fn warning_flags(compiler_output: &str) -> Vec<String> {
+    compiler_output
+        .lines()
+        .filter(|line| line.contains(": warning:"))
+        .map(|line| {
+            line.rsplit_once("[-W")
+                .and_then(|(_, rest)| rest.split_once(']'))
+                .map_or_else(|| "warning".into(), |(flag, _)| format!("-W{flag}"))
+        })
+        .collect()
+}
+
+pub fn truncate_middle(text: &str, max_lines: usize) -> Option<String> {
+    if max_lines == 0 {
+        return None;
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() <= max_lines {
+        return None;
+    }
+    let head = max_lines.div_ceil(2);
+    let tail = max_lines - head;
+    let omitted = lines.len() - head - tail;
+
+    let mut out = lines[..head].join("\n");
+    out.push_str(&format!(
+        "\n\n... {} lines omitted ...\n\n",
+        group_thousands(omitted)
+    ));
+    out.push_str(&lines[lines.len() - tail..].join("\n"));
+    Some(out)
+}
+
+pub fn group_thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(ch);
+    }
+    out
 }
 
 #[cfg(test)]
@@ -67,6 +257,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn exit_codes_described() {
+        assert_eq!(describe_exit(Some(0), 0), "0 (success)");
+        assert_eq!(describe_exit(Some(1), 1), "1 (expected)");
+        assert_eq!(describe_exit(Some(3), 0), "3, expected 0");
+        assert!(describe_exit(None, 0).contains("killed"));
+    }
+
     #[test]
     fn clean_success_no_headers() {
         let out = format_output(&make_output("hello world", "", 0));
@@ -87,9 +285,123 @@ mod tests {
         assert!(out.contains("STDERR"));
     }
 
+    #[test]
+    fn segments_split_by_stream_and_join_to_the_text() {
+        let output = make_output("ok", "warning", 2);
+        let segments = output_segments(&output, None);
+        let streams: Vec<(Stream, bool)> = segments.iter().map(|s| (s.stream, s.marker)).collect();
+        assert_eq!(
+            streams,
+            [
+                (Stream::Stdout, true),
+                (Stream::Stdout, false),
+                (Stream::Stderr, true),
+                (Stream::Stderr, false),
+                (Stream::Stdout, true),
+            ]
+        );
+        let joined: String = segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(joined, "STDOUT\nok\n\nSTDERR\nwarning\n\nExit code: 2");
+    }
+
     #[test]
     fn no_output_shows_placeholder() {
         let out = format_output(&make_output("", "", 0));
         assert_eq!(out, "(no output)");
     }
+
+    #[test]
+    fn silent_program_matches_empty_expected_output() {
+        let output = make_output("", "", 0);
+        assert!(crate::diff::compare("", &plain_text(&output.stdout, None)).passed);
+    }
+
+    #[test]
+    fn failing_exit_and_stderr_stay_out_of_the_checked_output() {
+        let output = make_output("Bye\n", "warning: low fuel\n", 1);
+        assert!(format_output(&output).contains("Exit code: 1"));
+        assert!(crate::diff::compare("Bye\n", &plain_text(&output.stdout, None)).passed);
+    }
+
+    #[test]
+    fn printed_marker_characters_come_back_unchanged() {
+        use crate::terminal::ansi::{self, Piece};
+        let printed = "\u{F0001} \u{F0100}\u{F0101} \x1b[31mred\x1b[0m";
+        let text = "\u{F0001} \u{F0100}\u{F0101} red";
+        assert_eq!(plain_text(printed.as_bytes(), None), text);
+        let colored = colored_output(&make_output(printed, "", 0), None);
+        assert_eq!(ansi::strip(&colored), text);
+        let markers: Vec<Piece> = ansi::pieces(&colored)
+            .filter(|p| matches!(p, Piece::Marker(_)))
+            .collect();
+        assert_eq!(
+            markers,
+            [
+                Piece::Marker(ansi::marker(Some(1))),
+                Piece::Marker(ansi::COLOR_RESET)
+            ]
+        );
+    }
+
+    #[test]
+    fn truncate_middle_keeps_short_output() {
+        assert!(truncate_middle("a\nb\nc", 3).is_none());
+        assert!(truncate_middle("a\nb\nc", 0).is_none());
+    }
+
+    #[test]
+    fn truncate_middle_keeps_head_and_tail() {
+        let text = (1..=10)
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let out = truncate_middle(&text, 4).unwrap();
+        assert_eq!(out, "1\n2\n\n... 6 lines omitted ...\n\n9\n10");
+    }
+
+    #[test]
+    fn diagnostics_counted() {
+        let log = "main.c: In function 'main':\n\
+                   main.c:3:9: warning: unused variable 'x' [-Wunused-variable]\n\
+                   main.c:4:5: warning: implicit declaration of function 'foo'\n\
+                   main.c:9:1: error: expected ';' before '}' token\n";
+        let d = count_diagnostics(log);
+        assert_eq!(
+            d,
+            Diagnostics {
+                warnings: 2,
+                errors: 1
+            }
+        );
+        assert_eq!(d.summary(), "2 warnings, 1 error");
+        assert_eq!(warning_flags(log), ["-Wunused-variable", "warning"]);
+    }
+
+    #[test]
+    fn diagnostics_summary_clean() {
+        assert_eq!(count_diagnostics("").summary(), "no warnings");
+    }
+
+    #[test]
+    fn first_error_names_the_file_and_line() {
+        let log = "STDERR\nmain.c: In function 'main':\nmain.c:4:5: warning: unused [-Wunused]\n\
+                   main.c:7:12: error: expected ';'\nmain.c:9:1: error: oops\n";
+        assert_eq!(first_error(log), Some((3, Some("main.c:7".into()))));
+        assert_eq!(
+            first_error("C:\\hw\\main.c:3: fatal error: x.h: No such file"),
+            Some((0, Some("C:\\hw\\main.c:3".into())))
+        );
+        assert_eq!(
+            first_error("collect2: error: ld returned 1 exit status"),
+            Some((0, None))
+        );
+        assert_eq!(first_error("main.c:4:5: warning: unused"), None);
+    }
+
+    #[test]
+    fn thousands_grouping() {
+        assert_eq!(group_thousands(7), "7");
+        assert_eq!(group_thousands(4213), "4,213");
+        assert_eq!(group_thousands(1234567), "1,234,567");
+    }
 }
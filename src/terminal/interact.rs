@@ -0,0 +1,417 @@
+use super::capture::{drain_chunks, drain_chunks_for, portable_status_to_std, strip_cpr};
+use super::limits::Limits;
+use crate::error::{Error, Result, io_err};
+use portable_pty::{CommandBuilder, PtySize, native_pty_system};
+use serde::Deserialize;
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::Output;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use std::{env, fs, thread};
+
+/// A scripted session for menu-driven programs: each step waits for some
+/// text to appear, then types a line.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    #[serde(default, rename = "step")]
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    /// Text to wait for in the program's output before sending.
+    pub expect: Option<String>,
+    /// Line to type, Enter is added.
+    pub send: Option<String>,
+}
+
+pub fn load(path: &Path) -> Result<Script> {
+    let content =
+        fs::read_to_string(path).map_err(|e| io_err(format!("reading {}", path.display()), e))?;
+    parse(&content)
+        .map_err(|e| Error::Validation(format!("bad interact script '{}': {e}", path.display())))
+}
+
+fn parse(content: &str) -> std::result::Result<Script, String> {
+    let script: Script = toml::from_str(content).map_err(|e| e.to_string())?;
+    if script.steps.is_empty() {
+        return Err("no [[step]] entries".into());
+    }
+    if let Some(n) = script
+        .steps
+        .iter()
+        .position(|s| s.expect.is_none() && s.send.is_none())
+    {
+        return Err(format!("step {} has neither expect nor send", n + 1));
+    }
+    Ok(script)
+}
+
+/// What a PTY run printed. The terminal echoes typed lines into the same
+/// stream as the program's own output.
+pub struct PtyRun {
+    pub output: Output,
+    /// `output.stdout` with the echoed lines taken back out, as the program
+    /// would print it with its input piped.
+    pub program_output: Vec<u8>,
+}
+
+/// Drives `argv` in a PTY through `script`. Typed lines are echoed by the
+/// terminal, so the transcript shows prompts and answers interleaved just
+/// as they'd appear on screen. Each `expect` gets the full `timeout`.
+pub fn run(
+    argv: &[OsString],
+    env: &[(&str, OsString)],
+    script: &Script,
+    timeout: Duration,
+    limits: &Limits,
+) -> Result<PtyRun> {
+    let mut session = Session::spawn(argv, env, limits)?;
+    for (n, step) in script.steps.iter().enumerate() {
+        if let Some(expect) = &step.expect {
+            session.expect(expect, timeout).map_err(|why| {
+                Error::Validation(format!(
+                    "interact step {}: waiting for '{expect}', {why}",
+                    n + 1
+                ))
+            })?;
+        }
+        if let Some(line) = &step.send {
+            session.send(&format!("{line}\n"))?;
+        }
+    }
+    session.finish(timeout, "after the last interact step")
+}
+
+/// Feeds piped input through a PTY a line at a time, each once the program
+/// has gone quiet, so it sees a real terminal and the transcript reads like
+/// someone typed the input at the prompts.
+pub fn feed(
+    argv: &[OsString],
+    env: &[(&str, OsString)],
+    input: &str,
+    timeout: Duration,
+    limits: &Limits,
+) -> Result<PtyRun> {
+    let mut session = Session::spawn(argv, env, limits)?;
+    for line in input.split_inclusive('\n') {
+        if !session.settle(timeout) {
+            break;
+        }
+        session.send(line)?;
+    }
+    // A terminal only treats EOF as end of input at the start of a line
+    if !input.is_empty() && !input.ends_with('\n') && session.settle(timeout) {
+        session.send(EOF_KEY)?;
+    }
+    if session.settle(timeout) {
+        session.send(EOF_KEY)?;
+    }
+    session.finish(timeout, "after its input ran out")
+}
+
+/// What a terminal sends for end of input.
+const EOF_KEY: &str = if cfg!(windows) { "\x1a\r\n" } else { "\x04" };
+/// How long output has to stop before the program counts as waiting.
+const IDLE: Duration = Duration::from_millis(150);
+/// How long to keep reading once the program has exited, for output the
+/// reader thread hasn't handed over yet.
+const LATE_OUTPUT: Duration = Duration::from_millis(180);
+
+struct Session {
+    child: Box<dyn portable_pty::Child + Send + Sync>,
+    master: Box<dyn portable_pty::MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    rx: mpsc::Receiver<Vec<u8>>,
+    transcript: Vec<u8>,
+    /// Expects only match output that arrived after the previous match.
+    matched: usize,
+    /// Where each typed line's echo should start in the transcript, and what
+    /// the terminal echoes for it.
+    echoes: Vec<(usize, Vec<u8>)>,
+    limits: Limits,
+}
+
+impl Session {
+    fn spawn(argv: &[OsString], env: &[(&str, OsString)], limits: &Limits) -> Result<Self> {
+        let pair = native_pty_system()
+            .openpty(PtySize {
+                rows: 40,
+                cols: 120,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .map_err(|e| Error::Validation(format!("opening PTY: {e}")))?;
+
+        let program = Path::new(&argv[0]);
+        super::exec::check_allowed(&format!("'{}'", program.display()))?;
+        let argv = limits.wrap(argv);
+        let mut cmd = CommandBuilder::new(&argv[0]);
+        cmd.args(&argv[1..]);
+        for (key, value) in env {
+            cmd.env(key, value);
+        }
+        cmd.cwd(env::current_dir().map_err(|e| io_err("current directory", e))?);
+        let child = pair
+            .slave
+            .spawn_command(cmd)
+            .map_err(|e| Error::Validation(format!("spawning '{}': {e}", program.display())))?;
+        drop(pair.slave);
+
+        let mut reader = pair
+            .master
+            .try_clone_reader()
+            .map_err(|e| Error::Validation(format!("opening PTY reader: {e}")))?;
+        let writer = pair
+            .master
+            .take_writer()
+            .map_err(|e| Error::Validation(format!("opening PTY writer: {e}")))?;
+        let (tx, rx) = mpsc::channel::<Vec<u8>>();
+        thread::spawn(move || {
+            let mut buf = [0_u8; 4096];
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 || tx.send(strip_cpr(&buf[..n])).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            master: pair.master,
+            writer,
+            rx,
+            transcript: Vec::new(),
+            matched: 0,
+            echoes: Vec::new(),
+            limits: *limits,
+        })
+    }
+
+    fn drain(&mut self) {
+        drain_chunks(&self.rx, &mut self.transcript, self.limits.max_output);
+    }
+
+    fn exited(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(Some(_)))
+    }
+
+    fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+
+    /// Looks for `text` in output that came after the previous match.
+    fn matched(&mut self, text: &str) -> bool {
+        let Some(pos) = find(&self.transcript[self.matched..], text.as_bytes()) else {
+            return false;
+        };
+        self.matched += pos + text.len();
+        true
+    }
+
+    /// Waits for `text` in new output. The error says why it never came.
+    fn expect(&mut self, text: &str, timeout: Duration) -> std::result::Result<(), String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.drain();
+            if self.matched(text) {
+                return Ok(());
+            }
+            if self.exited() {
+                // What it printed last may still be on its way from the reader
+                drain_chunks_for(
+                    &self.rx,
+                    &mut self.transcript,
+                    self.limits.max_output,
+                    LATE_OUTPUT,
+                );
+                if self.matched(text) {
+                    return Ok(());
+                }
+                return Err("the program exited first".into());
+            }
+            if Instant::now() >= deadline {
+                self.kill();
+                return Err(format!("not seen within {}s", timeout.as_secs()));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Waits until output has been quiet for a moment. False once the
+    /// program has exited, when there's no point sending more.
+    fn settle(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut last_len = self.transcript.len();
+        let mut quiet_since = Instant::now();
+        loop {
+            thread::sleep(Duration::from_millis(20));
+            self.drain();
+            if self.exited() {
+                return false;
+            }
+            if self.transcript.len() != last_len {
+                last_len = self.transcript.len();
+                quiet_since = Instant::now();
+            } else if quiet_since.elapsed() >= IDLE || Instant::now() >= deadline {
+                return true;
+            }
+        }
+    }
+
+    fn send(&mut self, text: &str) -> Result<()> {
+        // The end-of-input key isn't echoed
+        if text != EOF_KEY {
+            self.drain();
+            let echo = text.replace('\n', "\r\n").into_bytes();
+            self.echoes.push((self.transcript.len(), echo));
+        }
+        self.writer
+            .write_all(text.as_bytes())
+            .and_then(|()| self.writer.flush())
+            .map_err(|e| io_err("sending input to the program", e))
+    }
+
+    /// Waits for the program to exit and hands back what it printed.
+    fn finish(mut self, timeout: Duration, when: &str) -> Result<PtyRun> {
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            self.drain();
+            match self.child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() >= deadline => {
+                    self.kill();
+                    return Err(Error::Validation(format!(
+                        "program still running {}s {when}",
+                        timeout.as_secs()
+                    )));
+                }
+                Ok(None) => thread::sleep(Duration::from_millis(20)),
+                Err(e) => {
+                    self.kill();
+                    return Err(Error::Validation(format!("waiting for PTY process: {e}")));
+                }
+            }
+        };
+        let cap = self.limits.max_output;
+        drain_chunks_for(&self.rx, &mut self.transcript, cap, LATE_OUTPUT);
+        drop(self.writer);
+        drop(self.master);
+
+        let stderr = if self.transcript.len() >= cap {
+            self.limits.output_note().into_bytes()
+        } else {
+            Vec::new()
+        };
+        Ok(PtyRun {
+            program_output: without_echoes(&self.transcript, &self.echoes),
+            output: Output {
+                status: portable_status_to_std(status.exit_code()),
+                stdout: self.transcript,
+                stderr,
+            },
+        })
+    }
+}
+
+/// Takes each echo out of the transcript where it was expected. One that
+/// didn't turn up there, say because the program turned echo off, is left
+/// alone.
+fn without_echoes(transcript: &[u8], echoes: &[(usize, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(transcript.len());
+    let mut from = 0;
+    for (at, echo) in echoes {
+        if *at >= from && transcript[*at..].starts_with(echo) {
+            out.extend_from_slice(&transcript[from..*at]);
+            from = at + echo.len();
+        }
+    }
+    out.extend_from_slice(&transcript[from..]);
+    out
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_parses() {
+        let script = parse(
+            r#"
+            [[step]]
+            expect = "Choice:"
+            send = "1"
+
+            [[step]]
+            send = "q"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(script.steps.len(), 2);
+        assert_eq!(script.steps[0].expect.as_deref(), Some("Choice:"));
+        assert!(script.steps[1].expect.is_none());
+        assert!(parse("").is_err());
+        assert!(parse("[[step]]\n").is_err());
+        assert!(parse("[[step]]\nsned = \"1\"").is_err());
+    }
+
+    #[test]
+    fn find_matches_bytes() {
+        assert_eq!(find(b"Menu\r\nChoice: ", b"Choice:"), Some(6));
+        assert_eq!(find(b"Menu", b"Choice:"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn last_expect_sees_output_from_a_program_that_exited() {
+        let argv: Vec<OsString> = ["sh", "-c", "read choice; echo \"Bye $choice\""]
+            .iter()
+            .map(OsString::from)
+            .collect();
+        let script = parse(
+            r#"
+            [[step]]
+            send = "q"
+
+            [[step]]
+            expect = "Bye q"
+            "#,
+        )
+        .unwrap();
+        let run = run(
+            &argv,
+            &[],
+            &script,
+            Duration::from_secs(5),
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(run.program_output, b"Bye q\r\n");
+    }
+
+    #[test]
+    fn echoes_are_taken_out_where_they_were_typed() {
+        let transcript = b"Name: Ada\r\nAge: 36\r\nHi Ada, 36\r\n";
+        let echoes = vec![(6, b"Ada\r\n".to_vec()), (16, b"36\r\n".to_vec())];
+        assert_eq!(
+            without_echoes(transcript, &echoes),
+            b"Name: Age: Hi Ada, 36\r\n"
+        );
+        // Echo turned off for the second answer
+        let transcript = b"Name: Ada\r\nPIN: ok\r\n";
+        let echoes = vec![(6, b"Ada\r\n".to_vec()), (16, b"1234\r\n".to_vec())];
+        assert_eq!(without_echoes(transcript, &echoes), b"Name: PIN: ok\r\n");
+    }
+}
@@ -0,0 +1,105 @@
+use std::ffi::OsString;
+
+/// Output kept from a run before the program is stopped. The doc only shows
+/// a few hundred lines anyway, this just keeps a runaway loop from eating RAM.
+pub const DEFAULT_MAX_OUTPUT: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Address space cap in bytes, only enforced on Unix.
+    pub max_memory: Option<u64>,
+    /// Bytes of output per stream before the program is stopped.
+    pub max_output: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_memory: None,
+            max_output: DEFAULT_MAX_OUTPUT,
+        }
+    }
+}
+
+impl Limits {
+    /// Wraps `argv` so the memory limit applies to the program and nothing
+    /// else. `ulimit -v` takes KiB.
+    pub fn wrap(&self, argv: &[OsString]) -> Vec<OsString> {
+        let Some(prefix) = self.shell_prefix() else {
+            return argv.to_vec();
+        };
+        let mut wrapped: Vec<OsString> = vec![
+            "sh".into(),
+            "-c".into(),
+            format!("{prefix}exec \"$@\"").into(),
+            "sh".into(),
+        ];
+        wrapped.extend(argv.iter().cloned());
+        wrapped
+    }
+
+    /// Shell snippet to put in front of a `--run-command`.
+    pub fn shell_prefix(&self) -> Option<String> {
+        if cfg!(windows) {
+            return None;
+        }
+        let kib = self.max_memory? / 1024;
+        Some(format!("ulimit -v {kib} || exit 125; "))
+    }
+
+    pub fn output_note(&self) -> String {
+        format!(
+            "[output stopped at the {} limit]\n",
+            crate::ui::size(self.max_output as u64)
+        )
+    }
+}
+
+/// Parses sizes like `512K`, `256M`, `1G` or plain bytes, in binary units.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let t = s.trim();
+    let upper = t.to_ascii_uppercase();
+    let unit = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (digits, multiplier) = match unit.chars().last() {
+        Some('K') => (&unit[..unit.len() - 1], 1u64 << 10),
+        Some('M') => (&unit[..unit.len() - 1], 1 << 20),
+        Some('G') => (&unit[..unit.len() - 1], 1 << 30),
+        _ => (unit, 1),
+    };
+    digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("'{t}' isn't a size, use e.g. 512K, 256M or 1G"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_parse() {
+        assert_eq!(parse_size("256M"), Ok(256 << 20));
+        assert_eq!(parse_size("1g"), Ok(1 << 30));
+        assert_eq!(parse_size("512KiB"), Ok(512 << 10));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn memory_limit_wraps_argv() {
+        let limits = Limits {
+            max_memory: Some(256 << 20),
+            ..Limits::default()
+        };
+        let wrapped = limits.wrap(&["./prog".into(), "arg".into()]);
+        assert_eq!(wrapped[2], "ulimit -v 262144 || exit 125; exec \"$@\"");
+        assert_eq!(&wrapped[4..], &["./prog", "arg"]);
+        assert_eq!(Limits::default().wrap(&["./prog".into()]), ["./prog"]);
+    }
+}
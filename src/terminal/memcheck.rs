@@ -0,0 +1,143 @@
+use super::exec;
+use super::limits::Limits;
+use crate::error::{Result, io_err};
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+const VALGRIND: &str = "valgrind";
+pub const VALGRIND_ARGS: &[&str] = &["--leak-check=full"];
+
+pub struct MemCheck {
+    /// Valgrind's own report, without the `==pid==` prefixes.
+    pub report: String,
+    pub errors: usize,
+    /// Definitely plus indirectly lost bytes.
+    pub leaked_bytes: u64,
+}
+
+impl MemCheck {
+    pub fn is_clean(&self) -> bool {
+        self.errors == 0 && self.leaked_bytes == 0
+    }
+
+    pub fn summary(&self) -> String {
+        if self.is_clean() {
+            return "no leaks or memory errors".into();
+        }
+        let errors = if self.errors == 1 { "error" } else { "errors" };
+        format!(
+            "{} {errors}, {} bytes leaked",
+            self.errors, self.leaked_bytes
+        )
+    }
+}
+
+pub fn available() -> bool {
+    !super::exec::is_forbidden() && Command::new(VALGRIND).arg("--version").output().is_ok()
+}
+
+/// Runs `argv` under valgrind with `input` on stdin. The report goes to its
+/// own log file so the program's stderr doesn't get mixed into it.
+pub fn run(
+    argv: &[OsString],
+    input: &str,
+    timeout: Duration,
+    limits: &Limits,
+    log: &Path,
+) -> Result<MemCheck> {
+    let mut vg: Vec<OsString> = vec![VALGRIND.into()];
+    vg.extend(VALGRIND_ARGS.iter().map(OsString::from));
+    let mut log_arg = OsString::from("--log-file=");
+    log_arg.push(log);
+    vg.push(log_arg);
+    vg.extend(argv.iter().cloned());
+
+    // Valgrind's own overhead would trip a memory limit meant for the program
+    let limits = Limits {
+        max_memory: None,
+        ..*limits
+    };
+    let result = exec::run_with_input(&vg, &[], input, timeout, &limits);
+    let text = std::fs::read_to_string(log);
+    let _ = std::fs::remove_file(log);
+    result?;
+    let text = text.map_err(|e| io_err("reading valgrind log", e))?;
+    Ok(parse(&text))
+}
+
+pub fn parse(log: &str) -> MemCheck {
+    let report: Vec<&str> = log.lines().map(strip_pid).collect();
+    let errors = report
+        .iter()
+        .find_map(|l| l.strip_prefix("ERROR SUMMARY: "))
+        .and_then(leading_number)
+        .unwrap_or(0) as usize;
+    let lost = |kind: &str| {
+        report
+            .iter()
+            .find_map(|l| l.trim_start().strip_prefix(kind))
+            .and_then(leading_number)
+            .unwrap_or(0)
+    };
+    MemCheck {
+        report: report.join("\n").trim().to_string(),
+        errors,
+        leaked_bytes: lost("definitely lost: ") + lost("indirectly lost: "),
+    }
+}
+
+fn strip_pid(line: &str) -> &str {
+    line.strip_prefix("==")
+        .and_then(|rest| rest.split_once("== "))
+        .filter(|(pid, _)| pid.chars().all(|c| c.is_ascii_digit()))
+        .map_or(line, |(_, text)| text)
+}
+
+/// Parses "1,234 bytes ..." style counts.
+fn leading_number(text: &str) -> Option<u64> {
+    let digits: String = text
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == ',')
+        .filter(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEAKY: &str = "==4242== Memcheck, a memory error detector
+==4242== 
+==4242== HEAP SUMMARY:
+==4242==     in use at exit: 1,064 bytes in 2 blocks
+==4242== LEAK SUMMARY:
+==4242==    definitely lost: 1,024 bytes in 1 blocks
+==4242==    indirectly lost: 40 bytes in 1 blocks
+==4242==      possibly lost: 0 bytes in 0 blocks
+==4242== 
+==4242== ERROR SUMMARY: 3 errors from 2 contexts (suppressed: 0 from 0)
+";
+
+    #[test]
+    fn parses_leaks_and_errors() {
+        let m = parse(LEAKY);
+        assert_eq!(m.errors, 3);
+        assert_eq!(m.leaked_bytes, 1064);
+        assert!(!m.is_clean());
+        assert!(m.report.starts_with("Memcheck, a memory error detector"));
+        assert!(!m.report.contains("==4242=="));
+    }
+
+    #[test]
+    fn clean_run() {
+        let m = parse(
+            "==7== All heap blocks were freed -- no leaks are possible\n\
+             ==7== ERROR SUMMARY: 0 errors from 0 contexts (suppressed: 0 from 0)\n",
+        );
+        assert!(m.is_clean());
+        assert_eq!(m.summary(), "no leaks or memory errors");
+    }
+}
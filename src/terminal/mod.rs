@@ -1,89 +1,459 @@
+pub mod ansi;
 pub mod capture;
+pub mod echo;
 pub mod emulate;
+pub mod environment;
 pub mod exec;
 pub mod format;
+pub mod interact;
+pub mod limits;
+pub mod memcheck;
+pub mod remote;
+pub mod sanitize;
+pub mod shell_check;
 
+use crate::config::WindowsShell;
 use crate::error::{Error, Result, io_err};
+use crate::lang::LanguageBackend;
 use crate::ui;
+use format::{Segment, Stream};
 use std::env;
-use std::process::Command;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 const PTY_COLS: usize = 120;
 
+/// Name of the test binary when it's built in a user-chosen directory. It's
+/// kept stable so the path can be allowlisted by antivirus software.
+pub const LOCAL_BIN_NAME: &str = if cfg!(windows) {
+    "ap_run.exe"
+} else {
+    "ap_run"
+};
+
 pub struct RunCapture {
     pub command_display: String,
+    /// One entry per test case, in the order they ran.
+    pub cases: Vec<CaseCapture>,
+    pub compile_time: Option<Duration>,
+    /// Total across every case.
+    pub run_time: Duration,
+    pub compile: Option<CompileInfo>,
+    /// OS, architecture and shell the program ran under.
+    pub environment: Option<String>,
+}
+
+impl RunCapture {
+    /// A capture with no runs, for a pack made with `--no-run`.
+    pub fn skipped(command_display: String) -> Self {
+        Self {
+            command_display,
+            cases: Vec::new(),
+            compile_time: None,
+            run_time: Duration::ZERO,
+            compile: None,
+            environment: None,
+        }
+    }
+}
+
+pub struct CaseCapture {
+    pub name: Option<String>,
     pub formatted_output: String,
-    pub screenshot_text: String,
+    /// `formatted_output` with the colors the program printed in kept as
+    /// `ansi` markers, for the doc's text listing.
+    pub colored_output: String,
+    /// Only what the program wrote to stdout, without colors or the headings
+    /// and exit code of `formatted_output`. `--expected` is checked against
+    /// this.
+    pub stdout: String,
+    /// What the screenshot shows: the command line, then the output by
+    /// stream, with piped input echoed where it was read.
+    pub screenshot: Vec<Segment>,
+    /// Valgrind results when the run was repeated under it.
+    pub memcheck: Option<memcheck::MemCheck>,
+    /// Sanitizer reports when built with `--sanitize`, empty if none fired.
+    pub sanitizer_report: Option<String>,
+    /// `None` when the program was killed rather than exiting.
+    pub exit_code: Option<i32>,
+    pub error_case: bool,
+    /// Wall time of the run itself, not counting a valgrind repeat.
+    pub duration: Duration,
 }
 
-pub fn capture_run(
-    c_file: &std::path::Path,
-    run_command: Option<&str>,
-    display_command: &str,
-    input: Option<&str>,
-    timeout: Option<u64>,
-) -> Result<RunCapture> {
+/// One run of the program. Without input or a script the run is interactive.
+pub struct TestCase {
+    pub name: Option<String>,
+    pub input: Option<String>,
+    /// Output the run should produce, checked after capture.
+    pub expected: Option<String>,
+    /// Send/expect steps that drive the program in a PTY instead of a person.
+    pub script: Option<interact::Script>,
+    /// Fed deliberately bad input, so a non-zero exit is the expected outcome.
+    pub error_case: bool,
+}
+
+pub struct CompileInfo {
+    pub compiler: String,
+    /// Compiler invocation as shown in the doc, without the temp output path.
+    pub command: String,
+    pub diagnostics: format::Diagnostics,
+    /// Everything the compiler printed, empty for a clean build.
+    pub log: String,
+}
+
+pub struct RunOptions<'a> {
+    pub sources: &'a [PathBuf],
+    pub lang: &'a dyn LanguageBackend,
+    pub run_command: Option<&'a str>,
+    pub display_command: &'a str,
+    pub cases: &'a [TestCase],
+    pub timeout: Option<u64>,
+    pub deny_warnings: bool,
+    pub bin_dir: Option<&'a Path>,
+    pub valgrind: bool,
+    pub sanitize: &'a [String],
+    pub limits: limits::Limits,
+    /// Feed piped input through a terminal instead of a pipe.
+    pub pty: bool,
+    /// Show piped input after the prompts in the screenshot.
+    pub echo_input: bool,
+    /// Build and run on another machine instead.
+    pub remote: Option<&'a remote::Remote>,
+    /// Shell for the run command on Windows.
+    pub shell: WindowsShell,
+}
+
+pub fn capture_run(opts: &RunOptions<'_>) -> Result<RunCapture> {
+    let RunOptions {
+        sources,
+        lang,
+        run_command,
+        display_command,
+        cases,
+        timeout,
+        deny_warnings,
+        bin_dir,
+        valgrind,
+        sanitize,
+        limits,
+        pty,
+        echo_input,
+        remote,
+        shell,
+    } = *opts;
+    exec::check_allowed("the compiler or the program")?;
     let timeout = timeout
         .map(|s| Duration::from_secs(s.clamp(5, 300)))
         .unwrap_or(DEFAULT_TIMEOUT);
 
     if let Some(cmd) = run_command {
-        let output = exec::shell_exec_with_input(cmd, input, timeout)?;
-        let formatted = format::format_output(&output);
-        let screenshot_text = format!("$ {display_command}\n\n{formatted}");
+        if valgrind {
+            ui::warn("--valgrind only works with the built-in compile step, skipping");
+        }
+        if !sanitize.is_empty() {
+            ui::warn("--sanitize only works with the built-in compile step, skipping");
+        }
+        let started = Instant::now();
+        let mut captured = Vec::with_capacity(cases.len());
+        for case in cases {
+            announce(case, cases.len());
+            let case_started = Instant::now();
+            let call = exec::shell_call(cmd, shell);
+            let (output, cols) = match (&case.script, case.input.as_deref()) {
+                (Some(script), _) => (
+                    interact::run(&call.argv, &call.env, script, timeout, &limits)?.into(),
+                    Some(PTY_COLS),
+                ),
+                (None, Some(input)) if pty => (
+                    interact::feed(&call.argv, &call.env, input, timeout, &limits)?.into(),
+                    Some(PTY_COLS),
+                ),
+                (None, input) => (
+                    exec::shell_exec_with_input(cmd, shell, input, timeout, &limits)?.into(),
+                    None,
+                ),
+            };
+            captured.push(case_capture(
+                case,
+                display_command,
+                &output,
+                cols,
+                echo_input,
+                case_started.elapsed(),
+            ));
+        }
         return Ok(RunCapture {
             command_display: display_command.to_string(),
-            formatted_output: formatted,
-            screenshot_text,
+            cases: captured,
+            compile_time: None,
+            run_time: started.elapsed(),
+            compile: None,
+            environment: Some(environment::with_shell(shell)),
         });
     }
 
-    let compiler = exec::detect_compiler().ok_or_else(|| {
-        Error::Validation("no C compiler found (gcc/clang), use --run-command".into())
-    })?;
-
-    let ts = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis();
-    let bin_name = if cfg!(windows) {
-        format!("ap_run_{ts}_{}.exe", std::process::id())
-    } else {
-        format!("ap_run_{ts}_{}", std::process::id())
+    if remote.is_some() && (valgrind || !sanitize.is_empty()) {
+        ui::warn("--valgrind and --sanitize don't work with --remote, skipping");
+    }
+    let (valgrind, sanitize) = match remote {
+        Some(_) => (false, &[][..]),
+        None => (valgrind, sanitize),
+    };
+    // The memory limit goes in the remote run script, not on ssh
+    let limits = match remote {
+        Some(_) => limits::Limits {
+            max_memory: None,
+            ..limits
+        },
+        None => limits,
     };
-    let bin = env::temp_dir().join(bin_name);
-
-    ui::step(&format!("Compiling with {compiler}..."));
 
-    let compile = Command::new(compiler)
-        .arg(c_file)
-        .arg("-o")
-        .arg(&bin)
-        .output()
-        .map_err(|e| io_err(format!("running {compiler}"), e))?;
+    // Headers are listed as sources for the doc but only translation units
+    // go on the compiler command line
+    let units: Vec<&Path> = sources
+        .iter()
+        .filter(|p| !crate::lang::is_header(p))
+        .map(PathBuf::as_path)
+        .collect();
+    let bin = bin_path(bin_dir)?;
+    let started = Instant::now();
+    let (compiler, compile) = match remote {
+        Some(remote) => remote.compile(lang)?,
+        None => {
+            let compiler = lang.detect().ok_or_else(|| {
+                Error::Validation(format!(
+                    "no {} compiler found ({}), use --run-command or pack without output using --no-run",
+                    lang.name(),
+                    lang.compilers().join("/")
+                ))
+            })?;
+            ui::step(&format!("Compiling with {compiler}..."));
+            let mut compile_cmd = lang.compile_command(compiler, &units, &bin);
+            if !sanitize.is_empty() {
+                compile_cmd.args(sanitize::compile_flags(sanitize));
+            }
+            let compile = compile_cmd
+                .output()
+                .map_err(|e| io_err(format!("running {compiler}"), e))?;
+            (compiler.to_string(), compile)
+        }
+    };
+    let compile_time = started.elapsed();
+    // A remote build leaves nothing here to clean up
+    let discard_bin = || {
+        if remote.is_none() {
+            let _ = std::fs::remove_file(&bin);
+        }
+    };
 
     if !compile.status.success() {
         return Err(Error::CompileFailed(format::format_output(&compile)));
     }
+    let log = format!(
+        "{}{}",
+        String::from_utf8_lossy(&compile.stdout),
+        String::from_utf8_lossy(&compile.stderr)
+    )
+    .trim_end()
+    .to_string();
+    let unit_names: Vec<String> = units
+        .iter()
+        .map(|p| {
+            p.file_name()
+                .unwrap_or(p.as_os_str())
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    let compile_info = CompileInfo {
+        command: format!("{compiler} {}", unit_names.join(" ")),
+        compiler,
+        diagnostics: format::count_diagnostics(&log),
+        log,
+    };
+    if deny_warnings && compile_info.diagnostics.warnings > 0 {
+        discard_bin();
+        return Err(Error::WarningsDenied(compile_info.log));
+    }
 
-    let (run_output, pty_cols) = if let Some(input_str) = input {
-        (exec::run_with_input(&bin, input_str, timeout)?, None)
-    } else {
-        (capture::run_interactive(&bin, timeout)?, Some(PTY_COLS))
+    let valgrind = valgrind && {
+        let found = memcheck::available();
+        if !found {
+            ui::warn("valgrind not found, skipping the memory check");
+        }
+        found
     };
 
-    if let Err(e) = std::fs::remove_file(&bin) {
-        eprintln!("warning: couldn't clean up temp binary: {e}");
+    let started = Instant::now();
+    let run_argv = |tty: bool| match remote {
+        Some(remote) => remote.run_argv(tty),
+        None => lang.run_command(&bin),
+    };
+    let argv = run_argv(false);
+    let san_log = bin.with_extension("san");
+    let env = if sanitize.is_empty() {
+        Vec::new()
+    } else {
+        sanitize::env(&san_log)
+    };
+    let mut captured = Vec::with_capacity(cases.len());
+    for case in cases {
+        announce(case, cases.len());
+        let case_started = Instant::now();
+        let result = match (&case.script, case.input.as_deref()) {
+            (Some(script), _) => interact::run(&run_argv(true), &env, script, timeout, &limits)
+                .map(|r| (r.into(), Some(PTY_COLS))),
+            (None, Some(input)) if pty => {
+                interact::feed(&run_argv(true), &env, input, timeout, &limits)
+                    .map(|r| (r.into(), Some(PTY_COLS)))
+            }
+            (None, Some(input)) => {
+                exec::run_with_input(&argv, &env, input, timeout, &limits).map(|o| (o.into(), None))
+            }
+            (None, None) => capture::run_interactive(&run_argv(true), &env, timeout, &limits)
+                .map(|o| (o.into(), Some(PTY_COLS))),
+        };
+        let (output, cols) = match result {
+            Ok(r) => r,
+            Err(e) => {
+                discard_bin();
+                return Err(e);
+            }
+        };
+        let mut run = case_capture(
+            case,
+            display_command,
+            &output,
+            cols,
+            echo_input,
+            case_started.elapsed(),
+        );
+        if !sanitize.is_empty() {
+            run.sanitizer_report = Some(sanitize::take_reports(&san_log));
+        }
+        if valgrind {
+            ui::step("Running under valgrind...");
+            // Interactive runs get empty stdin, there's no one to type
+            let input = case.input.as_deref().unwrap_or("");
+            let log = bin.with_extension("valgrind.log");
+            match memcheck::run(&argv, input, timeout, &limits, &log) {
+                Ok(m) => run.memcheck = Some(m),
+                Err(e) => {
+                    discard_bin();
+                    return Err(e);
+                }
+            }
+        }
+        captured.push(run);
     }
+    let run_time = started.elapsed();
 
-    let formatted = format::format_output_with_cols(&run_output, pty_cols);
-    let screenshot_text = format!("$ {display_command}\n\n{formatted}");
+    if remote.is_none()
+        && let Err(e) = std::fs::remove_file(&bin)
+    {
+        ui::warn(&format!("couldn't clean up temp binary: {e}"));
+    }
 
     Ok(RunCapture {
         command_display: display_command.to_string(),
-        formatted_output: formatted,
-        screenshot_text,
+        cases: captured,
+        compile_time: Some(compile_time),
+        run_time,
+        compile: Some(compile_info),
+        environment: Some(match remote {
+            Some(r) => r.environment().to_string(),
+            None => environment::local(),
+        }),
     })
 }
+
+fn announce(case: &TestCase, total: usize) {
+    if total > 1
+        && let Some(name) = &case.name
+    {
+        ui::step(&format!("Running test '{name}'..."));
+    }
+}
+
+/// A finished run. PTY runs also know their output without the echo of
+/// what was typed.
+struct RunOutput {
+    output: std::process::Output,
+    program_output: Option<Vec<u8>>,
+}
+
+impl From<std::process::Output> for RunOutput {
+    fn from(output: std::process::Output) -> Self {
+        Self {
+            output,
+            program_output: None,
+        }
+    }
+}
+
+impl From<interact::PtyRun> for RunOutput {
+    fn from(run: interact::PtyRun) -> Self {
+        Self {
+            output: run.output,
+            program_output: Some(run.program_output),
+        }
+    }
+}
+
+fn case_capture(
+    case: &TestCase,
+    display_command: &str,
+    run: &RunOutput,
+    cols: Option<usize>,
+    echo_input: bool,
+    duration: Duration,
+) -> CaseCapture {
+    let output = &run.output;
+    let stdout = run.program_output.as_deref().unwrap_or(&output.stdout);
+    let colored = format::colored_output(output, cols);
+    let mut screenshot = vec![Segment::new(
+        Stream::Command,
+        format!("$ {}\n\n", ansi::escape(display_command)),
+    )];
+    // Only piped runs need it, a terminal echoes input by itself
+    match case.input.as_deref() {
+        Some(input) if echo_input && cols.is_none() => {
+            screenshot.extend(format::echoed_segments(output, input, cols));
+        }
+        _ => screenshot.extend(format::output_segments(output, cols)),
+    }
+    CaseCapture {
+        name: case.name.clone(),
+        screenshot,
+        formatted_output: ansi::strip(&colored),
+        colored_output: colored,
+        stdout: format::plain_text(stdout, cols),
+        memcheck: None,
+        sanitizer_report: None,
+        exit_code: output.status.code(),
+        error_case: case.error_case,
+        duration,
+    }
+}
+
+/// Where the compiled test binary goes: a fixed name inside `bin_dir` if one
+/// is configured, otherwise a unique name in the system temp directory.
+fn bin_path(bin_dir: Option<&Path>) -> Result<PathBuf> {
+    if let Some(dir) = bin_dir {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| io_err(format!("creating binary directory '{}'", dir.display()), e))?;
+        return Ok(dir.join(LOCAL_BIN_NAME));
+    }
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let bin_name = if cfg!(windows) {
+        format!("ap_run_{ts}_{}.exe", std::process::id())
+    } else {
+        format!("ap_run_{ts}_{}", std::process::id())
+    };
+    Ok(env::temp_dir().join(bin_name))
+}
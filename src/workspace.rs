@@ -0,0 +1,228 @@
+//! `ap workspace`: a course folder with one folder per assignment
+//! (`assignment1/` ... `assignmentN/`), marked by a file at its top so the
+//! assignments can be packed, and progress checked, from anywhere inside it.
+
+use crate::cli::{Cli, WorkspaceCommand};
+use crate::config;
+use crate::error::{Error, Result, io_err};
+use crate::history::{self, Entry};
+use crate::pack;
+use crate::ui;
+use crate::validate::{self, AssignmentLabel};
+use clap::Parser;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File that marks the top of a workspace.
+const MARKER: &str = ".ap-workspace";
+
+const MARKER_TEXT: &str = "# Marks this folder as an ap workspace, see `ap workspace status`\n";
+
+/// An assignment's folder in the workspace.
+#[derive(Debug)]
+struct Folder {
+    label: AssignmentLabel,
+    dir: PathBuf,
+}
+
+pub fn run_workspace_command(command: WorkspaceCommand) -> Result<()> {
+    match command {
+        WorkspaceCommand::Init => run_init(),
+        WorkspaceCommand::Status => run_status(),
+        WorkspaceCommand::Pack { assignment, args } => run_pack(&assignment, args),
+    }
+}
+
+fn run_init() -> Result<()> {
+    let cwd = env::current_dir().map_err(|e| io_err("current directory", e))?;
+    if let Some(root) = find_root(&cwd) {
+        if root == cwd {
+            ui::step("This folder is already a workspace");
+            return Ok(());
+        }
+        return Err(Error::Validation(format!(
+            "already inside the workspace at {}",
+            root.display()
+        )));
+    }
+    let cfg = config::load(&config::config_path()?)?;
+    let found = folders(&cwd, cfg.assignment_suffixes.as_deref())?;
+    fs::write(cwd.join(MARKER), MARKER_TEXT).map_err(|e| io_err("writing workspace marker", e))?;
+    ui::success(&format!("Made {} a workspace", cwd.display()));
+    match found.len() {
+        0 => ui::step("No assignment folders yet, name them assignment1, assignment2, ..."),
+        1 => ui::step("Found 1 assignment folder"),
+        n => ui::step(&format!("Found {n} assignment folders")),
+    }
+    Ok(())
+}
+
+fn run_status() -> Result<()> {
+    let root = workspace_root()?;
+    let cfg = config::load(&config::config_path()?)?;
+    let found = folders(&root, cfg.assignment_suffixes.as_deref())?;
+    if found.is_empty() {
+        ui::step("No assignment folders yet, name them assignment1, assignment2, ...");
+        return Ok(());
+    }
+    let past = history::load(&history::history_path()?)?;
+    let course = root.file_name().map_or_else(
+        || root.display().to_string(),
+        |n| n.to_string_lossy().into_owned(),
+    );
+    ui::header(&course);
+    let mut packed = 0;
+    for folder in &found {
+        let name = folder
+            .dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match last_pack(&past.entries, &folder.dir) {
+            Some(e) => {
+                packed += 1;
+                ui::kv(
+                    &name,
+                    &format!("packed {} as {}", history::date(e.timestamp), e.assignment),
+                );
+            }
+            None => ui::kv(&name, &ui::dim("not packed")),
+        }
+    }
+    ui::step(&format!("{packed} of {} packed", found.len()));
+    Ok(())
+}
+
+/// Packs the folder of `assignment` wherever in the workspace this is run
+/// from. `args` are pack flags, parsed as if given to `ap` in that folder.
+fn run_pack(assignment: &str, args: Vec<OsString>) -> Result<()> {
+    let mut cli = Cli::parse_from(std::iter::once(OsString::from("ap")).chain(args));
+    if cli.command.is_some() {
+        return Err(Error::Validation(
+            "ap workspace pack takes pack flags, not another command".into(),
+        ));
+    }
+    if cli.assignment.is_some() {
+        return Err(Error::Validation(
+            "ap workspace pack already names the assignment, drop -a".into(),
+        ));
+    }
+    let cfg = config::load(&config::config_path()?)?;
+    let suffixes = cfg.assignment_suffixes.as_deref();
+    let wanted = validate::parse_assignment(assignment, suffixes)?;
+    let root = workspace_root()?;
+    let folder = folders(&root, suffixes)?
+        .into_iter()
+        .find(|f| f.label.label().eq_ignore_ascii_case(&wanted.label()))
+        .ok_or_else(|| {
+            Error::Validation(format!(
+                "no folder for {} in {}",
+                wanted.label(),
+                root.display()
+            ))
+        })?;
+    env::set_current_dir(&folder.dir).map_err(|e| io_err("entering the assignment folder", e))?;
+    ui::step(&format!("Packing {}", folder.dir.display()));
+    cli.assignment = Some(wanted.label());
+    pack::run_pack(cli)
+}
+
+fn workspace_root() -> Result<PathBuf> {
+    let cwd = env::current_dir().map_err(|e| io_err("current directory", e))?;
+    find_root(&cwd).ok_or_else(|| {
+        Error::Validation(
+            "not inside a workspace, run ap workspace init in the course folder first".into(),
+        )
+    })
+}
+
+/// The nearest folder at or above `dir` holding the workspace marker.
+fn find_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|d| d.join(MARKER).is_file())
+        .map(Path::to_path_buf)
+}
+
+/// The assignment folders directly under `root`, in assignment order.
+fn folders(root: &Path, suffixes: Option<&[String]>) -> Result<Vec<Folder>> {
+    let entries = fs::read_dir(root).map_err(|e| io_err("reading workspace folder", e))?;
+    let mut found = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| io_err("reading workspace folder", e))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || !entry.path().is_dir() {
+            continue;
+        }
+        if let Some(label) = folder_label(&name, suffixes) {
+            found.push(Folder {
+                label,
+                dir: entry.path(),
+            });
+        }
+    }
+    found.sort_by(|a, b| {
+        (a.label.number, &a.label.suffix, &a.dir).cmp(&(b.label.number, &b.label.suffix, &b.dir))
+    });
+    Ok(found)
+}
+
+/// Reads a folder name like `assignment7`, `Assignment 7b`, `a7` or `7`.
+fn folder_label(name: &str, suffixes: Option<&[String]>) -> Option<AssignmentLabel> {
+    validate::parse_assignment(name, suffixes).ok().or_else(|| {
+        let rest = name.strip_prefix(['a', 'A'])?;
+        if !rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        validate::parse_assignment(rest, suffixes).ok()
+    })
+}
+
+/// The latest pack made from `dir`.
+fn last_pack<'a>(entries: &'a [Entry], dir: &Path) -> Option<&'a Entry> {
+    entries
+        .iter()
+        .filter(|e| e.source_dir == dir)
+        .max_by_key(|e| e.timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::scratch_dir;
+
+    #[test]
+    fn folders_are_found_in_assignment_order() {
+        let root = scratch_dir("workspace");
+        for dir in ["assignment10", "Assignment 2", "a3b", "notes", ".git", "a"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("assignment4"), "not a folder").unwrap();
+        fs::write(root.join(MARKER), MARKER_TEXT).unwrap();
+
+        let found = folders(&root, None).unwrap();
+        let labels: Vec<String> = found.iter().map(|f| f.label.label()).collect();
+        assert_eq!(labels, ["Assignment2", "Assignment3b", "Assignment10"]);
+        assert_eq!(find_root(&root.join("a3b")), Some(root.to_path_buf()));
+        assert_eq!(find_root(&env::temp_dir()), None);
+    }
+
+    #[test]
+    fn status_uses_the_latest_pack_of_each_folder() {
+        let entry = |timestamp, assignment: &str, dir: &str| Entry {
+            timestamp,
+            assignment: assignment.into(),
+            source_dir: dir.into(),
+            ..Default::default()
+        };
+        let entries = [
+            entry(10, "Assignment1", "/uni/cs101/assignment1"),
+            entry(30, "Assignment1-redo", "/uni/cs101/assignment1"),
+            entry(20, "Assignment2", "/uni/maths/assignment2"),
+        ];
+        let last = last_pack(&entries, Path::new("/uni/cs101/assignment1")).unwrap();
+        assert_eq!(last.assignment, "Assignment1-redo");
+        assert!(last_pack(&entries, Path::new("/uni/cs101/assignment2")).is_none());
+    }
+}